@@ -0,0 +1,28 @@
+#!/usr/bin/env python3
+"""A bot for DOSPong that keeps its paddle level with the ball.
+
+    DOSPong --bot2 "python3 examples/follow_bot.py"
+
+Each line on standard input is the field as JSON; answer every one with
+up, down or stay on a line of its own.
+"""
+
+import json
+import sys
+
+for line in sys.stdin:
+    state = json.loads(line)
+    paddle = next(p for p in state["paddles"] if p["player"] == state["you"])
+    middle = paddle["y"] + paddle["height"] / 2
+
+    # Go for the ball that's closest to this paddle
+    balls = sorted(state["balls"], key=lambda ball: abs(ball["x"] - paddle["x"]))
+    action = "stay"
+    if balls:
+        ball = balls[0]
+        if ball["y"] < middle - 1:
+            action = "up"
+        elif ball["y"] > middle + 1:
+            action = "down"
+
+    print(action, flush=True)
@@ -0,0 +1,1333 @@
+//! The flow of a match: which screen is up, what the keys do there, and
+//! when the simulation is allowed to run.
+
+use crate::bot::ExternalBot;
+use crate::config::{Action, Config, Key, KeyBindings};
+use crate::crash::{FrameInput, RECENT_FRAMES};
+use crate::event_stream::EventStream;
+use crate::frontend::{TerminalFrontend, SPARE_ROWS};
+use crate::records::{NewBests, Records};
+use crate::save;
+#[cfg(feature = "scripting")]
+use crate::scripts::Scripts;
+use crate::settings::{Outcome, Setting, SettingsMenu};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use dospong::net::{self, Client, Connection, Message, Server, Snapshot};
+use dospong::pong::{Dir, Game, Input, PowerUpSettings};
+use dospong::replay::{Record, Recorder};
+use dospong::rollback::{Desync, Session, TickInput};
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// How long a key counts as held after its last press when the terminal
+// doesn't report releases and we rely on autorepeat instead
+const KEY_HOLD_WINDOW: Duration = Duration::from_millis(150);
+// Two taps on a movement key this close together dash the paddle
+const DOUBLE_TAP: Duration = Duration::from_millis(200);
+// Whose paddle each key plays
+const PLAYER_ACTIONS: [(Action, u8); 14] = [
+    (Action::P1Up, 1),
+    (Action::P1Down, 1),
+    (Action::P1Serve, 1),
+    (Action::P1Dash, 1),
+    (Action::P1Charge, 1),
+    (Action::P2Up, 2),
+    (Action::P2Down, 2),
+    (Action::P2Serve, 2),
+    (Action::P2Dash, 2),
+    (Action::P2Charge, 2),
+    (Action::P3Left, 3),
+    (Action::P3Right, 3),
+    (Action::P4Left, 4),
+    (Action::P4Right, 4),
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum InputMode {
+    /// The terminal reports key releases, so a key is held until released
+    PressRelease,
+    /// No release events: a key counts as held for a short while after each
+    /// press, and the terminal's autorepeat keeps it alive
+    Autorepeat,
+}
+
+/// Held state of one movement action, which any of several physical keys
+/// can drive
+#[derive(Default)]
+struct HeldAction {
+    // Physical keys currently down for this action
+    down: Vec<KeyCode>,
+    last_pressed: Option<Instant>,
+    // When the key was last tapped, as opposed to held, until the next
+    // press decides whether that's a double tap
+    tapped: Option<Instant>,
+}
+
+impl HeldAction {
+    fn is_held(&self, mode: InputMode, now: Instant) -> bool {
+        match mode {
+            InputMode::PressRelease => !self.down.is_empty(),
+            InputMode::Autorepeat => self
+                .last_pressed
+                .is_some_and(|pressed| now.duration_since(pressed) < KEY_HOLD_WINDOW),
+        }
+    }
+}
+
+/// Movement key state for both players
+pub struct Controls {
+    mode: InputMode,
+    // Whether the kitty keyboard protocol is giving us real releases
+    enhanced: bool,
+    // Indexed by `Controls::slot`; each up/down (or left/right) pair sits
+    // side by side, with the charge keys after them
+    held: [HeldAction; 10],
+}
+
+impl Controls {
+    pub fn new(enhanced: bool) -> Self {
+        // Without the keyboard protocol, assume the worst until the terminal
+        // proves it sends releases
+        Controls {
+            mode: if enhanced {
+                InputMode::PressRelease
+            } else {
+                InputMode::Autorepeat
+            },
+            enhanced,
+            held: Default::default(),
+        }
+    }
+
+    fn slot(action: Action) -> Option<usize> {
+        match action {
+            Action::P1Up => Some(0),
+            Action::P1Down => Some(1),
+            Action::P2Up => Some(2),
+            Action::P2Down => Some(3),
+            Action::P3Left => Some(4),
+            Action::P3Right => Some(5),
+            Action::P4Left => Some(6),
+            Action::P4Right => Some(7),
+            Action::P1Charge => Some(8),
+            Action::P2Charge => Some(9),
+            _ => None,
+        }
+    }
+
+    /// Hold down a movement key. Returns whether this press makes a double
+    /// tap, which autorepeat coming in while a key is held never does.
+    pub fn press(&mut self, action: Action, code: KeyCode, now: Instant) -> bool {
+        let Some(slot) = Self::slot(action) else {
+            return false;
+        };
+        let code = Key::normalized(code);
+        let held = &mut self.held[slot];
+        let fresh = !held.is_held(self.mode, now);
+        let double_tap = held
+            .tapped
+            .take()
+            .is_some_and(|tapped| now.duration_since(tapped) < DOUBLE_TAP);
+        if fresh && !double_tap {
+            held.tapped = Some(now);
+        }
+        if !held.down.contains(&code) {
+            held.down.push(code);
+        }
+        held.last_pressed = Some(now);
+        // The charge keys have no other way to go
+        if slot >= 8 {
+            return double_tap;
+        }
+        // Going the other way in between doesn't make a double tap
+        self.held[slot ^ 1].tapped = None;
+        // Without releases, a press in the new direction has to cancel the
+        // old one or the two would fight until the old one times out
+        if self.mode == InputMode::Autorepeat {
+            self.held[slot ^ 1].last_pressed = None;
+        }
+        double_tap
+    }
+
+    pub fn release(&mut self, action: Action, code: KeyCode) {
+        self.mode = InputMode::PressRelease;
+        if let Some(slot) = Self::slot(action) {
+            let code = Key::normalized(code);
+            self.held[slot].down.retain(|&down| down != code);
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match (self.enhanced, self.mode) {
+            (true, _) => " keys: kitty protocol ",
+            (false, InputMode::PressRelease) => " keys: press/release ",
+            (false, InputMode::Autorepeat) => " keys: autorepeat ",
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        self.held = Default::default();
+    }
+
+    pub fn is_held(&self, action: Action, now: Instant) -> bool {
+        Self::slot(action).is_some_and(|slot| self.held[slot].is_held(self.mode, now))
+    }
+
+    /// Which way a paddle is moving, or last moved if it's standing still
+    pub fn heading(&self, up: Action, down: Action, now: Instant) -> Dir {
+        match Dir::from_keys(self.is_held(up, now), self.is_held(down, now)) {
+            Dir::Stay => {
+                let pressed =
+                    |action| Self::slot(action).and_then(|slot| self.held[slot].last_pressed);
+                match (pressed(up), pressed(down)) {
+                    (Some(up), Some(down)) if up > down => Dir::Up,
+                    (Some(_), None) => Dir::Up,
+                    (_, Some(_)) => Dir::Down,
+                    (None, None) => Dir::Stay,
+                }
+            }
+            dir => dir,
+        }
+    }
+}
+
+/// Where the match is. Each state decides what the keys do, whether the
+/// simulation runs, and which overlay is drawn over the field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameState {
+    /// The ball waits in the middle for the countdown to run out
+    Serving,
+    Playing,
+    Paused,
+    /// A set is over but not the match; Enter starts the next one
+    SetOver,
+    /// Someone won the match; R starts a rematch
+    GameOver,
+}
+
+impl GameState {
+    /// The state a game is in. Over the network the host decides, so this
+    /// is also how the other side follows along.
+    pub fn of(game: &Game) -> Self {
+        if game.winner.is_some() {
+            GameState::GameOver
+        } else if game.set_winner.is_some() {
+            GameState::SetOver
+        } else if game.paused {
+            GameState::Paused
+        } else if game.serve_timer.is_some() {
+            GameState::Serving
+        } else {
+            GameState::Playing
+        }
+    }
+
+    /// Whether the simulation advances in this state
+    pub fn runs_simulation(self) -> bool {
+        matches!(self, GameState::Serving | GameState::Playing)
+    }
+}
+
+/// How far ahead of a frame's deadline sleeping stops and spinning takes
+/// over, since a sleep can wake up late but a spin can't
+const SPIN_BEFORE_DEADLINE: Duration = Duration::from_millis(1);
+
+/// Starts frames at a steady rate, however long each one takes to update
+/// and draw
+pub struct FramePacer {
+    // None runs as fast as the loop goes
+    frame_time: Option<Duration>,
+    deadline: Instant,
+}
+
+impl FramePacer {
+    /// `frame_rate` frames a second, or uncapped for 0
+    pub fn new(frame_rate: u16) -> Self {
+        FramePacer {
+            frame_time: (frame_rate > 0).then(|| Duration::from_secs(1) / frame_rate as u32),
+            deadline: Instant::now(),
+        }
+    }
+
+    /// Wait for the next frame's deadline. A frame that overran starts the
+    /// next one straight away, and the deadlines after it count from then
+    /// rather than rushing to catch up.
+    pub fn wait(&mut self) {
+        let Some(frame_time) = self.frame_time else {
+            return;
+        };
+        self.deadline += frame_time;
+        let now = Instant::now();
+        if now >= self.deadline {
+            self.deadline = now;
+            return;
+        }
+        let left = self.deadline - now;
+        if left > SPIN_BEFORE_DEADLINE {
+            std::thread::sleep(left - SPIN_BEFORE_DEADLINE);
+        }
+        while Instant::now() < self.deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// Ctrl+Q and Ctrl+C quit whatever the bindings say. Raw mode turns
+/// Ctrl+C into an ordinary key press, so without this it would do nothing.
+pub fn always_quits(code: KeyCode, modifiers: KeyModifiers) -> bool {
+    matches!(code, KeyCode::Char('q' | 'Q' | 'c' | 'C'))
+        && modifiers.contains(KeyModifiers::CONTROL)
+}
+
+/// Ctrl+S saves the match to resume later
+fn saves(code: KeyCode, modifiers: KeyModifiers) -> bool {
+    matches!(code, KeyCode::Char('s' | 'S')) && modifiers.contains(KeyModifiers::CONTROL)
+}
+
+/// What handling an event asks the app to do next
+pub enum Transition {
+    Stay,
+    To(GameState),
+    Quit,
+}
+
+/// How this side takes part in the match
+pub enum Link {
+    /// Both paddles are played here, by two people or one and the computer
+    Local { recorder: Option<Recorder> },
+    /// Runs the match for a joining player and any spectators, who can't
+    /// reach a match hosted through the relay
+    Host {
+        conn: Connection,
+        server: Option<Server>,
+        // Snapshots sent so far, and the joining player's last input applied
+        tick: u32,
+        acked_input: u32,
+        // Whether the joining player is holding their charge key, as of
+        // their last input
+        their_charge: bool,
+    },
+    /// Follows the host's match, playing Player 2 unless spectating
+    Client {
+        conn: Connection,
+        client: Client,
+        spectating: bool,
+    },
+    /// Runs the match alongside the other player, hosting with the server
+    /// for spectators and joining without
+    Rollback {
+        conn: Connection,
+        session: Session,
+        server: Option<Server>,
+    },
+}
+
+pub struct App {
+    game: Game,
+    frontend: TerminalFrontend,
+    state: GameState,
+    // What the settings start from, and what they save over
+    config: Config,
+    keys: KeyBindings,
+    controls: Controls,
+    // Serve presses since the last frame, for each player
+    serve: [bool; 2],
+    // And which way each player dashed, if they did
+    dash: [Dir; 2],
+    link: Link,
+    net_debug: bool,
+    records: Records,
+    pacer: FramePacer,
+    // How saving the match on the way out went, to say once the terminal
+    // is back
+    saved: Option<Result<PathBuf, String>>,
+    // The tick a rollback match's two games went out of step at, and how
+    // writing down this side's went
+    desync: Option<(u32, Result<PathBuf, String>)>,
+    // Where the match's events go, with --event-stream
+    events: Option<EventStream>,
+    // The program playing Player 2, with --bot2
+    bot: Option<ExternalBot>,
+    // How long each player has gone without a key while the match is on,
+    // and who it's paused for until they show they're still there
+    idle: [Duration; 4],
+    afk: Option<u8>,
+    // This side's last few frames of input, for a crash report
+    recent_inputs: VecDeque<FrameInput>,
+    // What the custom powerups do
+    #[cfg(feature = "scripting")]
+    scripts: Option<Scripts>,
+}
+
+impl App {
+    pub fn new(
+        game: Game,
+        frontend: TerminalFrontend,
+        config: Config,
+        link: Link,
+        net_debug: bool,
+        records: Records,
+        pacer: FramePacer,
+    ) -> Self {
+        let controls = Controls::new(frontend.enhanced());
+        App {
+            state: GameState::of(&game),
+            game,
+            frontend,
+            keys: config.keys.clone(),
+            config,
+            controls,
+            serve: [false; 2],
+            dash: [Dir::Stay; 2],
+            link,
+            net_debug,
+            records,
+            pacer,
+            saved: None,
+            desync: None,
+            events: None,
+            bot: None,
+            idle: [Duration::ZERO; 4],
+            afk: None,
+            recent_inputs: VecDeque::with_capacity(RECENT_FRAMES),
+            #[cfg(feature = "scripting")]
+            scripts: None,
+        }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn recent_inputs(&self) -> &VecDeque<FrameInput> {
+        &self.recent_inputs
+    }
+
+    /// Have `bot` play Player 2 from now on
+    pub fn play_bot2(&mut self, bot: ExternalBot) {
+        self.bot = Some(bot);
+    }
+
+    /// Run the callbacks of the custom powerups `scripts` added to the game
+    #[cfg(feature = "scripting")]
+    pub fn use_scripts(&mut self, scripts: Scripts) {
+        self.scripts = Some(scripts);
+    }
+
+    /// Send everything that happens in the match to `stream` from now on
+    pub fn stream_events(&mut self, stream: EventStream) {
+        self.game.set_events(true);
+        self.events = Some(stream);
+    }
+
+    /// Play until someone quits
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut last_frame = Instant::now();
+        loop {
+            let now = Instant::now();
+            let dt = now.duration_since(last_frame).as_secs_f32();
+            last_frame = now;
+
+            // Handle input (non-blocking)
+            while let Some(event) = self.frontend.next_event() {
+                match self.handle_event(event?)? {
+                    Transition::Stay => {}
+                    Transition::To(state) => {
+                        if state != self.state {
+                            log::info!("{:?} -> {:?}", self.state, state);
+                        }
+                        self.state = state;
+                    }
+                    Transition::Quit => return Ok(()),
+                }
+            }
+            self.frontend.input_hint = self.controls.describe();
+
+            let started = Instant::now();
+            let state = self.update(dt)?;
+            if self.desync.is_some() {
+                return Ok(());
+            }
+            let stats = &mut self.frontend.frame_stats;
+            stats.add_frame(dt);
+            stats.update = started.elapsed();
+            if state == GameState::GameOver && self.state != GameState::GameOver {
+                self.match_over()?;
+            } else if state != GameState::GameOver {
+                self.frontend.new_bests = NewBests::default();
+            }
+            if state != self.state {
+                log::info!("{:?} -> {:?}", self.state, state);
+            }
+            self.state = state;
+            self.watch_idle(dt)?;
+            if self.state != GameState::Paused {
+                self.frontend.notice = None;
+            }
+            if let Some(stream) = &self.events {
+                stream.send(std::mem::take(&mut self.game.events));
+            }
+
+            // Render
+            self.frontend.play_sounds(&mut self.game);
+            let started = Instant::now();
+            self.frontend.render(&self.game, self.state)?;
+            self.frontend.frame_stats.render = started.elapsed();
+
+            self.pacer.wait();
+        }
+    }
+
+    /// Give the terminal back, finish off any recording and save the
+    /// records, then say how the match went on the normal screen
+    pub fn finish(self) -> io::Result<()> {
+        let summary = Self::summary(&self.game);
+        if let Link::Local {
+            recorder: Some(recorder),
+        } = self.link
+        {
+            recorder.finish()?;
+        }
+        self.frontend.leave()?;
+        // Anything but events on standard output would spoil the stream
+        let mut on_stdout = true;
+        if let Some(stream) = self.events {
+            on_stdout = !stream.to_stdout();
+            if let Err(err) = stream.finish() {
+                eprintln!("DOSPong: couldn't write the event stream: {}", err);
+            }
+        }
+        let mut lines = vec![summary];
+        match self.saved {
+            Some(Ok(path)) => lines.push(format!(
+                "Saved the match to {}; --resume carries on with it.",
+                path.display()
+            )),
+            Some(Err(message)) => eprintln!("DOSPong: couldn't save the match: {}", message),
+            None => {}
+        }
+        match self.desync {
+            Some((tick, Ok(path))) => eprintln!(
+                "DOSPong: the two games went out of step at tick {}, so the match was stopped. \
+                 This side's game is in {}.",
+                tick,
+                path.display()
+            ),
+            Some((tick, Err(message))) => eprintln!(
+                "DOSPong: the two games went out of step at tick {}, so the match was stopped \
+                 (couldn't write this side's down: {})",
+                tick, message
+            ),
+            None => {}
+        }
+        for line in lines {
+            if on_stdout {
+                println!("{}", line);
+            } else {
+                eprintln!("{}", line);
+            }
+        }
+        if let Err(message) = self.records.save() {
+            eprintln!("DOSPong: couldn't save the stats: {}", message);
+        }
+        #[cfg(feature = "scripting")]
+        for error in self.scripts.iter().flat_map(|scripts| &scripts.errors) {
+            eprintln!("DOSPong: powerup script {}", error);
+        }
+        Ok(())
+    }
+
+    /// One line on how the match went, for when the game is gone
+    fn summary(game: &Game) -> String {
+        let mut summary = String::new();
+        if let Some(practice) = &game.practice {
+            let _ = write!(summary, "Practice: best streak {}", practice.best_streak);
+        } else if let Some(survival) = &game.survival {
+            let _ = write!(summary, "Survival: {} returns", survival.returns);
+        } else {
+            if game.best_of > 1 {
+                let _ = write!(summary, "Sets {}-{}, ", game.p1_sets, game.p2_sets);
+            }
+            let label = |side| {
+                let player = game.player_at(side);
+                match game.handicap(player).is_set() {
+                    true => format!("{}(H)", game.name(player)),
+                    false => game.name(player),
+                }
+            };
+            let (p1, p2) = (label(1), label(2));
+            let _ = write!(summary, "{} {} - {} {}", p1, game.p1_score, game.p2_score, p2);
+            if game.four_player() {
+                let _ = write!(summary, ", P3 {}, P4 {}", game.p3_score, game.p4_score);
+            }
+            if let Some(side) = game.winner {
+                let _ = write!(summary, ", {} wins", game.long_name(game.player_at(side)));
+                if game.timed.as_ref().is_some_and(|timed| timed.overtime) {
+                    summary.push_str(" in overtime");
+                }
+            }
+        }
+        let seconds = game.stats.duration.as_secs();
+        let _ = write!(
+            summary,
+            " in {}:{:02}, longest rally {}",
+            seconds / 60,
+            seconds % 60,
+            game.stats.longest_rally
+        );
+        summary
+    }
+
+    /// Add a match that just ended to the records. Spectators only watched,
+    /// so theirs don't count.
+    fn match_over(&mut self) -> io::Result<()> {
+        self.frontend.send_notification(&Self::summary(&self.game))?;
+        if matches!(self.link, Link::Client { spectating: true, .. }) {
+            return Ok(());
+        }
+        self.frontend.new_bests = self.records.add_match(&self.game);
+        Ok(())
+    }
+
+    fn record(&mut self, record: Record) -> io::Result<()> {
+        match &mut self.link {
+            Link::Local {
+                recorder: Some(recorder),
+            } => recorder.record(&record),
+            _ => Ok(()),
+        }
+    }
+
+    fn handle_event(&mut self, event: Event) -> io::Result<Transition> {
+        match event {
+            Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: kind @ (event::KeyEventKind::Press | event::KeyEventKind::Repeat),
+                ..
+            }) => {
+                if always_quits(code, modifiers) {
+                    return Ok(Transition::Quit);
+                }
+                let first_press = kind == event::KeyEventKind::Press;
+                // And F2, F3 and F4 always show or hide the event feed, the
+                // debug overlay and the hitboxes
+                if let KeyCode::F(number @ 2..=4) = code {
+                    if first_press {
+                        match number {
+                            2 => self.frontend.toggle_feed(),
+                            3 => self.frontend.toggle_debug(),
+                            _ => self.frontend.toggle_debug_draw(),
+                        }
+                    }
+                    return Ok(Transition::Stay);
+                }
+                if self.frontend.chat_input.is_some() {
+                    return Ok(self.chat_key(code, modifiers));
+                }
+                if first_press
+                    && matches!(code, KeyCode::Char('t') | KeyCode::Char('T'))
+                    && self.keys.action(code).is_none()
+                    && self.chat_player().is_some()
+                {
+                    // This paddle stops while typing, but the match goes on
+                    self.controls.release_all();
+                    self.frontend.chat_input = Some(String::new());
+                    return Ok(Transition::Stay);
+                }
+                if self.frontend.quit_prompt {
+                    return self.quit_prompt_key(code, first_press);
+                }
+                if self.frontend.settings.is_some() {
+                    return self.settings_key(code);
+                }
+                if saves(code, modifiers) && self.can_save() {
+                    if first_press {
+                        // The notice says how it went
+                        let _ = self.save_match()?;
+                        return Ok(Transition::To(GameState::of(&self.game)));
+                    }
+                    return Ok(Transition::Stay);
+                }
+                match self.state {
+                    GameState::Serving | GameState::Playing | GameState::Paused => {
+                        self.match_key(code, first_press)
+                    }
+                    GameState::SetOver => self.set_over_key(code),
+                    GameState::GameOver => self.game_over_key(code),
+                }
+            }
+            Event::Key(KeyEvent {
+                code,
+                kind: event::KeyEventKind::Release,
+                ..
+            }) => {
+                if let Some(action) = self.keys.action(code) {
+                    self.controls.release(action, code);
+                }
+                Ok(Transition::Stay)
+            }
+            Event::Resize(width, height) => {
+                log::info!("terminal resized to {}x{}", width, height);
+                let height = height.saturating_sub(SPARE_ROWS);
+                self.record(Record::Resize { width, height })?;
+                // A network game's field was agreed on up front
+                let fixed_size = !matches!(self.link, Link::Local { .. });
+                if self.frontend.resize(width, height) && !fixed_size {
+                    self.game.resize(width, self.frontend.field_height(height));
+                }
+                Ok(Transition::Stay)
+            }
+            _ => Ok(Transition::Stay),
+        }
+    }
+
+    /// Which player here a key belongs to. Hosting, either set of keys
+    /// plays this side's paddle; joining, nobody here is watched.
+    fn player_of(&self, action: Action) -> Option<u8> {
+        let player = PLAYER_ACTIONS.iter().find(|&&(a, _)| a == action).map(|&(_, p)| p);
+        match self.link {
+            Link::Local { .. } => player,
+            Link::Host { .. } => player.map(|_| 1),
+            _ => None,
+        }
+    }
+
+    /// Whether a player is watched for going quiet: a person, rather than
+    /// the computer or a bot, with a paddle in this match
+    fn watches(&self, player: u8) -> bool {
+        let side = self.game.side_of(player);
+        match player {
+            1 | 2 if matches!(self.link, Link::Local { .. } | Link::Host { .. }) => match side {
+                1 => self.game.left_ai.is_none(),
+                _ => self.game.ai.is_none() && !(player == 2 && self.bot.is_some()),
+            },
+            3 | 4 => matches!(self.link, Link::Local { .. }) && self.game.four_player(),
+            _ => false,
+        }
+    }
+
+    /// Count how long each player has gone without a key while the match is
+    /// being played, and pause it to ask after one who's let a ball come at
+    /// them for `afk_timeout` seconds
+    fn watch_idle(&mut self, dt: f32) -> io::Result<()> {
+        if !self.game.paused {
+            self.afk = None;
+        }
+        let timeout = Duration::from_secs(self.config.game.afk_timeout as u64);
+        if timeout.is_zero() || self.state != GameState::Playing || self.frontend.holds_game() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        // A key held down all along counts, not just presses
+        for (action, _) in PLAYER_ACTIONS {
+            let player = self.player_of(action).filter(|_| self.controls.is_held(action, now));
+            if let Some(player) = player {
+                self.idle[player as usize - 1] = Duration::ZERO;
+            }
+        }
+        for player in 1..=4 {
+            if !self.watches(player) {
+                continue;
+            }
+            let index = player as usize - 1;
+            self.idle[index] += Duration::from_secs_f32(dt);
+            // Only the two side players swap ends
+            let side = if player <= 2 { self.game.side_of(player) } else { player };
+            if self.idle[index] < timeout || !self.ball_coming_at(side) {
+                continue;
+            }
+            self.idle[index] = Duration::ZERO;
+            if matches!(self.link, Link::Local { .. }) {
+                self.record(Record::Pause)?;
+            }
+            self.game.toggle_pause();
+            log::info!("paused for player {}, who hasn't pressed a key in {:?}", player, timeout);
+            self.afk = Some(player);
+            let name = self.game.name(player);
+            self.frontend.notice = Some(format!("Is {} still there? Press any key", name));
+            self.state = GameState::of(&self.game);
+            break;
+        }
+        Ok(())
+    }
+
+    /// Whether any ball is heading for a side's goal
+    fn ball_coming_at(&self, side: u8) -> bool {
+        self.game.balls.iter().any(|ball| match side {
+            1 => ball.vel.x < 0.0,
+            2 => ball.vel.x > 0.0,
+            3 => ball.vel.y < 0.0,
+            _ => ball.vel.y > 0.0,
+        })
+    }
+
+    /// A player is back: start the clock on them again, and carry on if
+    /// the match was paused waiting for them
+    fn back(&mut self, player: u8) -> io::Result<Option<Transition>> {
+        self.idle[player as usize - 1] = Duration::ZERO;
+        if self.afk != Some(player) {
+            return Ok(None);
+        }
+        self.afk = None;
+        if self.game.paused {
+            if matches!(self.link, Link::Local { .. }) {
+                self.record(Record::Pause)?;
+            }
+            self.game.toggle_pause();
+        }
+        Ok(Some(Transition::To(GameState::of(&self.game))))
+    }
+
+    /// Keys while the match is on, paused or not
+    fn match_key(&mut self, code: KeyCode, first_press: bool) -> io::Result<Transition> {
+        let player = self.keys.action(code).and_then(|action| self.player_of(action));
+        if let Some(player) = player.filter(|_| first_press) {
+            if let Some(transition) = self.back(player)? {
+                return Ok(transition);
+            }
+        }
+        match self.keys.action(code) {
+            Some(Action::Pause) if first_press => match &mut self.link {
+                // The host pauses for both; the next snapshot says so
+                Link::Client { conn, .. } => conn.send(&Message::Pause),
+                Link::Rollback { session, .. } => session.pause(),
+                _ => {
+                    self.record(Record::Pause)?;
+                    self.game.toggle_pause();
+                    return Ok(Transition::To(GameState::of(&self.game)));
+                }
+            },
+            Some(Action::Help) if first_press => {
+                self.record(Record::Help)?;
+                self.frontend.toggle_help();
+            }
+            // Only on this machine, where nobody else's game has to match
+            Some(Action::Settings)
+                if first_press && self.game.paused && matches!(self.link, Link::Local { .. }) =>
+            {
+                self.open_settings();
+            }
+            Some(Action::Settings) => {}
+            Some(Action::P1Serve) if first_press => self.serve[0] = true,
+            Some(Action::P2Serve) if first_press => self.serve[1] = true,
+            Some(Action::P1Dash) if first_press => {
+                self.dash[0] = self.controls.heading(Action::P1Up, Action::P1Down, Instant::now());
+            }
+            Some(Action::P2Dash) if first_press => {
+                self.dash[1] = self.controls.heading(Action::P2Up, Action::P2Down, Instant::now());
+            }
+            Some(Action::P1Dash | Action::P2Dash) => {}
+            Some(Action::Quit) if first_press => self.toggle_quit_prompt()?,
+            Some(Action::Quit) => {}
+            // A double tap on a movement key dashes that way
+            Some(action) => match (action, self.controls.press(action, code, Instant::now())) {
+                (Action::P1Up, true) => self.dash[0] = Dir::Up,
+                (Action::P1Down, true) => self.dash[0] = Dir::Down,
+                (Action::P2Up, true) => self.dash[1] = Dir::Up,
+                (Action::P2Down, true) => self.dash[1] = Dir::Down,
+                _ => {}
+            },
+            None => {}
+        }
+        Ok(Transition::Stay)
+    }
+
+    /// Keys between sets: Enter for the next one or quit
+    fn set_over_key(&mut self, code: KeyCode) -> io::Result<Transition> {
+        match code {
+            KeyCode::Enter => {
+                self.controls.release_all();
+                match &mut self.link {
+                    Link::Client { spectating: false, conn, .. } => conn.send(&Message::NextSet),
+                    Link::Client { .. } => {}
+                    Link::Rollback { session, .. } => session.next_set(),
+                    _ => {
+                        self.record(Record::NextSet)?;
+                        self.game.next_set();
+                        return Ok(Transition::To(GameState::of(&self.game)));
+                    }
+                }
+            }
+            _ if self.keys.action(code) == Some(Action::Quit) => self.toggle_quit_prompt()?,
+            _ => {}
+        }
+        Ok(Transition::Stay)
+    }
+
+    /// Keys once the match is decided: R for a rematch or quit
+    fn game_over_key(&mut self, code: KeyCode) -> io::Result<Transition> {
+        match code {
+            KeyCode::Char('r') | KeyCode::Char('R') => {
+                self.controls.release_all();
+                match &mut self.link {
+                    Link::Client { conn, .. } => conn.send(&Message::Rematch),
+                    Link::Rollback { session, .. } => session.rematch(),
+                    _ => {
+                        self.record(Record::Rematch)?;
+                        self.game.rematch();
+                        return Ok(Transition::To(GameState::of(&self.game)));
+                    }
+                }
+            }
+            _ if self.keys.action(code) == Some(Action::Quit) => return Ok(Transition::Quit),
+            _ => {}
+        }
+        Ok(Transition::Stay)
+    }
+
+    /// Which player this side says things as, if it can chat at all
+    fn chat_player(&self) -> Option<u8> {
+        if self.frontend.connection_lost {
+            return None;
+        }
+        match &self.link {
+            Link::Host { .. } => Some(1),
+            Link::Client { spectating: false, .. } => Some(2),
+            Link::Rollback { session, .. } => Some(session.player()),
+            _ => None,
+        }
+    }
+
+    /// The player at the other end of the link, or `None` for a spectator,
+    /// who hears both players from the host
+    fn peer_player(&self) -> Option<u8> {
+        match &self.link {
+            Link::Host { .. } => Some(2),
+            Link::Client { spectating: false, .. } => Some(1),
+            Link::Rollback { session, .. } => Some(3 - session.player()),
+            _ => None,
+        }
+    }
+
+    /// Keys while the chat line is open: Enter says it, Esc doesn't
+    fn chat_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Transition {
+        let Some(typed) = &mut self.frontend.chat_input else {
+            return Transition::Stay;
+        };
+        match code {
+            KeyCode::Char(c)
+                if !modifiers.contains(KeyModifiers::CONTROL)
+                    && typed.chars().count() < net::MAX_CHAT_LENGTH =>
+            {
+                typed.push(c)
+            }
+            KeyCode::Backspace => {
+                typed.pop();
+            }
+            KeyCode::Esc => self.frontend.chat_input = None,
+            KeyCode::Enter => {
+                let text = net::clean_chat(typed.trim());
+                self.frontend.chat_input = None;
+                if let (false, Some(player)) = (text.is_empty(), self.chat_player()) {
+                    self.say(player, text);
+                }
+            }
+            _ => {}
+        }
+        Transition::Stay
+    }
+
+    /// Send a chat message to the other player and anyone watching
+    fn say(&mut self, player: u8, text: String) {
+        self.frontend.add_chat(format!("{}: {}", self.game.name(player), text));
+        let message = Message::Chat { player, text };
+        match &mut self.link {
+            Link::Host { conn, server, .. } | Link::Rollback { conn, server, .. } => {
+                conn.send(&message);
+                if let Some(server) = server {
+                    server.broadcast(&message);
+                }
+            }
+            Link::Client { conn, .. } => conn.send(&message),
+            Link::Local { .. } => {}
+        }
+    }
+
+    /// Keys while asking whether to quit: Y quits, and N or the quit key
+    /// again carries on
+    fn quit_prompt_key(&mut self, code: KeyCode, first_press: bool) -> io::Result<Transition> {
+        match code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => {
+                // Quitting part way through keeps the match to carry on with
+                if self.can_save() {
+                    self.saved = Some(self.save_match()?);
+                }
+                return Ok(Transition::Quit);
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') => self.toggle_quit_prompt()?,
+            _ if first_press && self.keys.action(code) == Some(Action::Quit) => {
+                self.toggle_quit_prompt()?
+            }
+            _ => {}
+        }
+        Ok(Transition::Stay)
+    }
+
+    /// Only a match on this machine can be saved, since a network game
+    /// needs both ends, and only while it's still being played
+    fn can_save(&self) -> bool {
+        matches!(self.link, Link::Local { .. }) && self.game.winner.is_none()
+    }
+
+    /// Pause the match and save it, so it's paused again when it's resumed
+    fn save_match(&mut self) -> io::Result<Result<PathBuf, String>> {
+        if !self.game.paused {
+            self.record(Record::Pause)?;
+            self.game.toggle_pause();
+        }
+        let saved = save::save(&self.game);
+        self.frontend.notice = Some(match &saved {
+            Ok(_) => "Match saved, --resume carries on with it".to_string(),
+            Err(message) => format!("Couldn't save the match: {}", message),
+        });
+        Ok(saved)
+    }
+
+    /// Ask whether to quit, or stop asking. The match holds while it asks,
+    /// and nobody's paddle keeps moving from before.
+    fn toggle_quit_prompt(&mut self) -> io::Result<()> {
+        self.record(Record::QuitPrompt)?;
+        self.controls.release_all();
+        self.frontend.quit_prompt = !self.frontend.quit_prompt;
+        Ok(())
+    }
+
+    /// Put the settings up over the paused match, starting from what it's
+    /// being played with
+    fn open_settings(&mut self) {
+        self.controls.release_all();
+        let mut settings = SettingsMenu::new(self.config.clone());
+        let tuning = self.game.tuning();
+        let config = &mut settings.config;
+        config.game.ball_speed = tuning.ball_speed;
+        config.game.paddle_speed = tuning.paddle_speed;
+        config.game.paddle_height = tuning.paddle_height;
+        config.game.max_ball_speed = tuning.max_ball_speed;
+        config.game.score_limit = self.game.score_limit;
+        config.powerups.enabled = !self.game.powerup_settings().enabled.is_empty();
+        config.display.theme = self.frontend.theme.name.to_string();
+        config.display.bell = self.frontend.bell;
+        self.frontend.settings = Some(settings);
+    }
+
+    /// Keys while the settings are up. Every change applies straight away.
+    fn settings_key(&mut self, code: KeyCode) -> io::Result<Transition> {
+        let action = self.keys.action(code);
+        let Some(settings) = &mut self.frontend.settings else {
+            return Ok(Transition::Stay);
+        };
+        match settings.handle_key(code, action) {
+            Outcome::Changed(setting) => self.apply_setting(setting)?,
+            Outcome::Closed => {
+                if let Some(settings) = self.frontend.settings.take() {
+                    self.config = settings.config;
+                }
+            }
+            Outcome::Nothing => {}
+        }
+        Ok(Transition::Stay)
+    }
+
+    fn apply_setting(&mut self, setting: Setting) -> io::Result<()> {
+        let Some(settings) = &self.frontend.settings else {
+            return Ok(());
+        };
+        match setting {
+            Setting::Theme => self.frontend.theme = settings.theme(),
+            Setting::Sound => self.frontend.bell = settings.config.display.bell,
+            _ => {
+                let tuning = settings.tuning();
+                let score_limit = settings.config.game.score_limit;
+                if setting == Setting::Powerups {
+                    // Already checked when the config was loaded
+                    let enabled = settings
+                        .config
+                        .powerups
+                        .settings()
+                        .map(|powerups| powerups.enabled)
+                        .unwrap_or_default();
+                    self.game.set_powerup_settings(PowerUpSettings {
+                        enabled,
+                        ..self.game.powerup_settings().clone()
+                    });
+                }
+                self.game.set_tuning(tuning);
+                self.game.score_limit = score_limit;
+                self.record(Record::Settings {
+                    tuning,
+                    score_limit,
+                    powerups: self.game.powerup_settings().enabled.clone(),
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Advance one frame and return the state the match ends up in
+    fn update(&mut self, dt: f32) -> io::Result<GameState> {
+        let holds = self.frontend.holds_game();
+        let mut runs = self.state.runs_simulation() && !holds;
+
+        let now = Instant::now();
+        let controls = &self.controls;
+        let pressed = |action| controls.is_held(action, now);
+        let [p1_serve, p2_serve] = std::mem::take(&mut self.serve);
+        let [p1_dash, mut p2_dash] = std::mem::take(&mut self.dash);
+        let p1 = Dir::from_keys(pressed(Action::P1Up), pressed(Action::P1Down));
+        let mut p2 = Dir::from_keys(pressed(Action::P2Up), pressed(Action::P2Down));
+        if let Some(bot) = &mut self.bot {
+            // The bot only hears about ticks the match actually moves on
+            p2 = if runs { bot.tick(&self.game) } else { Dir::Stay };
+            p2_dash = Dir::Stay;
+            self.frontend.bot_stats = Some(bot.describe());
+        }
+        let p3 = Dir::from_keys(pressed(Action::P3Left), pressed(Action::P3Right));
+        let p4 = Dir::from_keys(pressed(Action::P4Left), pressed(Action::P4Right));
+        let p1_charge = pressed(Action::P1Charge);
+        let p2_charge = pressed(Action::P2Charge) && self.bot.is_none();
+        // Input goes by side, and the players swap sides between sets
+        let input = if self.game.swapped {
+            Input {
+                p1: p2,
+                p2: p1,
+                p1_serve: p2_serve,
+                p2_serve: p1_serve,
+                p3,
+                p4,
+                p1_dash: p2_dash,
+                p2_dash: p1_dash,
+                p1_charge: p2_charge,
+                p2_charge: p1_charge,
+            }
+        } else {
+            Input {
+                p1,
+                p2,
+                p1_serve,
+                p2_serve,
+                p3,
+                p4,
+                p1_dash,
+                p2_dash,
+                p1_charge,
+                p2_charge,
+            }
+        };
+        if self.recent_inputs.len() == RECENT_FRAMES {
+            self.recent_inputs.pop_front();
+        }
+        self.recent_inputs.push_back(FrameInput { dt, input });
+        // Over the network each side has one paddle, which either set of
+        // movement keys controls
+        let dir = Dir::from_keys(
+            pressed(Action::P1Up) || pressed(Action::P2Up),
+            pressed(Action::P1Down) || pressed(Action::P2Down),
+        );
+        let serve = p1_serve || p2_serve;
+        let dash = match p1_dash {
+            Dir::Stay => p2_dash,
+            dash => dash,
+        };
+        let charge = p1_charge || p2_charge;
+        let peer = self.peer_player();
+
+        let (conn, mut server) = match &mut self.link {
+            Link::Local { recorder } => {
+                if let Some(recorder) = recorder {
+                    recorder.record(&Record::Frame { input, dt })?;
+                }
+                if runs {
+                    self.game.step(input, dt);
+                    #[cfg(feature = "scripting")]
+                    if let Some(scripts) = &mut self.scripts {
+                        scripts.run(&mut self.game, dt);
+                    }
+                }
+                return Ok(GameState::of(&self.game));
+            }
+            Link::Host {
+                conn,
+                server,
+                tick,
+                acked_input,
+                their_charge,
+            } => {
+                // Each input message is one of the joining player's frames
+                // of movement, the same amount they already moved locally
+                while let Some(message) = conn.receive() {
+                    match message {
+                        Message::Input {
+                            seq,
+                            dir,
+                            dt: their_dt,
+                            serve,
+                            dash,
+                            charge,
+                        } => {
+                            *acked_input = seq;
+                            *their_charge = charge;
+                            if dir != Dir::Stay || serve || dash != Dir::Stay || charge {
+                                self.idle[1] = Duration::ZERO;
+                                if self.afk == Some(2) && self.game.paused {
+                                    self.afk = None;
+                                    self.game.toggle_pause();
+                                    runs = GameState::of(&self.game).runs_simulation() && !holds;
+                                }
+                            }
+                            if runs {
+                                let side = self.game.side_of(2);
+                                self.game.set_charging(side, charge);
+                                self.game.move_paddle(side, dir, their_dt);
+                                self.game.dash_paddle(side, dash);
+                                if serve {
+                                    self.game.release_balls(side);
+                                }
+                            }
+                        }
+                        Message::Pause => {
+                            self.game.toggle_pause();
+                            runs = GameState::of(&self.game).runs_simulation() && !holds;
+                        }
+                        Message::Rematch if GameState::of(&self.game) == GameState::GameOver => {
+                            self.game.rematch();
+                            runs = GameState::of(&self.game).runs_simulation() && !holds;
+                        }
+                        Message::NextSet if GameState::of(&self.game) == GameState::SetOver => {
+                            self.game.next_set();
+                            runs = GameState::of(&self.game).runs_simulation() && !holds;
+                        }
+                        _ => {}
+                    }
+                }
+
+                if runs {
+                    let input = if self.game.side_of(1) == 1 {
+                        Input {
+                            p1: dir,
+                            p1_serve: serve,
+                            p1_dash: dash,
+                            p1_charge: charge,
+                            p2_charge: *their_charge,
+                            ..Input::default()
+                        }
+                    } else {
+                        Input {
+                            p2: dir,
+                            p2_serve: serve,
+                            p2_dash: dash,
+                            p2_charge: charge,
+                            p1_charge: *their_charge,
+                            ..Input::default()
+                        }
+                    };
+                    self.game.step(input, dt);
+                }
+                *tick = tick.wrapping_add(1);
+                let snapshot = Snapshot::capture(&self.game, *tick, *acked_input);
+                let snapshot = Message::Snapshot(Box::new(snapshot));
+                conn.send(&snapshot);
+                if let Some(server) = server {
+                    server.broadcast(&snapshot);
+                    server.poll(&self.game, *tick, *acked_input);
+                    if self.net_debug {
+                        let watching = server.spectator_count();
+                        self.frontend.net_stats = Some(format!(" {} watching ", watching));
+                    }
+                }
+                (conn, server.as_mut())
+            }
+            Link::Client {
+                conn,
+                client,
+                spectating,
+            } => {
+                if !*spectating {
+                    // Nothing moves while the help or the too-small notice is up
+                    let (dir, dash) = if holds { (Dir::Stay, Dir::Stay) } else { (dir, dash) };
+                    let keys = TickInput {
+                        dir,
+                        serve: serve && !holds,
+                        dash,
+                        charge: charge && !holds,
+                        ..TickInput::default()
+                    };
+                    client.send_input(conn, &mut self.game, keys, dt);
+                }
+                client.receive(conn, &mut self.game);
+                self.game.tick_effects(dt);
+                if self.net_debug {
+                    self.frontend.net_stats = Some(client.debug_line());
+                }
+                (conn, None)
+            }
+            Link::Rollback {
+                conn,
+                session,
+                server,
+            } => {
+                // The match goes on for the other player, so only this
+                // paddle waits
+                let keys = match holds {
+                    true => TickInput::default(),
+                    false => TickInput {
+                        dir,
+                        serve,
+                        dash,
+                        charge,
+                        ..TickInput::default()
+                    },
+                };
+                let result = session.update(conn, &mut self.game, keys, dt);
+                if let Err(desync) = result {
+                    self.desync = Some((desync.tick, write_desync(&desync)));
+                }
+                if let Some(server) = server {
+                    let snapshot = Snapshot::capture(&self.game, session.tick(), 0);
+                    server.broadcast(&Message::Snapshot(Box::new(snapshot)));
+                    server.poll(&self.game, session.tick(), 0);
+                }
+                if self.net_debug {
+                    self.frontend.net_stats = Some(session.debug_line());
+                }
+                (conn, server.as_mut())
+            }
+        };
+
+        self.frontend.link_quality = Some(conn.quality());
+        if conn.take_unstable() {
+            log::warn!("connection unstable");
+            self.game.add_event("Connection unstable".to_string());
+        }
+        // The other player's chat, which anyone watching hears too. Whoever
+        // is at the other end can only speak for themselves.
+        for (player, text) in conn.take_chat() {
+            let player = peer.unwrap_or(player);
+            self.frontend.add_chat(format!("{}: {}", self.game.name(player), text));
+            if let Some(server) = &mut server {
+                server.broadcast(&Message::Chat { player, text });
+            }
+        }
+        if conn.is_closed() && !self.frontend.connection_lost {
+            log::warn!("connection lost");
+            self.frontend.connection_lost = true;
+            self.game.paused = false;
+        }
+        Ok(GameState::of(&self.game))
+    }
+}
+
+/// Write down this side's game at the tick a rollback match went out of
+/// step, to compare with the other side's
+fn write_desync(desync: &Desync) -> Result<PathBuf, String> {
+    let dir = dirs::data_dir().ok_or("no data directory")?.join("dospong");
+    fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+    let path = dir.join(format!("desync-{}-player{}.json", desync.tick, desync.player));
+    let text = serde_json::to_string_pretty(desync).map_err(|err| err.to_string())?;
+    fs::write(&path, text).map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(path)
+}
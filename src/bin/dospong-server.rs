@@ -0,0 +1,186 @@
+//! The relay matches over the internet meet through. One player opens a
+//! room and gets a code, the other joins with it, and from then on every
+//! frame either sends goes to the other as it is. Nothing is simulated
+//! here, so it doesn't matter what the match is or which version of the
+//! game plays it beyond the relay protocol. Each connection is its own
+//! task, so any number of rooms share a few threads.
+
+use dospong::relay::{
+    self, Reply, Request, DEFAULT_PORT, MAX_PACKET_SIZE, RELAY_VERSION, ROOM_IDLE_TIMEOUT,
+    ROOM_WAIT_TIMEOUT,
+};
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::time::timeout;
+
+// How long a new connection has to say what it wants
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Rooms waiting for a second player, beyond which new ones are turned away
+const MAX_ROOMS: usize = 10_000;
+
+/// The rooms waiting for a second player, by code, each with the way to
+/// hand that player to the task looking after the room
+type Rooms = Arc<Mutex<HashMap<String, oneshot::Sender<TcpStream>>>>;
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let port = match std::env::args().nth(1) {
+        Some(arg) => match arg.parse() {
+            Ok(port) => port,
+            Err(_) => {
+                eprintln!("Usage: dospong-server [PORT]  (the default port is {})", DEFAULT_PORT);
+                std::process::exit(2);
+            }
+        },
+        None => DEFAULT_PORT,
+    };
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    eprintln!("dospong-server: relaying on port {}", port);
+
+    let rooms = Rooms::default();
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            // Out of file descriptors, say, which passes once some close
+            Err(err) => {
+                eprintln!("dospong-server: couldn't accept a connection: {}", err);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let rooms = rooms.clone();
+        tokio::spawn(async move {
+            let _ = stream.set_nodelay(true);
+            // However a connection ends, it only ends its own room
+            let _ = handle(stream, rooms).await;
+        });
+    }
+}
+
+/// See what a new connection is after, and open or join a room for it
+async fn handle(mut stream: TcpStream, rooms: Rooms) -> io::Result<()> {
+    let frame = timeout(REQUEST_TIMEOUT, read_frame(&mut stream)).await??;
+    match Request::decode(&frame) {
+        Some(Request::Create { version } | Request::Join { version, .. })
+            if version != RELAY_VERSION =>
+        {
+            let reason = format!(
+                "the relay speaks version {}, but this game speaks {}",
+                RELAY_VERSION, version
+            );
+            write_reply(&mut stream, &Reply::Refused(reason)).await
+        }
+        Some(Request::Create { .. }) => open_room(stream, rooms).await,
+        Some(Request::Join { code, .. }) => join_room(stream, rooms, code).await,
+        None => Ok(()),
+    }
+}
+
+async fn open_room(mut host: TcpStream, rooms: Rooms) -> io::Result<()> {
+    let (sender, receiver) = oneshot::channel();
+    let code = {
+        let mut rooms = rooms.lock().expect("no task panics holding the rooms");
+        // Rooms whose host has gone are still in here until now
+        rooms.retain(|_, sender| !sender.is_closed());
+        if rooms.len() < MAX_ROOMS {
+            let mut rng = rand::thread_rng();
+            let code = std::iter::repeat_with(|| relay::random_code(&mut rng))
+                .find(|code| !rooms.contains_key(code))
+                .expect("the codes never run out");
+            rooms.insert(code.clone(), sender);
+            Some(code)
+        } else {
+            None
+        }
+    };
+    let Some(code) = code else {
+        let reason = "the relay is full, try again later".to_string();
+        return write_reply(&mut host, &Reply::Refused(reason)).await;
+    };
+    write_reply(&mut host, &Reply::Created { code: code.clone() }).await?;
+
+    // The host has nothing more to say until paired, so anything read
+    // from it means it hung up
+    let mut unexpected = [0u8; 1];
+    let guest = tokio::select! {
+        guest = receiver => guest.ok(),
+        _ = tokio::time::sleep(ROOM_WAIT_TIMEOUT) => None,
+        _ = host.read(&mut unexpected) => None,
+    };
+    {
+        // Unless a guest took the room, it's still there, and closed now
+        let mut rooms = rooms.lock().expect("no task panics holding the rooms");
+        if rooms.get(&code).is_some_and(|sender| sender.is_closed()) {
+            rooms.remove(&code);
+        }
+    }
+    let Some(mut guest) = guest else {
+        return Ok(());
+    };
+    write_reply(&mut host, &Reply::Paired).await?;
+    write_reply(&mut guest, &Reply::Paired).await?;
+    relay_frames(host, guest).await
+}
+
+async fn join_room(mut guest: TcpStream, rooms: Rooms, code: String) -> io::Result<()> {
+    let code = relay::normalize_code(&code).unwrap_or(code);
+    let sender = rooms.lock().expect("no task panics holding the rooms").remove(&code);
+    let reason = match sender {
+        Some(sender) => match sender.send(guest) {
+            Ok(()) => return Ok(()),
+            Err(stream) => {
+                guest = stream;
+                "whoever opened that room has left".to_string()
+            }
+        },
+        None => format!("there's no room {}", code),
+    };
+    write_reply(&mut guest, &Reply::Refused(reason)).await
+}
+
+/// Pass frames between the two players until either hangs up, sends a
+/// frame too big to be the game's, or goes quiet for too long
+async fn relay_frames(host: TcpStream, guest: TcpStream) -> io::Result<()> {
+    let (mut host_in, mut host_out) = host.into_split();
+    let (mut guest_in, mut guest_out) = guest.into_split();
+    tokio::select! {
+        result = pass_on(&mut host_in, &mut guest_out) => result,
+        result = pass_on(&mut guest_in, &mut host_out) => result,
+    }
+}
+
+async fn pass_on(
+    from: &mut (impl AsyncRead + Unpin),
+    to: &mut (impl AsyncWrite + Unpin),
+) -> io::Result<()> {
+    loop {
+        let frame = timeout(ROOM_IDLE_TIMEOUT, read_frame(from)).await??;
+        write_frame(to, &frame).await?;
+    }
+}
+
+async fn read_frame(stream: &mut (impl AsyncRead + Unpin)) -> io::Result<Vec<u8>> {
+    let len = stream.read_u16_le().await? as usize;
+    if len > MAX_PACKET_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too big"));
+    }
+    let mut frame = vec![0u8; len];
+    stream.read_exact(&mut frame).await?;
+    Ok(frame)
+}
+
+async fn write_frame(stream: &mut (impl AsyncWrite + Unpin), frame: &[u8]) -> io::Result<()> {
+    let mut out = Vec::with_capacity(2 + frame.len());
+    out.extend_from_slice(&(frame.len() as u16).to_le_bytes());
+    out.extend_from_slice(frame);
+    stream.write_all(&out).await
+}
+
+async fn write_reply(stream: &mut TcpStream, reply: &Reply) -> io::Result<()> {
+    write_frame(stream, &reply.encode()).await
+}
@@ -0,0 +1,241 @@
+//! Player 2 played by another program. It's sent the field as one line of
+//! JSON every tick on its standard input and answers each with `up`, `down`
+//! or `stay` on its standard output. The game never waits for it: a bot too
+//! slow to answer keeps doing what it last said, and one that's gone stops
+//! moving.
+
+use dospong::pong::{Dir, Game, PowerUpType};
+use serde::Serialize;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+
+/// The player the bot plays
+const BOT_PLAYER: u8 = 2;
+
+#[derive(Serialize)]
+struct BotBall {
+    x: f32,
+    y: f32,
+    /// In cells per second
+    vx: f32,
+    vy: f32,
+}
+
+#[derive(Serialize)]
+struct BotPaddle {
+    player: u8,
+    x: u16,
+    /// The top row
+    y: i16,
+    height: u16,
+}
+
+#[derive(Serialize)]
+struct BotPowerUp {
+    powerup: PowerUpType,
+    player: u8,
+    /// Seconds before it wears off
+    remaining: f32,
+}
+
+/// Everything a bot is told each tick
+#[derive(Serialize)]
+struct BotState {
+    tick: u64,
+    width: u16,
+    height: u16,
+    /// Which player the bot is
+    you: u8,
+    balls: Vec<BotBall>,
+    paddles: Vec<BotPaddle>,
+    powerups: Vec<BotPowerUp>,
+}
+
+impl BotState {
+    fn of(game: &Game, tick: u64) -> Self {
+        let scale = game.ball_speed_scale() * 60.0;
+        let balls = game
+            .balls
+            .iter()
+            .map(|ball| BotBall {
+                x: ball.pos.x,
+                y: ball.pos.y,
+                vx: ball.vel.x * scale,
+                vy: ball.vel.y * scale,
+            })
+            .collect();
+        let sides = [
+            (1, 2, game.p1_y, game.p1_height),
+            (2, game.width.saturating_sub(3), game.p2_y, game.p2_height),
+        ];
+        let paddles = sides
+            .into_iter()
+            .map(|(side, x, y, height)| BotPaddle {
+                player: game.player_at(side),
+                x,
+                y,
+                height,
+            })
+            .collect();
+        let powerups = game
+            .active_powerups
+            .iter()
+            .map(|active| BotPowerUp {
+                powerup: active.ptype,
+                player: game.player_at(active.player),
+                remaining: active.remaining.as_secs_f32(),
+            })
+            .collect();
+        BotState {
+            tick,
+            width: game.width,
+            height: game.height,
+            you: BOT_PLAYER,
+            balls,
+            paddles,
+            powerups,
+        }
+    }
+}
+
+/// What the bot's answers come to
+enum Reply {
+    Action(Dir),
+    /// It closed its output or exited
+    Gone,
+}
+
+pub struct ExternalBot {
+    child: Child,
+    // Space for one state the bot hasn't taken yet. It's only ever behind
+    // by one, so a bot that stops reading can't fill memory.
+    states: Option<SyncSender<String>>,
+    replies: Receiver<Reply>,
+    action: Dir,
+    tick: u64,
+    // Ticks the bot didn't answer in time for
+    missed: u64,
+    // Set once the bot has exited, after which its paddle stays put
+    gone: bool,
+}
+
+impl ExternalBot {
+    /// Run `command`, a program and any arguments split at spaces. There's
+    /// no shell in between, so stopping it stops the bot itself.
+    pub fn spawn(command: &str) -> io::Result<Self> {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no command given"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            // Anything it prints there would land on the game's screen
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin was piped");
+        let stdout = child.stdout.take().expect("stdout was piped");
+
+        let (states, to_send) = mpsc::sync_channel(1);
+        thread::spawn(move || send_states(stdin, to_send));
+        let (sender, replies) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let dir = match line.trim() {
+                    "up" => Dir::Up,
+                    "down" => Dir::Down,
+                    "stay" => Dir::Stay,
+                    // Anything else is ignored rather than guessed at
+                    _ => continue,
+                };
+                if sender.send(Reply::Action(dir)).is_err() {
+                    return;
+                }
+            }
+            let _ = sender.send(Reply::Gone);
+        });
+
+        Ok(ExternalBot {
+            child,
+            states: Some(states),
+            replies,
+            action: Dir::Stay,
+            tick: 0,
+            missed: 0,
+            gone: false,
+        })
+    }
+
+    /// Tell the bot how `game` stands and take its latest answer, which goes
+    /// on being used until a newer one comes in
+    pub fn tick(&mut self, game: &Game) -> Dir {
+        if self.gone {
+            return Dir::Stay;
+        }
+        let mut answered = false;
+        while let Ok(reply) = self.replies.try_recv() {
+            match reply {
+                Reply::Action(dir) => {
+                    self.action = dir;
+                    answered = true;
+                }
+                Reply::Gone => {
+                    self.stop();
+                    return Dir::Stay;
+                }
+            }
+        }
+        // The first tick has nothing to answer yet
+        if !answered && self.tick > 0 {
+            self.missed += 1;
+        }
+        self.tick += 1;
+        let state = BotState::of(game, self.tick);
+        if let (Some(states), Ok(line)) = (&self.states, serde_json::to_string(&state)) {
+            // A bot still on the last state skips this one
+            if let Err(TrySendError::Disconnected(_)) = states.try_send(line) {
+                self.stop();
+                return Dir::Stay;
+            }
+        }
+        self.action
+    }
+
+    /// A line for the debug overlay
+    pub fn describe(&self) -> String {
+        if self.gone {
+            "bot gone, paddle frozen".to_string()
+        } else {
+            format!("bot missed {} of {} ticks", self.missed, self.tick)
+        }
+    }
+
+    fn stop(&mut self) {
+        self.gone = true;
+        self.states = None;
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Drop for ExternalBot {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Write each state to the bot as a line, until it stops reading or the
+/// game is done with it
+fn send_states(mut stdin: ChildStdin, states: Receiver<String>) {
+    for line in states {
+        if writeln!(stdin, "{}", line).and_then(|_| stdin.flush()).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Recording what the game draws as an asciinema cast, in the v2 format:
+//! a JSON header line, then a line for everything written to the terminal
+//! between two flushes, stamped with the seconds since recording started.
+//! The renderer only sends what changed each frame, so a long match stays
+//! a manageable size.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// The cursor is kept hidden while the game draws, which a player only knows
+// if the cast says so, and shows again at the end
+const HIDE_CURSOR: &[u8] = b"\x1b[?25l";
+const SHOW_CURSOR: &[u8] = b"\x1b[?25h";
+
+pub struct CastWriter {
+    file: BufWriter<File>,
+    started: Instant,
+    // What's been written since the last flush, which goes out as one event
+    pending: Vec<u8>,
+}
+
+impl CastWriter {
+    /// Start a cast of a terminal `width` by `height`, the whole screen
+    pub fn create(path: &str, width: u16, height: u16) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        writeln!(
+            file,
+            "{{\"version\": 2, \"width\": {}, \"height\": {}, \"timestamp\": {}, \
+             \"title\": \"DOSPong\"}}",
+            width, height, timestamp
+        )?;
+        let mut cast = CastWriter {
+            file,
+            started: Instant::now(),
+            pending: HIDE_CURSOR.to_vec(),
+        };
+        cast.flush()?;
+        Ok(cast)
+    }
+
+    /// Keep `bytes` for the next event
+    pub fn output(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+    }
+
+    /// Write out everything since the last flush as one event
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&self.pending);
+        let event = format!("[{:.6}, \"o\", {}]", self.elapsed(), json_string(&text));
+        self.pending.clear();
+        writeln!(self.file, "{}", event)
+    }
+
+    /// Note that the terminal is now `width` by `height`
+    pub fn resize(&mut self, width: u16, height: u16) -> io::Result<()> {
+        self.flush()?;
+        let size = format!("{}x{}", width, height);
+        writeln!(self.file, "[{:.6}, \"r\", {}]", self.elapsed(), json_string(&size))
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.output(SHOW_CURSOR);
+        self.flush()?;
+        self.file.flush()
+    }
+
+    fn elapsed(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+}
+
+/// `text` as a JSON string, quotes and all. The escape sequences that make
+/// up most of a frame have to be written as \u001b.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            ch if (ch as u32) < 0x20 || ch == '\u{7f}' => {
+                quoted.push_str(&format!("\\u{:04x}", ch as u32));
+            }
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::time::Duration;
+
+    #[test]
+    fn a_cast_reads_back_as_asciinema_v2() {
+        let path = std::env::temp_dir().join(format!("dospong-{}.cast", std::process::id()));
+        let path = path.to_str().unwrap();
+        let frames = ["\x1b[2J\x1b[1;1H\x1b[38;5;15m●", "\"quoted\" \\ and\ttabs\r\n", "█▀ ♥"];
+        // Far longer than the microseconds the stamps go down to, so every
+        // event is stamped later than the one before
+        let pause = || std::thread::sleep(Duration::from_millis(2));
+        let mut cast = CastWriter::create(path, 80, 27).unwrap();
+        for frame in frames {
+            pause();
+            cast.output(frame.as_bytes());
+            cast.flush().unwrap();
+        }
+        pause();
+        cast.resize(100, 30).unwrap();
+        pause();
+        cast.finish().unwrap();
+        let text = std::fs::read_to_string(path).unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let mut lines = text.lines();
+        let header: Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(header["version"], 2);
+        assert_eq!((header["width"].as_u64(), header["height"].as_u64()), (Some(80), Some(27)));
+        let (mut output, mut resizes, mut last) = (String::new(), Vec::new(), -1.0);
+        for line in lines {
+            let event: (f64, String, String) = serde_json::from_str(line).unwrap();
+            assert!(event.0 > last, "{} came after {}", event.0, last);
+            last = event.0;
+            match event.1.as_str() {
+                "o" => output.push_str(&event.2),
+                "r" => resizes.push(event.2),
+                kind => panic!("an event of kind {:?}", kind),
+            }
+        }
+        let drawn: String = frames.concat();
+        assert_eq!(output, format!("\x1b[?25l{}\x1b[?25h", drawn));
+        assert_eq!(resizes, ["100x30"]);
+    }
+}
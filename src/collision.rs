@@ -0,0 +1,193 @@
+//! The shapes the physics works with and how they meet. Everything is in
+//! cells, from the top left corner of the field, and the tests are the ones
+//! the game has always made, so moving onto them changes nothing.
+
+use serde::{Deserialize, Serialize};
+use std::ops::{Add, AddAssign, Mul, Neg, Sub};
+
+/// A position, or a velocity in cells per 60th of a second
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vec2 {
+    pub const ZERO: Vec2 = Vec2 { x: 0.0, y: 0.0 };
+
+    pub const fn new(x: f32, y: f32) -> Self {
+        Vec2 { x, y }
+    }
+
+    /// Its length, which for a velocity is the speed
+    pub fn length(self) -> f32 {
+        self.x.hypot(self.y)
+    }
+
+    /// Which way it points, in radians from the positive x axis
+    pub fn angle(self) -> f32 {
+        self.y.atan2(self.x)
+    }
+
+    /// `length` long in the direction `angle`
+    pub fn from_angle(angle: f32, length: f32) -> Self {
+        Vec2::new(length * angle.cos(), length * angle.sin())
+    }
+
+    /// The cell it's in
+    pub fn cell(self) -> (i32, i32) {
+        (self.x.floor() as i32, self.y.floor() as i32)
+    }
+}
+
+impl Add for Vec2 {
+    type Output = Vec2;
+
+    fn add(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl AddAssign for Vec2 {
+    fn add_assign(&mut self, other: Vec2) {
+        self.x += other.x;
+        self.y += other.y;
+    }
+}
+
+impl Sub for Vec2 {
+    type Output = Vec2;
+
+    fn sub(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul<f32> for Vec2 {
+    type Output = Vec2;
+
+    fn mul(self, scale: f32) -> Vec2 {
+        Vec2::new(self.x * scale, self.y * scale)
+    }
+}
+
+impl Neg for Vec2 {
+    type Output = Vec2;
+
+    fn neg(self) -> Vec2 {
+        Vec2::new(-self.x, -self.y)
+    }
+}
+
+/// A block of whole cells, `width` columns by `height` rows from the cell
+/// at `x`, `y`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// The square reaching `reach` cells every way from the cell at `x`, `y`
+    pub fn around(x: i32, y: i32, reach: i32) -> Self {
+        Rect::new(x - reach, y - reach, 2 * reach + 1, 2 * reach + 1)
+    }
+
+    pub fn contains_cell(&self, column: i32, row: i32) -> bool {
+        self.contains_column(column) && self.contains_row(row)
+    }
+
+    pub fn contains_column(&self, column: i32) -> bool {
+        column >= self.x && column < self.x + self.width
+    }
+
+    pub fn contains_row(&self, row: i32) -> bool {
+        row >= self.y && row < self.y + self.height
+    }
+}
+
+/// A straight move from `start` to `end`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Segment {
+    pub start: Vec2,
+    pub end: Vec2,
+}
+
+impl Segment {
+    pub fn new(start: Vec2, end: Vec2) -> Self {
+        Segment { start, end }
+    }
+
+    pub fn delta(&self) -> Vec2 {
+        self.end - self.start
+    }
+
+    /// The point `t` of the way along, from 0 at the start to 1 at the end
+    pub fn at(&self, t: f32) -> Vec2 {
+        self.start + self.delta() * t
+    }
+
+    /// How far along the line through it `x` is, 0 being the start and 1
+    /// the end, or `None` for a move that doesn't go left or right
+    pub fn fraction_at_x(&self, x: f32) -> Option<f32> {
+        let dx = self.end.x - self.start.x;
+        (dx != 0.0).then(|| (x - self.start.x) / dx)
+    }
+
+    /// The same as `fraction_at_x`, for `y`
+    pub fn fraction_at_y(&self, y: f32) -> Option<f32> {
+        let dy = self.end.y - self.start.y;
+        (dy != 0.0).then(|| (y - self.start.y) / dy)
+    }
+}
+
+/// A line across the whole field, for something to bounce off
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Line {
+    /// Up and down at this x
+    Vertical(f32),
+    /// Left and right at this y
+    Horizontal(f32),
+}
+
+/// How far along `segment` it crosses the vertical line at `x`, if it does
+pub fn segment_vs_vline(segment: &Segment, x: f32) -> Option<f32> {
+    segment.fraction_at_x(x).filter(|t| (0.0..=1.0).contains(t))
+}
+
+/// How far along `segment` it crosses the horizontal line at `y`, if it does
+pub fn segment_vs_hline(segment: &Segment, y: f32) -> Option<f32> {
+    segment.fraction_at_y(y).filter(|t| (0.0..=1.0).contains(t))
+}
+
+/// Bounce something at `pos` going at `vel` back from `line`, which it
+/// crossed on its last move, mirroring however far it went past it
+pub fn reflect(pos: &mut Vec2, vel: &mut Vec2, line: Line) {
+    match line {
+        Line::Vertical(x) => {
+            pos.x = 2.0 * x - pos.x;
+            vel.x = -vel.x;
+        }
+        Line::Horizontal(y) => {
+            pos.y = 2.0 * y - pos.y;
+            vel.y = -vel.y;
+        }
+    }
+}
+
+/// Whether `point` is in one of `rect`'s cells
+pub fn point_in_rect(point: Vec2, rect: &Rect) -> bool {
+    let (column, row) = point.cell();
+    rect.contains_cell(column, row)
+}
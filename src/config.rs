@@ -0,0 +1,612 @@
+use crate::theme::Theme;
+use crossterm::event::KeyCode;
+use dospong::pong::{
+    Handicap, PowerUpSettings, PowerUpType, Tuning, BALL_SPEED_RANGE, DEFAULT_MAX_POWERUPS,
+    DEFAULT_POWERUP_RATE, DEFAULT_SCORE_LIMIT, HANDICAP_SPEED_RANGE, MAX_BALL_SPEED_RANGE,
+    PADDLE_HEIGHT_RANGE, PADDLE_SPEED_RANGE, SCORE_LIMIT_RANGE,
+};
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+const CONFIG_HEADER: &str = "\
+# DOSPong configuration
+#
+# Each action takes a key or a list of keys. Keys are single characters
+# (\"a\", \"4\") or names: space, enter, esc, tab, backspace, up, down, left,
+# right, home, end, pageup, pagedown, insert, delete, f1-f12.
+
+";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    P1Up,
+    P1Down,
+    P2Up,
+    P2Down,
+    P1Serve,
+    P2Serve,
+    P1Dash,
+    P2Dash,
+    P1Charge,
+    P2Charge,
+    P3Left,
+    P3Right,
+    P4Left,
+    P4Right,
+    Pause,
+    Help,
+    Settings,
+    Quit,
+}
+
+/// A key as written in the config file
+#[derive(Clone, Copy, PartialEq)]
+pub struct Key(pub KeyCode);
+
+impl Key {
+    /// Letters are matched case-insensitively, so Shift or Caps Lock
+    /// doesn't stop a paddle from moving
+    pub fn normalized(code: KeyCode) -> KeyCode {
+        match code {
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            other => other,
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        let mut chars = name.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Some(Key(Self::normalized(KeyCode::Char(c))));
+        }
+        let code = match name.to_ascii_lowercase().as_str() {
+            "space" => KeyCode::Char(' '),
+            "enter" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "insert" => KeyCode::Insert,
+            "delete" => KeyCode::Delete,
+            other => {
+                let number = other.strip_prefix('f')?.parse().ok()?;
+                if !(1..=12).contains(&number) {
+                    return None;
+                }
+                KeyCode::F(number)
+            }
+        };
+        Some(Key(code))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::Enter => write!(f, "enter"),
+            KeyCode::Esc => write!(f, "esc"),
+            KeyCode::Tab => write!(f, "tab"),
+            KeyCode::Backspace => write!(f, "backspace"),
+            KeyCode::Up => write!(f, "up"),
+            KeyCode::Down => write!(f, "down"),
+            KeyCode::Left => write!(f, "left"),
+            KeyCode::Right => write!(f, "right"),
+            KeyCode::Home => write!(f, "home"),
+            KeyCode::End => write!(f, "end"),
+            KeyCode::PageUp => write!(f, "pageup"),
+            KeyCode::PageDown => write!(f, "pagedown"),
+            KeyCode::Insert => write!(f, "insert"),
+            KeyCode::Delete => write!(f, "delete"),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            _ => write!(f, "?"),
+        }
+    }
+}
+
+impl Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Key::parse(&name).ok_or_else(|| serde::de::Error::custom(format!("unknown key '{}'", name)))
+    }
+}
+
+/// All the keys bound to one action. Written in the config as either a
+/// single key or a list of them.
+#[derive(Clone, PartialEq)]
+pub struct KeyList(pub Vec<Key>);
+
+impl KeyList {
+    fn of(codes: &[KeyCode]) -> Self {
+        KeyList(codes.iter().map(|&code| Key(code)).collect())
+    }
+
+    fn contains(&self, code: KeyCode) -> bool {
+        self.0.iter().any(|key| key.0 == code)
+    }
+}
+
+impl fmt::Display for KeyList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, key) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", key)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for KeyList {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyList {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyListVisitor;
+
+        impl<'de> Visitor<'de> for KeyListVisitor {
+            type Value = KeyList;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a key name or a list of key names")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<KeyList, E> {
+                Key::parse(name)
+                    .map(|key| KeyList(vec![key]))
+                    .ok_or_else(|| E::custom(format!("unknown key '{}'", name)))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<KeyList, A::Error> {
+                let mut keys = Vec::new();
+                while let Some(key) = seq.next_element()? {
+                    keys.push(key);
+                }
+                Ok(KeyList(keys))
+            }
+        }
+
+        deserializer.deserialize_any(KeyListVisitor)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    pub p1_up: KeyList,
+    pub p1_down: KeyList,
+    pub p2_up: KeyList,
+    pub p2_down: KeyList,
+    pub p1_serve: KeyList,
+    pub p2_serve: KeyList,
+    /// Dash the way the paddle is going, the same as a double tap
+    pub p1_dash: KeyList,
+    pub p2_dash: KeyList,
+    /// Held to charge a smash, keeping the paddle still
+    pub p1_charge: KeyList,
+    pub p2_charge: KeyList,
+    /// The top and bottom paddles, in four-player mode
+    pub p3_left: KeyList,
+    pub p3_right: KeyList,
+    pub p4_left: KeyList,
+    pub p4_right: KeyList,
+    pub pause: KeyList,
+    pub help: KeyList,
+    /// Opens the settings while the game is paused
+    pub settings: KeyList,
+    pub quit: KeyList,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            p1_up: KeyList::of(&[KeyCode::Char('a'), KeyCode::Char('w'), KeyCode::Up]),
+            p1_down: KeyList::of(&[KeyCode::Char('d'), KeyCode::Char('s'), KeyCode::Down]),
+            p2_up: KeyList::of(&[KeyCode::Char('4'), KeyCode::Char('8'), KeyCode::Char('i')]),
+            p2_down: KeyList::of(&[KeyCode::Char('6'), KeyCode::Char('2'), KeyCode::Char('k')]),
+            p1_serve: KeyList::of(&[KeyCode::Char('e')]),
+            p2_serve: KeyList::of(&[KeyCode::Char('5'), KeyCode::Char('j')]),
+            p1_dash: KeyList::of(&[KeyCode::Char('q')]),
+            p2_dash: KeyList::of(&[KeyCode::Char('0'), KeyCode::Char('l')]),
+            p1_charge: KeyList::of(&[KeyCode::Char('f')]),
+            p2_charge: KeyList::of(&[KeyCode::Char('9'), KeyCode::Char('u')]),
+            p3_left: KeyList::of(&[KeyCode::Char('z')]),
+            p3_right: KeyList::of(&[KeyCode::Char('x')]),
+            p4_left: KeyList::of(&[KeyCode::Char(',')]),
+            p4_right: KeyList::of(&[KeyCode::Char('.')]),
+            pause: KeyList::of(&[KeyCode::Char('p'), KeyCode::Char(' ')]),
+            help: KeyList::of(&[KeyCode::Char('h'), KeyCode::F(1)]),
+            settings: KeyList::of(&[KeyCode::Char('o')]),
+            quit: KeyList::of(&[KeyCode::Esc]),
+        }
+    }
+}
+
+impl KeyBindings {
+    fn entries(&self) -> [(&'static str, &KeyList, Action); 18] {
+        [
+            ("p1_up", &self.p1_up, Action::P1Up),
+            ("p1_down", &self.p1_down, Action::P1Down),
+            ("p2_up", &self.p2_up, Action::P2Up),
+            ("p2_down", &self.p2_down, Action::P2Down),
+            ("p1_serve", &self.p1_serve, Action::P1Serve),
+            ("p2_serve", &self.p2_serve, Action::P2Serve),
+            ("p1_dash", &self.p1_dash, Action::P1Dash),
+            ("p2_dash", &self.p2_dash, Action::P2Dash),
+            ("p1_charge", &self.p1_charge, Action::P1Charge),
+            ("p2_charge", &self.p2_charge, Action::P2Charge),
+            ("p3_left", &self.p3_left, Action::P3Left),
+            ("p3_right", &self.p3_right, Action::P3Right),
+            ("p4_left", &self.p4_left, Action::P4Left),
+            ("p4_right", &self.p4_right, Action::P4Right),
+            ("pause", &self.pause, Action::Pause),
+            ("help", &self.help, Action::Help),
+            ("settings", &self.settings, Action::Settings),
+            ("quit", &self.quit, Action::Quit),
+        ]
+    }
+
+    pub fn action(&self, code: KeyCode) -> Option<Action> {
+        let code = Key::normalized(code);
+        self.entries()
+            .into_iter()
+            .find(|(_, keys, _)| keys.contains(code))
+            .map(|(_, _, action)| action)
+    }
+
+    /// One line per action, for the help overlay
+    pub fn help_lines(&self) -> Vec<String> {
+        let labels = [
+            "Player 1 up",
+            "Player 1 down",
+            "Player 2 up",
+            "Player 2 down",
+            "Player 1 serve",
+            "Player 2 serve",
+            "Player 1 dash",
+            "Player 2 dash",
+            "Player 1 charge",
+            "Player 2 charge",
+            "Player 3 left",
+            "Player 3 right",
+            "Player 4 left",
+            "Player 4 right",
+            "Pause",
+            "Help",
+            "Settings",
+            "Quit",
+        ];
+        labels
+            .iter()
+            .zip(self.entries())
+            .map(|(label, (_, keys, _))| format!("{:<14} {}", label, keys))
+            .collect()
+    }
+
+    /// An action without keys can't be used, and two actions on one key
+    /// would silently leave one of them unusable
+    fn validate(&self) -> Result<(), String> {
+        let entries = self.entries();
+        for (i, &(name, keys, _)) in entries.iter().enumerate() {
+            if keys.0.is_empty() {
+                return Err(format!("{} has no keys", name));
+            }
+            for (j, key) in keys.0.iter().enumerate() {
+                if keys.0[..j].contains(key) {
+                    return Err(format!("key '{}' is listed twice for {}", key, name));
+                }
+                if let Some(&(other, _, _)) = entries[..i].iter().find(|(_, k, _)| k.contains(key.0)) {
+                    return Err(format!("key '{}' is bound to both {} and {}", key, other, name));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which powerups turn up and how often
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerUpConfig {
+    /// Off plays classic Pong, whatever `types` says
+    pub enabled: bool,
+    pub types: Vec<String>,
+    /// Powerups per second, on average
+    pub spawn_rate: f32,
+    pub max_on_field: usize,
+}
+
+impl Default for PowerUpConfig {
+    fn default() -> Self {
+        PowerUpConfig {
+            enabled: true,
+            types: PowerUpType::ALL.iter().map(|ptype| ptype.name().to_string()).collect(),
+            spawn_rate: DEFAULT_POWERUP_RATE,
+            max_on_field: DEFAULT_MAX_POWERUPS,
+        }
+    }
+}
+
+impl PowerUpConfig {
+    pub fn settings(&self) -> Result<PowerUpSettings, String> {
+        let mut enabled = Vec::new();
+        for name in &self.types {
+            let ptype = PowerUpType::from_name(name)
+                .ok_or_else(|| format!("unknown powerup '{}'", name))?;
+            enabled.push(ptype);
+        }
+        if !self.enabled {
+            enabled.clear();
+        }
+        if !(self.spawn_rate >= 0.0 && self.spawn_rate.is_finite()) {
+            return Err("spawn_rate has to be a number of powerups per second".to_string());
+        }
+        Ok(PowerUpSettings {
+            enabled,
+            spawn_rate: self.spawn_rate,
+            max_on_field: self.max_on_field,
+        })
+    }
+}
+
+/// How the game is drawn
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    /// Each side's score in big block digits on the field, like classic
+    /// Pong, instead of as text on the status bar
+    pub big_score: bool,
+    /// Sounds for hits and points: the terminal bell, or generated blips
+    /// when built with the `audio` feature
+    pub bell: bool,
+    /// Shake the screen for a moment on the hardest hits
+    pub shake: bool,
+    /// Start matches with the event feed showing
+    pub feed: bool,
+    /// Draw the field in half blocks, two rows to every row of the screen,
+    /// in games on this machine
+    pub half_block: bool,
+    /// Draw balls in braille dots, which place them finer than a cell
+    pub braille: bool,
+    /// Frames drawn a second, or 0 for as many as the terminal keeps up with
+    pub frame_rate: u16,
+    /// The colors to draw with: default, cga, amber or green
+    pub theme: String,
+    /// Paddles, powerups and the effects on the status bar told apart by
+    /// shape and label rather than mostly by color
+    pub colorblind: bool,
+    /// Show the score in the terminal's title while a match is on
+    pub title: bool,
+    /// A desktop notification when a match ends, and when hosting once the
+    /// other player has joined, for terminals that show them
+    pub notify: bool,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            big_score: false,
+            bell: true,
+            shake: false,
+            feed: false,
+            half_block: false,
+            braille: false,
+            frame_rate: 60,
+            theme: Theme::DEFAULT.name.to_string(),
+            colorblind: false,
+            title: true,
+            notify: false,
+        }
+    }
+}
+
+impl DisplayConfig {
+    pub fn theme(&self) -> Result<Theme, String> {
+        Theme::from_name(&self.theme).ok_or_else(|| {
+            format!("unknown theme '{}', the themes are {}", self.theme, Theme::names())
+        })
+    }
+}
+
+/// How a match plays. The speeds and paddle height only apply to games on
+/// this machine, since both ends of a network game have to play the same.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    /// Points to win a game, unless --score-limit says otherwise
+    pub score_limit: u16,
+    /// Whether one point short of the score limit all makes it two clear
+    /// points to win, rather than the next one
+    pub deuce: bool,
+    /// A multiple of the usual ball speed, from 0.5 to 2
+    pub ball_speed: f32,
+    /// Rows a paddle moves at a time, from 1 to 3
+    pub paddle_speed: i16,
+    /// From 3 to 9 rows
+    pub paddle_height: u16,
+    /// How far a long rally raises the speed limit, from 1 to 2 times the
+    /// one it starts with
+    pub max_ball_speed: f32,
+    /// Seconds a player can go without a key while a ball comes at them
+    /// before the match pauses to ask whether they're still there, or 0
+    /// never to ask
+    pub afk_timeout: u16,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        let tuning = Tuning::default();
+        GameConfig {
+            score_limit: DEFAULT_SCORE_LIMIT,
+            deuce: true,
+            ball_speed: tuning.ball_speed,
+            paddle_speed: tuning.paddle_speed,
+            paddle_height: tuning.paddle_height,
+            max_ball_speed: tuning.max_ball_speed,
+            afk_timeout: 30,
+        }
+    }
+}
+
+impl GameConfig {
+    pub fn tuning(&self) -> Result<Tuning, String> {
+        if !BALL_SPEED_RANGE.contains(&self.ball_speed) {
+            return Err("ball_speed has to be from 0.5 to 2".to_string());
+        }
+        if !PADDLE_SPEED_RANGE.contains(&self.paddle_speed) {
+            return Err("paddle_speed has to be from 1 to 3".to_string());
+        }
+        if !PADDLE_HEIGHT_RANGE.contains(&self.paddle_height) {
+            return Err("paddle_height has to be from 3 to 9".to_string());
+        }
+        if !MAX_BALL_SPEED_RANGE.contains(&self.max_ball_speed) {
+            return Err("max_ball_speed has to be from 1 to 2".to_string());
+        }
+        Ok(Tuning {
+            ball_speed: self.ball_speed,
+            paddle_speed: self.paddle_speed,
+            paddle_height: self.paddle_height,
+            max_ball_speed: self.max_ball_speed,
+        })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !SCORE_LIMIT_RANGE.contains(&self.score_limit) {
+            return Err("score_limit has to be from 1 to 99".to_string());
+        }
+        self.tuning().map(|_| ())
+    }
+}
+
+/// What the players are called, unless --p1-name or --p2-name says
+/// otherwise. Left empty, a player goes by "P1" or "P2". Over the network
+/// the host plays under Player 1's name and the joining player under
+/// Player 2's.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlayersConfig {
+    pub p1_name: String,
+    pub p2_name: String,
+    /// Written the way `parse_handicap` reads them, and only for games on
+    /// this machine
+    pub p1_handicap: String,
+    pub p2_handicap: String,
+}
+
+impl PlayersConfig {
+    pub fn handicaps(&self) -> Result<[Handicap; 2], String> {
+        let p1 = parse_handicap(&self.p1_handicap).map_err(|err| format!("p1_handicap: {}", err))?;
+        let p2 = parse_handicap(&self.p2_handicap).map_err(|err| format!("p2_handicap: {}", err))?;
+        Ok([p1, p2])
+    }
+}
+
+/// Read a handicap written like "height=7,speed=1.5,start=3", any of which
+/// can be left out, so an empty one is no handicap at all
+pub fn parse_handicap(spec: &str) -> Result<Handicap, String> {
+    let mut handicap = Handicap::default();
+    for part in spec.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        let Some((key, value)) = part.split_once('=') else {
+            return Err(format!("'{}' should be like height=7, speed=1.5 or start=3", part));
+        };
+        let value = value.trim();
+        match key.trim() {
+            "height" => {
+                let height = value
+                    .parse()
+                    .ok()
+                    .filter(|height| PADDLE_HEIGHT_RANGE.contains(height))
+                    .ok_or("height has to be from 3 to 9")?;
+                handicap.paddle_height = Some(height);
+            }
+            "speed" => {
+                handicap.paddle_speed = value
+                    .parse()
+                    .ok()
+                    .filter(|speed| HANDICAP_SPEED_RANGE.contains(speed))
+                    .ok_or("speed has to be from 0.5 to 2")?;
+            }
+            "start" => {
+                handicap.head_start =
+                    value.parse().map_err(|_| "start has to be a number of points")?;
+            }
+            other => {
+                return Err(format!("there's no '{}', only height, speed and start", other));
+            }
+        }
+    }
+    Ok(handicap)
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub keys: KeyBindings,
+    pub game: GameConfig,
+    pub powerups: PowerUpConfig,
+    pub display: DisplayConfig,
+    pub players: PlayersConfig,
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".config").join("dospong").join("config.toml"))
+    }
+
+    /// Read the config file, writing out the defaults if there isn't one yet
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = Self::path() else {
+            return Ok(Config::default());
+        };
+
+        let config = match fs::read_to_string(&path) {
+            Ok(text) => toml::from_str::<Config>(&text)
+                .map_err(|err| format!("{}: {}", path.display(), err))?,
+            Err(_) => {
+                let config = Config::default();
+                // Not being able to write the defaults isn't worth failing over
+                let _ = config.save();
+                config
+            }
+        };
+
+        config
+            .keys
+            .validate()
+            .and_then(|()| config.powerups.settings().map(|_| ()))
+            .and_then(|()| config.game.validate())
+            .and_then(|()| config.display.theme().map(|_| ()))
+            .and_then(|()| config.players.handicaps().map(|_| ()))
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(config)
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::path().ok_or("no home directory to save the config in")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        let text = toml::to_string_pretty(self).map_err(|err| err.to_string())?;
+        fs::write(&path, format!("{}{}", CONFIG_HEADER, text)).map_err(|err| err.to_string())
+    }
+}
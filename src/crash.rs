@@ -0,0 +1,88 @@
+//! What's written down when the game panics, for attaching to an issue. The
+//! panic hook notes the message and a backtrace, then once the match has
+//! unwound the bundle is written with the game as it was at the time.
+
+use dospong::pong::{Game, Input};
+use serde::Serialize;
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Frames of input kept for the bundle
+pub const RECENT_FRAMES: usize = 300;
+
+// The first panic's message and backtrace. A panic while writing the bundle
+// is only a second one, so it doesn't replace them.
+static PANIC: Mutex<Option<(String, String)>> = Mutex::new(None);
+
+/// One frame's input and how long the frame was
+#[derive(Clone, Copy, Serialize)]
+pub struct FrameInput {
+    pub dt: f32,
+    pub input: Input,
+}
+
+/// What the match was started with, which the game doesn't keep
+pub struct Context {
+    pub seed: u64,
+    pub config: String,
+}
+
+#[derive(Serialize)]
+struct Bundle<'a> {
+    version: &'static str,
+    panic: &'a str,
+    backtrace: &'a str,
+    seed: u64,
+    config: &'a str,
+    snapshot: serde_json::Value,
+    inputs: &'a VecDeque<FrameInput>,
+}
+
+/// Note a panic, from the panic hook
+pub fn note_panic(info: &PanicHookInfo) {
+    if let Ok(mut noted) = PANIC.lock() {
+        if noted.is_none() {
+            let backtrace = Backtrace::force_capture().to_string();
+            *noted = Some((info.to_string(), backtrace));
+        }
+    }
+}
+
+/// Write the crash bundle to a file in the temp directory and say where.
+/// The game is what broke, so taking its snapshot may panic too, and that
+/// only leaves the snapshot out.
+pub fn write_bundle(
+    context: &Context,
+    game: &Game,
+    inputs: &VecDeque<FrameInput>,
+) -> Result<PathBuf, String> {
+    let (message, backtrace) = PANIC
+        .lock()
+        .ok()
+        .and_then(|noted| noted.clone())
+        .unwrap_or_else(|| ("an unknown panic".to_string(), String::new()));
+    let snapshot = panic::catch_unwind(AssertUnwindSafe(|| serde_json::to_value(game.snapshot())));
+    let snapshot = match snapshot {
+        Ok(Ok(snapshot)) => snapshot,
+        Ok(Err(err)) => format!("couldn't write out the game: {}", err).into(),
+        Err(_) => "taking a snapshot of the game panicked too".into(),
+    };
+    let bundle = Bundle {
+        version: env!("CARGO_PKG_VERSION"),
+        panic: &message,
+        backtrace: &backtrace,
+        seed: context.seed,
+        config: &context.config,
+        snapshot,
+        inputs,
+    };
+    let text = serde_json::to_string_pretty(&bundle).map_err(|err| err.to_string())?;
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let path = std::env::temp_dir().join(format!("dospong-crash-{}.json", since_epoch.as_secs()));
+    std::fs::write(&path, text).map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(path)
+}
@@ -0,0 +1,87 @@
+//! Writing the match's events out as they happen, one JSON object a line,
+//! for other programs to follow. The writing happens on a thread of its
+//! own, so a slow disk or a reader that falls behind never holds up a frame.
+
+use dospong::events::TimedEvent;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+pub struct EventStream {
+    events: Option<Sender<Vec<TimedEvent>>>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+    to_stdout: bool,
+}
+
+impl EventStream {
+    /// Write to the file at `path`, or standard output for "-"
+    pub fn open(path: &str) -> io::Result<Self> {
+        let to_stdout = path == "-";
+        let out: Box<dyn Write + Send> = if to_stdout {
+            Box::new(io::stdout())
+        } else {
+            Box::new(File::create(path)?)
+        };
+        let (sender, events) = mpsc::channel();
+        let thread = thread::spawn(move || write_events(BufWriter::new(out), events));
+        Ok(EventStream {
+            events: Some(sender),
+            thread: Some(thread),
+            to_stdout,
+        })
+    }
+
+    pub fn to_stdout(&self) -> bool {
+        self.to_stdout
+    }
+
+    /// Pass on a frame's events. Ones sent after writing has failed are
+    /// dropped; `finish` says why.
+    pub fn send(&self, events: Vec<TimedEvent>) {
+        if events.is_empty() {
+            return;
+        }
+        if let Some(sender) = &self.events {
+            let _ = sender.send(events);
+        }
+    }
+
+    /// Wait for everything sent to be written out
+    pub fn finish(mut self) -> io::Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> io::Result<()> {
+        // With nothing left to send, the thread writes what it has and ends
+        self.events = None;
+        match self.thread.take().map(|thread| thread.join()) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("the event writer stopped")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// Write each frame's events as they come, flushing whenever the thread
+/// catches up
+fn write_events(mut out: impl Write, events: Receiver<Vec<TimedEvent>>) -> io::Result<()> {
+    while let Ok(frame) = events.recv() {
+        let mut next = Some(frame);
+        while let Some(frame) = next {
+            for event in frame {
+                serde_json::to_writer(&mut out, &event)?;
+                out.write_all(b"\n")?;
+            }
+            next = events.try_recv().ok();
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,37 @@
+//! What happens in a match, one thing at a time, in a shape other programs
+//! can read. The game only keeps these when asked to, and a frontend takes
+//! them each frame to send wherever they're wanted.
+
+use crate::pong::PowerUpType;
+use serde::{Deserialize, Serialize};
+
+/// Players are numbered as they are on screen, whichever side they're on
+/// this set, and speeds are in cells per second
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum GameEvent {
+    /// A ball left for `toward`'s goal
+    Serve { toward: u8 },
+    /// A ball reached `player`'s paddle going at `speed`
+    PaddleHit { player: u8, speed: f32 },
+    /// A ball came off an edge or a wall
+    WallBounce,
+    PowerupSpawn { powerup: PowerUpType, x: u16, y: u16 },
+    PowerupCollected { player: u8, powerup: PowerUpType },
+    /// A ball got past `conceded`, and `player` got the point for it if
+    /// anyone did. The scores are everyone's afterwards, Player 1 first.
+    Score {
+        player: Option<u8>,
+        conceded: u8,
+        scores: Vec<u16>,
+    },
+    GameOver { winner: u8, scores: Vec<u16> },
+}
+
+/// An event and when it happened, in seconds of play since the match began
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimedEvent {
+    pub time: f32,
+    #[serde(flatten)]
+    pub event: GameEvent,
+}
@@ -0,0 +1,2455 @@
+//! Drawing the game in a terminal. This is the only place that changes
+//! terminal modes or writes to the screen, and it owns the thread reading
+//! the terminal's events while the game has it.
+
+use crate::app::GameState;
+use crate::cast::CastWriter;
+use crate::input::EventReader;
+use crate::records::NewBests;
+use crate::settings::SettingsMenu;
+use crate::sound::SoundPlayer;
+use crate::theme::Theme;
+use crossterm::{
+    cursor::{Hide, Show},
+    event::{
+        Event, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute,
+    style::Color,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen, SetTitle},
+};
+use dospong::collision::Vec2;
+use dospong::net::{FoundHost, LinkQuality, MAX_CHAT_LENGTH};
+use dospong::pong::{
+    ActivePowerUp, Axis, EffectKind, Game, Paddle, PowerUpType, Timed, WallKind, FEED_LENGTH,
+    FEED_LIFETIME, MAX_BRICK_HP, PORTAL_HEIGHT, POWERUP_BLINK, POWERUP_SIZE,
+    PRACTICE_TARGET_HEIGHT, SCORE_CELEBRATION, SMASH_CHARGE, SPIN_VISIBLE, SURVIVAL_LIVES,
+};
+use std::fmt::Write as _;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use std::time::{Duration, Instant};
+
+pub const MIN_WIDTH: u16 = 40;
+pub const MIN_HEIGHT: u16 = 15;
+/// Rows of the terminal the field doesn't get: the status bar on top, and
+/// the last row, which is left empty so writing to it can't scroll
+pub const SPARE_ROWS: u16 = 2;
+// Unchanged cells shorter than this between two changes are rewritten rather
+// than skipped, since a cursor move costs about as many bytes
+const MAX_RUN_GAP: u16 = 4;
+// How long the scorer's half lights up at the start of a celebration
+const SCORE_FLASH: Duration = Duration::from_millis(200);
+// Where the field's top left corner goes on screen, under the status bar
+const FIELD_ORIGIN: (usize, usize) = (0, 1);
+// Columns and rows a second the ball behind the start menu moves
+const MENU_BALL_SPEED: (f32, f32) = (24.0, 9.0);
+// Frames the demo's PRESS ANY KEY stays on, then off, for
+const DEMO_BLINK_FRAMES: u64 = 30;
+// The widest the event feed gets, short of half the field
+const FEED_WIDTH: u16 = 30;
+// Room for a whole chat message and who said it
+const CHAT_WIDTH: u16 = MAX_CHAT_LENGTH as u16 + 16;
+// How the ping drawn on the status bar is read: round trips under these
+// get three bars and two, and losing more than this many pings a "!"
+const GOOD_RTT: Duration = Duration::from_millis(60);
+const FAIR_RTT: Duration = Duration::from_millis(150);
+const MAX_PING_LOSS: f32 = 0.05;
+// A timed match's clock turns red and blinks for the last of it
+const CLOCK_WARNING: Duration = Duration::from_secs(15);
+// How long SWITCHING SIDES stays up once the players change ends
+const SIDE_SWITCH_NOTICE: Duration = Duration::from_secs(2);
+// How many dots show where a ball is heading with --debug-draw, and how
+// many frames apart they are
+const VELOCITY_DOTS: u16 = 5;
+const FRAMES_PER_DOT: f32 = 2.0;
+
+/// 3x5 block digits, drawn with '█' wherever the pattern isn't a space
+const BIG_DIGITS: [[&str; 5]; 10] = [
+    ["███", "█ █", "█ █", "█ █", "███"],
+    [" █ ", "██ ", " █ ", " █ ", "███"],
+    ["███", "  █", "███", "█  ", "███"],
+    ["███", "  █", "███", "  █", "███"],
+    ["█ █", "█ █", "███", "  █", "  █"],
+    ["███", "█  ", "███", "  █", "███"],
+    ["███", "█  ", "███", "█ █", "███"],
+    ["███", "  █", "  █", "  █", "  █"],
+    ["███", "█ █", "███", "█ █", "███"],
+    ["███", "█ █", "███", "  █", "███"],
+];
+
+/// A character as it goes on screen. Only half blocks showing two colors
+/// at once set a background.
+#[derive(Clone, Copy, PartialEq)]
+struct Cell {
+    ch: char,
+    fg: Color,
+    bg: Option<Color>,
+}
+
+impl Cell {
+    const BLANK: Cell = Cell {
+        ch: ' ',
+        fg: Color::White,
+        bg: None,
+    };
+}
+
+// Set while the game has the terminal, so it's given back exactly once,
+// whether that's on the way out, in a panic or when the frontend is dropped
+static TERMINAL_TAKEN: AtomicBool = AtomicBool::new(false);
+static ENHANCED: AtomicBool = AtomicBool::new(false);
+static PANIC_HOOK: Once = Once::new();
+// Set once the terminal's title has been saved and changed to the score
+static TITLE_CHANGED: AtomicBool = AtomicBool::new(false);
+// Set when standard output is wanted for something else
+static ON_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Draw the game on standard error from now on, leaving standard output
+/// free to be piped somewhere
+pub fn draw_on_stderr() {
+    ON_STDERR.store(true, Ordering::SeqCst);
+}
+
+/// Wherever the game is being drawn
+fn terminal_out() -> Box<dyn Write> {
+    if ON_STDERR.load(Ordering::SeqCst) {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::stdout())
+    }
+}
+
+/// Give the terminal back the way we found it, if the game still has it
+fn restore_terminal() -> io::Result<()> {
+    if !TERMINAL_TAKEN.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let mut stdout = terminal_out();
+    if ENHANCED.swap(false, Ordering::SeqCst) {
+        execute!(stdout, PopKeyboardEnhancementFlags)?;
+    }
+    if TITLE_CHANGED.swap(false, Ordering::SeqCst) {
+        // Terminals that kept the old title put it back, and the rest are
+        // left with a blank one rather than a stale score
+        execute!(stdout, SetTitle(""))?;
+        write!(stdout, "{}", POP_TITLE)?;
+    }
+    execute!(stdout, LeaveAlternateScreen, Show)?;
+    terminal::disable_raw_mode()
+}
+
+// The xterm sequences that save the window title and put it back, which
+// terminals without them ignore
+const PUSH_TITLE: &str = "\x1b[22;2t";
+const POP_TITLE: &str = "\x1b[23;2t";
+
+/// Gives the terminal back when dropped, however the game ends
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn take() -> Self {
+        TERMINAL_TAKEN.store(true, Ordering::SeqCst);
+        // A panic message printed on the alternate screen vanishes with it,
+        // so put the terminal back before the message goes out
+        PANIC_HOOK.call_once(|| {
+            let default_hook = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                crate::crash::note_panic(info);
+                let _ = restore_terminal();
+                default_hook(info);
+            }));
+        });
+        TerminalGuard
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = restore_terminal();
+    }
+}
+
+/// How long the main loop is taking, collected every frame and shown on
+/// the debug overlay
+#[derive(Default)]
+pub struct FrameStats {
+    /// Seconds the last frame took, start to start
+    pub frame: f32,
+    /// `frame` smoothed over roughly the last second
+    pub average_frame: f32,
+    pub update: Duration,
+    pub render: Duration,
+}
+
+impl FrameStats {
+    pub fn add_frame(&mut self, dt: f32) {
+        self.frame = dt;
+        self.average_frame = if self.average_frame > 0.0 {
+            self.average_frame * 0.95 + dt * 0.05
+        } else {
+            dt
+        };
+    }
+}
+
+/// The terminal, copied into a cast as it's written when one is being
+/// recorded
+struct Screen {
+    stdout: Box<dyn Write>,
+    cast: Option<CastWriter>,
+}
+
+impl Write for Screen {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.stdout.write(buf)?;
+        if let Some(cast) = &mut self.cast {
+            cast.output(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    /// Every flush ends a frame, and each one is an event in the cast
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()?;
+        match &mut self.cast {
+            Some(cast) => cast.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes text onto the end of a row of cells, all in one color
+struct CellWriter<'a> {
+    cells: &'a mut Vec<(char, Color)>,
+    color: Color,
+}
+
+impl std::fmt::Write for CellWriter<'_> {
+    fn write_str(&mut self, text: &str) -> std::fmt::Result {
+        self.cells.extend(text.chars().map(|c| (c, self.color)));
+        Ok(())
+    }
+}
+
+/// A player as the status bar shows them: their name, or their number when
+/// they have none or there isn't room, with `marks` adding an "(H)" for a
+/// handicap and a "×2" when their next point counts double
+#[derive(Clone, Copy)]
+struct Label<'a> {
+    game: &'a Game,
+    player: u8,
+    named: bool,
+    marks: bool,
+}
+
+impl<'a> Label<'a> {
+    fn name(game: &'a Game, player: u8) -> Self {
+        Label {
+            game,
+            player,
+            named: true,
+            marks: false,
+        }
+    }
+}
+
+impl std::fmt::Display for Label<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (game, player) = (self.game, self.player);
+        match game.given_name(player) {
+            name if self.named && !name.is_empty() => f.write_str(name)?,
+            _ => write!(f, "P{}", player)?,
+        }
+        if !self.marks {
+            return Ok(());
+        }
+        if game.handicap(player).is_set() {
+            f.write_str("(H)")?;
+        }
+        let double = match game.side_of(player) {
+            1 => game.p1_double,
+            _ => game.p2_double,
+        };
+        if double {
+            f.write_str("×2")?;
+        }
+        Ok(())
+    }
+}
+
+/// The bit of each dot of a braille character, by row and then column
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+pub struct TerminalFrontend {
+    stdout: Screen,
+    // Whether the kitty keyboard protocol was turned on
+    enhanced: bool,
+    // None when nothing is reading a terminal, as when drawing off screen
+    input: Option<EventReader>,
+    _guard: TerminalGuard,
+    // Size of the buffers below. The field is drawn `scale` rows to a
+    // screen row, into `rows` rows on screen.
+    width: u16,
+    height: u16,
+    scale: u16,
+    rows: u16,
+    // Playable size of the terminal, `SPARE_ROWS` short of its real height
+    screen: (u16, u16),
+    // The status bar above the field, and what goes in the middle of it and
+    // on either side as it's worked out, kept so no frame has to allocate
+    status: Vec<(char, Color)>,
+    middle_cells: Vec<(char, Color)>,
+    side_cells: Vec<(char, Color)>,
+    buffer: Vec<Vec<char>>,
+    color_buffer: Vec<Vec<Color>>,
+    // The field as it goes on screen, with rows drawn at a finer scale
+    // merged into half blocks
+    cells: Vec<Vec<Cell>>,
+    // What the terminal is currently showing, to diff the next frame against
+    prev_status: Vec<(char, Color)>,
+    prev_cells: Vec<Vec<Cell>>,
+    // Escape sequences for the frame being built, reused between frames
+    frame: String,
+    // Counts rendered frames, for anything that blinks
+    frames_drawn: u64,
+    needs_clear: bool,
+    /// Set while the terminal can't fit `min_size`
+    pub too_small: Option<(u16, u16)>,
+    /// The smallest field the terminal has to fit before play continues
+    pub min_size: (u16, u16),
+    pub show_help: bool,
+    pub help_lines: Vec<String>,
+    /// Asking whether to really quit the match
+    pub quit_prompt: bool,
+    /// A line under the pause banner, such as how saving the match went
+    pub notice: Option<String>,
+    /// The computer playing itself until someone presses a key
+    pub demo: bool,
+    /// The settings, while they're up over a paused match
+    pub settings: Option<SettingsMenu>,
+    /// Draw the two sides' scores in big digits on the field
+    pub big_score: bool,
+    /// Keep the score in the terminal's title
+    pub live_title: bool,
+    /// Send desktop notifications through the terminal
+    pub notify: bool,
+    // What the title was last set to, so it's only sent when that changes,
+    // and where the next one is worked out
+    title: Option<String>,
+    title_text: String,
+    /// Make sounds for hits and points
+    pub bell: bool,
+    sound: SoundPlayer,
+    /// Shake the field on hard hits
+    pub shake: bool,
+    /// Draw the field with two of its rows to every screen row
+    pub half_block: bool,
+    /// Draw balls in braille dots, placed finer than a cell
+    pub braille: bool,
+    pub theme: Theme,
+    /// Tell paddles and powerups apart by shape as well as color
+    pub colorblind: bool,
+    /// The symbol and color of each custom powerup, in the game's order
+    pub custom_looks: Vec<(char, Color)>,
+    // The same for each built-in powerup, in `PowerUpType::ALL`'s order,
+    // worked out once rather than a color name parsed every frame
+    builtin_looks: [(char, Color); PowerUpType::ALL.len()],
+    // Where on screen the field goes this frame and went last frame, which
+    // only differ from `FIELD_ORIGIN` while it shakes
+    field_origin: (usize, usize),
+    drawn_origin: (usize, usize),
+    /// How key presses are being read, shown on the bottom border
+    pub input_hint: &'static str,
+    /// Connection numbers shown by --net-debug
+    pub net_stats: Option<String>,
+    /// How the connection is holding up in a network match, shown on the
+    /// right of the status bar
+    pub link_quality: Option<LinkQuality>,
+    /// What's being typed to say in a network match, while the chat line is
+    /// open
+    pub chat_input: Option<String>,
+    // What's been said, with when, which goes in the event feed whether
+    // the feed is shown or not
+    chat: Vec<(Instant, String)>,
+    // Whether the players were on each other's sides last frame, and when
+    // that last changed
+    swapped: bool,
+    switched_sides: Option<Instant>,
+    /// How the --bot2 program is keeping up, for the debug overlay
+    pub bot_stats: Option<String>,
+    /// Show the last few things that happened in the bottom left corner
+    pub show_feed: bool,
+    /// Show the frame timings in the top left corner
+    pub show_debug: bool,
+    /// Draw what the collision checks see over the field
+    pub debug_draw: bool,
+    pub frame_stats: FrameStats,
+    // Size of the last frame written, for the debug overlay
+    frame_bytes: usize,
+    pub spectating: bool,
+    pub connection_lost: bool,
+    /// Records the match that just ended beat, marked on the game over screen
+    pub new_bests: NewBests,
+}
+
+impl TerminalFrontend {
+    /// Take over the terminal: alternate screen, raw mode, and real key
+    /// release events where the terminal supports them
+    pub fn enter() -> io::Result<Self> {
+        let guard = TerminalGuard::take();
+        let mut stdout = terminal_out();
+        execute!(stdout, EnterAlternateScreen, Hide)?;
+        terminal::enable_raw_mode()?;
+
+        // The check asks the terminal through standard output, which may not
+        // be the terminal while drawing on standard error
+        let enhanced = !ON_STDERR.load(Ordering::SeqCst)
+            && terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(
+                    KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                        | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+                        | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                )
+            )?;
+            ENHANCED.store(true, Ordering::SeqCst);
+        }
+
+        // Only once the keyboard enhancement check above has had its answer,
+        // or the reader could take it
+        let input = EventReader::start();
+
+        let (width, height) = terminal::size()?;
+        Ok(Self::new(stdout, guard, Some(input), enhanced, (width, height)))
+    }
+
+    /// A frontend drawing to `stdout` as a terminal of `size`, with
+    /// `input` reading its keys
+    fn new(
+        stdout: Box<dyn Write>,
+        guard: TerminalGuard,
+        input: Option<EventReader>,
+        enhanced: bool,
+        size: (u16, u16),
+    ) -> Self {
+        let (width, height) = size;
+        let mut frontend = TerminalFrontend {
+            stdout: Screen { stdout, cast: None },
+            enhanced,
+            input,
+            _guard: guard,
+            width: 0,
+            height: 0,
+            scale: 1,
+            rows: 0,
+            screen: (width, height.saturating_sub(SPARE_ROWS)),
+            status: Vec::new(),
+            middle_cells: Vec::new(),
+            side_cells: Vec::new(),
+            buffer: Vec::new(),
+            color_buffer: Vec::new(),
+            cells: Vec::new(),
+            prev_status: Vec::new(),
+            prev_cells: Vec::new(),
+            frame: String::new(),
+            frames_drawn: 0,
+            needs_clear: true,
+            too_small: None,
+            min_size: (MIN_WIDTH, MIN_HEIGHT),
+            show_help: false,
+            help_lines: Vec::new(),
+            quit_prompt: false,
+            notice: None,
+            demo: false,
+            settings: None,
+            big_score: false,
+            live_title: false,
+            notify: false,
+            title: None,
+            title_text: String::new(),
+            bell: false,
+            sound: SoundPlayer::new(),
+            shake: false,
+            half_block: false,
+            braille: false,
+            theme: Theme::DEFAULT,
+            colorblind: false,
+            custom_looks: Vec::new(),
+            builtin_looks: PowerUpType::ALL.map(|ptype| {
+                let effect = ptype.effect();
+                (effect.symbol(), Color::try_from(effect.color()).unwrap_or(Color::White))
+            }),
+            field_origin: FIELD_ORIGIN,
+            drawn_origin: FIELD_ORIGIN,
+            input_hint: "",
+            net_stats: None,
+            link_quality: None,
+            chat_input: None,
+            chat: Vec::new(),
+            swapped: false,
+            switched_sides: None,
+            bot_stats: None,
+            show_feed: false,
+            show_debug: false,
+            debug_draw: false,
+            frame_stats: FrameStats::default(),
+            frame_bytes: 0,
+            spectating: false,
+            connection_lost: false,
+            new_bests: NewBests::default(),
+        };
+        frontend.fit_to(width, height.saturating_sub(SPARE_ROWS), 1);
+        frontend
+    }
+
+    /// Give the terminal back the way we found it
+    pub fn leave(self) -> io::Result<()> {
+        if let Some(input) = self.input {
+            input.stop();
+        }
+        restore_terminal()?;
+        match self.stdout.cast {
+            Some(cast) => cast.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Copy everything drawn from now on into an asciinema cast at `path`
+    pub fn record_cast(&mut self, path: &str) -> io::Result<()> {
+        let (width, height) = self.screen;
+        self.stdout.cast = Some(CastWriter::create(path, width, height + SPARE_ROWS)?);
+        // The cast starts on a blank screen like the terminal did
+        self.needs_clear = true;
+        Ok(())
+    }
+
+    pub fn enhanced(&self) -> bool {
+        self.enhanced
+    }
+
+    /// The next terminal event, if one has come in since the last call
+    pub fn next_event(&self) -> Option<io::Result<Event>> {
+        self.input.as_ref()?.next()
+    }
+
+    /// Note the terminal's new playable size. Returns false while it's too
+    /// small for `min_size`, in which case the game should be held.
+    pub fn resize(&mut self, width: u16, height: u16) -> bool {
+        self.screen = (width, height);
+        if let Some(cast) = &mut self.stdout.cast {
+            // Trouble writing the file comes up again with the next frame
+            let _ = cast.resize(width, height + SPARE_ROWS);
+        }
+        let (min_width, min_height) = self.min_size;
+        if width < min_width || height < min_height {
+            self.too_small = Some((width, height));
+            return false;
+        }
+        self.too_small = None;
+        self.needs_clear = true;
+        true
+    }
+
+    /// How many rows a field gets on `rows` rows of the screen
+    pub fn field_height(&self, rows: u16) -> u16 {
+        if self.half_block {
+            rows * 2
+        } else {
+            rows
+        }
+    }
+
+    /// Redraw everything on the next frame
+    pub fn repaint(&mut self) {
+        self.needs_clear = true;
+    }
+
+    /// Take the sounds `game` has made since last time, and play them if
+    /// sound is on
+    pub fn play_sounds(&mut self, game: &mut Game) {
+        let sounds = std::mem::take(&mut game.sounds);
+        if self.bell {
+            let now = Instant::now();
+            for sound in sounds {
+                self.sound.play(sound, now);
+            }
+        }
+    }
+
+    pub fn toggle_help(&mut self) {
+        self.show_help = !self.show_help;
+    }
+
+    /// Put a chat line in the event feed
+    pub fn add_chat(&mut self, line: String) {
+        if self.chat.len() >= FEED_LENGTH {
+            self.chat.remove(0);
+        }
+        self.chat.push((Instant::now(), line));
+    }
+
+    pub fn toggle_feed(&mut self) {
+        self.show_feed = !self.show_feed;
+    }
+
+    pub fn toggle_debug(&mut self) {
+        self.show_debug = !self.show_debug;
+    }
+
+    pub fn toggle_debug_draw(&mut self) {
+        self.debug_draw = !self.debug_draw;
+    }
+
+    /// Whether something on screen is keeping the game from running
+    pub fn holds_game(&self) -> bool {
+        self.show_help
+            || self.quit_prompt
+            || self.settings.is_some()
+            || self.too_small.is_some()
+            || self.connection_lost
+    }
+
+    /// Size the buffers for a field of `width` x `height`, drawn `scale`
+    /// rows to a screen row
+    fn fit_to(&mut self, width: u16, height: u16, scale: u16) {
+        if width == self.width && height == self.height && scale == self.scale {
+            return;
+        }
+        self.width = width;
+        self.height = height;
+        self.scale = scale;
+        self.rows = height / scale;
+        self.status = vec![(' ', Color::White); width as usize];
+        self.prev_status = vec![(' ', Color::White); width as usize];
+        self.buffer = vec![vec![' '; width as usize]; height as usize];
+        self.color_buffer = vec![vec![Color::White; width as usize]; height as usize];
+        self.cells = vec![vec![Cell::BLANK; width as usize]; self.rows as usize];
+        self.prev_cells = vec![vec![Cell::BLANK; width as usize]; self.rows as usize];
+        self.frame = String::with_capacity((width as usize + 10) * self.rows as usize);
+        self.needs_clear = true;
+    }
+
+    /// Draw the field, with the overlay that goes with `state` on top
+    pub fn render(&mut self, game: &Game, state: GameState) -> io::Result<()> {
+        self.fit_to(game.width, game.height, if self.half_block { 2 } else { 1 });
+
+        if let Some((width, height)) = self.too_small {
+            let (min_width, min_height) = self.min_size;
+            let message = format!(
+                "Terminal too small ({}x{}), need at least {}x{}",
+                width,
+                height + SPARE_ROWS,
+                min_width,
+                min_height + SPARE_ROWS
+            );
+            let message: String = message.chars().take(width as usize).collect();
+            write!(self.stdout, "\x1b[0m\x1b[2J\x1b[1;1H{}", message)?;
+            self.stdout.flush()?;
+            self.needs_clear = true;
+            return Ok(());
+        }
+
+        self.clear_buffers();
+        if self.live_title {
+            self.show_title(game)?;
+        }
+        if game.swapped != self.swapped {
+            self.swapped = game.swapped;
+            self.switched_sides = Some(Instant::now());
+        }
+
+        // The score goes in the middle of the status bar, named for
+        // whoever is on each side, which changes between sets
+        let width = self.width as usize;
+        let field = || {
+            game.active_powerups
+                .iter()
+                .filter(|p| p.ptype.effect().affects_field() || p.player > 2)
+        };
+        // The clock leads, so the score doesn't shift along as it counts
+        // down. Both go in buffers kept from frame to frame.
+        let mut middle = std::mem::take(&mut self.middle_cells);
+        let mut cells = std::mem::take(&mut self.side_cells);
+        middle.clear();
+        if let Some(timed) = &game.timed {
+            self.clock_cells(timed, &mut middle);
+        }
+        let clock_len = middle.len();
+        self.score_cells(game, true, &mut middle);
+        // Long names give way to numbers rather than crowd out the field's
+        // powerups on a narrow screen
+        cells.clear();
+        self.effect_cells(field(), usize::MAX, &mut cells);
+        let wanted = middle.len() + if cells.is_empty() { 0 } else { cells.len() + 2 };
+        if wanted > width {
+            middle.truncate(clock_len);
+            self.score_cells(game, false, &mut middle);
+        }
+        let text = self.theme.text;
+        if middle.len() > width {
+            middle.truncate(width.saturating_sub(1));
+            middle.push(('…', text));
+        }
+
+        // Active powerups go on the status bar too: each player's on their
+        // side of the score, and the ones that change the field after it.
+        // The top and bottom players have no side of the score, so theirs
+        // go in with the field's.
+        cells.clear();
+        self.effect_cells(field(), width.saturating_sub(middle.len() + 2), &mut cells);
+        if !cells.is_empty() {
+            middle.extend([(' ', text); 2]);
+            middle.extend_from_slice(&cells);
+        }
+        let middle_start = width.saturating_sub(middle.len()) / 2;
+        self.draw_status(middle_start, &middle);
+        let effects = |player| {
+            game.active_powerups
+                .iter()
+                .filter(move |p| p.player == player && !p.ptype.effect().affects_field())
+        };
+        cells.clear();
+        self.effect_cells(effects(1), middle_start.saturating_sub(2), &mut cells);
+        self.draw_status(1, &cells);
+        let mut room = width.saturating_sub(middle_start + middle.len() + 2);
+        let mut end = width.saturating_sub(1);
+        cells.clear();
+        if let Some(quality) = self.link_quality {
+            self.quality_cells(quality, &mut cells);
+        }
+        if !cells.is_empty() && cells.len() <= room {
+            self.draw_status(end - cells.len(), &cells);
+            room = room.saturating_sub(cells.len() + 2);
+            end = end.saturating_sub(cells.len() + 2);
+        }
+        cells.clear();
+        self.effect_cells(effects(2), room, &mut cells);
+        self.draw_status(end.saturating_sub(cells.len()), &cells);
+        self.middle_cells = middle;
+        self.side_cells = cells;
+
+        // Draw input mode hint on the bottom border, or the chat line
+        // while it's open
+        let bottom = self.rows - 1;
+        if let Some(typed) = self.chat_input.take() {
+            let line = format!(" Say: {}_ ", typed);
+            self.draw_text(2, bottom, &line, self.theme.highlight);
+            self.chat_input = Some(typed);
+        } else if !self.input_hint.is_empty() && self.rows > 1 {
+            self.draw_text(2, bottom, self.input_hint, self.theme.faint_text);
+        }
+
+        // Draw network stats on the right of the bottom border
+        if let Some(stats) = self.net_stats.take() {
+            let len = stats.chars().count() as u16;
+            let start = self.width.saturating_sub(len + 2);
+            self.draw_text(start, bottom, &stats, self.theme.faint_text);
+            self.net_stats = Some(stats);
+        }
+
+        if self.spectating {
+            let color = self.theme.highlight;
+            self.draw_text_centered(bottom, " SPECTATING — Esc to leave ", color);
+        }
+
+        // Light up the scorer's half just after a point, under everything
+        // else
+        let flashes = game
+            .effects
+            .iter()
+            .filter(|e| e.kind == EffectKind::Score && SCORE_CELEBRATION - e.remaining < SCORE_FLASH);
+        for effect in flashes {
+            let (w, h) = (self.width, self.height);
+            let (columns, rows) = match effect.player {
+                1 => (1..w / 2, 1..h - 1),
+                2 => (w / 2 + 1..w - 1, 1..h - 1),
+                3 => (1..w - 1, 1..h / 2),
+                _ => (1..w - 1, h / 2..h - 1),
+            };
+            let color = self.player_colors(game.player_at(effect.player)).1;
+            for y in rows {
+                for x in columns.clone() {
+                    self.buffer[y as usize][x as usize] = '░';
+                    self.color_buffer[y as usize][x as usize] = color;
+                }
+            }
+        }
+
+        // Draw center line
+        for y in 0..self.height {
+            if y % (2 * self.scale) == 0 {
+                self.buffer[y as usize][(self.width / 2) as usize] = '┊';
+                self.color_buffer[y as usize][(self.width / 2) as usize] = self.theme.center_line;
+            }
+        }
+
+        // Draw big scores near the top of each half, before anything that
+        // moves so balls and powerups show on top of them
+        if self.shows_big_score(game) {
+            for (side, score) in [(1, game.p1_score), (2, game.p2_score)] {
+                let center = if side == 1 { self.width / 4 } else { self.width * 3 / 4 };
+                let color = self.player_colors(game.player_at(side)).0;
+                self.draw_big_number(center, 2, score, color);
+            }
+        }
+
+        // Draw walls, leaving the top and bottom borders alone
+        for wall in &game.walls {
+            let (ch, color) = match wall.kind {
+                WallKind::Center => ('█', self.theme.center_wall),
+                WallKind::Small => ('█', self.theme.small_walls),
+                WallKind::Obstacle => ('▓', self.theme.obstacles),
+                WallKind::Moving => ('█', self.theme.moving_wall),
+            };
+            let rows = wall.y.max(1)..(wall.y + wall.height).min(self.height - 1);
+            if wall.x < self.width {
+                for y in rows {
+                    self.buffer[y as usize][wall.x as usize] = ch;
+                    self.color_buffer[y as usize][wall.x as usize] = color;
+                }
+            }
+        }
+
+        // Draw bricks, shaded and colored by how many hits they have left
+        for brick in &game.bricks {
+            let hp = brick.hp.clamp(1, MAX_BRICK_HP) as usize - 1;
+            if brick.x < self.width && brick.y < self.height {
+                let (x, y) = (brick.x as usize, brick.y as usize);
+                self.buffer[y][x] = ['░', '▒', '▓'][hp];
+                self.color_buffer[y][x] = self.theme.bricks[hp];
+            }
+        }
+
+        // Draw portals
+        if let Some((left, right)) = game.portals {
+            let color = self.powerup_look(PowerUpType::Portal).1;
+            for portal in [left, right] {
+                for y in portal.y..portal.y + PORTAL_HEIGHT {
+                    if y < self.height && portal.x < self.width {
+                        self.buffer[y as usize][portal.x as usize] = '▒';
+                        self.color_buffer[y as usize][portal.x as usize] = color;
+                    }
+                }
+            }
+        }
+
+        // Draw shields on the goal lines, and a white flash where one just
+        // broke
+        let flash = game.shield_flash.map(|(player, _)| player);
+        let goals = [
+            (1, 0, game.p1_shield, self.player_colors(game.player_at(1)).0),
+            (2, self.width.saturating_sub(1), game.p2_shield, self.player_colors(game.player_at(2)).0),
+        ];
+        for (player, x, shield, color) in goals {
+            let color = if flash == Some(player) {
+                self.theme.flash
+            } else if shield {
+                color
+            } else {
+                continue;
+            };
+            for y in 1..self.height.saturating_sub(1) {
+                self.buffer[y as usize][x as usize] = '▓';
+                self.color_buffer[y as usize][x as usize] = color;
+            }
+        }
+
+        // Show off a new score in the middle for a moment, in place of the
+        // serve countdown. The messages between sets and at the end say it
+        // already.
+        let celebration = game.effects.iter().find(|e| e.kind == EffectKind::Score);
+        let decided = matches!(state, GameState::SetOver | GameState::GameOver);
+        if let Some(effect) = celebration.filter(|_| !decided) {
+            let score = game.scores()[effect.player as usize - 1];
+            let color = self.player_colors(game.player_at(effect.player)).0;
+            let y = (self.rows / 2).saturating_sub(2);
+            self.draw_big_number(self.width / 2, y, score, color);
+        } else if let Some(remaining) = game.serve_timer {
+            let seconds = remaining.as_secs_f32().ceil() as usize;
+            let x = (self.width / 2).saturating_sub(1);
+            let y = (self.rows / 2).saturating_sub(2);
+            self.draw_big_digit(x, y, seconds.min(9), self.theme.highlight);
+        }
+
+        // Draw P1 paddle
+        let flash = self.theme.flash;
+        let status = self.paddle_status(game, 1);
+        let (main, second) = match status {
+            _ if game.effect(EffectKind::PaddleHit, 1).is_some() => (flash, flash),
+            Some((_, color)) => (color, color),
+            None => self.player_colors(game.player_at(1)),
+        };
+        self.draw_paddle(1, 2, game.p1_y, game.p1_height, game.p1_bent, main);
+        if let Some(y) = game.p1_second_y {
+            self.draw_paddle(1, 2, y, game.p1_height, false, second);
+        }
+        if let Some((marker, color)) = status {
+            self.draw_paddle_marker(2, game.p1_y, game.p1_height, marker, color);
+        }
+        self.draw_charge_bar(game, 1, 1);
+        self.draw_dash_pip(game, 1, 1);
+
+        // Draw the wall in place of the P2 paddle, with any practice target
+        if game.has_wall() {
+            let x = game.wall_x() as usize;
+            let target = match &game.practice {
+                Some(practice) => practice.target_y..practice.target_y + PRACTICE_TARGET_HEIGHT,
+                None => 0..0,
+            };
+            for y in 1..self.height.saturating_sub(1) {
+                let (ch, color) = if target.contains(&y) {
+                    ('█', self.theme.practice_target)
+                } else {
+                    ('▒', self.theme.practice_wall)
+                };
+                self.buffer[y as usize][x] = ch;
+                self.color_buffer[y as usize][x] = color;
+            }
+        }
+
+        // Draw P2 paddle
+        let p2_x = self.width.saturating_sub(3);
+        let status = self.paddle_status(game, 2);
+        let (main, second) = match status {
+            _ if game.effect(EffectKind::PaddleHit, 2).is_some() => (flash, flash),
+            Some((_, color)) => (color, color),
+            None => self.player_colors(game.player_at(2)),
+        };
+        if !game.has_wall() {
+            self.draw_paddle(2, p2_x, game.p2_y, game.p2_height, game.p2_bent, main);
+            if let Some(y) = game.p2_second_y {
+                self.draw_paddle(2, p2_x, y, game.p2_height, false, second);
+            }
+            if let Some((marker, color)) = status {
+                self.draw_paddle_marker(p2_x, game.p2_y, game.p2_height, marker, color);
+            }
+            self.draw_charge_bar(game, 2, p2_x + 1);
+            self.draw_dash_pip(game, 2, p2_x + 1);
+        }
+
+        // Draw the top and bottom paddles
+        for paddle in &game.edge_paddles {
+            let color = match self.paddle_status(game, paddle.player) {
+                _ if game.effect(EffectKind::PaddleHit, paddle.player).is_some() => flash,
+                Some((_, color)) => color,
+                None => self.player_colors(paddle.player).0,
+            };
+            self.draw_edge_paddle(paddle, color);
+        }
+
+        // Draw balls, blended between the last two physics steps
+        // An invisible ball flickers instead of vanishing while the center
+        // wall is up, since the wall alone already hides it for a while
+        let alpha = game.interpolation();
+        self.frames_drawn = self.frames_drawn.wrapping_add(1);
+        let flicker_off = self.frames_drawn.is_multiple_of(2);
+        for ball in &game.balls {
+            if game.is_invisible(ball) && (!game.center_wall || flicker_off) {
+                continue;
+            }
+            let Vec2 { x, y } = ball.path().at(alpha);
+            // The faster a rally gets, the hotter the ball looks
+            let heat = game.ball_heat(ball);
+            let color = if heat < 1.0 / 3.0 {
+                self.theme.ball
+            } else if heat < 2.0 / 3.0 {
+                self.theme.fast_ball[0]
+            } else {
+                self.theme.fast_ball[1]
+            };
+            if self.braille && self.theme.braille && self.scale == 1 {
+                self.draw_braille_ball(x, y, color);
+                continue;
+            }
+            let (x, y) = (x as usize, y as usize);
+            if y < self.height as usize && x < self.width as usize {
+                // A ball with spin on it looks different, so the curve
+                // doesn't come as a surprise. In half blocks the ball is a
+                // single half of a cell, too small for any glyph.
+                let theme = &self.theme;
+                self.buffer[y][x] = if self.scale > 1 {
+                    '█'
+                } else if ball.spin.abs() >= SPIN_VISIBLE {
+                    theme.spin_glyph
+                } else {
+                    theme.ball_glyph
+                };
+                self.color_buffer[y][x] = color;
+            }
+        }
+
+        // Draw powerups as a square of their symbol, blinking once they're
+        // about to go. In colorblind mode each one fills its square in its
+        // own shape instead.
+        for powerup in &game.powerups {
+            if powerup.remaining < POWERUP_BLINK && flicker_off {
+                continue;
+            }
+            let (symbol, color) = self.powerup_look(powerup.ptype);
+            let shape = powerup.ptype.effect().shape();
+
+            let half = POWERUP_SIZE as i16 / 2;
+            for dy in -half..=half {
+                for dx in -half..=half {
+                    let row = shape[(dy + half) as usize].as_bytes();
+                    if self.colorblind && row[(dx + half) as usize] == b' ' {
+                        continue;
+                    }
+                    let px = (powerup.x as i16 + dx) as usize;
+                    let py = (powerup.y as i16 + dy) as usize;
+                    if py < self.height as usize && px < self.width as usize {
+                        self.buffer[py][px] = symbol;
+                        self.color_buffer[py][px] = color;
+                    }
+                }
+            }
+        }
+
+        if self.debug_draw {
+            self.draw_hitboxes(game);
+        }
+
+        if self.demo && (self.frames_drawn / DEMO_BLINK_FRAMES).is_multiple_of(2) {
+            let theme = self.theme;
+            self.draw_text_centered(self.rows / 2, " PRESS ANY KEY ", theme.highlight);
+        }
+
+        // Draw pause banner
+        if state == GameState::Paused {
+            let theme = self.theme;
+            self.draw_banner("  PAUSED  ", theme.faint_text, theme.dim_text);
+            if let Some(notice) = self.notice.take() {
+                self.draw_text_centered(self.rows / 2 + 2, &notice, theme.text);
+                self.notice = Some(notice);
+            }
+        }
+
+        // Each player's keys still move their own paddle after a switch, so
+        // say for a moment which paddle that now is
+        let switching = self.switched_sides.is_some_and(|at| at.elapsed() < SIDE_SWITCH_NOTICE);
+        if switching && matches!(state, GameState::Serving | GameState::Playing) {
+            let theme = self.theme;
+            self.draw_banner("  SWITCHING SIDES  ", theme.faint_text, theme.highlight);
+            let sides = format!(
+                " ◀ {}    {} ▶ ",
+                game.long_name(game.player_at(1)),
+                game.long_name(game.player_at(2))
+            );
+            self.draw_text_centered(self.rows / 2 + 2, &sides, theme.text);
+        }
+
+        if self.connection_lost {
+            let theme = self.theme;
+            let text = "  CONNECTION LOST  —  press Esc to quit  ";
+            self.draw_banner(text, theme.alert_banner, theme.alert);
+        }
+
+        // Draw help overlay
+        if self.show_help {
+            self.draw_help(game);
+        }
+
+        if self.settings.is_some() {
+            self.draw_settings();
+        }
+
+        if self.quit_prompt {
+            let theme = self.theme;
+            self.draw_banner("  Quit match? Y/N  ", theme.faint_text, theme.text);
+        }
+
+        // Draw the set score between sets
+        if let (GameState::SetOver, Some(side)) = (state, game.set_winner) {
+            let message = format!(
+                "{} takes set {}  —  Sets {}-{}",
+                game.long_name(game.player_at(side)),
+                game.p1_sets + game.p2_sets,
+                game.p1_sets,
+                game.p2_sets
+            );
+            self.draw_text_centered(self.rows / 2, &message, self.theme.text);
+            let next = if game.ai.is_none() {
+                "Press Enter for the next set, players swap sides"
+            } else {
+                "Press Enter for the next set"
+            };
+            self.draw_text_centered(self.rows / 2 + 2, next, self.theme.dim_text);
+        }
+
+        // Draw game over message
+        if let (GameState::GameOver, Some(survival)) = (state, &game.survival) {
+            let message = format!(
+                "Out of lives after {} returns  —  press R to try again, Esc to quit",
+                survival.returns
+            );
+            self.draw_text_centered(self.rows / 2, &message, self.theme.text);
+            if self.new_bests.survival {
+                self.draw_text_centered(self.rows / 2 + 2, "NEW BEST", self.theme.highlight);
+            }
+        } else if let (GameState::GameOver, Some(side)) = (state, game.winner) {
+            let message = if game.best_of > 1 {
+                format!(
+                    "{} wins the match {}-{}  —  press R to rematch, Esc to quit",
+                    game.long_name(game.player_at(side)),
+                    game.p1_sets.max(game.p2_sets),
+                    game.p1_sets.min(game.p2_sets)
+                )
+            } else {
+                let winner = game.long_name(game.player_at(side));
+                format!("{} wins  —  press R to rematch, Esc to quit", winner)
+            };
+            self.draw_text_centered(self.rows / 2, &message, self.theme.text);
+            self.draw_stats(game);
+        }
+
+        self.chat.retain(|(said, _)| said.elapsed() < FEED_LIFETIME);
+        if self.show_feed || !self.chat.is_empty() {
+            self.draw_feed(game);
+        }
+        if self.show_debug {
+            self.draw_debug(game);
+        }
+
+        // Shake the field on a hard hit, moving it up over the status bar
+        // and sideways where the screen has room, so none of it goes off
+        // the screen
+        self.field_origin = FIELD_ORIGIN;
+        if self.shake && game.effects.iter().any(|e| e.kind == EffectKind::HardHit) {
+            let room = self.screen.0.saturating_sub(self.width) as usize;
+            self.field_origin = if self.frames_drawn.is_multiple_of(2) {
+                (0, 0)
+            } else {
+                (room.min(1), FIELD_ORIGIN.1)
+            };
+        }
+
+        self.build_frame();
+        if self.bell && self.sound.bell_due(Instant::now()) {
+            self.frame.push('\x07');
+        }
+
+        // Write entire frame at once
+        self.stdout.write_all(self.frame.as_bytes())?;
+        self.stdout.flush()?;
+        self.frame_bytes = self.frame.len();
+
+        Ok(())
+    }
+
+    /// Draw the list of games found on the local network, with `selected`
+    /// highlighted. Games speaking another protocol version are greyed out.
+    pub fn render_lobby(
+        &mut self,
+        hosts: &[FoundHost],
+        selected: usize,
+        searching: bool,
+    ) -> io::Result<()> {
+        let (width, height) = self.screen;
+        self.fit_to(width, height, 1);
+        if let Some((width, height)) = self.too_small {
+            let message = format!("Terminal too small ({}x{})", width, height + SPARE_ROWS);
+            let message: String = message.chars().take(width as usize).collect();
+            write!(self.stdout, "\x1b[0m\x1b[2J\x1b[1;1H{}", message)?;
+            self.needs_clear = true;
+            return self.stdout.flush();
+        }
+
+        self.clear_buffers();
+
+        let theme = self.theme;
+        self.draw_text_centered(2, "Games on the local network", theme.text);
+        if hosts.is_empty() {
+            let message = if searching {
+                "Searching..."
+            } else {
+                "No games found yet, still looking"
+            };
+            self.draw_text_centered(self.rows / 2, message, theme.faint_text);
+        }
+        let lines: Vec<(String, Color)> = hosts
+            .iter()
+            .enumerate()
+            .map(|(i, host)| {
+                let marker = if i == selected { '>' } else { ' ' };
+                let mut line = format!(
+                    "{} {}'s {}  ({})",
+                    marker, host.announcement.player, host.announcement.game, host.address
+                );
+                let color = if !host.compatible() {
+                    line.push_str("  - different version");
+                    theme.faint_text
+                } else if i == selected {
+                    theme.highlight
+                } else {
+                    theme.text
+                };
+                (line, color)
+            })
+            .collect();
+        let widest = lines.iter().map(|(line, _)| line.chars().count()).max().unwrap_or(0);
+        let left = self.width.saturating_sub(widest as u16) / 2;
+        for (i, (line, color)) in lines.iter().enumerate() {
+            let y = 4 + i as u16;
+            if y + 3 >= self.rows {
+                break;
+            }
+            self.draw_text(left, y, line, *color);
+        }
+        self.draw_text_centered(
+            self.rows - 3,
+            "Up/Down to choose, Enter to join, Esc to quit",
+            theme.faint_text,
+        );
+
+        self.build_frame();
+        self.stdout.write_all(self.frame.as_bytes())?;
+        self.stdout.flush()
+    }
+
+    /// Draw the start menu: a `heading` over a list of `items` with the
+    /// `selected` one picked out, a `message` under them and a ball that's
+    /// been bouncing around behind it all for `elapsed` seconds
+    pub fn render_menu(
+        &mut self,
+        heading: &str,
+        items: &[String],
+        selected: usize,
+        message: &str,
+        elapsed: f32,
+    ) -> io::Result<()> {
+        let (width, height) = self.screen;
+        self.fit_to(width, height, 1);
+        if let Some((width, height)) = self.too_small {
+            let message = format!("Terminal too small ({}x{})", width, height + SPARE_ROWS);
+            let message: String = message.chars().take(width as usize).collect();
+            write!(self.stdout, "\x1b[0m\x1b[2J\x1b[1;1H{}", message)?;
+            self.needs_clear = true;
+            return self.stdout.flush();
+        }
+
+        self.clear_buffers();
+
+        let theme = self.theme;
+        // Back and forth between the borders, worked out from the time alone
+        // so a resize can't leave it outside them
+        let bounce = |distance: f32, span: f32| {
+            let span = span.max(1.0);
+            let along = distance % (2.0 * span);
+            if along > span {
+                2.0 * span - along
+            } else {
+                along
+            }
+        };
+        let ball_x = 1.0 + bounce(elapsed * MENU_BALL_SPEED.0, self.width as f32 - 3.0);
+        let ball_y = 1.0 + bounce(elapsed * MENU_BALL_SPEED.1, self.rows as f32 - 3.0);
+        self.put(ball_x as usize, ball_y as usize, theme.ball_glyph, theme.faint_text);
+
+        let top = (self.rows / 2).saturating_sub(items.len() as u16 / 2 + 3).max(1);
+        self.draw_text_centered(top, "D O S P O N G", theme.highlight);
+        self.draw_text_centered(top + 2, heading, theme.text);
+        let widest = items.iter().map(|item| item.chars().count()).max().unwrap_or(0);
+        let left = self.width.saturating_sub(widest as u16 + 2) / 2;
+        for (i, item) in items.iter().enumerate() {
+            let y = top + 4 + i as u16;
+            if y + 3 >= self.rows {
+                break;
+            }
+            if i == selected {
+                self.draw_text(left, y, &format!("> {}", item), theme.highlight);
+            } else {
+                self.draw_text(left + 2, y, item, theme.text);
+            }
+        }
+        let below = top + 5 + items.len() as u16;
+        if below + 3 < self.rows {
+            self.draw_text_centered(below, message, theme.alert);
+        }
+        self.draw_text_centered(
+            self.rows - 3,
+            "Up/Down to choose, Enter to pick, Esc to go back",
+            theme.faint_text,
+        );
+
+        self.build_frame();
+        self.stdout.write_all(self.frame.as_bytes())?;
+        self.stdout.flush()
+    }
+
+    /// Blank the status bar and the field, and draw the top and bottom
+    /// borders
+    fn clear_buffers(&mut self) {
+        let theme = self.theme;
+        self.status.fill((' ', theme.text));
+        for row in &mut self.buffer {
+            row.fill(' ');
+        }
+        for row in &mut self.color_buffer {
+            row.fill(theme.text);
+        }
+        // In half blocks the borders are as thin as a ball is tall
+        let border = if self.scale > 1 { '█' } else { '─' };
+        for y in [0, (self.height - 1) as usize] {
+            self.buffer[y].fill(border);
+            self.color_buffer[y].fill(theme.border);
+        }
+    }
+
+    /// Merge every `scale` rows of the field into a row of `cells`. Two
+    /// rows become half blocks wherever either has a block in it, and
+    /// anything else shows the top row's character unless that's blank.
+    fn compose(&mut self) {
+        let scale = self.scale as usize;
+        for (row, cells) in self.cells.iter_mut().enumerate() {
+            let top = row * scale;
+            let bottom = top + scale - 1;
+            for (x, cell) in cells.iter_mut().enumerate() {
+                let (upper, upper_color) = (self.buffer[top][x], self.color_buffer[top][x]);
+                let (lower, lower_color) = (self.buffer[bottom][x], self.color_buffer[bottom][x]);
+                let block = |ch| scale > 1 && matches!(ch, '█' | '▓' | '▒');
+                let (ch, fg, bg) = match (block(upper), block(lower)) {
+                    (true, true) if upper_color == lower_color => ('█', upper_color, None),
+                    (true, true) => ('▀', upper_color, Some(lower_color)),
+                    (true, false) => ('▀', upper_color, None),
+                    (false, true) => ('▄', lower_color, None),
+                    _ if upper == ' ' => (lower, lower_color, None),
+                    _ => (upper, upper_color, None),
+                };
+                *cell = Cell { ch, fg, bg };
+            }
+        }
+    }
+
+    /// Fill `frame` with the escape sequences that bring the terminal from
+    /// `prev_cells` to the field in `buffer`, and remember that as what's on
+    /// screen
+    fn build_frame(&mut self) {
+        self.compose();
+        // Build complete frame in a single string buffer before outputting
+        // This prevents tearing and ensures atomic screen updates
+        let frame = &mut self.frame;
+        frame.clear();
+        // Everything moves when the field shakes or stops shaking
+        let (origin_x, origin_y) = self.field_origin;
+        let full_repaint = self.needs_clear || self.field_origin != self.drawn_origin;
+        if self.needs_clear {
+            frame.push_str("\x1b[0m\x1b[2J");
+            self.needs_clear = false;
+        }
+
+        // The status bar is the top row of the screen, and the field goes
+        // under it unless it's shaking
+        let width = self.width as usize;
+        let mut colors = None;
+        if origin_y > 0 {
+            Self::diff_row(
+                frame,
+                0,
+                0,
+                width,
+                |x| {
+                    let (ch, fg) = self.status[x];
+                    Cell { ch, fg, bg: None }
+                },
+                |x| full_repaint || self.status[x] != self.prev_status[x],
+                &mut colors,
+            );
+        }
+        // Blank whatever part of the field's usual place it has moved off
+        let rows = self.rows as usize;
+        if self.field_origin != FIELD_ORIGIN {
+            frame.push_str("\x1b[0m");
+            colors = None;
+            let (usual_x, usual_y) = FIELD_ORIGIN;
+            for row in usual_y..usual_y + rows {
+                let covered = (origin_y..origin_y + rows).contains(&row);
+                let blank = if covered { origin_x - usual_x } else { width };
+                if blank > 0 {
+                    let _ = write!(frame, "\x1b[{};{}H", row + 1, usual_x + 1);
+                    frame.extend(std::iter::repeat_n(' ', blank));
+                }
+            }
+        }
+        for y in 0..rows {
+            Self::diff_row(
+                frame,
+                origin_y + y,
+                origin_x,
+                width,
+                |x| self.cells[y][x],
+                |x| full_repaint || self.cells[y][x] != self.prev_cells[y][x],
+                &mut colors,
+            );
+        }
+
+        self.drawn_origin = self.field_origin;
+        self.prev_status.copy_from_slice(&self.status);
+        for (prev, row) in self.prev_cells.iter_mut().zip(&self.cells) {
+            prev.copy_from_slice(row);
+        }
+    }
+
+    /// Add the cells of screen row `y` that differ from what's already on
+    /// screen to `frame`, grouped into runs so each run costs a single cursor
+    /// move. `cell` is what a column should show, with column 0 drawn at
+    /// screen column `origin_x`.
+    fn diff_row(
+        frame: &mut String,
+        y: usize,
+        origin_x: usize,
+        width: usize,
+        cell: impl Fn(usize) -> Cell,
+        changed: impl Fn(usize) -> bool,
+        colors: &mut Option<(Color, Option<Color>)>,
+    ) {
+        let mut x = 0;
+        while x < width {
+            if !changed(x) {
+                x += 1;
+                continue;
+            }
+
+            let mut end = x + 1;
+            let mut scan = end;
+            while scan < width && scan - end < MAX_RUN_GAP as usize {
+                if changed(scan) {
+                    end = scan + 1;
+                }
+                scan += 1;
+            }
+
+            let _ = write!(frame, "\x1b[{};{}H", y + 1, origin_x + x + 1); // Move to position
+            for x in x..end {
+                let Cell { ch, fg, bg } = cell(x);
+                let (current_fg, current_bg) = colors.unzip();
+                if current_fg != Some(fg) {
+                    frame.push_str(Self::color_to_ansi(fg));
+                }
+                if current_bg != Some(bg) {
+                    frame.push_str(bg.map_or("\x1b[49m", Self::background_to_ansi));
+                }
+                *colors = Some((fg, bg));
+                frame.push(ch);
+            }
+            x = end;
+        }
+    }
+
+    fn color_to_ansi(color: Color) -> &'static str {
+        match color {
+            Color::Black => "\x1b[30m",
+            Color::DarkGrey => "\x1b[90m",
+            Color::Red => "\x1b[31m",
+            Color::DarkRed => "\x1b[91m",
+            Color::Green => "\x1b[32m",
+            Color::DarkGreen => "\x1b[92m",
+            Color::Yellow => "\x1b[33m",
+            Color::DarkYellow => "\x1b[93m",
+            Color::Blue => "\x1b[34m",
+            Color::DarkBlue => "\x1b[94m",
+            Color::Magenta => "\x1b[35m",
+            Color::DarkMagenta => "\x1b[95m",
+            Color::Cyan => "\x1b[36m",
+            Color::DarkCyan => "\x1b[96m",
+            Color::White => "\x1b[37m",
+            Color::Grey => "\x1b[97m",
+            _ => "\x1b[37m",
+        }
+    }
+
+    fn background_to_ansi(color: Color) -> &'static str {
+        match color {
+            Color::Black => "\x1b[40m",
+            Color::DarkGrey => "\x1b[100m",
+            Color::Red => "\x1b[41m",
+            Color::DarkRed => "\x1b[101m",
+            Color::Green => "\x1b[42m",
+            Color::DarkGreen => "\x1b[102m",
+            Color::Yellow => "\x1b[43m",
+            Color::DarkYellow => "\x1b[103m",
+            Color::Blue => "\x1b[44m",
+            Color::DarkBlue => "\x1b[104m",
+            Color::Magenta => "\x1b[45m",
+            Color::DarkMagenta => "\x1b[105m",
+            Color::Cyan => "\x1b[46m",
+            Color::DarkCyan => "\x1b[106m",
+            Color::White => "\x1b[47m",
+            Color::Grey => "\x1b[107m",
+            _ => "\x1b[47m",
+        }
+    }
+
+    /// A box in the middle of a dimmed field with how the match is won, the
+    /// controls and what each powerup in play does
+    fn draw_help(&mut self, game: &Game) {
+        let title = " Help ";
+        let theme = self.theme;
+        let text = |line: &str| -> Vec<(char, Color)> {
+            line.chars().map(|ch| (ch, theme.text)).collect()
+        };
+        let mut lines = vec![text(&Self::win_condition(game)), Vec::new()];
+        lines.extend(self.help_lines.iter().map(|line| text(line)));
+        let enabled = &game.powerup_settings().enabled;
+        if !enabled.is_empty() {
+            lines.push(Vec::new());
+            let entries: Vec<_> = enabled
+                .iter()
+                .filter(|ptype| game.arcade || !ptype.effect().arcade_only())
+                .filter(|ptype| !game.breakout() || !ptype.effect().uses_middle())
+                .map(|&ptype| {
+                    let mut entry = vec![self.powerup_look(ptype), (' ', theme.text)];
+                    entry.extend(text(ptype.effect().description()));
+                    entry
+                })
+                .collect();
+            // Two columns of powerups when one is too tall for the field
+            if lines.len() + entries.len() + 2 > self.rows as usize {
+                let half = entries.len().div_ceil(2);
+                let column = entries[..half].iter().map(Vec::len).max().unwrap_or(0) + 3;
+                for (i, left) in entries[..half].iter().enumerate() {
+                    let mut line = left.clone();
+                    if let Some(right) = entries.get(half + i) {
+                        line.resize(column, (' ', theme.text));
+                        line.extend(right);
+                    }
+                    lines.push(line);
+                }
+            } else {
+                lines.extend(entries);
+            }
+        }
+
+        for row in &mut self.color_buffer {
+            row.fill(theme.faint_text);
+        }
+        self.draw_box(title, &lines);
+    }
+
+    /// The settings in a box in the middle of the field, with the one being
+    /// changed picked out
+    fn draw_settings(&mut self) {
+        let Some(settings) = &self.settings else {
+            return;
+        };
+        let theme = self.theme;
+        let mut lines: Vec<Vec<(char, Color)>> = settings
+            .lines()
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let (marker, color) = if i == settings.selected {
+                    ('>', theme.highlight)
+                } else {
+                    (' ', theme.text)
+                };
+                format!("{} {}", marker, line).chars().map(|ch| (ch, color)).collect()
+            })
+            .collect();
+        lines.push(Vec::new());
+        let hint = if settings.message.is_empty() {
+            "Left/Right to change, Esc to go back"
+        } else {
+            &settings.message
+        };
+        lines.push(hint.chars().map(|ch| (ch, theme.faint_text)).collect());
+        self.draw_box(" Settings ", &lines);
+    }
+
+    /// A box with `title` on its top border, centered on the field, around
+    /// `lines` of colored characters. Whatever doesn't fit is cut off.
+    fn draw_box(&mut self, title: &str, lines: &[Vec<(char, Color)>]) {
+        let inner_width = lines
+            .iter()
+            .map(Vec::len)
+            .max()
+            .unwrap_or(0)
+            .max(title.chars().count());
+        let box_width = (inner_width + 4).min(self.width as usize);
+        let box_height = (lines.len() + 2).min(self.rows as usize);
+        let left = (self.width as usize - box_width) / 2;
+        let top = (self.rows as usize - box_height) / 2;
+        for row in 0..box_height {
+            let y = top + row;
+            for col in 0..box_width {
+                let x = left + col;
+                let last_row = row + 1 == box_height;
+                let last_col = col + 1 == box_width;
+                let ch = match (row, col) {
+                    (0, 0) => '┌',
+                    (0, _) if last_col => '┐',
+                    (_, 0) if last_row => '└',
+                    _ if last_row && last_col => '┘',
+                    (0, _) => '─',
+                    _ if last_row => '─',
+                    (_, 0) => '│',
+                    _ if last_col => '│',
+                    _ => ' ',
+                };
+                self.put(x, y, ch, self.theme.text);
+            }
+        }
+
+        for (i, ch) in title.chars().enumerate() {
+            let x = left + 2 + i;
+            if x + 1 < left + box_width {
+                self.put(x, top, ch, self.theme.text);
+            }
+        }
+        for (row, line) in lines.iter().enumerate() {
+            let y = top + 1 + row;
+            if y + 1 >= top + box_height {
+                break;
+            }
+            for (i, &(ch, color)) in line.iter().enumerate() {
+                let x = left + 2 + i;
+                if x + 2 > left + box_width {
+                    break;
+                }
+                self.put(x, y, ch, color);
+            }
+        }
+    }
+
+    /// How the match being played is won, as the help box's first line
+    fn win_condition(game: &Game) -> String {
+        if game.practice.is_some() {
+            "Hit the green target again and again to build a streak".to_string()
+        } else if game.survival.is_some() {
+            format!("Return as many balls as you can with {} lives", SURVIVAL_LIVES)
+        } else if let Some(timed) = &game.timed {
+            let seconds = timed.limit.as_secs();
+            format!(
+                "Most points after {}:{:02} wins, or the next point on a tie",
+                seconds / 60,
+                seconds % 60
+            )
+        } else if game.best_of > 1 {
+            format!(
+                "First to {} points takes a set, best of {} sets wins",
+                game.score_limit, game.best_of
+            )
+        } else if game.deuce && game.score_limit > 1 && !game.four_player() {
+            let deuce = game.score_limit - 1;
+            format!("First to {} points wins, by two from {}-{}", game.score_limit, deuce, deuce)
+        } else {
+            format!("First to {} points wins", game.score_limit)
+        }
+    }
+
+    /// The frame timings and what's on the field, on a blank patch in the
+    /// top left corner so they stay readable however busy the field is
+    fn draw_debug(&mut self, game: &Game) {
+        let stats = &self.frame_stats;
+        let fps = |seconds: f32| if seconds > 0.0 { 1.0 / seconds } else { 0.0 };
+        let ms = |time: Duration| time.as_secs_f32() * 1000.0;
+        // Across the field, the way the speed limit goes
+        let ball_speed = game.balls.iter().map(|ball| ball.vel.x.abs()).fold(0.0, f32::max);
+        let mut lines = vec![
+            format!("FPS {:.0} (avg {:.1})", fps(stats.frame), fps(stats.average_frame)),
+            format!("frame {:.2} ms (avg {:.2})", stats.frame * 1e3, stats.average_frame * 1e3),
+            format!("update {:.2} ms", ms(stats.update)),
+            format!("render {:.2} ms", ms(stats.render)),
+            format!("wrote {} bytes", self.frame_bytes),
+            format!("balls {}  powerups {}", game.balls.len(), game.active_powerups.len()),
+            format!("ball speed {:.2} of {:.2}", ball_speed, game.speed_limit()),
+        ];
+        lines.extend(self.bot_stats.clone());
+        let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) + 2;
+        let color = self.theme.text;
+        for (i, line) in lines.iter().enumerate() {
+            let y = 1 + i as u16;
+            if y + 1 >= self.rows {
+                break;
+            }
+            self.draw_text(1, y, &" ".repeat(width), color);
+            self.draw_text(2, y, line, color);
+        }
+    }
+
+    /// What the collision checks see, over everything else on the field:
+    /// the paddle and wall cells a ball bounces off, each powerup's pickup
+    /// area and where each ball is heading over the next few frames
+    fn draw_hitboxes(&mut self, game: &Game) {
+        let theme = self.theme;
+        let mut mark = |x: i16, y: i16, ch: Option<char>, color: Color| {
+            if x < 0 || y < 0 || x >= self.width as i16 || y >= self.height as i16 {
+                return;
+            }
+            let (x, y) = (x as usize, y as usize);
+            // Cells already drawn keep what's in them and only change color
+            match ch {
+                Some(ch) => self.buffer[y][x] = ch,
+                None if self.buffer[y][x] == ' ' => self.buffer[y][x] = '░',
+                None => {}
+            }
+            self.color_buffer[y][x] = color;
+        };
+
+        for (x, y) in game.paddle_cells() {
+            mark(x, y, None, theme.highlight);
+        }
+        let mut walls: Vec<(u16, u16, u16)> =
+            game.walls.iter().map(|wall| (wall.x, wall.y, wall.height)).collect();
+        if game.has_wall() {
+            walls.push((game.wall_x(), 0, self.height));
+        }
+        for (x, top, height) in walls {
+            for y in top..top + height {
+                mark(x as i16, y as i16, None, theme.alert);
+            }
+        }
+
+        let half = POWERUP_SIZE as i16 / 2;
+        for powerup in &game.powerups {
+            let (x, y) = (powerup.x as i16, powerup.y as i16);
+            for d in -half + 1..half {
+                mark(x + d, y - half, Some('─'), theme.faint_text);
+                mark(x + d, y + half, Some('─'), theme.faint_text);
+                mark(x - half, y + d, Some('│'), theme.faint_text);
+                mark(x + half, y + d, Some('│'), theme.faint_text);
+            }
+            mark(x - half, y - half, Some('┌'), theme.faint_text);
+            mark(x + half, y - half, Some('┐'), theme.faint_text);
+            mark(x - half, y + half, Some('└'), theme.faint_text);
+            mark(x + half, y + half, Some('┘'), theme.faint_text);
+        }
+
+        // The velocities are in cells per frame at 60 frames a second
+        let scale = game.ball_speed_scale() * FRAMES_PER_DOT;
+        for ball in game.balls.iter().filter(|b| b.stuck_to.is_none()) {
+            for dot in 1..=VELOCITY_DOTS {
+                let ahead = scale * dot as f32;
+                let Vec2 { x, y } = ball.pos + ball.vel * ahead;
+                mark(x.floor() as i16, y.floor() as i16, Some('·'), theme.text);
+            }
+        }
+    }
+
+    /// The event feed, newest at the bottom and fading as it gets older.
+    /// It keeps to the left half, between the paddle and the middle, and
+    /// above the bottom paddle in four-player games. Chat goes in with the
+    /// game's events, and still shows while they're hidden.
+    fn draw_feed(&mut self, game: &Game) {
+        let start = 4;
+        let room = (self.width / 2).saturating_sub(start + 2).min(FEED_WIDTH) as usize;
+        let lowest = if game.four_player() { 3 } else { 2 };
+        let Some(bottom) = self.rows.checked_sub(lowest) else {
+            return;
+        };
+        let theme = self.theme;
+        // Chat can go past the middle, so a whole message fits
+        let chat_room = self.width.saturating_sub(start + 2).min(CHAT_WIDTH) as usize;
+        let mut entries: Vec<(Duration, String, usize)> = match self.show_feed {
+            true => game.feed.iter().map(|(age, text)| (*age, text.clone(), room)).collect(),
+            false => Vec::new(),
+        };
+        let chat = self.chat.iter().map(|(said, line)| (said.elapsed(), line.clone(), chat_room));
+        entries.extend(chat);
+        entries.sort_by_key(|&(age, ..)| std::cmp::Reverse(age));
+        let newest_first: Vec<_> = entries.into_iter().rev().take(FEED_LENGTH).collect();
+        for (i, (age, text, room)) in newest_first.into_iter().enumerate() {
+            let Some(y) = bottom.checked_sub(i as u16).filter(|&y| y > 0) else {
+                break;
+            };
+            let color = match age.as_secs_f32() / FEED_LIFETIME.as_secs_f32() {
+                share if share < 0.4 => theme.text,
+                share if share < 0.7 => theme.dim_text,
+                _ => theme.faint_text,
+            };
+            let clipped: String = text.chars().take(room).collect();
+            self.draw_text(start, y, &clipped, color);
+        }
+    }
+
+    /// Whether the points go on the field in big digits. Modes with more
+    /// or fewer than two scores keep them on the status bar.
+    fn shows_big_score(&self, game: &Game) -> bool {
+        self.big_score
+            && !game.four_player()
+            && game.practice.is_none()
+            && game.survival.is_none()
+    }
+
+    /// `value` in big digits side by side, centered on `center_x`
+    fn draw_big_number(&mut self, center_x: u16, y: u16, value: u16, color: Color) {
+        let digits: Vec<usize> = value
+            .to_string()
+            .chars()
+            .filter_map(|c| c.to_digit(10))
+            .map(|d| d as usize)
+            .collect();
+        let width = digits.len() as u16 * 4 - 1;
+        let x = center_x.saturating_sub(width / 2);
+        for (i, &digit) in digits.iter().enumerate() {
+            self.draw_big_digit(x + i as u16 * 4, y, digit, color);
+        }
+    }
+
+    fn draw_big_digit(&mut self, x: u16, y: u16, digit: usize, color: Color) {
+        for (dy, row) in BIG_DIGITS[digit].iter().enumerate() {
+            for (dx, cell) in row.chars().enumerate() {
+                let px = x as usize + dx;
+                let py = y as usize + dy;
+                if cell != ' ' && py < self.rows as usize && px < self.width as usize {
+                    self.put(px, py, '█', color);
+                }
+            }
+        }
+    }
+
+    /// The symbol and color a powerup is shown with, on the field and in
+    /// the list of active effects
+    fn powerup_look(&self, ptype: PowerUpType) -> (char, Color) {
+        let (symbol, color) = match ptype {
+            PowerUpType::Custom(index) => {
+                let look = self.custom_looks.get(index as usize);
+                look.copied().unwrap_or(('?', Color::White))
+            }
+            ptype => {
+                let index = PowerUpType::ALL.iter().position(|&p| p == ptype);
+                index.map_or(('?', Color::White), |i| self.builtin_looks[i])
+            }
+        };
+        (symbol, self.theme.powerups.unwrap_or(color))
+    }
+
+    /// The match's numbers in a small table under the game over message
+    fn draw_stats(&mut self, game: &Game) {
+        let stats = &game.stats;
+        let seconds = stats.duration.as_secs();
+        let best = |new: bool| if new { " — NEW BEST" } else { "" };
+        // A column per paddle, named for whoever is on it
+        let paddles = if game.four_player() { 4 } else { 2 };
+        let row = |label: &str, values: &[u32; 4]| {
+            let mut line = format!("{:<14}", label);
+            for value in &values[..paddles] {
+                let _ = write!(line, "{:>6}", value);
+            }
+            line
+        };
+        let mut header = format!("{:<14}", "");
+        for side in 1..=paddles as u8 {
+            // As much of the name as fits the column
+            let name: String = game.name(game.player_at(side)).chars().take(5).collect();
+            let _ = write!(header, "{:>6}", name);
+        }
+        let lines = [
+            header,
+            row("Hits", &stats.hits),
+            row("Powerups", &stats.powerups),
+            format!(
+                "{:<14}{:>12}{}",
+                "Longest rally",
+                stats.longest_rally,
+                best(self.new_bests.rally)
+            ),
+            format!(
+                "{:<14}{:>12.1}{}",
+                "Fastest ball",
+                stats.top_speed,
+                best(self.new_bests.speed)
+            ),
+            format!("{:<14}{:>12}", "Match time", format!("{}:{:02}", seconds / 60, seconds % 60)),
+        ];
+        // Lined up on the table's left edge, so a record mark doesn't push
+        // its row out of line
+        let x = self.width.saturating_sub(26) / 2;
+        for (i, line) in lines.iter().enumerate() {
+            let y = self.rows / 2 + 2 + i as u16;
+            if y + 1 >= self.rows {
+                break;
+            }
+            let color = if line.ends_with("NEW BEST") {
+                self.theme.highlight
+            } else {
+                self.theme.dim_text
+            };
+            self.draw_text(x, y, line, color);
+        }
+    }
+
+    /// Active powerups as their symbol, or their label in colorblind mode,
+    /// and the seconds they have left, as many as fit in `room` columns,
+    /// with '…' standing in for the rest, added to the end of `cells`
+    fn effect_cells<'a>(
+        &self,
+        effects: impl Iterator<Item = &'a ActivePowerUp>,
+        room: usize,
+        cells: &mut Vec<(char, Color)>,
+    ) {
+        let dim = self.theme.dim_text;
+        let start = cells.len();
+        let mut effects = effects.peekable();
+        while let Some(effect) = effects.next() {
+            let (symbol, color) = self.powerup_look(effect.ptype);
+            let seconds = effect.remaining.as_secs_f32().ceil() as u64;
+            let label = effect.ptype.effect().label();
+            let digits = seconds.checked_ilog10().unwrap_or(0) as usize + 1;
+            let entry_len = match self.colorblind {
+                true => label.chars().count() + 1 + digits,
+                false => 1 + digits,
+            };
+            let used = cells.len() - start;
+            let gap = (used > 0) as usize;
+            // Keep space for the '…' while there are more to come
+            let reserve = if effects.peek().is_some() { 2 } else { 0 };
+            if used + gap + entry_len + reserve > room {
+                if used + gap < room {
+                    if gap == 1 {
+                        cells.push((' ', dim));
+                    }
+                    cells.push(('…', dim));
+                }
+                break;
+            }
+            if gap == 1 {
+                cells.push((' ', dim));
+            }
+            if self.colorblind {
+                cells.extend(label.chars().map(|c| (c, self.theme.text)));
+                cells.push((':', dim));
+            } else {
+                cells.push((symbol, color));
+            }
+            let _ = write!(CellWriter { cells, color: dim }, "{}", seconds);
+        }
+    }
+
+    /// Ask the terminal to pop up a desktop notification saying `text`, in
+    /// both of the escapes terminals use for it, since the ones that know
+    /// neither ignore them. Nothing is sent when the game isn't being drawn
+    /// on a terminal at all.
+    pub fn send_notification(&mut self, text: &str) -> io::Result<()> {
+        let on_terminal = match ON_STDERR.load(Ordering::SeqCst) {
+            true => io::stderr().is_terminal(),
+            false => io::stdout().is_terminal(),
+        };
+        if !self.notify || !on_terminal {
+            return Ok(());
+        }
+        // A name with control characters in it would end the escape early
+        let text: String = text.chars().filter(|c| !c.is_control()).collect();
+        write!(self.stdout, "\x1b]9;{}\x07\x1b]777;notify;DOSPong;{}\x07", text, text)?;
+        self.stdout.flush()
+    }
+
+    /// Put the score in the terminal's title, only when it changes since
+    /// some terminals keep a log of every title they're sent
+    fn show_title(&mut self, game: &Game) -> io::Result<()> {
+        // Worked out in a buffer kept for it, so an unchanged title costs
+        // nothing
+        let mut title = std::mem::take(&mut self.title_text);
+        title.clear();
+        title.push_str("DOSPong");
+        if game.practice.is_none() && game.survival.is_none() {
+            let name = |side| Label::name(game, game.player_at(side));
+            let scores = (game.p1_score, game.p2_score);
+            let _ = write!(title, " — {} {} : {} {}", name(1), scores.0, scores.1, name(2));
+        }
+        let changed = self.title.as_deref() != Some(title.as_str());
+        if changed {
+            if !TITLE_CHANGED.swap(true, Ordering::SeqCst) {
+                write!(self.stdout, "{}", PUSH_TITLE)?;
+            }
+            execute!(self.stdout, SetTitle(&title))?;
+            self.title = Some(title.clone());
+        }
+        self.title_text = title;
+        Ok(())
+    }
+
+    /// The status bar's score, with each player's name or, short of room,
+    /// their number, an "(H)" for one with a handicap and a "×2" for one
+    /// whose next point counts double, added to the end of `cells`
+    fn score_cells(&self, game: &Game, named: bool, cells: &mut Vec<(char, Color)>) {
+        let label = |player| Label {
+            game,
+            player,
+            named,
+            marks: true,
+        };
+        let mut score = CellWriter {
+            cells,
+            color: self.theme.text,
+        };
+        if game.best_of > 1 {
+            let _ = write!(score, "Sets {}-{} · ", game.p1_sets, game.p2_sets);
+        }
+        if let Some(practice) = &game.practice {
+            let _ = write!(
+                score,
+                "PRACTICE  Streak {}  Best {}",
+                practice.streak, practice.best_streak
+            );
+        } else if let Some(survival) = &game.survival {
+            let _ = score.write_str("SURVIVAL  ");
+            for i in 0..SURVIVAL_LIVES {
+                let _ = score.write_char(if i < survival.lives { '♥' } else { '♡' });
+            }
+            let (returns, level) = (survival.returns, survival.level() + 1);
+            let _ = write!(score, "  Returns {}  Level {}", returns, level);
+        } else if self.shows_big_score(game) {
+            // The points are on the field, so only say who's where
+            let _ = write!(score, "{}  vs  {}", label(game.player_at(1)), label(game.player_at(2)));
+        } else {
+            let _ = write!(
+                score,
+                "{}: {}  {}: {}",
+                label(game.player_at(1)),
+                game.p1_score,
+                label(game.player_at(2)),
+                game.p2_score
+            );
+        }
+        if game.four_player() {
+            let _ = write!(score, "  P3: {}  P4: {}", game.p3_score, game.p4_score);
+        }
+        if let Some(side) = game.advantage() {
+            let name = Label {
+                marks: false,
+                ..label(game.player_at(side))
+            };
+            let _ = write!(score, "  ADVANTAGE {}", name);
+        } else if game.in_deuce() {
+            let _ = score.write_str("  DEUCE");
+        }
+        if let Some(difficulty) = game.ai {
+            let _ = write!(score, "  (CPU {})", difficulty.name());
+        }
+        if game.stats.rally > 1 {
+            let _ = write!(score, "  Rally {}", game.stats.rally);
+        }
+        // What the rally would be worth to whoever wins it, going up as it
+        // goes on
+        if game.arcade {
+            let _ = write!(score, "  Worth {}", game.rally_points());
+        }
+        if let Some(wind) = &game.wind {
+            let _ = write!(score, "  Wind {}", Self::wind_arrow(wind.force));
+        }
+    }
+
+    /// A timed match's clock as M:SS, rounded up so it only shows 0:00 once
+    /// time is up, and then OVERTIME while a tie waits for the next point,
+    /// added to the end of `cells`
+    fn clock_cells(&self, timed: &Timed, cells: &mut Vec<(char, Color)>) {
+        let alert = self.theme.alert;
+        if timed.overtime {
+            cells.extend("OVERTIME  ".chars().map(|c| (c, alert)));
+            return;
+        }
+        let seconds = timed.left.as_millis().div_ceil(1000);
+        let color = match timed.left >= CLOCK_WARNING {
+            true => self.theme.text,
+            false => alert,
+        };
+        let start = cells.len();
+        let _ = write!(CellWriter { cells, color }, "{}:{:02}  ", seconds / 60, seconds % 60);
+        // Blinking along with the match time, so it holds still when paused
+        if timed.left < CLOCK_WARNING && timed.left.subsec_millis() < 500 {
+            cells[start..].fill((' ', alert));
+        }
+    }
+
+    /// Which of the eight ways the wind is blowing, rows going down the
+    /// screen, or a dot while it's all but still
+    fn wind_arrow(force: Vec2) -> char {
+        const ARROWS: [char; 8] = ['→', '↘', '↓', '↙', '←', '↖', '↑', '↗'];
+        if force.length() < 0.0005 {
+            return '·';
+        }
+        let eighth = (force.angle() / std::f32::consts::FRAC_PI_4).round() as i32;
+        ARROWS[eighth.rem_euclid(8) as usize]
+    }
+
+    /// The round trip time and up to three bars for how good it is, or a
+    /// red "!" in place of the bars while pings are going missing
+    fn quality_cells(&self, quality: LinkQuality, cells: &mut Vec<(char, Color)>) {
+        let dim = self.theme.dim_text;
+        let mut rtt = CellWriter { cells, color: dim };
+        let _ = match quality.rtt {
+            Some(time) => write!(rtt, "{}ms ", time.as_millis()),
+            None => rtt.write_str("--ms "),
+        };
+        if quality.loss > MAX_PING_LOSS {
+            cells.push(('!', self.theme.alert));
+            return;
+        }
+        let bars = match quality.rtt {
+            Some(rtt) if rtt < GOOD_RTT => 3,
+            Some(rtt) if rtt < FAIR_RTT => 2,
+            Some(_) => 1,
+            None => 0,
+        };
+        for (i, bar) in ['▂', '▄', '▆'].into_iter().enumerate() {
+            let color = if i < bars { self.theme.text } else { self.theme.faint_text };
+            cells.push((bar, color));
+        }
+    }
+
+    fn draw_status(&mut self, start_x: usize, cells: &[(char, Color)]) {
+        for (slot, &cell) in self.status.iter_mut().skip(start_x).zip(cells) {
+            *slot = cell;
+        }
+    }
+
+    fn draw_text_centered(&mut self, y: u16, text: &str, color: Color) {
+        let len = text.chars().count() as u16;
+        self.draw_text(self.width.saturating_sub(len) / 2, y, text, color);
+    }
+
+    /// Text goes on rows of the screen rather than of the field, so it
+    /// stays readable whatever scale the field is drawn at
+    fn draw_text(&mut self, start_x: u16, y: u16, text: &str, color: Color) {
+        if y >= self.rows {
+            return;
+        }
+        for (i, ch) in text.chars().enumerate() {
+            let x = start_x + i as u16;
+            if x >= self.width {
+                break;
+            }
+            self.put(x as usize, y as usize, ch, color);
+        }
+    }
+
+    /// A row across the middle of the screen with `text` on it
+    fn draw_banner(&mut self, text: &str, fill: Color, color: Color) {
+        let y = self.rows as usize / 2;
+        for x in 0..self.width as usize {
+            self.put(x, y, '░', fill);
+        }
+        self.draw_text_centered(y as u16, text, color);
+    }
+
+    /// A ball as a square of 2x2 braille dots. A cell has 2x4 dots, so the
+    /// ball moves in half columns and quarter rows, and can spread over up
+    /// to four cells. Balls sharing a cell share its character. Only empty
+    /// cells, the center line and the score flash are drawn over.
+    fn draw_braille_ball(&mut self, x: f32, y: f32, color: Color) {
+        let (dot_x, dot_y) = ((x * 2.0).floor() as i32, (y * 4.0).floor() as i32);
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let (px, py) = (dot_x + dx, dot_y + dy);
+            let (cell_x, cell_y) = (px.div_euclid(2) as usize, py.div_euclid(4) as usize);
+            if px < 0 || py < 0 || cell_x >= self.width as usize || cell_y >= self.height as usize {
+                continue;
+            }
+            let current = self.buffer[cell_y][cell_x];
+            let dots = match current {
+                ' ' | '┊' | '░' => 0,
+                '\u{2800}'..='\u{28FF}' => current as u32 - 0x2800,
+                _ => continue,
+            };
+            let dot = BRAILLE_DOTS[(py % 4) as usize][(px % 2) as usize];
+            self.buffer[cell_y][cell_x] = char::from_u32(0x2800 | dots | dot).unwrap_or(current);
+            self.color_buffer[cell_y][cell_x] = color;
+        }
+    }
+
+    /// Draw `ch` over every row of the field that goes on screen row `y`
+    fn put(&mut self, x: usize, y: usize, ch: char, color: Color) {
+        let scale = self.scale as usize;
+        for row in y * scale..(y + 1) * scale {
+            self.buffer[row][x] = ch;
+            self.color_buffer[row][x] = color;
+        }
+    }
+
+    /// A ❄ just above a frozen paddle, or below it when it's against the top
+    /// The marker and color for a paddle an opponent's debuff is holding.
+    /// A frozen paddle can't move at all, so that's what gets shown.
+    /// The main and second paddle colors for a player, whichever side
+    /// they're on
+    fn player_colors(&self, player: u8) -> (Color, Color) {
+        self.theme.players[(player.clamp(1, 4) - 1) as usize]
+    }
+
+    fn paddle_status(&self, game: &Game, player: u8) -> Option<(char, Color)> {
+        if game.is_frozen(player) {
+            Some(('❄', self.powerup_look(PowerUpType::Freeze).1))
+        } else if game.controls_reversed(player) {
+            Some(('↕', self.theme.highlight))
+        } else {
+            None
+        }
+    }
+
+    fn draw_paddle_marker(
+        &mut self,
+        x: u16,
+        paddle_y: i16,
+        paddle_height: u16,
+        marker: char,
+        color: Color,
+    ) {
+        let y = if paddle_y > 1 { paddle_y - 1 } else { paddle_y + paddle_height as i16 };
+        if y > 0 && y < self.height as i16 - 1 && x < self.width {
+            self.buffer[y as usize][x as usize] = marker;
+            self.color_buffer[y as usize][x as usize] = color;
+        }
+    }
+
+    /// A bar behind a side paddle filling up from the bottom as it charges
+    /// a smash, lit up once it's full
+    fn draw_charge_bar(&mut self, game: &Game, side: u8, x: u16) {
+        let charge = game.charge[side as usize - 1];
+        if charge.is_zero() || x >= self.width {
+            return;
+        }
+        let (y, height) = match side {
+            1 => (game.p1_y, game.p1_height),
+            _ => (game.p2_y, game.p2_height),
+        };
+        let full = charge >= SMASH_CHARGE;
+        let share = charge.as_secs_f32() / SMASH_CHARGE.as_secs_f32();
+        let cells = (share * height as f32).ceil() as i16;
+        let color = match full {
+            true => self.theme.highlight,
+            false => self.player_colors(game.player_at(side)).0,
+        };
+        let bottom = y + height as i16 - 1;
+        let inside = |row: &i16| *row > 0 && *row < self.height as i16 - 1;
+        for row in (bottom - cells + 1..=bottom).filter(inside) {
+            self.buffer[row as usize][x as usize] = if side == 1 { '▐' } else { '▌' };
+            self.color_buffer[row as usize][x as usize] = color;
+        }
+    }
+
+    /// A pip behind a side paddle while it waits to dash again
+    fn draw_dash_pip(&mut self, game: &Game, side: u8, x: u16) {
+        if game.dash_cooldown[side as usize - 1].is_zero() {
+            return;
+        }
+        let (y, height) = match side {
+            1 => (game.p1_y, game.p1_height),
+            _ => (game.p2_y, game.p2_height),
+        };
+        let y = y + height as i16 / 2;
+        if y > 0 && y < self.height as i16 - 1 && x < self.width {
+            self.buffer[y as usize][x as usize] = '·';
+            self.color_buffer[y as usize][x as usize] = self.player_colors(game.player_at(side)).0;
+        }
+    }
+
+    /// What a player's paddle is drawn with. In colorblind mode each
+    /// player's paddle has its own half block, so they can be told apart
+    /// without their colors.
+    fn paddle_glyph(&self, player: u8) -> char {
+        if !self.colorblind {
+            return self.theme.paddle_glyph;
+        }
+        match player {
+            1 => '▌',
+            2 => '▐',
+            3 => '▀',
+            _ => '▄',
+        }
+    }
+
+    /// Draw a plain paddle along either axis
+    fn draw_edge_paddle(&mut self, paddle: &Paddle, color: Color) {
+        let (player, line, pos, len) = (paddle.player, paddle.line, paddle.pos, paddle.len);
+        match paddle.axis {
+            Axis::Vertical => self.draw_paddle(player, line, pos, len, false, color),
+            Axis::Horizontal => {
+                let glyph = self.paddle_glyph(player);
+                let y = paddle.line as usize;
+                for i in 0..paddle.len {
+                    let x = paddle.pos + i as i16;
+                    if x >= 0 && x < self.width as i16 && y < self.height as usize {
+                        self.buffer[y][x as usize] = glyph;
+                        self.color_buffer[y][x as usize] = color;
+                    }
+                }
+            }
+        }
+    }
+
+    fn draw_paddle(&mut self, player: u8, x: u16, y: i16, height: u16, bent: bool, color: Color) {
+        let glyph = self.paddle_glyph(player);
+        if bent {
+            // Bent paddle: <>, with the sweet spot at its tip picked out
+            let tip = (height.max(1) - 1) / 2;
+            for i in 0..height {
+                let py = y + i as i16;
+                if py >= 0 && py < self.height as i16 {
+                    let offset = if i < height / 2 { i } else { height - i - 1 };
+                    let px = x + offset;
+                    if px < self.width {
+                        self.buffer[py as usize][px as usize] = glyph;
+                        self.color_buffer[py as usize][px as usize] =
+                            if offset == tip { self.theme.flash } else { color };
+                    }
+                }
+            }
+        } else {
+            // Normal paddle
+            for i in 0..height {
+                let py = y + i as i16;
+                if py >= 0 && py < self.height as i16 && x < self.width {
+                    self.buffer[py as usize][x as usize] = glyph;
+                    self.color_buffer[py as usize][x as usize] = color;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dospong::pong::{AiDifficulty, Input};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Whatever the frontend writes to its terminal, kept to look at
+    #[derive(Clone, Default)]
+    struct Written(Rc<RefCell<Vec<u8>>>);
+
+    impl Written {
+        /// How many bytes have been written since last time, keeping the
+        /// room they took for next time
+        fn take(&self) -> usize {
+            let mut written = self.0.borrow_mut();
+            let len = written.len();
+            written.clear();
+            len
+        }
+    }
+
+    impl Write for Written {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A frontend with no terminal behind it, the size `game` needs
+    fn offscreen(game: &Game) -> (TerminalFrontend, Written) {
+        let written = Written::default();
+        let size = (game.width, game.height + SPARE_ROWS);
+        let out = Box::new(written.clone());
+        let frontend = TerminalFrontend::new(out, TerminalGuard, None, false, size);
+        (frontend, written)
+    }
+
+    /// The system allocator, counting what this thread asks of it
+    struct Counting;
+
+    thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    fn count_allocation() {
+        // Not there while the thread is being torn down, when nothing is
+        // being measured anyway
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            count_allocation();
+            unsafe { std::alloc::System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            unsafe { std::alloc::System.dealloc(ptr, layout) }
+        }
+
+        unsafe fn realloc(
+            &self,
+            ptr: *mut u8,
+            layout: std::alloc::Layout,
+            size: usize,
+        ) -> *mut u8 {
+            count_allocation();
+            unsafe { std::alloc::System.realloc(ptr, layout, size) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    #[test]
+    fn frames_dont_allocate() {
+        let mut game = Game::new(80, 24, 1);
+        game.active_powerups.push(ActivePowerUp {
+            ptype: PowerUpType::SlowBall,
+            player: 1,
+            remaining: Duration::from_secs(5),
+        });
+        let (mut frontend, written) = offscreen(&game);
+        frontend.live_title = true;
+        // The first frames size the buffers
+        for _ in 0..2 {
+            frontend.render(&game, GameState::Playing).unwrap();
+        }
+        written.take();
+
+        for _ in 0..20 {
+            let ball = &mut game.balls[0];
+            ball.prev = ball.pos;
+            ball.pos.x += 1.0;
+            let before = ALLOCATIONS.with(|count| count.get());
+            frontend.render(&game, GameState::Playing).unwrap();
+            let allocations = ALLOCATIONS.with(|count| count.get()) - before;
+            assert_eq!(allocations, 0, "a frame allocated {} times", allocations);
+            written.take();
+        }
+    }
+
+    /// Far smaller than the game will start on, which nothing along the
+    /// way should underflow or index past the end on
+    #[test]
+    fn a_tiny_field_plays_and_draws() {
+        for seed in 0..10 {
+            let mut game = Game::new(10, 5, seed);
+            game.ai = Some(AiDifficulty::Hard);
+            game.left_ai = Some(AiDifficulty::Easy);
+            let (mut frontend, written) = offscreen(&game);
+            frontend.half_block = seed % 3 == 1;
+            frontend.braille = seed % 3 == 2;
+            for _ in 0..3000 {
+                game.step(Input::default(), 1.0 / 60.0);
+                frontend.render(&game, GameState::of(&game)).unwrap();
+            }
+            assert!(written.take() > 0);
+        }
+    }
+
+    #[test]
+    fn frames_only_write_what_changed() {
+        let mut game = Game::new(80, 24, 1);
+        let (mut frontend, written) = offscreen(&game);
+        let mut frame = |game: &Game| {
+            frontend.render(game, GameState::Serving).unwrap();
+            written.take()
+        };
+
+        let full = frame(&game);
+        let unchanged = frame(&game);
+        let ball = &mut game.balls[0];
+        ball.pos.y += 3.0;
+        ball.prev = ball.pos;
+        let one_ball_moved = frame(&game);
+
+        // Every cell of the field at least once, a byte or more each
+        assert!(full >= 80 * 24, "a full repaint took {} bytes", full);
+        assert_eq!(unchanged, 0);
+        // Where the ball was and where it is, with a cursor move and colors
+        // for each
+        assert!(
+            one_ball_moved > 0 && one_ball_moved <= 64,
+            "moving one ball took {} bytes",
+            one_ball_moved
+        );
+    }
+}
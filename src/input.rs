@@ -0,0 +1,71 @@
+//! Reading terminal events on a thread of their own, so a read that takes
+//! longer than it should holds up nothing but that thread.
+
+use crossterm::event::{self, Event};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Events that can wait to be handled before the reader holds off. A full
+// channel makes the reader wait rather than drop any, and the main loop
+// empties it every frame.
+const CAPACITY: usize = 256;
+// How often the reader checks whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+pub struct EventReader {
+    events: Receiver<io::Result<Event>>,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl EventReader {
+    pub fn start() -> Self {
+        let (sender, events) = mpsc::sync_channel(CAPACITY);
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopping = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            while !stopping.load(Ordering::Relaxed) {
+                let event = match event::poll(POLL_INTERVAL) {
+                    Ok(false) => continue,
+                    Ok(true) => event::read(),
+                    Err(err) => Err(err),
+                };
+                // Reading can't go on after an error, and there's nobody
+                // to send to once the receiver is gone
+                let failed = event.is_err();
+                if sender.send(event).is_err() || failed {
+                    return;
+                }
+            }
+        });
+        EventReader {
+            events,
+            stop,
+            thread,
+        }
+    }
+
+    /// The next event read, if there is one, without waiting for it
+    pub fn next(&self) -> Option<io::Result<Event>> {
+        match self.events.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(io::Error::other(
+                "the thread reading the terminal stopped",
+            ))),
+        }
+    }
+
+    /// Stop the thread and wait for it, so nothing reads the terminal once
+    /// it's handed back
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // A reader waiting on a full channel gives up once it's gone
+        drop(self.events);
+        let _ = self.thread.join();
+    }
+}
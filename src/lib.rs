@@ -0,0 +1,10 @@
+//! DOSPong's game logic, kept apart from the terminal so it can be driven by
+//! tests, bots, replays and the network as easily as by a keyboard.
+
+pub mod collision;
+pub mod events;
+pub mod net;
+pub mod pong;
+pub mod relay;
+pub mod replay;
+pub mod rollback;
@@ -0,0 +1,56 @@
+//! What the game was doing, written to a file with --log for attaching to a
+//! bug report. Only ever the file: the screen is the game's while it runs,
+//! so nothing logged can end up on standard output or standard error.
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::sync::Mutex;
+use std::time::Instant;
+
+struct FileLogger {
+    out: Mutex<LineWriter<File>>,
+    started: Instant,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Debug
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let seconds = self.started.elapsed().as_secs_f32();
+        // A line that can't be written is lost, the game goes on
+        if let Ok(mut out) = self.out.lock() {
+            let _ = writeln!(
+                out,
+                "{:>9.3} {:<5} {}: {}",
+                seconds,
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut out) = self.out.lock() {
+            let _ = out.flush();
+        }
+    }
+}
+
+/// Log to the file at `path` from now on, each line as it happens so a
+/// crash loses nothing
+pub fn start(path: &str) -> io::Result<()> {
+    let logger = FileLogger {
+        out: Mutex::new(LineWriter::new(File::create(path)?)),
+        started: Instant::now(),
+    };
+    log::set_boxed_logger(Box::new(logger)).map_err(io::Error::other)?;
+    log::set_max_level(LevelFilter::Debug);
+    Ok(())
+}
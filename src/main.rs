@@ -1,632 +1,1429 @@
+mod app;
+mod bot;
+mod cast;
+mod config;
+mod crash;
+mod event_stream;
+mod frontend;
+mod input;
+mod logging;
+mod records;
+mod save;
+#[cfg(feature = "scripting")]
+mod scripts;
+mod settings;
+mod sound;
+mod theme;
+
+use app::{always_quits, App, FramePacer, GameState, Link};
+use bot::ExternalBot;
+use config::{Action, Config, KeyBindings};
+use event_stream::EventStream;
 use crossterm::{
-    cursor::{Hide, MoveTo, Show},
-    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
-    execute, queue,
-    style::{Color, Print, ResetColor, SetForegroundColor},
-    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+    event::{self, Event, KeyCode, KeyEvent},
+    terminal,
+};
+use dospong::net::{self, Client, Finder};
+use dospong::pong::{
+    clean_name, AiDifficulty, Game, Handicap, Input, PowerUpSettings, PowerUpType, Tuning,
+    DEFAULT_BEST_OF, MAX_FRAME_DT, SCORE_LIMIT_RANGE, TIME_LIMIT_RANGE,
 };
-use rand::Rng;
-use std::io::{self, Write};
+use dospong::relay;
+use dospong::replay::{self, Record, Recorder, Replay};
+use dospong::rollback::Session;
+use frontend::{TerminalFrontend, MIN_HEIGHT, MIN_WIDTH, SPARE_ROWS};
+use records::Records;
+use settings::{Outcome, Setting, SettingsMenu};
+use theme::Theme;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
 use std::time::{Duration, Instant};
 
-const PADDLE_HEIGHT: u16 = 5;
-const BALL_SPEED: f32 = 0.75;
-const PADDLE_SPEED: i16 = 1;
-const POWERUP_SPAWN_CHANCE: f32 = 0.002;
-const POWERUP_DURATION: Duration = Duration::from_secs(10);
-const POWERUP_SIZE: u16 = 5;
-
-#[derive(Clone, Copy, PartialEq)]
-enum PowerUpType {
-    DoublePaddle,
-    CenterWall,
-    TwoSmallWalls,
-    BentPaddle,
-    SplitBall,
-}
-
-struct PowerUp {
-    x: u16,
-    y: u16,
-    ptype: PowerUpType,
-}
+const DEFAULT_MAX_SPECTATORS: usize = 4;
+// How long the start menu waits for a key before the demo starts
+const DEMO_IDLE: Duration = Duration::from_secs(30);
+// How long the demo shows who won before playing again
+const DEMO_REMATCH_DELAY: Duration = Duration::from_secs(3);
 
-struct ActivePowerUp {
-    ptype: PowerUpType,
-    player: u8,
-    end_time: Instant,
+enum NetMode {
+    Host(u16),
+    Join(String),
+    Spectate(String),
+    // Through the relay, hosting or joining by room code
+    OnlineHost,
+    OnlineJoin(String),
 }
 
-struct Ball {
-    x: f32,
-    y: f32,
-    vx: f32,
-    vy: f32,
+struct Options {
+    ai: Option<AiDifficulty>,
+    // The config's unless given
+    score_limit: Option<u16>,
+    best_of: Option<u16>,
+    // Played against the clock instead of to the score limit
+    time_limit: Option<Duration>,
+    // Points worth more the longer the rally
+    arcade: bool,
+    // Pulls on the balls besides their own speed
+    gravity: bool,
+    wind: bool,
+    four_player: bool,
+    practice: bool,
+    survival: bool,
+    obstacles: bool,
+    // Bricks across the middle to break for points
+    breakout: bool,
+    net: Option<NetMode>,
+    // Both players run the match, when hosting
+    rollback: bool,
+    // The dospong-server to meet at, with --online
+    relay: Option<String>,
+    net_debug: bool,
+    debug: bool,
+    debug_draw: bool,
+    demo: bool,
+    find: bool,
+    stats: bool,
+    max_spectators: usize,
+    seed: Option<u64>,
+    record: Option<String>,
+    replay: Option<String>,
+    // Where to write what's drawn as an asciinema cast
+    record_cast: Option<String>,
+    // Where to write the match's events, or "-" for standard output
+    event_stream: Option<String>,
+    // Where to log what happens, for a bug report
+    log: Option<String>,
+    // A program to play Player 2
+    bot2: Option<String>,
+    // The config's unless given
+    p1_name: Option<String>,
+    p2_name: Option<String>,
+    p1_handicap: Option<Handicap>,
+    p2_handicap: Option<Handicap>,
+    // Carry on with the saved match
+    resume: bool,
+    // Override the config's powerups for this game
+    no_powerups: bool,
+    powerup_types: Option<Vec<PowerUpType>>,
+    powerup_rate: Option<f32>,
+    max_powerups: Option<usize>,
+    theme: Option<Theme>,
+    half_block: bool,
 }
 
-struct Game {
-    width: u16,
-    height: u16,
-    p1_y: i16,
-    p2_y: i16,
-    p1_second_y: Option<i16>,
-    p2_second_y: Option<i16>,
-    p1_bent: bool,
-    p2_bent: bool,
-    balls: Vec<Ball>,
-    p1_score: u16,
-    p2_score: u16,
-    powerups: Vec<PowerUp>,
-    active_powerups: Vec<ActivePowerUp>,
-    center_wall: bool,
-    two_small_walls: bool,
-    last_frame: Instant,
-    buffer: Vec<Vec<char>>,
-    color_buffer: Vec<Vec<Color>>,
-}
-
-impl Game {
-    fn new(width: u16, height: u16) -> Self {
-        let mut game = Game {
-            width,
-            height,
-            p1_y: (height / 2) as i16,
-            p2_y: (height / 2) as i16,
-            p1_second_y: None,
-            p2_second_y: None,
-            p1_bent: false,
-            p2_bent: false,
-            balls: vec![Ball {
-                x: (width / 2) as f32,
-                y: (height / 2) as f32,
-                vx: BALL_SPEED,
-                vy: BALL_SPEED * 0.5,
-            }],
-            p1_score: 0,
-            p2_score: 0,
-            powerups: Vec::new(),
-            active_powerups: Vec::new(),
-            center_wall: false,
-            two_small_walls: false,
-            last_frame: Instant::now(),
-            buffer: vec![vec![' '; width as usize]; height as usize],
-            color_buffer: vec![vec![Color::White; width as usize]; height as usize],
+impl Options {
+    fn parse() -> Result<Self, String> {
+        let mut options = Options {
+            ai: None,
+            score_limit: None,
+            best_of: None,
+            time_limit: None,
+            arcade: false,
+            gravity: false,
+            wind: false,
+            four_player: false,
+            practice: false,
+            survival: false,
+            obstacles: false,
+            breakout: false,
+            net: None,
+            rollback: false,
+            relay: None,
+            net_debug: false,
+            debug: false,
+            debug_draw: false,
+            demo: false,
+            find: false,
+            stats: false,
+            max_spectators: DEFAULT_MAX_SPECTATORS,
+            seed: None,
+            record: None,
+            replay: None,
+            record_cast: None,
+            event_stream: None,
+            log: None,
+            bot2: None,
+            p1_name: None,
+            p2_name: None,
+            p1_handicap: None,
+            p2_handicap: None,
+            resume: false,
+            no_powerups: false,
+            powerup_types: None,
+            powerup_rate: None,
+            max_powerups: None,
+            theme: None,
+            half_block: false,
         };
-        game.reset_ball();
-        game
-    }
-
-    fn reset_ball(&mut self) {
-        self.balls.clear();
-        let mut rng = rand::thread_rng();
-        let vx = if rng.gen_bool(0.5) {
-            BALL_SPEED
-        } else {
-            -BALL_SPEED
-        };
-        let vy = rng.gen_range(-BALL_SPEED..BALL_SPEED);
-        self.balls.push(Ball {
-            x: (self.width / 2) as f32,
-            y: (self.height / 2) as f32,
-            vx,
-            vy,
-        });
-    }
-
-    fn update(&mut self, dt: f32) {
-        // Spawn powerups
-        let mut rng = rand::thread_rng();
-        if rng.gen::<f32>() < POWERUP_SPAWN_CHANCE && self.powerups.len() < 2 {
-            let powerup_types = [
-                PowerUpType::DoublePaddle,
-                PowerUpType::CenterWall,
-                PowerUpType::TwoSmallWalls,
-                PowerUpType::BentPaddle,
-                PowerUpType::SplitBall,
-            ];
-            self.powerups.push(PowerUp {
-                x: rng.gen_range(self.width / 4..3 * self.width / 4),
-                y: rng.gen_range(2..self.height - 2),
-                ptype: powerup_types[rng.gen_range(0..powerup_types.len())],
-            });
-        }
-
-        // Extract data needed for collision checks
-        let width = self.width;
-        let height = self.height;
-        let p1_y = self.p1_y;
-        let p2_y = self.p2_y;
-        let p1_second_y = self.p1_second_y;
-        let p2_second_y = self.p2_second_y;
-        let p1_bent = self.p1_bent;
-        let p2_bent = self.p2_bent;
-        let center_wall = self.center_wall;
-        let two_small_walls = self.two_small_walls;
-
-        // Update balls
-        let mut new_balls = Vec::new();
-        let mut scored = false;
-        let mut score_player = 0;
-
-        for ball in &mut self.balls {
-            ball.x += ball.vx * dt * 60.0;
-            ball.y += ball.vy * dt * 60.0;
-
-            // Top/bottom collision
-            if ball.y <= 0.0 || ball.y >= (height - 1) as f32 {
-                ball.vy = -ball.vy;
-                ball.y = ball.y.clamp(0.0, (height - 1) as f32);
-            }
-
-            // Check center wall collision
-            if center_wall {
-                let wall_x = width / 2;
-                if (ball.x as u16) == wall_x && ball.vx.abs() > 0.0 {
-                    ball.vx = -ball.vx;
-                }
-            }
-
-            // Check two small walls collision
-            if two_small_walls {
-                let wall_x = width / 2;
-                let wall1_start = height / 4;
-                let wall1_end = wall1_start + height / 6;
-                let wall2_start = 3 * height / 4 - height / 6;
-                let wall2_end = 3 * height / 4;
-
-                if (ball.x as u16) == wall_x {
-                    let by = ball.y as u16;
-                    if (by >= wall1_start && by < wall1_end)
-                        || (by >= wall2_start && by < wall2_end)
-                    {
-                        ball.vx = -ball.vx;
-                    }
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--single-player" => {
+                    options.ai.get_or_insert(AiDifficulty::Normal);
                 }
-            }
-
-            // P1 paddle collision
-            let hit_p1 = Self::check_paddle_collision_static(ball, 2, p1_y, p1_bent)
-                || p1_second_y
-                    .map(|y| Self::check_paddle_collision_static(ball, 2, y, false))
-                    .unwrap_or(false);
-
-            if hit_p1 && ball.vx < 0.0 {
-                ball.vx = -ball.vx * 1.05;
-                let paddle_center = p1_y as f32 + PADDLE_HEIGHT as f32 / 2.0;
-                ball.vy = (ball.y - paddle_center) * 0.15;
-            }
-
-            // P2 paddle collision
-            let hit_p2 = Self::check_paddle_collision_static(ball, width - 3, p2_y, p2_bent)
-                || p2_second_y
-                    .map(|y| Self::check_paddle_collision_static(ball, width - 3, y, false))
-                    .unwrap_or(false);
-
-            if hit_p2 && ball.vx > 0.0 {
-                ball.vx = -ball.vx * 1.05;
-                let paddle_center = p2_y as f32 + PADDLE_HEIGHT as f32 / 2.0;
-                ball.vy = (ball.y - paddle_center) * 0.15;
-            }
-
-            // Scoring
-            if ball.x <= 0.0 {
-                scored = true;
-                score_player = 2;
-            } else if ball.x >= (width - 1) as f32 {
-                scored = true;
-                score_player = 1;
-            }
-
-            // Clamp ball speed
-            ball.vx = ball.vx.clamp(-1.0, 1.0);
-            ball.vy = ball.vy.clamp(-0.8, 0.8);
-        }
-
-        // Collect ball positions for powerup collision check
-        let ball_positions: Vec<(f32, f32)> = self.balls.iter().map(|b| (b.x, b.y)).collect();
-        
-        // Now handle powerup collisions with mutable access
-        for (ball_x, ball_y) in ball_positions {
-            let bx = ball_x as u16;
-            let by = ball_y as u16;
-            let player = if bx < self.width / 2 { 1 } else { 2 };
-
-            self.powerups.retain(|p| {
-                // Check collision with 3x3 powerup area
-                let hit = (p.x as i16 - bx as i16).abs() <= (POWERUP_SIZE / 2) as i16 
-                       && (p.y as i16 - by as i16).abs() <= (POWERUP_SIZE / 2) as i16;
-                
-                if hit {
-                    match p.ptype {
-                        PowerUpType::SplitBall => {
-                            // Split into 3 balls - use the original ball data
-                            let mut rng = rand::thread_rng();
-                            for _ in 0..2 {
-                                new_balls.push(Ball {
-                                    x: ball_x,
-                                    y: ball_y,
-                                    vx: if bx < self.width / 2 { BALL_SPEED } else { -BALL_SPEED },
-                                    vy: rng.gen_range(-BALL_SPEED..BALL_SPEED),
-                                });
-                            }
-                        }
-                        _ => {
-                            self.active_powerups.push(ActivePowerUp {
-                                ptype: p.ptype,
-                                player,
-                                end_time: Instant::now() + POWERUP_DURATION,
-                            });
+                "--difficulty" => {
+                    let name = args
+                        .next()
+                        .ok_or("--difficulty needs a value (easy, normal or hard)")?;
+                    let difficulty = AiDifficulty::from_name(&name)
+                        .ok_or(format!("unknown difficulty '{}' (expected easy, normal or hard)", name))?;
+                    options.ai = Some(difficulty);
+                }
+                "--score-limit" => {
+                    options.score_limit = Some(
+                        args.next()
+                            .and_then(|value| value.parse().ok())
+                            .filter(|limit| SCORE_LIMIT_RANGE.contains(limit))
+                            .ok_or("--score-limit needs a number from 1 to 99")?,
+                    );
+                }
+                "--best-of" => {
+                    options.best_of = Some(
+                        args.next()
+                            .and_then(|value| value.parse().ok())
+                            .filter(|&sets: &u16| sets % 2 == 1)
+                            .ok_or("--best-of needs an odd number of sets, like 3 or 5")?,
+                    );
+                }
+                "--timed" => {
+                    options.time_limit = Some(
+                        args.next()
+                            .as_deref()
+                            .and_then(parse_clock)
+                            .filter(|limit| TIME_LIMIT_RANGE.contains(limit))
+                            .ok_or("--timed needs a time from 0:10 to 60:00, like 3 or 2:30")?,
+                    );
+                }
+                "--arcade" => options.arcade = true,
+                "--gravity" => options.gravity = true,
+                "--wind" => options.wind = true,
+                "--four-player" => options.four_player = true,
+                "--practice" => options.practice = true,
+                "--survival" => options.survival = true,
+                "--obstacles" => options.obstacles = true,
+                "--breakout" => options.breakout = true,
+                "--host" => {
+                    let port = args
+                        .next()
+                        .and_then(|value| value.parse().ok())
+                        .ok_or("--host needs a port number")?;
+                    options.net = Some(NetMode::Host(port));
+                }
+                "--join" => {
+                    let address = args.next().ok_or("--join needs an address like host:port")?;
+                    options.net = Some(NetMode::Join(address));
+                }
+                "--spectate" => {
+                    let address = args
+                        .next()
+                        .ok_or("--spectate needs an address like host:port")?;
+                    options.net = Some(NetMode::Spectate(address));
+                }
+                "--online" => {
+                    options.net = Some(match args.next().as_deref() {
+                        Some("host") => NetMode::OnlineHost,
+                        Some("join") => {
+                            let code = args.next().ok_or("--online join needs the room code")?;
+                            let code = relay::normalize_code(&code)
+                                .ok_or(format!("'{}' isn't a room code", code))?;
+                            NetMode::OnlineJoin(code)
                         }
-                    }
-                    false
-                } else {
-                    true
+                        _ => return Err("--online needs host or join CODE".to_string()),
+                    });
                 }
-            });
-        }
-
-        self.balls.append(&mut new_balls);
-
-        if scored {
-            if score_player == 1 {
-                self.p1_score += 1;
-            } else {
-                self.p2_score += 1;
-            }
-            self.reset_ball();
-            self.center_wall = false;
-            self.two_small_walls = false;
-        }
-
-        // Update active powerups
-        let now = Instant::now();
-        self.active_powerups.retain(|p| now < p.end_time);
-
-        // Reset powerup effects
-        self.p1_second_y = None;
-        self.p2_second_y = None;
-        self.p1_bent = false;
-        self.p2_bent = false;
-        self.center_wall = false;
-        self.two_small_walls = false;
-
-        // Apply active powerup effects
-        for powerup in &self.active_powerups {
-            match powerup.ptype {
-                PowerUpType::DoublePaddle => {
-                    if powerup.player == 1 {
-                        self.p1_second_y = Some(self.p1_y + PADDLE_HEIGHT as i16 + 2);
-                    } else {
-                        self.p2_second_y = Some(self.p2_y + PADDLE_HEIGHT as i16 + 2);
+                "--p1-name" | "--p2-name" => {
+                    let name = args
+                        .next()
+                        .filter(|name| !clean_name(name).is_empty())
+                        .ok_or(format!("{} needs a name", arg))?;
+                    match arg.as_str() {
+                        "--p1-name" => options.p1_name = Some(name),
+                        _ => options.p2_name = Some(name),
                     }
                 }
-                PowerUpType::BentPaddle => {
-                    if powerup.player == 1 {
-                        self.p1_bent = true;
-                    } else {
-                        self.p2_bent = true;
+                "--p1-handicap" | "--p2-handicap" => {
+                    let spec = args.next().ok_or(format!(
+                        "{} needs a handicap like height=7,speed=1.5,start=3",
+                        arg
+                    ))?;
+                    let handicap =
+                        config::parse_handicap(&spec).map_err(|err| format!("{}: {}", arg, err))?;
+                    match arg.as_str() {
+                        "--p1-handicap" => options.p1_handicap = Some(handicap),
+                        _ => options.p2_handicap = Some(handicap),
                     }
                 }
-                PowerUpType::CenterWall => {
-                    self.center_wall = true;
+                "--relay" => {
+                    let address = args.next().ok_or("--relay needs an address like host:port")?;
+                    options.relay = Some(address);
                 }
-                PowerUpType::TwoSmallWalls => {
-                    self.two_small_walls = true;
+                "--max-spectators" => {
+                    options.max_spectators = args
+                        .next()
+                        .and_then(|value| value.parse().ok())
+                        .ok_or("--max-spectators needs a number")?;
                 }
-                _ => {}
+                "--seed" => {
+                    let seed = args
+                        .next()
+                        .and_then(|value| value.parse().ok())
+                        .ok_or("--seed needs a whole number")?;
+                    options.seed = Some(seed);
+                }
+                "--record" => {
+                    options.record = Some(args.next().ok_or("--record needs a file name")?);
+                }
+                "--replay" => {
+                    options.replay = Some(args.next().ok_or("--replay needs a file name")?);
+                }
+                "--record-cast" => {
+                    let path = args.next().ok_or("--record-cast needs a file name")?;
+                    options.record_cast = Some(path);
+                }
+                "--event-stream" => {
+                    let path = args
+                        .next()
+                        .ok_or("--event-stream needs a file name, or - for standard output")?;
+                    options.event_stream = Some(path);
+                }
+                "--log" => options.log = Some(args.next().ok_or("--log needs a file name")?),
+                "--bot2" => {
+                    let command = args.next().ok_or("--bot2 needs a command to run")?;
+                    options.bot2 = Some(command);
+                }
+                "--no-powerups" => options.no_powerups = true,
+                "--powerups" => {
+                    let list = args
+                        .next()
+                        .ok_or("--powerups needs a comma-separated list like freeze,portal")?;
+                    let types = list
+                        .split(',')
+                        .map(|name| {
+                            PowerUpType::from_name(name.trim())
+                                .ok_or(format!("unknown powerup '{}'", name.trim()))
+                        })
+                        .collect::<Result<_, _>>()?;
+                    options.powerup_types = Some(types);
+                }
+                "--powerup-rate" => {
+                    options.powerup_rate = Some(
+                        args.next()
+                            .and_then(|value| value.parse().ok())
+                            .filter(|rate: &f32| *rate >= 0.0 && rate.is_finite())
+                            .ok_or("--powerup-rate needs a number of powerups per second")?,
+                    );
+                }
+                "--max-powerups" => {
+                    options.max_powerups = Some(
+                        args.next()
+                            .and_then(|value| value.parse().ok())
+                            .ok_or("--max-powerups needs a number")?,
+                    );
+                }
+                "--theme" => {
+                    let name = args
+                        .next()
+                        .ok_or(format!("--theme needs a name ({})", Theme::names()))?;
+                    let theme = Theme::from_name(&name).ok_or(format!(
+                        "unknown theme '{}', the themes are {}",
+                        name,
+                        Theme::names()
+                    ))?;
+                    options.theme = Some(theme);
+                }
+                "--half-block" => options.half_block = true,
+                "--net-debug" => options.net_debug = true,
+                "--rollback" => options.rollback = true,
+                "--debug" => options.debug = true,
+                "--debug-draw" => options.debug_draw = true,
+                "--demo" => options.demo = true,
+                "--resume" => options.resume = true,
+                "--find" => options.find = true,
+                "--stats" => options.stats = true,
+                _ => return Err(format!("unknown argument '{}'", arg)),
             }
         }
+        options.check()?;
+        Ok(options)
     }
 
-    fn check_paddle_collision_static(ball: &Ball, paddle_x: u16, paddle_y: i16, bent: bool) -> bool {
-        let bx = ball.x as u16;
-        let by = ball.y as u16;
+    /// Whether nothing says what to play, so the start menu should ask
+    fn picks_nothing(&self) -> bool {
+        self.ai.is_none()
+            && !self.four_player
+            && !self.practice
+            && !self.survival
+            && self.net.is_none()
+            && !self.find
+            && self.replay.is_none()
+            && !self.resume
+            && self.bot2.is_none()
+    }
 
-        if bent {
-            // Bent paddle shape: <>
-            for i in 0..PADDLE_HEIGHT {
-                let py = (paddle_y + i as i16) as u16;
-                if by == py {
-                    let offset = if i < PADDLE_HEIGHT / 2 { i } else { PADDLE_HEIGHT - i - 1 };
-                    if bx == paddle_x + offset as u16 {
-                        return true;
-                    }
-                }
+    /// Turn down options that can't be used together
+    fn check(&self) -> Result<(), String> {
+        if self.demo && !self.picks_nothing() {
+            return Err("--demo starts at the start menu, so it can't pick a game".to_string());
+        }
+        let picks_game = self.ai.is_some()
+            || self.four_player
+            || self.practice
+            || self.survival
+            || self.bot2.is_some();
+        let elsewhere = self.net.is_some() || self.find || self.replay.is_some();
+        if self.resume && (picks_game || elsewhere || self.record.is_some()) {
+            return Err(
+                "--resume carries on the saved match as it was, so it can't pick another game"
+                    .to_string(),
+            );
+        }
+        if self.bot2.is_some() {
+            if self.ai.is_some() {
+                return Err("--bot2 plays Player 2, so it can't be single-player".to_string());
+            }
+            if self.net.is_some() || self.find || self.replay.is_some() {
+                return Err("--bot2 only plays games on this machine".to_string());
             }
-        } else {
-            // Normal paddle
-            if bx == paddle_x && by >= paddle_y as u16 && by < (paddle_y + PADDLE_HEIGHT as i16) as u16 {
-                return true;
+            if self.practice || self.survival {
+                return Err("practice and survival have no Player 2 for --bot2 to play".to_string());
             }
         }
-        false
-    }
-
-    fn move_paddle(&mut self, player: u8, direction: i16) {
-        let paddle_y = if player == 1 {
-            &mut self.p1_y
-        } else {
-            &mut self.p2_y
-        };
-
-        *paddle_y += direction * PADDLE_SPEED;
-        *paddle_y = (*paddle_y).clamp(0, self.height as i16 - PADDLE_HEIGHT as i16);
-    }
-
-    fn render(&mut self, stdout: &mut io::Stdout) -> io::Result<()> {
-        // Clear buffers
-        for row in &mut self.buffer {
-            row.fill(' ');
+        if self.ai.is_some() && (self.net.is_some() || self.find) {
+            return Err(
+                "single-player mode can't be combined with network play".to_string(),
+            );
         }
-        for row in &mut self.color_buffer {
-            row.fill(Color::White);
+        if (self.record.is_some() || self.replay.is_some())
+            && (self.net.is_some() || self.find)
+        {
+            return Err("--record and --replay only work for games on this machine".to_string());
         }
-
-        // Draw borders
-        for x in 0..self.width {
-            self.buffer[0][x as usize] = '─';
-            self.buffer[(self.height - 1) as usize][x as usize] = '─';
+        // Joining, the match runs on the host, and a replay doesn't run
+        // it at all
+        let joining = matches!(
+            self.net,
+            Some(NetMode::Join(_) | NetMode::Spectate(_) | NetMode::OnlineJoin(_))
+        );
+        if self.event_stream.is_some() && (joining || self.find || self.replay.is_some()) {
+            return Err(
+                "--event-stream only works for matches run here, hosting or on one machine"
+                    .to_string(),
+            );
         }
-
-        // Draw center line
-        for y in 0..self.height {
-            if y % 2 == 0 {
-                self.buffer[y as usize][(self.width / 2) as usize] = '┊';
-                self.color_buffer[y as usize][(self.width / 2) as usize] = Color::DarkGrey;
-            }
+        if self.record.is_some() && self.replay.is_some() {
+            return Err("--record and --replay can't be used together".to_string());
         }
-
-        // Draw center wall
-        if self.center_wall {
-            for y in 1..(self.height - 1) {
-                self.buffer[y as usize][(self.width / 2) as usize] = '█';
-                self.color_buffer[y as usize][(self.width / 2) as usize] = Color::Yellow;
+        // Both ends of a network game play on the same field, whatever
+        // their terminals are drawing it with
+        if self.half_block && (self.net.is_some() || self.find) {
+            return Err("--half-block only works for games on this machine".to_string());
+        }
+        if self.half_block && self.replay.is_some() {
+            return Err("a replay is drawn the way it was recorded, so drop --half-block".to_string());
+        }
+        let sets_powerups = self.no_powerups
+            || self.powerup_types.is_some()
+            || self.powerup_rate.is_some()
+            || self.max_powerups.is_some();
+        if self.four_player {
+            if self.ai.is_some() {
+                return Err("four-player mode needs four people, so it can't be single-player".to_string());
+            }
+            if self.net.is_some() || self.find {
+                return Err("four-player mode only works on one machine".to_string());
+            }
+            if self.best_of.is_some_and(|sets| sets > 1) {
+                return Err("four-player mode is a single game, so it can't be played in sets".to_string());
             }
         }
-
-        // Draw two small walls
-        if self.two_small_walls {
-            let wall_x = (self.width / 2) as usize;
-            let wall1_start = self.height / 4;
-            let wall1_end = wall1_start + self.height / 6;
-            let wall2_start = 3 * self.height / 4 - self.height / 6;
-            let wall2_end = 3 * self.height / 4;
-
-            for y in wall1_start..wall1_end {
-                self.buffer[y as usize][wall_x] = '█';
-                self.color_buffer[y as usize][wall_x] = Color::Cyan;
+        if self.time_limit.is_some() {
+            if self.four_player || self.practice || self.survival {
+                return Err("--timed is for matches between two players".to_string());
+            }
+            if self.best_of.is_some_and(|sets| sets > 1) {
+                return Err("a timed match is a single game, so it can't be played in sets".to_string());
             }
-            for y in wall2_start..wall2_end {
-                self.buffer[y as usize][wall_x] = '█';
-                self.color_buffer[y as usize][wall_x] = Color::Cyan;
+            if self.score_limit.is_some() {
+                return Err("a timed match is won on time, so drop --score-limit".to_string());
             }
         }
-
-        // Draw P1 paddle
-        self.draw_paddle(2, self.p1_y, self.p1_bent, Color::Blue);
-        if let Some(y) = self.p1_second_y {
-            self.draw_paddle(2, y, false, Color::Cyan);
+        if self.arcade && (self.four_player || self.practice || self.survival) {
+            return Err("arcade scoring is for matches between two players".to_string());
         }
-
-        // Draw P2 paddle
-        self.draw_paddle(self.width - 3, self.p2_y, self.p2_bent, Color::Red);
-        if let Some(y) = self.p2_second_y {
-            self.draw_paddle(self.width - 3, y, false, Color::Magenta);
+        if self.breakout && (self.four_player || self.practice || self.survival) {
+            return Err("breakout is for matches between two players".to_string());
         }
-
-        // Draw balls
-        for ball in &self.balls {
-            let x = ball.x as usize;
-            let y = ball.y as usize;
-            if y < self.height as usize && x < self.width as usize {
-                self.buffer[y][x] = '●';
-                self.color_buffer[y][x] = Color::White;
+        if self.breakout && self.obstacles {
+            return Err("the bricks take up the middle of the field, so --breakout can't have --obstacles".to_string());
+        }
+        if (self.gravity || self.wind) && self.four_player {
+            return Err("gravity and wind need a top and bottom to bounce off, which four players don't have".to_string());
+        }
+        if self.practice && self.survival {
+            return Err("pick one of --practice and --survival".to_string());
+        }
+        if self.practice || self.survival {
+            let mode = if self.practice { "practice" } else { "survival" };
+            if self.ai.is_some() || self.four_player {
+                return Err(format!("{} is played alone against the wall", mode));
+            }
+            if self.net.is_some() || self.find {
+                return Err(format!("{} only works on one machine", mode));
             }
+            if self.best_of.is_some_and(|sets| sets > 1) {
+                return Err(format!("{} runs don't have sets, so drop --best-of", mode));
+            }
+        }
+        if sets_powerups && joining {
+            return Err("the host decides on the powerups, so they can't be set when joining".to_string());
+        }
+        if self.obstacles && joining {
+            return Err("the host decides on the obstacle course, so --obstacles can't be set when joining".to_string());
+        }
+        if (self.gravity || self.wind) && joining {
+            return Err("the host decides on gravity and wind, so they can't be set when joining".to_string());
+        }
+        if self.breakout && joining {
+            return Err("the host decides on breakout, so --breakout can't be set when joining".to_string());
+        }
+        if self.arcade && joining {
+            return Err("the host decides how points are scored, so --arcade can't be set when joining".to_string());
+        }
+        if self.time_limit.is_some() && joining {
+            return Err("the host decides whether the match is timed, so --timed can't be set when joining".to_string());
+        }
+        if self.best_of.is_some() && joining {
+            return Err("the host decides how many sets are played, so --best-of can't be set when joining".to_string());
+        }
+        let hosting = matches!(self.net, Some(NetMode::Host(_) | NetMode::OnlineHost));
+        if self.rollback && !hosting {
+            return Err(
+                "--rollback is the host's choice, so it goes with --host or --online host"
+                    .to_string(),
+            );
+        }
+        let online = matches!(self.net, Some(NetMode::OnlineHost | NetMode::OnlineJoin(_)));
+        if online && self.relay.is_none() {
+            return Err("--online needs --relay with the address of a dospong-server".to_string());
+        }
+        if self.relay.is_some() && !online {
+            return Err("--relay is where --online games meet, so it goes with --online".to_string());
+        }
+        // A rollback match plays ticks over again, which would send their
+        // events twice
+        if self.rollback && self.event_stream.is_some() {
+            return Err("--event-stream can't follow a --rollback match".to_string());
         }
+        // Like the speeds in the config, since both ends have to play the
+        // same game
+        let handicapped = self.p1_handicap.is_some() || self.p2_handicap.is_some();
+        if handicapped && (self.net.is_some() || self.find) {
+            return Err("handicaps only work on one machine".to_string());
+        }
+        if self.find && self.net.is_some() {
+            return Err("--find picks the game to join itself, so drop --host/--join/--spectate".to_string());
+        }
+        Ok(())
+    }
+}
 
-        // Draw powerups (3x3 size)
-        for powerup in &self.powerups {
-            let symbol = match powerup.ptype {
-                PowerUpType::DoublePaddle => '║',
-                PowerUpType::CenterWall => '█',
-                PowerUpType::TwoSmallWalls => '▓',
-                PowerUpType::BentPaddle => '⟨',
-                PowerUpType::SplitBall => '✦',
-            };
-            let color = match powerup.ptype {
-                PowerUpType::DoublePaddle => Color::Cyan,
-                PowerUpType::CenterWall => Color::Yellow,
-                PowerUpType::TwoSmallWalls => Color::Magenta,
-                PowerUpType::BentPaddle => Color::Green,
-                PowerUpType::SplitBall => Color::White,
-            };
-            
-            // Draw 3x3 powerup
-            for dy in -(POWERUP_SIZE as i16 / 2)..=(POWERUP_SIZE as i16 / 2) {
-                for dx in -(POWERUP_SIZE as i16 / 2)..=(POWERUP_SIZE as i16 / 2) {
-                    let px = (powerup.x as i16 + dx) as usize;
-                    let py = (powerup.y as i16 + dy) as usize;
-                    if py < self.height as usize && px < self.width as usize {
-                        self.buffer[py][px] = symbol;
-                        self.color_buffer[py][px] = color;
-                    }
+/// A time given as minutes, like "3", or minutes and seconds, like "2:30"
+fn parse_clock(value: &str) -> Option<Duration> {
+    let (minutes, seconds) = match value.split_once(':') {
+        Some((minutes, seconds)) if seconds.len() == 2 => (minutes, seconds.parse().ok()?),
+        Some(_) => return None,
+        None => (value, 0),
+    };
+    let minutes: u64 = minutes.parse().ok()?;
+    (seconds < 60).then(|| Duration::from_secs(minutes * 60 + seconds))
+}
+
+/// Play a recorded match back at the speed it was played. The quit key or
+/// Ctrl+Q or Ctrl+C stops it early.
+fn play_replay(
+    game: &mut Game,
+    replay: &Replay,
+    keys: &KeyBindings,
+    frontend: &mut TerminalFrontend,
+) -> io::Result<()> {
+    let stop_requested = |frontend: &mut TerminalFrontend| -> io::Result<bool> {
+        while let Some(event) = frontend.next_event() {
+            match event? {
+                Event::Key(KeyEvent {
+                    code,
+                    modifiers,
+                    kind: event::KeyEventKind::Press,
+                    ..
+                }) if always_quits(code, modifiers) || keys.action(code) == Some(Action::Quit) => {
+                    return Ok(true);
                 }
+                // The recording decides the playfield size, not this terminal
+                Event::Resize(..) => frontend.repaint(),
+                _ => {}
             }
         }
+        Ok(false)
+    };
 
-        // Build complete frame in a single string buffer before outputting
-        // This prevents tearing and ensures atomic screen updates
-        let mut frame_buffer = String::with_capacity((self.width as usize + 10) * self.height as usize);
-        
-        for y in 0..self.height {
-            frame_buffer.push_str(&format!("\x1b[{};{}H", y + 1, 1)); // Move to position
-            let mut current_color = Color::White;
-            for x in 0..self.width {
-                let ch = self.buffer[y as usize][x as usize];
-                let color = self.color_buffer[y as usize][x as usize];
-                if color != current_color {
-                    frame_buffer.push_str(&Self::color_to_ansi(color));
-                    current_color = color;
+    frontend.input_hint = " replay ";
+    for record in &replay.records {
+        if stop_requested(frontend)? {
+            return Ok(());
+        }
+        match *record {
+            Record::Frame { input, dt } => {
+                // Run the simulation exactly when the recorded match did
+                if GameState::of(game).runs_simulation() && !frontend.holds_game() {
+                    game.step(input, dt);
+                }
+                frontend.play_sounds(game);
+                frontend.render(game, GameState::of(game))?;
+                std::thread::sleep(Duration::from_secs_f32(dt.clamp(0.0, MAX_FRAME_DT)));
+            }
+            Record::Pause => game.toggle_pause(),
+            Record::Help => frontend.toggle_help(),
+            Record::Rematch => game.rematch(),
+            Record::NextSet => game.next_set(),
+            Record::QuitPrompt => frontend.quit_prompt = !frontend.quit_prompt,
+            Record::Settings {
+                tuning,
+                score_limit,
+                ref powerups,
+            } => {
+                game.set_tuning(tuning);
+                game.score_limit = score_limit;
+                game.set_powerup_settings(PowerUpSettings {
+                    enabled: powerups.clone(),
+                    ..game.powerup_settings().clone()
+                });
+            }
+            Record::Resize { width, height } => {
+                if frontend.resize(width, height) {
+                    game.resize(width, frontend.field_height(height));
                 }
-                frame_buffer.push(ch);
             }
         }
+    }
 
-        // Draw score
-        frame_buffer.push_str(&format!(
-            "\x1b[{};{}H\x1b[37mP1: {}  P2: {}\x1b[0m",
-            1,
-            self.width / 2 - 9,
-            self.p1_score,
-            self.p2_score
-        ));
+    frontend.input_hint = " end of replay ";
+    loop {
+        frontend.render(game, GameState::of(game))?;
+        if stop_requested(frontend)? {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
 
-        // Write entire frame at once
-        write!(stdout, "{}", frame_buffer)?;
-        stdout.flush()?;
+/// Show the games being announced on the local network until one is picked.
+/// Returns its address, or None if the player gave up.
+fn find_game(keys: &KeyBindings) -> io::Result<Option<String>> {
+    let mut finder = Finder::new()?;
+    let mut frontend = TerminalFrontend::enter()?;
 
-        Ok(())
-    }
+    let started = Instant::now();
+    let mut selected = 0;
+    let choice = 'search: loop {
+        finder.poll();
+        selected = selected.min(finder.hosts.len().saturating_sub(1));
 
-    fn color_to_ansi(color: Color) -> String {
-        match color {
-            Color::Black => "\x1b[30m".to_string(),
-            Color::DarkGrey => "\x1b[90m".to_string(),
-            Color::Red => "\x1b[31m".to_string(),
-            Color::DarkRed => "\x1b[91m".to_string(),
-            Color::Green => "\x1b[32m".to_string(),
-            Color::DarkGreen => "\x1b[92m".to_string(),
-            Color::Yellow => "\x1b[33m".to_string(),
-            Color::DarkYellow => "\x1b[93m".to_string(),
-            Color::Blue => "\x1b[34m".to_string(),
-            Color::DarkBlue => "\x1b[94m".to_string(),
-            Color::Magenta => "\x1b[35m".to_string(),
-            Color::DarkMagenta => "\x1b[95m".to_string(),
-            Color::Cyan => "\x1b[36m".to_string(),
-            Color::DarkCyan => "\x1b[96m".to_string(),
-            Color::White => "\x1b[37m".to_string(),
-            Color::Grey => "\x1b[97m".to_string(),
-            _ => "\x1b[37m".to_string(),
-        }
-    }
-
-    fn draw_paddle(&mut self, x: u16, y: i16, bent: bool, color: Color) {
-        if bent {
-            // Bent paddle: <>
-            for i in 0..PADDLE_HEIGHT {
-                let py = y + i as i16;
-                if py >= 0 && py < self.height as i16 {
-                    let offset = if i < PADDLE_HEIGHT / 2 { i } else { PADDLE_HEIGHT - i - 1 };
-                    let px = x + offset as u16;
-                    if px < self.width {
-                        self.buffer[py as usize][px as usize] = '█';
-                        self.color_buffer[py as usize][px as usize] = color;
+        while let Some(event) = frontend.next_event() {
+            match event? {
+                Event::Key(KeyEvent {
+                    code,
+                    modifiers,
+                    kind: event::KeyEventKind::Press | event::KeyEventKind::Repeat,
+                    ..
+                }) => {
+                    if always_quits(code, modifiers) {
+                        break 'search None;
+                    }
+                    match (code, keys.action(code)) {
+                        (KeyCode::Enter, _) => {
+                            if let Some(host) = finder.hosts.get(selected) {
+                                if host.compatible() {
+                                    break 'search Some(host.address.to_string());
+                                }
+                            }
+                        }
+                        (KeyCode::Up, _) | (_, Some(Action::P1Up | Action::P2Up)) => {
+                            selected = selected.saturating_sub(1);
+                        }
+                        (KeyCode::Down, _) | (_, Some(Action::P1Down | Action::P2Down)) => {
+                            selected = (selected + 1).min(finder.hosts.len().saturating_sub(1));
+                        }
+                        (_, Some(Action::Quit)) => break 'search None,
+                        _ => {}
                     }
                 }
-            }
-        } else {
-            // Normal paddle
-            for i in 0..PADDLE_HEIGHT {
-                let py = y + i as i16;
-                if py >= 0 && py < self.height as i16 {
-                    self.buffer[py as usize][x as usize] = '█';
-                    self.color_buffer[py as usize][x as usize] = color;
+                Event::Resize(width, height) => {
+                    frontend.resize(width, height.saturating_sub(SPARE_ROWS));
                 }
+                _ => {}
             }
         }
-    }
-}
 
-fn main() -> io::Result<()> {
-    let mut stdout = io::stdout();
+        // Give hosts a couple of seconds to announce before saying none were found
+        let searching = started.elapsed() < Duration::from_secs(2);
+        frontend.render_lobby(&finder.hosts, selected, searching)?;
+        std::thread::sleep(Duration::from_millis(16));
+    };
 
-    // Setup terminal
-    execute!(stdout, EnterAlternateScreen, Hide)?;
-    terminal::enable_raw_mode()?;
+    frontend.leave()?;
+    Ok(choice)
+}
 
+/// The computer playing itself with every powerup on, until a key is
+/// pressed. Nothing about it goes into the records. Returns true for Ctrl+Q
+/// or Ctrl+C, which quit the game rather than just the demo.
+fn play_demo(frontend: &mut TerminalFrontend, frame_rate: u16) -> io::Result<bool> {
     let (width, height) = terminal::size()?;
-    let mut game = Game::new(width, height.saturating_sub(1));
-
-    let mut p1_up = false;
-    let mut p1_down = false;
-    let mut p2_up = false;
-    let mut p2_down = false;
-    let mut running = true;
-
-    // Game loop
-    while running {
-        let now = Instant::now();
-        let dt = now.duration_since(game.last_frame).as_secs_f32();
-        game.last_frame = now;
+    let height = height.saturating_sub(SPARE_ROWS);
+    let mut game = Game::new(width, frontend.field_height(height), rand::random());
+    game.ai = Some(AiDifficulty::Normal);
+    game.left_ai = Some(AiDifficulty::Normal);
+    game.set_powerup_settings(PowerUpSettings::default());
 
-        // Handle input (non-blocking)
-        while event::poll(Duration::from_millis(0))? {
-            let event = event::read()?;
-            match event {
+    frontend.demo = true;
+    let mut pacer = FramePacer::new(frame_rate);
+    let mut last_frame = Instant::now();
+    let mut game_over_for = Duration::ZERO;
+    let quit = 'demo: loop {
+        while let Some(event) = frontend.next_event() {
+            match event? {
                 Event::Key(KeyEvent {
                     code,
                     modifiers,
                     kind: event::KeyEventKind::Press,
                     ..
-                }) => {
-                    match code {
-                        KeyCode::Char('a') | KeyCode::Char('A') => p1_up = true,
-                        KeyCode::Char('d') | KeyCode::Char('D') => p1_down = true,
-                        KeyCode::Char('4') => p2_up = true,
-                        KeyCode::Char('6') => p2_down = true,
-                        KeyCode::Char('q') | KeyCode::Char('Q') => {
-                            if modifiers.contains(KeyModifiers::CONTROL) {
-                                running = false;
-                            }
-                        }
-                        KeyCode::Esc => running = false,
-                        _ => {}
+                }) => break 'demo always_quits(code, modifiers),
+                Event::Resize(width, height) => {
+                    let height = height.saturating_sub(SPARE_ROWS);
+                    if frontend.resize(width, height) {
+                        game.resize(width, frontend.field_height(height));
                     }
                 }
+                _ => {}
+            }
+        }
+
+        let now = Instant::now();
+        let dt = now.duration_since(last_frame).as_secs_f32();
+        last_frame = now;
+        if game.winner.is_some() {
+            game_over_for += Duration::from_secs_f32(dt);
+            if game_over_for >= DEMO_REMATCH_DELAY {
+                game.rematch();
+                game_over_for = Duration::ZERO;
+            }
+        }
+        if !frontend.holds_game() {
+            game.step(Input::default(), dt.min(MAX_FRAME_DT));
+        }
+        frontend.play_sounds(&mut game);
+        frontend.render(&game, GameState::of(&game))?;
+        pacer.wait();
+    };
+
+    frontend.demo = false;
+    frontend.repaint();
+    Ok(quit)
+}
+
+/// The screens of the start menu
+enum Menu {
+    Modes,
+    Difficulty,
+    /// Typing the name of a replay file
+    ReplayFile(String),
+    Settings(Box<SettingsMenu>),
+}
+
+const MODES: [&str; 9] = [
+    "Resume",
+    "Two Player",
+    "Single Player",
+    "Four Player",
+    "Practice",
+    "Survival",
+    "Replay",
+    "Settings",
+    "Quit",
+];
+const DIFFICULTIES: [AiDifficulty; 3] =
+    [AiDifficulty::Easy, AiDifficulty::Normal, AiDifficulty::Hard];
+
+/// Let the player pick what to play, and set `options` up for it. Anything
+/// changed on the settings screen goes into `config`. Returns false if
+/// they'd rather quit.
+fn start_menu(config: &mut Config, options: &mut Options) -> io::Result<bool> {
+    let mut frontend = TerminalFrontend::enter()?;
+    // Already checked when the config was loaded
+    frontend.theme = options.theme.or(config.display.theme().ok()).unwrap_or(Theme::DEFAULT);
+    let keys = config.keys.clone();
+
+    let started = Instant::now();
+    let mut menu = Menu::Modes;
+    // Straight to the saved match when there is one
+    let mut selected = if save::exists() { 0 } else { 1 };
+    let mut message = String::new();
+    let mut idle_since = Instant::now();
+    let mut demo = options.demo;
+    let picked = 'menu: loop {
+        if demo || (matches!(menu, Menu::Modes) && idle_since.elapsed() >= DEMO_IDLE) {
+            if play_demo(&mut frontend, config.display.frame_rate)? {
+                break 'menu false;
+            }
+            demo = false;
+            idle_since = Instant::now();
+        }
+
+        while let Some(event) = frontend.next_event() {
+            let (code, modifiers) = match event? {
                 Event::Key(KeyEvent {
                     code,
-                    kind: event::KeyEventKind::Release,
+                    modifiers,
+                    kind: event::KeyEventKind::Press | event::KeyEventKind::Repeat,
                     ..
-                }) => {
-                    match code {
-                        KeyCode::Char('a') | KeyCode::Char('A') => p1_up = false,
-                        KeyCode::Char('d') | KeyCode::Char('D') => p1_down = false,
-                        KeyCode::Char('4') => p2_up = false,
-                        KeyCode::Char('6') => p2_down = false,
-                        _ => {}
+                }) => (code, modifiers),
+                Event::Resize(width, height) => {
+                    frontend.resize(width, height.saturating_sub(SPARE_ROWS));
+                    continue;
+                }
+                _ => continue,
+            };
+            if always_quits(code, modifiers) {
+                break 'menu false;
+            }
+            idle_since = Instant::now();
+            message.clear();
+
+            // Every key but these goes into the file name
+            if let Menu::ReplayFile(path) = &mut menu {
+                match code {
+                    KeyCode::Enter => match Replay::load(path) {
+                        Ok(_) => {
+                            options.replay = Some(path.clone());
+                            break 'menu true;
+                        }
+                        Err(err) => message = format!("Can't play {}", err),
+                    },
+                    KeyCode::Esc => {
+                        menu = Menu::Modes;
+                        selected = MODES.iter().position(|&mode| mode == "Replay").unwrap_or(0);
+                    }
+                    KeyCode::Backspace => {
+                        path.pop();
+                    }
+                    KeyCode::Char(ch) => path.push(ch),
+                    _ => {}
+                }
+                continue;
+            }
+            if let Menu::Settings(settings) = &mut menu {
+                match settings.handle_key(code, keys.action(code)) {
+                    Outcome::Changed(Setting::Theme) => frontend.theme = settings.theme(),
+                    Outcome::Closed => {
+                        // It started out with the command line's choices, so
+                        // it has the last word now
+                        *config = settings.config.clone();
+                        options.theme = None;
+                        options.score_limit = None;
+                        menu = Menu::Modes;
+                        selected = MODES.iter().position(|&mode| mode == "Settings").unwrap_or(0);
                     }
+                    _ => {}
                 }
+                continue;
+            }
+
+            let count = match menu {
+                Menu::Difficulty => DIFFICULTIES.len(),
+                _ => MODES.len(),
+            };
+            match (code, keys.action(code)) {
+                (KeyCode::Enter, _) => match menu {
+                    Menu::Modes => match MODES[selected] {
+                        "Resume" if save::exists() => {
+                            options.resume = true;
+                            break 'menu true;
+                        }
+                        "Resume" => message = "There's no saved match".to_string(),
+                        "Two Player" => break 'menu true,
+                        "Single Player" => {
+                            menu = Menu::Difficulty;
+                            selected = 1;
+                        }
+                        "Four Player" => {
+                            options.four_player = true;
+                            break 'menu true;
+                        }
+                        "Practice" => {
+                            options.practice = true;
+                            break 'menu true;
+                        }
+                        "Survival" => {
+                            options.survival = true;
+                            break 'menu true;
+                        }
+                        "Replay" => {
+                            menu = Menu::ReplayFile(String::new());
+                            selected = 0;
+                        }
+                        "Settings" => {
+                            let mut settings = SettingsMenu::new(config.clone());
+                            // Whatever the command line says is what's being
+                            // played with
+                            if let Some(theme) = options.theme {
+                                settings.config.display.theme = theme.name.to_string();
+                            }
+                            if let Some(limit) = options.score_limit {
+                                settings.config.game.score_limit = limit;
+                            }
+                            menu = Menu::Settings(Box::new(settings));
+                        }
+                        _ => break 'menu false,
+                    },
+                    Menu::Difficulty => {
+                        options.ai = Some(DIFFICULTIES[selected]);
+                        break 'menu true;
+                    }
+                    Menu::ReplayFile(_) | Menu::Settings(_) => {}
+                },
+                (KeyCode::Up, _) | (_, Some(Action::P1Up | Action::P2Up)) => {
+                    selected = selected.saturating_sub(1);
+                }
+                (KeyCode::Down, _) | (_, Some(Action::P1Down | Action::P2Down)) => {
+                    selected = (selected + 1).min(count - 1);
+                }
+                (KeyCode::Esc, _) | (_, Some(Action::Quit)) => match menu {
+                    Menu::Modes => break 'menu false,
+                    _ => {
+                        menu = Menu::Modes;
+                        selected = MODES
+                            .iter()
+                            .position(|&mode| mode == "Single Player")
+                            .unwrap_or(0);
+                    }
+                },
                 _ => {}
             }
         }
 
-        // Update paddle positions
-        if p1_up {
-            game.move_paddle(1, -1);
+        let (heading, items) = match &menu {
+            Menu::Modes => ("", MODES.map(String::from).to_vec()),
+            Menu::Difficulty => (
+                "Single Player",
+                DIFFICULTIES.map(|difficulty| difficulty.name().to_string()).to_vec(),
+            ),
+            Menu::ReplayFile(path) => ("Replay file, then Enter", vec![format!("{}_", path)]),
+            Menu::Settings(settings) => ("Settings", settings.lines()),
+        };
+        let (selected, message) = match &menu {
+            Menu::Settings(settings) => (settings.selected, &settings.message),
+            _ => (selected, &message),
+        };
+        let elapsed = started.elapsed().as_secs_f32();
+        frontend.render_menu(heading, &items, selected, message, elapsed)?;
+        std::thread::sleep(Duration::from_millis(16));
+    };
+
+    frontend.leave()?;
+    Ok(picked)
+}
+
+fn main() -> io::Result<()> {
+    let mut options = match Options::parse() {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("DOSPong: {}", message);
+            eprintln!(
+                "Usage: DOSPong [--single-player] [--difficulty easy|normal|hard] [--score-limit N]\n       \
+                 [--host PORT | --join ADDRESS:PORT | --spectate ADDRESS:PORT | --find]\n       \
+                 [--online host | --online join CODE] [--relay ADDRESS:PORT]\n       \
+                 [--max-spectators N] [--rollback] [--net-debug] [--seed N]\n       \
+                 [--record FILE | --replay FILE]\n       \
+                 [--no-powerups | --powerups LIST] [--powerup-rate N] [--max-powerups N]\n       \
+                 [--best-of N] [--timed M:SS] [--arcade] [--four-player]\n       \
+                 [--practice | --survival] [--obstacles] [--breakout] [--gravity] [--wind]\n       \
+                 [--theme NAME] [--half-block]\n       \
+                 [--debug] [--debug-draw] [--demo] [--resume] [--record-cast FILE]\n       \
+                 [--event-stream FILE|-] [--bot2 COMMAND] [--p1-name NAME] [--p2-name NAME]\n       \
+                 [--p1-handicap SPEC] [--p2-handicap SPEC] [--log FILE] [--stats]"
+            );
+            std::process::exit(2);
         }
-        if p1_down {
-            game.move_paddle(1, 1);
+    };
+
+    if options.stats {
+        println!("{}", Records::load().table());
+        return Ok(());
+    }
+
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("DOSPong: config error in {}", message);
+            std::process::exit(2);
         }
-        if p2_up {
-            game.move_paddle(2, -1);
+    };
+    if let Some(path) = &options.log {
+        if let Err(err) = logging::start(path) {
+            eprintln!("DOSPong: can't log to {}: {}", path, err);
+            std::process::exit(1);
         }
-        if p2_down {
-            game.move_paddle(2, 1);
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        log::info!("DOSPong {} started with {:?}", env!("CARGO_PKG_VERSION"), args);
+        match toml::to_string(&config) {
+            Ok(text) => log::info!("config:\n{}", text.trim_end()),
+            Err(err) => log::warn!("couldn't write out the config: {}", err),
         }
+    }
+    // Refuse to start in a terminal that can't fit the playfield plus the
+    // status bar and the spare bottom row, before touching any terminal modes
+    let (width, height) = terminal::size()?;
+    if width < MIN_WIDTH || height < MIN_HEIGHT + SPARE_ROWS {
+        eprintln!(
+            "DOSPong needs a terminal of at least {}x{}, but this one is only {}x{}.",
+            MIN_WIDTH,
+            MIN_HEIGHT + SPARE_ROWS,
+            width,
+            height
+        );
+        eprintln!("Enlarge the window (or reduce the font size) and try again.");
+        std::process::exit(1);
+    }
 
-        // Update game state
-        game.update(dt);
+    // Standard output is left to the events, the start menu and all
+    if options.event_stream.as_deref() == Some("-") {
+        frontend::draw_on_stderr();
+    }
 
-        // Render
-        game.render(&mut stdout)?;
+    if options.picks_nothing() {
+        if !start_menu(&mut config, &mut options)? {
+            return Ok(());
+        }
+        // What was picked may not go with the rest of the command line
+        if let Err(message) = options.check() {
+            eprintln!("DOSPong: {}", message);
+            std::process::exit(2);
+        }
+    }
 
-        // Cap framerate to ~60 FPS
-        std::thread::sleep(Duration::from_millis(16));
+    let keys = config.keys.clone();
+    // Already checked when the config was loaded
+    let mut powerups = config.powerups.settings().unwrap_or_default();
+    if let Some(types) = options.powerup_types.take() {
+        powerups.enabled = types;
+    }
+    if options.no_powerups {
+        powerups.enabled.clear();
     }
+    if let Some(rate) = options.powerup_rate {
+        powerups.spawn_rate = rate;
+    }
+    if let Some(max) = options.max_powerups {
+        powerups.max_on_field = max;
+    }
+
+    // Already checked when the config was loaded
+    let theme = options.theme.or(config.display.theme().ok()).unwrap_or(Theme::DEFAULT);
+    let mut score_limit = options.score_limit.unwrap_or(config.game.score_limit);
+    let mut deuce = config.game.deuce;
+    // Both ends of a network game have to play the same game
+    let mut tuning = match options.net {
+        Some(_) => Tuning::default(),
+        None => config.game.tuning().unwrap_or_default(),
+    };
+
+    if options.find {
+        match find_game(&keys)? {
+            Some(address) => options.net = Some(NetMode::Join(address)),
+            None => return Ok(()),
+        }
+    }
+    // Already checked when the config was loaded
+    let configured = config.players.handicaps().unwrap_or_default();
+    let mut handicaps = match options.net {
+        Some(_) => [Handicap::default(); 2],
+        None => [
+            options.p1_handicap.unwrap_or(configured[0]),
+            options.p2_handicap.unwrap_or(configured[1]),
+        ],
+    };
+
+    // Both players play on the same field, so connect before setting up the
+    // terminal and use the size agreed on during the handshake
+    let spectating = matches!(options.net, Some(NetMode::Spectate(_)));
+    let mut link = Link::Local { recorder: None };
+    let (mut width, mut height) = (width, height.saturating_sub(SPARE_ROWS));
+    // Over the network each side names the player it plays, and the host
+    // tells everyone both
+    let mut names = [
+        options.p1_name.take().unwrap_or_else(|| config.players.p1_name.clone()),
+        options.p2_name.take().unwrap_or_else(|| config.players.p2_name.clone()),
+    ];
+    if let Some(mode) = &options.net {
+        let client = |(conn, width, height, rollback, names)| {
+            let link = match rollback && !spectating {
+                true => Link::Rollback {
+                    conn,
+                    session: Session::join(),
+                    server: None,
+                },
+                false => Link::Client {
+                    conn,
+                    client: Client::new(),
+                    spectating,
+                },
+            };
+            (link, width, height, names)
+        };
+        let [p1_name, p2_name] = &names;
+        let result = match mode {
+            NetMode::Host(port) => {
+                eprintln!("Waiting for the other player to join on port {}...", port);
+                let max_spectators = options.max_spectators;
+                net::host(*port, width, height, max_spectators, options.rollback, p1_name).map(
+                    |(conn, width, height, their_name, server)| {
+                        let link = match options.rollback {
+                            true => Link::Rollback {
+                                conn,
+                                session: Session::host(),
+                                server: Some(server),
+                            },
+                            false => Link::Host {
+                                conn,
+                                server: Some(server),
+                                tick: 0,
+                                acked_input: 0,
+                                their_charge: false,
+                            },
+                        };
+                        (link, width, height, [p1_name.clone(), their_name])
+                    },
+                )
+            }
+            NetMode::OnlineHost => {
+                let relay = options.relay.as_deref().unwrap_or_default();
+                eprintln!("Opening a room on {}...", relay);
+                let show_code = |code: &str| {
+                    eprintln!(
+                        "Room code {}: the other player joins with --online join {} --relay {}",
+                        code, code, relay
+                    );
+                };
+                net::host_online(relay, width, height, options.rollback, p1_name, show_code).map(
+                    |(conn, width, height, their_name)| {
+                        let link = match options.rollback {
+                            true => Link::Rollback {
+                                conn,
+                                session: Session::host(),
+                                server: None,
+                            },
+                            false => Link::Host {
+                                conn,
+                                server: None,
+                                tick: 0,
+                                acked_input: 0,
+                                their_charge: false,
+                            },
+                        };
+                        (link, width, height, [p1_name.clone(), their_name])
+                    },
+                )
+            }
+            NetMode::OnlineJoin(code) => {
+                let relay = options.relay.as_deref().unwrap_or_default();
+                eprintln!("Joining room {} on {}...", code, relay);
+                net::join_online(relay, code, width, height, p2_name).map(client)
+            }
+            NetMode::Join(address) => {
+                eprintln!("Connecting to {}...", address);
+                net::join(address, width, height, false, p2_name).map(client)
+            }
+            NetMode::Spectate(address) => {
+                eprintln!("Connecting to {} (waits for the match to start)...", address);
+                net::join(address, width, height, true, "").map(client)
+            }
+        };
+        match result {
+            Ok((connected, agreed_width, agreed_height, agreed_names)) => {
+                link = connected;
+                width = agreed_width;
+                height = agreed_height;
+                names = agreed_names;
+                log::info!("connected on {}x{}, players {:?}", width, height, names);
+            }
+            Err(err) => {
+                log::error!("couldn't start the network game: {}", err);
+                eprintln!("DOSPong: couldn't start the network game: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let replay = match &options.replay {
+        Some(path) => match Replay::load(path) {
+            Ok(replay) => Some(replay),
+            Err(message) => {
+                eprintln!("DOSPong: can't play {}", message);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    // Half blocks fit two rows of the field on every row of the screen
+    let half_block = match &replay {
+        Some(replay) => replay.header.half_block,
+        None => options.half_block || (config.display.half_block && options.net.is_none()),
+    };
+    let scale = if half_block { 2 } else { 1 };
+    height *= scale;
+    if let Some(replay) = &replay {
+        let header = &replay.header;
+        if header.width > width || header.height > height {
+            eprintln!(
+                "DOSPong: this replay needs a terminal of at least {}x{}",
+                header.width,
+                header.height / scale + SPARE_ROWS
+            );
+            std::process::exit(1);
+        }
+        width = header.width;
+        height = header.height;
+        options.seed = Some(header.seed);
+        options.ai = header.ai;
+        score_limit = header.score_limit;
+        deuce = header.deuce;
+        tuning = header.tuning;
+        options.best_of = Some(header.best_of);
+        options.time_limit = header.time_limit;
+        options.arcade = header.arcade;
+        options.gravity = header.gravity;
+        options.wind = header.wind;
+        options.four_player = header.four_player;
+        options.practice = header.practice;
+        options.survival = header.survival;
+        options.obstacles = header.obstacles;
+        options.breakout = header.breakout;
+        powerups = header.powerups.clone();
+        names = header.names.clone();
+        handicaps = header.handicaps;
+    }
+    // A timed match has no score to win at
+    let head_start = handicaps.iter().find(|h| h.head_start >= score_limit);
+    if let (Some(handicap), None) = (head_start, options.time_limit) {
+        eprintln!(
+            "DOSPong: a head start of {} would win a game to {} before it starts",
+            handicap.head_start, score_limit
+        );
+        std::process::exit(2);
+    }
+
+    let best_of = options.best_of.unwrap_or(DEFAULT_BEST_OF);
+
+    // Print the seed so a match can be played again exactly with --seed
+    let seed = options.seed.unwrap_or_else(rand::random);
+    eprintln!("DOSPong: seed {}", seed);
+    log::info!("seed {}", seed);
 
-    // Cleanup
-    execute!(stdout, LeaveAlternateScreen, Show)?;
-    terminal::disable_raw_mode()?;
+    if let Some(path) = &options.record {
+        let header = replay::Header {
+            seed,
+            width,
+            height,
+            score_limit,
+            deuce,
+            best_of,
+            time_limit: options.time_limit,
+            arcade: options.arcade,
+            gravity: options.gravity,
+            wind: options.wind,
+            four_player: options.four_player,
+            practice: options.practice,
+            survival: options.survival,
+            obstacles: options.obstacles,
+            breakout: options.breakout,
+            half_block,
+            ai: options.ai,
+            powerups: powerups.clone(),
+            tuning,
+            names: names.clone(),
+            handicaps,
+        };
+        match Recorder::create(path, &header) {
+            Ok(recorder) => link = Link::Local { recorder: Some(recorder) },
+            Err(err) => {
+                eprintln!("DOSPong: can't record to {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let resumed = match options.resume {
+        true => match save::resume() {
+            Ok(game) => Some(game),
+            Err(message) => {
+                eprintln!("DOSPong: can't resume the match: {}", message);
+                std::process::exit(1);
+            }
+        },
+        false => None,
+    };
 
-    Ok(())
-}
\ No newline at end of file
+    let event_stream = match &options.event_stream {
+        Some(path) => match EventStream::open(path) {
+            Ok(stream) => Some(stream),
+            Err(err) => {
+                eprintln!("DOSPong: can't write events to {}: {}", path, err);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let bot = match &options.bot2 {
+        Some(command) => match ExternalBot::spawn(command) {
+            Ok(bot) => Some(bot),
+            Err(err) => {
+                eprintln!("DOSPong: can't start the bot {}: {}", command, err);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    // Custom powerups only turn up in games on this machine, and not in
+    // recorded ones, since a replay wouldn't run the scripts
+    #[cfg(feature = "scripting")]
+    let mut scripts = {
+        let on_this_machine = options.net.is_none() && !options.find;
+        (on_this_machine && options.record.is_none() && replay.is_none())
+            .then(scripts::Scripts::load)
+    };
+    #[cfg(feature = "scripting")]
+    for error in scripts.iter_mut().flat_map(|scripts| scripts.errors.drain(..)) {
+        eprintln!("DOSPong: powerup script {}", error);
+    }
+
+    let records = Records::load();
+    let mut frontend = TerminalFrontend::enter()?;
+    frontend.help_lines = keys.help_lines();
+    frontend.big_score = config.display.big_score;
+    frontend.live_title = config.display.title;
+    frontend.notify = config.display.notify;
+    frontend.bell = config.display.bell;
+    frontend.shake = config.display.shake;
+    frontend.show_feed = config.display.feed;
+    frontend.half_block = half_block;
+    frontend.braille = config.display.braille;
+    frontend.colorblind = config.display.colorblind;
+    frontend.theme = theme;
+    frontend.spectating = spectating;
+    frontend.show_debug = options.debug;
+    frontend.debug_draw = options.debug_draw;
+    if let Some(path) = &options.record_cast {
+        if let Err(err) = frontend.record_cast(path) {
+            frontend.leave()?;
+            eprintln!("DOSPong: can't record a cast to {}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+    // Over the network the field is agreed on up front and can't shrink to
+    // fit this terminal, so the whole of it has to fit
+    if options.net.is_some() {
+        frontend.min_size = (width, height);
+    }
+
+    let mut game = Game::new(width, height, seed);
+    game.ai = options.ai;
+    game.score_limit = score_limit;
+    game.deuce = deuce;
+    game.arcade = options.arcade;
+    game.gravity = options.gravity;
+    if options.wind {
+        game.start_wind();
+    }
+    game.set_tuning(tuning);
+    game.best_of = best_of;
+    game.set_four_player(options.four_player);
+    if options.practice {
+        game.start_practice();
+    }
+    if options.survival {
+        game.start_survival();
+    }
+    if let Some(limit) = options.time_limit {
+        game.start_timed(limit);
+    }
+    game.set_obstacle_course(options.obstacles);
+    game.set_breakout(options.breakout);
+    game.set_powerup_settings(powerups);
+    for (player, name) in [1, 2].into_iter().zip(&names) {
+        game.set_name(player, name);
+    }
+    if matches!(options.net, Some(NetMode::Host(_) | NetMode::OnlineHost)) {
+        frontend.send_notification(&format!("{} joined the match", game.long_name(2)))?;
+    }
+    game.set_handicaps(handicaps);
+    if let Some(saved) = resumed {
+        game = saved;
+        // The field the match was saved on may not fit this terminal
+        if (game.width, game.height) != (width, height) {
+            game.resize(width, height);
+        }
+    }
+
+    #[cfg(feature = "scripting")]
+    if let Some(scripts) = &mut scripts {
+        frontend.custom_looks = scripts.register(&mut game);
+    }
+
+    // Both players of a rollback match start from the host's game
+    if let Link::Rollback { conn, session, .. } = &mut link {
+        if let Err(err) = session.start(conn, &mut game) {
+            frontend.leave()?;
+            eprintln!("DOSPong: couldn't start the network game: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(replay) = &replay {
+        play_replay(&mut game, replay, &keys, &mut frontend)?;
+        return frontend.leave();
+    }
+
+    let crash_context = crash::Context {
+        seed,
+        config: toml::to_string(&config).unwrap_or_default(),
+    };
+    let pacer = FramePacer::new(config.display.frame_rate);
+    let mut app = App::new(game, frontend, config, link, options.net_debug, records, pacer);
+    if let Some(stream) = event_stream {
+        app.stream_events(stream);
+    }
+    if let Some(bot) = bot {
+        app.play_bot2(bot);
+    }
+    #[cfg(feature = "scripting")]
+    if let Some(scripts) = scripts {
+        app.use_scripts(scripts);
+    }
+    // The panic hook has already given the terminal back by the time the
+    // match has unwound to here
+    match panic::catch_unwind(AssertUnwindSafe(|| app.run())) {
+        Ok(result) => result?,
+        Err(payload) => {
+            match crash::write_bundle(&crash_context, app.game(), app.recent_inputs()) {
+                Ok(path) => eprintln!(
+                    "DOSPong crashed. What it was doing is in {}, please attach that to an issue.",
+                    path.display()
+                ),
+                Err(err) => eprintln!("DOSPong: couldn't write a crash report: {}", err),
+            }
+            panic::resume_unwind(payload);
+        }
+    }
+    app.finish()
+}
@@ -0,0 +1,1836 @@
+//! Two-player games over TCP. The host runs the simulation and streams
+//! snapshots of it to the joining player, who only sends paddle input back,
+//! unless the host asked for rollback, where both run it (see `rollback`).
+
+use crate::collision::Vec2;
+use crate::pong::{
+    clean_name, ActivePowerUp, Ball, Brick, Dir, EffectKind, Game, MatchStats, PortalRect,
+    PowerUp, PowerUpType, Sound, Timed, WallKind, WallRect, Wind, BRICK_COLUMNS, MAX_FRAME_DT,
+};
+use crate::relay;
+use crate::rollback::TickInput;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+pub const PROTOCOL_VERSION: u16 = 35;
+pub(crate) const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// The most a game sent to start a rollback match can come to, so its
+/// frame still fits through the relay with the tag and lengths around it
+pub(crate) const MAX_START_STATE: usize = relay::MAX_PACKET_SIZE - 16;
+/// Hosts announce themselves to the local network on this UDP port
+pub const DISCOVERY_PORT: u16 = 47_777;
+const ANNOUNCE_MAGIC: &[u8; 4] = b"DPNG";
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+// A host that hasn't been heard from for this long has stopped announcing,
+// usually because someone joined it
+const HOST_EXPIRY: Duration = Duration::from_secs(4);
+// A peer that lets this much unsent data pile up is too slow to keep up
+// with, so further snapshots are dropped until it catches up
+const MAX_PENDING_BYTES: usize = 64 * 1024;
+// Inputs the host hasn't confirmed yet, beyond which the oldest are forgotten
+const MAX_UNACKED_INPUTS: usize = 256;
+// How often each side pings the other, and how long an answer can take
+// before the ping counts as lost
+const PING_INTERVAL: Duration = Duration::from_millis(500);
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+// How many of the latest pings the loss is worked out over
+const PING_WINDOW: usize = 20;
+// A round trip longer than this says the connection is struggling, which
+// is said at most this often
+const RTT_SPIKE: Duration = Duration::from_millis(200);
+const UNSTABLE_NOTICE_INTERVAL: Duration = Duration::from_secs(10);
+// Chat kept for `take_chat` at most, so a peer that never stops talking
+// can't use up memory on a connection nobody takes it from
+const MAX_PENDING_CHAT: usize = 8;
+// A ball that moved further than this between snapshots was served or
+// teleported, so it's drawn at its new spot instead of sliding there
+const MAX_BLEND_DISTANCE: f32 = 4.0;
+
+const TAG_HELLO: u8 = 0;
+const TAG_WELCOME: u8 = 1;
+const TAG_INPUT: u8 = 2;
+const TAG_PAUSE: u8 = 3;
+const TAG_REMATCH: u8 = 4;
+const TAG_SNAPSHOT: u8 = 5;
+const TAG_REFUSED: u8 = 6;
+const TAG_NEXT_SET: u8 = 7;
+const TAG_START: u8 = 8;
+const TAG_TICK: u8 = 9;
+const TAG_CHECKSUM: u8 = 10;
+const TAG_PING: u8 = 11;
+const TAG_PONG: u8 = 12;
+const TAG_CHAT: u8 = 13;
+
+/// The longest chat message, in characters
+pub const MAX_CHAT_LENGTH: usize = 40;
+
+pub enum Message {
+    /// Sent by whoever connects with its terminal's playfield size,
+    /// whether it only wants to watch, and the name it plays under
+    Hello {
+        version: u16,
+        width: u16,
+        height: u16,
+        spectator: bool,
+        name: String,
+    },
+    /// The host's reply with the playfield size both sides will use,
+    /// whether the two players each run the match with rollback, and what
+    /// Players 1 and 2 are called
+    Welcome {
+        version: u16,
+        width: u16,
+        height: u16,
+        rollback: bool,
+        names: [String; 2],
+    },
+    /// The joining player's paddle movement and how long the frame it was
+    /// held for took, whether they pressed serve, which way they dashed and
+    /// whether they're charging, sent every frame and numbered so the host
+    /// can say which ones it has applied
+    Input {
+        seq: u32,
+        dir: Dir,
+        dt: f32,
+        serve: bool,
+        dash: Dir,
+        charge: bool,
+    },
+    Pause,
+    Rematch,
+    /// Start the next set once the last one is over
+    NextSet,
+    // Boxed, being so much bigger than the rest
+    Snapshot(Box<Snapshot>),
+    /// The host turned the connection away, with the reason why
+    Refused(String),
+    /// The whole game as the host starts it, serialized, for a rollback
+    /// match to begin from
+    Start(String),
+    /// One player's input for a tick of a rollback match, with how many
+    /// ticks ahead of the other player's last input they were
+    Tick { tick: u32, input: TickInput, lead: i8 },
+    /// A checksum of the game as it stood before `tick` in a rollback match
+    Checksum { tick: u32, sum: u64 },
+    /// Sent every `PING_INTERVAL` and answered with a `Pong` of the same
+    /// number, which `Connection` takes care of itself
+    Ping(u32),
+    Pong(u32),
+    /// Something a player said, which the host passes on to spectators
+    Chat { player: u8, text: String },
+}
+
+/// `text` as it's safe to send and to show: cut to `MAX_CHAT_LENGTH`, and
+/// with no control characters, which could be escape sequences for whoever
+/// reads it
+pub fn clean_chat(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control()).take(MAX_CHAT_LENGTH).collect()
+}
+
+/// Everything the joining player needs to draw the host's game
+pub struct Snapshot {
+    // Counts up with every snapshot the host sends
+    tick: u32,
+    // The last input from the joining player that the host has applied
+    ack: u32,
+    p1_y: i16,
+    p2_y: i16,
+    // How far past those rows the side paddles have moved, so the joining
+    // player's own moves on top come out as they would on the host
+    paddle_carry: [f32; 2],
+    p1_height: u16,
+    p2_height: u16,
+    p1_second_y: Option<i16>,
+    p2_second_y: Option<i16>,
+    p1_bent: bool,
+    p2_bent: bool,
+    dash_cooldown: [Duration; 2],
+    charge: [Duration; 2],
+    p1_score: u16,
+    p2_score: u16,
+    score_limit: u16,
+    deuce: bool,
+    best_of: u16,
+    timed: Option<Timed>,
+    gravity: bool,
+    /// Only which way the wind blows, since the joining player doesn't
+    /// simulate it
+    wind: Option<Vec2>,
+    p1_sets: u16,
+    p2_sets: u16,
+    set_winner: Option<u8>,
+    swapped: bool,
+    winner: Option<u8>,
+    paused: bool,
+    serve_timer: Option<Duration>,
+    center_wall: bool,
+    two_small_walls: bool,
+    walls: Vec<WallRect>,
+    bricks: Vec<Brick>,
+    portals: Option<(PortalRect, PortalRect)>,
+    p1_shield: bool,
+    p2_shield: bool,
+    shield_flash: Option<(u8, Duration)>,
+    arcade: bool,
+    p1_double: bool,
+    p2_double: bool,
+    stats: MatchStats,
+    balls: Vec<(f32, f32, f32, f32, f32)>,
+    powerups: Vec<(u16, u16, PowerUpType, Duration)>,
+    active_powerups: Vec<(PowerUpType, u8, Duration)>,
+}
+
+impl Snapshot {
+    pub fn capture(game: &Game, tick: u32, ack: u32) -> Self {
+        Snapshot {
+            tick,
+            ack,
+            p1_y: game.p1_y,
+            p2_y: game.p2_y,
+            paddle_carry: [game.paddle_carry[0], game.paddle_carry[1]],
+            p1_height: game.p1_height,
+            p2_height: game.p2_height,
+            p1_second_y: game.p1_second_y,
+            p2_second_y: game.p2_second_y,
+            p1_bent: game.p1_bent,
+            p2_bent: game.p2_bent,
+            dash_cooldown: game.dash_cooldown,
+            charge: game.charge,
+            p1_score: game.p1_score,
+            p2_score: game.p2_score,
+            score_limit: game.score_limit,
+            deuce: game.deuce,
+            best_of: game.best_of,
+            timed: game.timed.clone(),
+            gravity: game.gravity,
+            wind: game.wind.as_ref().map(|wind| wind.force),
+            p1_sets: game.p1_sets,
+            p2_sets: game.p2_sets,
+            set_winner: game.set_winner,
+            swapped: game.swapped,
+            winner: game.winner,
+            paused: game.paused,
+            serve_timer: game.serve_timer,
+            center_wall: game.center_wall,
+            two_small_walls: game.two_small_walls,
+            walls: game.walls.clone(),
+            bricks: game.bricks.clone(),
+            portals: game.portals,
+            p1_shield: game.p1_shield,
+            p2_shield: game.p2_shield,
+            shield_flash: game.shield_flash,
+            arcade: game.arcade,
+            p1_double: game.p1_double,
+            p2_double: game.p2_double,
+            stats: game.stats.clone(),
+            balls: game
+                .balls
+                .iter()
+                .map(|b| (b.pos.x, b.pos.y, b.vel.x, b.vel.y, b.spin))
+                .collect(),
+            powerups: game
+                .powerups
+                .iter()
+                .map(|p| (p.x, p.y, p.ptype, p.remaining))
+                .collect(),
+            active_powerups: game
+                .active_powerups
+                .iter()
+                .map(|p| (p.ptype, p.player, p.remaining))
+                .collect(),
+        }
+    }
+
+    pub fn apply(&self, game: &mut Game) {
+        // Effects and sounds aren't sent, so work them out from what
+        // changed
+        let mut hit = false;
+        for side in 1..=2u8 {
+            let index = side as usize - 1;
+            if self.stats.hits[index] > game.stats.hits[index] {
+                game.add_effect(EffectKind::PaddleHit, side);
+                hit = true;
+            }
+        }
+        if hit {
+            game.add_sound(Sound::PaddleHit);
+        }
+        // A ball heading back up or down that no paddle touched bounced off
+        // something
+        let turned = game
+            .balls
+            .iter()
+            .zip(&self.balls)
+            .any(|(ball, &(_, _, _, vy, _))| ball.vel.y * vy < 0.0);
+        if turned && !hit {
+            game.add_sound(Sound::WallBounce);
+        }
+        let picked_up = |stats: &MatchStats| stats.powerups.iter().sum::<u32>();
+        if picked_up(&self.stats) > picked_up(&game.stats) {
+            game.add_sound(Sound::PowerUp);
+        }
+        if self.p1_score > game.p1_score {
+            game.add_effect(EffectKind::Score, 1);
+        }
+        if self.p2_score > game.p2_score {
+            game.add_effect(EffectKind::Score, 2);
+        }
+        let scored = [self.p1_score > game.p1_score, self.p2_score > game.p2_score];
+        for (side, scored) in [1, 2].into_iter().zip(scored) {
+            if scored {
+                game.add_sound(Sound::Score);
+                let player = if self.swapped { 3 - side } else { side };
+                let score = format!("{}-{}", self.p1_score, self.p2_score);
+                game.add_event(format!("{} scores! {}", game.name(player), score));
+            }
+        }
+        game.p1_y = self.p1_y;
+        game.p2_y = self.p2_y;
+        game.paddle_carry[..2].copy_from_slice(&self.paddle_carry);
+        game.p1_height = self.p1_height;
+        game.p2_height = self.p2_height;
+        game.p1_second_y = self.p1_second_y;
+        game.p2_second_y = self.p2_second_y;
+        game.p1_bent = self.p1_bent;
+        game.p2_bent = self.p2_bent;
+        game.dash_cooldown = self.dash_cooldown;
+        game.charge = self.charge;
+        game.p1_score = self.p1_score;
+        game.p2_score = self.p2_score;
+        game.score_limit = self.score_limit;
+        game.deuce = self.deuce;
+        game.best_of = self.best_of;
+        game.timed = self.timed.clone();
+        game.gravity = self.gravity;
+        game.wind = self.wind.map(|force| Wind {
+            force,
+            heading: force,
+            change_in: Duration::ZERO,
+        });
+        game.p1_sets = self.p1_sets;
+        game.p2_sets = self.p2_sets;
+        game.set_winner = self.set_winner;
+        game.swapped = self.swapped;
+        game.winner = self.winner;
+        game.paused = self.paused;
+        game.serve_timer = self.serve_timer;
+        game.center_wall = self.center_wall;
+        game.two_small_walls = self.two_small_walls;
+        game.walls = self.walls.clone();
+        game.bricks = self.bricks.clone();
+        game.portals = self.portals;
+        game.p1_shield = self.p1_shield;
+        game.p2_shield = self.p2_shield;
+        game.shield_flash = self.shield_flash;
+        game.arcade = self.arcade;
+        game.p1_double = self.p1_double;
+        game.p2_double = self.p2_double;
+        game.stats = self.stats.clone();
+        game.balls = self
+            .balls
+            .iter()
+            .map(|&(x, y, vx, vy, spin)| Ball {
+                spin,
+                ..Ball::new(x, y, vx, vy)
+            })
+            .collect();
+        game.powerups = self
+            .powerups
+            .iter()
+            .map(|&(x, y, ptype, remaining)| PowerUp {
+                x,
+                y,
+                ptype,
+                remaining,
+            })
+            .collect();
+        game.active_powerups = self
+            .active_powerups
+            .iter()
+            .map(|&(ptype, player, remaining)| ActivePowerUp {
+                ptype,
+                player,
+                remaining,
+            })
+            .collect();
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        put_u32(out, self.tick);
+        put_u32(out, self.ack);
+        let flags = self.p1_bent as u8
+            | (self.p2_bent as u8) << 1
+            | (self.paused as u8) << 2
+            | (self.center_wall as u8) << 3
+            | (self.two_small_walls as u8) << 4
+            | (self.portals.is_some() as u8) << 5
+            | (self.p1_shield as u8) << 6
+            | (self.p2_shield as u8) << 7;
+        out.push(flags);
+        let scoring = self.arcade as u8 | (self.p1_double as u8) << 1 | (self.p2_double as u8) << 2;
+        out.push(scoring);
+        put_i16(out, self.p1_y);
+        put_i16(out, self.p2_y);
+        put_f32(out, self.paddle_carry[0]);
+        put_f32(out, self.paddle_carry[1]);
+        put_u16(out, self.p1_height);
+        put_u16(out, self.p2_height);
+        put_opt_i16(out, self.p1_second_y);
+        put_opt_i16(out, self.p2_second_y);
+        for time in self.dash_cooldown.iter().chain(&self.charge) {
+            put_u16(out, time.as_millis() as u16);
+        }
+        put_u16(out, self.p1_score);
+        put_u16(out, self.p2_score);
+        put_u16(out, self.score_limit);
+        out.push(self.deuce as u8);
+        put_u16(out, self.best_of);
+        // No time limit is 0, which a real one can't be
+        match &self.timed {
+            Some(timed) => {
+                put_u32(out, timed.limit.as_millis() as u32);
+                put_u32(out, timed.left.as_millis() as u32);
+                out.push(timed.overtime as u8);
+            }
+            None => put_u32(out, 0),
+        }
+        out.push(self.gravity as u8);
+        out.push(self.wind.is_some() as u8);
+        if let Some(force) = self.wind {
+            put_f32(out, force.x);
+            put_f32(out, force.y);
+        }
+        put_u16(out, self.p1_sets);
+        put_u16(out, self.p2_sets);
+        out.push(self.set_winner.unwrap_or(0));
+        out.push(self.swapped as u8);
+        out.push(self.winner.unwrap_or(0));
+        put_u32(out, self.serve_timer.map_or(0, |t| t.as_millis().max(1) as u32));
+        if let Some(portals) = self.portals {
+            for portal in [portals.0, portals.1] {
+                put_u16(out, portal.x);
+                put_u16(out, portal.y);
+            }
+        }
+        let (flash_player, flash) = self.shield_flash.unwrap_or_default();
+        out.push(flash_player);
+        put_u16(out, flash.as_millis() as u16);
+        let stats = &self.stats;
+        put_u32(out, stats.rally);
+        put_u32(out, stats.longest_rally);
+        for value in stats.hits.into_iter().chain(stats.powerups) {
+            put_u32(out, value);
+        }
+        put_f32(out, stats.top_speed);
+        put_u32(out, stats.duration.as_millis() as u32);
+
+        out.push(self.walls.len().min(u8::MAX as usize) as u8);
+        for wall in self.walls.iter().take(u8::MAX as usize) {
+            put_u16(out, wall.x);
+            put_u16(out, wall.y);
+            put_u16(out, wall.height);
+            out.push(wall_kind_to_u8(wall.kind));
+        }
+        // The bricks only ever fill one band, so they go as its hit points
+        // row by row, with 0 for a broken one, rather than one by one
+        let first_column = self.bricks.iter().map(|brick| brick.x).min().unwrap_or(0);
+        let rows = self.bricks.iter().map(|brick| brick.y + 1).max().unwrap_or(0);
+        let mut band = vec![0u8; rows as usize * BRICK_COLUMNS as usize];
+        for brick in &self.bricks {
+            let column = (brick.x - first_column).min(BRICK_COLUMNS - 1);
+            band[(brick.y * BRICK_COLUMNS + column) as usize] = brick.hp;
+        }
+        put_u16(out, first_column);
+        put_u16(out, rows);
+        out.extend_from_slice(&band);
+        out.push(self.balls.len().min(u8::MAX as usize) as u8);
+        for &(x, y, vx, vy, spin) in self.balls.iter().take(u8::MAX as usize) {
+            put_f32(out, x);
+            put_f32(out, y);
+            put_f32(out, vx);
+            put_f32(out, vy);
+            put_f32(out, spin);
+        }
+        out.push(self.powerups.len().min(u8::MAX as usize) as u8);
+        for &(x, y, ptype, remaining) in self.powerups.iter().take(u8::MAX as usize) {
+            put_u16(out, x);
+            put_u16(out, y);
+            out.push(powerup_to_u8(ptype));
+            put_u32(out, remaining.as_millis() as u32);
+        }
+        out.push(self.active_powerups.len().min(u8::MAX as usize) as u8);
+        for &(ptype, player, remaining) in self.active_powerups.iter().take(u8::MAX as usize) {
+            out.push(powerup_to_u8(ptype));
+            out.push(player);
+            put_u32(out, remaining.as_millis() as u32);
+        }
+    }
+
+    fn decode(input: &mut Reader) -> Option<Self> {
+        let tick = input.u32()?;
+        let ack = input.u32()?;
+        let flags = input.u8()?;
+        let scoring = input.u8()?;
+        let p1_y = input.i16()?;
+        let p2_y = input.i16()?;
+        let paddle_carry = [input.f32()?, input.f32()?];
+        let p1_height = input.u16()?;
+        let p2_height = input.u16()?;
+        let p1_second_y = input.opt_i16()?;
+        let p2_second_y = input.opt_i16()?;
+        let mut time = || Some(Duration::from_millis(input.u16()? as u64));
+        let dash_cooldown = [time()?, time()?];
+        let charge = [time()?, time()?];
+        let p1_score = input.u16()?;
+        let p2_score = input.u16()?;
+        let score_limit = input.u16()?;
+        let deuce = input.u8()? != 0;
+        let best_of = input.u16()?;
+        let timed = match input.u32()? {
+            0 => None,
+            limit => Some(Timed {
+                limit: Duration::from_millis(limit as u64),
+                left: Duration::from_millis(input.u32()? as u64),
+                overtime: input.u8()? != 0,
+            }),
+        };
+        let gravity = input.u8()? != 0;
+        let wind = match input.u8()? != 0 {
+            true => Some(Vec2::new(input.f32()?, input.f32()?)),
+            false => None,
+        };
+        let p1_sets = input.u16()?;
+        let p2_sets = input.u16()?;
+        let set_winner = Some(input.u8()?).filter(|&w| w != 0);
+        let swapped = input.u8()? != 0;
+        let winner = Some(input.u8()?).filter(|&w| w != 0);
+        let serve_ms = input.u32()?;
+        let portals = if flags & 32 != 0 {
+            let mut portal = || Some(PortalRect { x: input.u16()?, y: input.u16()? });
+            Some((portal()?, portal()?))
+        } else {
+            None
+        };
+        let flash_player = input.u8()?;
+        let flash = Duration::from_millis(input.u16()? as u64);
+        let stats = MatchStats {
+            rally: input.u32()?,
+            longest_rally: input.u32()?,
+            hits: [input.u32()?, input.u32()?, input.u32()?, input.u32()?],
+            powerups: [input.u32()?, input.u32()?, input.u32()?, input.u32()?],
+            top_speed: input.f32()?,
+            duration: Duration::from_millis(input.u32()? as u64),
+        };
+
+        let mut walls = Vec::new();
+        for _ in 0..input.u8()? {
+            walls.push(WallRect {
+                x: input.u16()?,
+                y: input.u16()?,
+                height: input.u16()?,
+                kind: wall_kind_from_u8(input.u8()?)?,
+            });
+        }
+        let first_column = input.u16()?;
+        let last_column = first_column.checked_add(BRICK_COLUMNS)?;
+        let mut bricks = Vec::new();
+        for y in 0..input.u16()? {
+            for x in first_column..last_column {
+                let hp = input.u8()?;
+                if hp > 0 {
+                    bricks.push(Brick { x, y, hp });
+                }
+            }
+        }
+        let mut balls = Vec::new();
+        for _ in 0..input.u8()? {
+            balls.push((input.f32()?, input.f32()?, input.f32()?, input.f32()?, input.f32()?));
+        }
+        let mut powerups = Vec::new();
+        for _ in 0..input.u8()? {
+            let (x, y) = (input.u16()?, input.u16()?);
+            let ptype = powerup_from_u8(input.u8()?)?;
+            let remaining = Duration::from_millis(input.u32()? as u64);
+            powerups.push((x, y, ptype, remaining));
+        }
+        let mut active_powerups = Vec::new();
+        for _ in 0..input.u8()? {
+            let ptype = powerup_from_u8(input.u8()?)?;
+            let player = input.u8()?;
+            let remaining = Duration::from_millis(input.u32()? as u64);
+            active_powerups.push((ptype, player, remaining));
+        }
+
+        Some(Snapshot {
+            tick,
+            ack,
+            p1_y,
+            p2_y,
+            paddle_carry,
+            p1_height,
+            p2_height,
+            p1_second_y,
+            p2_second_y,
+            p1_bent: flags & 1 != 0,
+            p2_bent: flags & 2 != 0,
+            dash_cooldown,
+            charge,
+            p1_score,
+            p2_score,
+            score_limit,
+            deuce,
+            best_of,
+            timed,
+            gravity,
+            wind,
+            p1_sets,
+            p2_sets,
+            set_winner,
+            swapped,
+            winner,
+            paused: flags & 4 != 0,
+            serve_timer: (serve_ms > 0).then(|| Duration::from_millis(serve_ms as u64)),
+            center_wall: flags & 8 != 0,
+            two_small_walls: flags & 16 != 0,
+            walls,
+            bricks,
+            portals,
+            p1_shield: flags & 64 != 0,
+            p2_shield: flags & 128 != 0,
+            shield_flash: (flash_player != 0).then_some((flash_player, flash)),
+            arcade: scoring & 1 != 0,
+            p1_double: scoring & 2 != 0,
+            p2_double: scoring & 4 != 0,
+            stats,
+            balls,
+            powerups,
+            active_powerups,
+        })
+    }
+}
+
+fn wall_kind_to_u8(kind: WallKind) -> u8 {
+    match kind {
+        WallKind::Center => 0,
+        WallKind::Small => 1,
+        WallKind::Obstacle => 2,
+        WallKind::Moving => 3,
+    }
+}
+
+fn wall_kind_from_u8(value: u8) -> Option<WallKind> {
+    match value {
+        0 => Some(WallKind::Center),
+        1 => Some(WallKind::Small),
+        2 => Some(WallKind::Obstacle),
+        3 => Some(WallKind::Moving),
+        _ => None,
+    }
+}
+
+fn powerup_to_u8(ptype: PowerUpType) -> u8 {
+    match ptype {
+        PowerUpType::DoublePaddle => 0,
+        PowerUpType::CenterWall => 1,
+        PowerUpType::TwoSmallWalls => 2,
+        PowerUpType::BentPaddle => 3,
+        PowerUpType::SplitBall => 4,
+        PowerUpType::Freeze => 5,
+        PowerUpType::SlowBall => 6,
+        PowerUpType::GrowPaddle => 7,
+        PowerUpType::ShrinkOpponent => 8,
+        PowerUpType::Sticky => 9,
+        PowerUpType::Portal => 10,
+        PowerUpType::InvisibleBall => 11,
+        PowerUpType::Shield => 12,
+        PowerUpType::ReverseControls => 13,
+        PowerUpType::ScoreMultiplier => 14,
+        PowerUpType::MovingWall => 15,
+        // Custom ones follow on from the built-in ones
+        PowerUpType::Custom(index) => 16u8.saturating_add(index),
+    }
+}
+
+fn powerup_from_u8(value: u8) -> Option<PowerUpType> {
+    match value {
+        0 => Some(PowerUpType::DoublePaddle),
+        1 => Some(PowerUpType::CenterWall),
+        2 => Some(PowerUpType::TwoSmallWalls),
+        3 => Some(PowerUpType::BentPaddle),
+        4 => Some(PowerUpType::SplitBall),
+        5 => Some(PowerUpType::Freeze),
+        6 => Some(PowerUpType::SlowBall),
+        7 => Some(PowerUpType::GrowPaddle),
+        8 => Some(PowerUpType::ShrinkOpponent),
+        9 => Some(PowerUpType::Sticky),
+        10 => Some(PowerUpType::Portal),
+        11 => Some(PowerUpType::InvisibleBall),
+        12 => Some(PowerUpType::Shield),
+        13 => Some(PowerUpType::ReverseControls),
+        14 => Some(PowerUpType::ScoreMultiplier),
+        15 => Some(PowerUpType::MovingWall),
+        index => Some(PowerUpType::Custom(index - 16)),
+    }
+}
+
+pub(crate) fn dir_to_u8(dir: Dir) -> u8 {
+    match dir {
+        Dir::Stay => 0,
+        Dir::Up => 1,
+        Dir::Down => 2,
+    }
+}
+
+pub(crate) fn dir_from_u8(value: u8) -> Option<Dir> {
+    match value {
+        0 => Some(Dir::Stay),
+        1 => Some(Dir::Up),
+        2 => Some(Dir::Down),
+        _ => None,
+    }
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_i16(out: &mut Vec<u8>, value: i16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_opt_i16(out: &mut Vec<u8>, value: Option<i16>) {
+    match value {
+        Some(value) => {
+            out.push(1);
+            put_i16(out, value);
+        }
+        None => out.push(0),
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+/// A player name, cleaned up by `clean_name` so it's short enough for a
+/// length byte
+fn put_name(out: &mut Vec<u8>, name: &str) {
+    let name = clean_name(name);
+    out.push(name.len() as u8);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn put_f32(out: &mut Vec<u8>, value: f32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, rest) = self.data.split_first_chunk::<N>()?;
+        self.data = rest;
+        Some(*head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take::<1>().map(|[b]| b)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        self.take().map(i16::from_le_bytes)
+    }
+
+    fn opt_i16(&mut self) -> Option<Option<i16>> {
+        match self.u8()? {
+            0 => Some(None),
+            _ => self.i16().map(Some),
+        }
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.take().map(f32::from_le_bytes)
+    }
+
+    /// A player name, cleaned up again whatever the other end sent
+    fn name(&mut self) -> Option<String> {
+        let len = self.u8()? as usize;
+        let bytes = self.data.get(..len)?;
+        self.data = &self.data[len..];
+        Some(clean_name(&String::from_utf8_lossy(bytes)))
+    }
+}
+
+impl Message {
+    /// Append the message to `out` as a length-prefixed frame
+    fn encode(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        put_u16(out, 0);
+        match self {
+            Message::Hello {
+                version,
+                width,
+                height,
+                spectator,
+                name,
+            } => {
+                out.push(TAG_HELLO);
+                put_u16(out, *version);
+                put_u16(out, *width);
+                put_u16(out, *height);
+                out.push(*spectator as u8);
+                put_name(out, name);
+            }
+            Message::Welcome {
+                version,
+                width,
+                height,
+                rollback,
+                names,
+            } => {
+                out.push(TAG_WELCOME);
+                put_u16(out, *version);
+                put_u16(out, *width);
+                put_u16(out, *height);
+                out.push(*rollback as u8);
+                names.iter().for_each(|name| put_name(out, name));
+            }
+            Message::Input {
+                seq,
+                dir,
+                dt,
+                serve,
+                dash,
+                charge,
+            } => {
+                out.push(TAG_INPUT);
+                put_u32(out, *seq);
+                let keys = dir_to_u8(*dir)
+                    | (*serve as u8) << 2
+                    | dir_to_u8(*dash) << 3
+                    | (*charge as u8) << 5;
+                out.push(keys);
+                put_f32(out, *dt);
+            }
+            Message::Pause => out.push(TAG_PAUSE),
+            Message::Rematch => out.push(TAG_REMATCH),
+            Message::NextSet => out.push(TAG_NEXT_SET),
+            Message::Snapshot(snapshot) => {
+                out.push(TAG_SNAPSHOT);
+                snapshot.encode(out);
+            }
+            Message::Refused(reason) => {
+                out.push(TAG_REFUSED);
+                let bytes = &reason.as_bytes()[..reason.len().min(255)];
+                out.push(bytes.len() as u8);
+                out.extend_from_slice(bytes);
+            }
+            Message::Start(state) => {
+                out.push(TAG_START);
+                // Never more than `MAX_START_STATE`, which the host checks
+                // before sending it
+                put_u16(out, state.len() as u16);
+                out.extend_from_slice(state.as_bytes());
+            }
+            Message::Tick { tick, input, lead } => {
+                out.push(TAG_TICK);
+                put_u32(out, *tick);
+                let keys = dir_to_u8(input.dir) as u16
+                    | (input.serve as u16) << 2
+                    | (input.pause as u16) << 3
+                    | (input.next_set as u16) << 4
+                    | (input.rematch as u16) << 5
+                    | (dir_to_u8(input.dash) as u16) << 6
+                    | (input.charge as u16) << 8;
+                put_u16(out, keys);
+                out.push(*lead as u8);
+            }
+            Message::Checksum { tick, sum } => {
+                out.push(TAG_CHECKSUM);
+                put_u32(out, *tick);
+                out.extend_from_slice(&sum.to_le_bytes());
+            }
+            Message::Ping(seq) => {
+                out.push(TAG_PING);
+                put_u32(out, *seq);
+            }
+            Message::Pong(seq) => {
+                out.push(TAG_PONG);
+                put_u32(out, *seq);
+            }
+            Message::Chat { player, text } => {
+                out.push(TAG_CHAT);
+                out.push(*player);
+                // Forty characters of four bytes at the most
+                let text = clean_chat(text);
+                out.push(text.len() as u8);
+                out.extend_from_slice(text.as_bytes());
+            }
+        }
+        let len = (out.len() - start - 2) as u16;
+        out[start..start + 2].copy_from_slice(&len.to_le_bytes());
+    }
+
+    fn decode(frame: &[u8]) -> Option<Self> {
+        let mut input = Reader { data: frame };
+        let message = match input.u8()? {
+            TAG_HELLO => Message::Hello {
+                version: input.u16()?,
+                width: input.u16()?,
+                height: input.u16()?,
+                spectator: input.u8()? != 0,
+                // An older version that sends none is turned away for its
+                // version all the same
+                name: input.name().unwrap_or_default(),
+            },
+            TAG_WELCOME => Message::Welcome {
+                version: input.u16()?,
+                width: input.u16()?,
+                height: input.u16()?,
+                rollback: input.u8()? != 0,
+                names: [input.name().unwrap_or_default(), input.name().unwrap_or_default()],
+            },
+            TAG_INPUT => {
+                let seq = input.u32()?;
+                let keys = input.u8()?;
+                // However long a frame really took, it can only move a
+                // paddle as far as a frame in the simulation does
+                let dt = input.f32()?;
+                if !(0.0..=MAX_FRAME_DT).contains(&dt) {
+                    return None;
+                }
+                Message::Input {
+                    seq,
+                    dir: dir_from_u8(keys & 3)?,
+                    dt,
+                    serve: keys & 4 != 0,
+                    dash: dir_from_u8(keys >> 3 & 3)?,
+                    charge: keys & 32 != 0,
+                }
+            }
+            TAG_PAUSE => Message::Pause,
+            TAG_REMATCH => Message::Rematch,
+            TAG_NEXT_SET => Message::NextSet,
+            TAG_SNAPSHOT => Message::Snapshot(Box::new(Snapshot::decode(&mut input)?)),
+            TAG_REFUSED => {
+                let len = input.u8()? as usize;
+                let bytes = input.data.get(..len)?;
+                Message::Refused(String::from_utf8_lossy(bytes).into_owned())
+            }
+            TAG_START => {
+                let len = input.u16()? as usize;
+                let bytes = input.data.get(..len)?;
+                Message::Start(String::from_utf8(bytes.to_vec()).ok()?)
+            }
+            TAG_TICK => {
+                let tick = input.u32()?;
+                let keys = input.u16()?;
+                let lead = input.u8()? as i8;
+                Message::Tick {
+                    tick,
+                    input: TickInput {
+                        dir: dir_from_u8(keys as u8 & 3)?,
+                        serve: keys & 4 != 0,
+                        pause: keys & 8 != 0,
+                        next_set: keys & 16 != 0,
+                        rematch: keys & 32 != 0,
+                        dash: dir_from_u8((keys >> 6) as u8 & 3)?,
+                        charge: keys & 256 != 0,
+                    },
+                    lead,
+                }
+            }
+            TAG_CHECKSUM => Message::Checksum {
+                tick: input.u32()?,
+                sum: input.u64()?,
+            },
+            TAG_PING => Message::Ping(input.u32()?),
+            TAG_PONG => Message::Pong(input.u32()?),
+            TAG_CHAT => {
+                let player = input.u8()?;
+                let len = input.u8()? as usize;
+                let bytes = input.data.get(..len)?;
+                // Whatever the other end did or didn't clean up
+                let text = clean_chat(&String::from_utf8_lossy(bytes));
+                Message::Chat { player, text }
+            }
+            _ => return None,
+        };
+        Some(message)
+    }
+}
+
+/// How the connection is holding up, going by its pings
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkQuality {
+    /// The smoothed round trip time, once a ping has come back
+    pub rtt: Option<Duration>,
+    /// How many of the latest pings never came back, from 0 to 1
+    pub loss: f32,
+}
+
+/// A non-blocking, message-framed TCP connection
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    closed: bool,
+    // When the last ping went. Nothing's pinged until the peer has said
+    // something, so a ping can't get in ahead of its handshake.
+    last_ping: Option<Instant>,
+    next_ping: u32,
+    // Pings still waiting for an answer, with when each went
+    pings: VecDeque<(u32, Instant)>,
+    // Whether each of the latest pings came back in time, oldest first
+    ping_results: VecDeque<bool>,
+    rtt: Option<Duration>,
+    // When a slow round trip was last noticed, and whether that's still
+    // to be said
+    last_spike: Option<Instant>,
+    unstable: bool,
+    // Chat that has come in, by player, until it's taken
+    chat: Vec<(u8, String)>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        Ok(Connection {
+            stream,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+            closed: false,
+            last_ping: None,
+            next_ping: 0,
+            pings: VecDeque::new(),
+            ping_results: VecDeque::new(),
+            rtt: None,
+            last_spike: None,
+            unstable: false,
+            chat: Vec::new(),
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn quality(&self) -> LinkQuality {
+        let lost = self.ping_results.iter().filter(|&&answered| !answered).count();
+        LinkQuality {
+            rtt: self.rtt,
+            loss: lost as f32 / self.ping_results.len().max(1) as f32,
+        }
+    }
+
+    /// Whether a round trip took too long since this was last asked, and
+    /// long enough since the last time to say so again
+    pub fn take_unstable(&mut self) -> bool {
+        std::mem::take(&mut self.unstable)
+    }
+
+    /// The chat that has come in since this was last asked, by player
+    pub fn take_chat(&mut self) -> Vec<(u8, String)> {
+        std::mem::take(&mut self.chat)
+    }
+
+    /// Send a ping if one is due, and give up on any that have taken too
+    /// long to come back
+    fn ping(&mut self) {
+        let now = Instant::now();
+        while self.pings.front().is_some_and(|&(_, sent)| now - sent >= PING_TIMEOUT) {
+            self.pings.pop_front();
+            self.record_ping(false);
+        }
+        if self.last_ping.is_some_and(|last| now - last >= PING_INTERVAL) {
+            self.last_ping = Some(now);
+            let seq = self.next_ping;
+            self.next_ping = seq.wrapping_add(1);
+            self.pings.push_back((seq, now));
+            self.send(&Message::Ping(seq));
+        }
+    }
+
+    fn pong(&mut self, seq: u32) {
+        // One that comes back after it was given up on was already lost
+        let Some(i) = self.pings.iter().position(|&(sent, _)| sent == seq) else {
+            return;
+        };
+        let (_, sent) = self.pings.remove(i).expect("just found");
+        let now = Instant::now();
+        let sample = now - sent;
+        // Smoothed the way TCP does it, so one slow ping doesn't swing it
+        self.rtt = Some(self.rtt.map_or(sample, |rtt| (rtt * 7 + sample) / 8));
+        self.record_ping(true);
+        if sample > RTT_SPIKE
+            && self.last_spike.is_none_or(|last| now - last >= UNSTABLE_NOTICE_INTERVAL)
+        {
+            self.last_spike = Some(now);
+            self.unstable = true;
+        }
+    }
+
+    fn record_ping(&mut self, answered: bool) {
+        if self.ping_results.len() >= PING_WINDOW {
+            self.ping_results.pop_front();
+        }
+        self.ping_results.push_back(answered);
+    }
+
+    /// Queue a message and push out as much as the socket takes right now.
+    /// Snapshots are dropped rather than queued while the peer is behind.
+    pub fn send(&mut self, message: &Message) {
+        if self.closed {
+            return;
+        }
+        if matches!(message, Message::Snapshot(_)) && self.outgoing.len() > MAX_PENDING_BYTES {
+            self.flush();
+            return;
+        }
+        message.encode(&mut self.outgoing);
+        self.flush();
+    }
+
+    fn flush(&mut self) {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => {
+                    self.closed = true;
+                    return;
+                }
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => {
+                    self.closed = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// The next complete message from the peer, if one has arrived. Pings
+    /// are answered and timed on the way, and chat is kept for
+    /// `take_chat`, so neither is returned.
+    pub fn receive(&mut self) -> Option<Message> {
+        if !self.closed {
+            let mut chunk = [0u8; 4096];
+            loop {
+                match self.stream.read(&mut chunk) {
+                    Ok(0) => {
+                        self.closed = true;
+                        break;
+                    }
+                    Ok(read) => self.incoming.extend_from_slice(&chunk[..read]),
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    Err(_) => {
+                        self.closed = true;
+                        break;
+                    }
+                }
+            }
+            self.flush();
+            self.ping();
+        }
+
+        loop {
+            match self.next_frame()? {
+                Message::Ping(seq) => self.send(&Message::Pong(seq)),
+                Message::Pong(seq) => self.pong(seq),
+                Message::Chat { player, text } => {
+                    if self.chat.len() >= MAX_PENDING_CHAT {
+                        self.chat.remove(0);
+                    }
+                    self.chat.push((player, text));
+                }
+                message => return Some(message),
+            }
+        }
+    }
+
+    fn next_frame(&mut self) -> Option<Message> {
+        let len = u16::from_le_bytes([*self.incoming.first()?, *self.incoming.get(1)?]) as usize;
+        if self.incoming.len() < 2 + len {
+            return None;
+        }
+        let message = Message::decode(&self.incoming[2..2 + len]);
+        self.incoming.drain(..2 + len);
+        match message {
+            // A frame we can't make sense of means the stream can't be trusted
+            None => self.closed = true,
+            // The peer is past its handshake, so it can be pinged
+            Some(_) => {
+                self.last_ping.get_or_insert_with(Instant::now);
+            }
+        }
+        message
+    }
+}
+
+struct PendingInput {
+    seq: u32,
+    sent_at: Instant,
+    // What was applied to the paddle locally, Stay if the game was halted,
+    // and for how long
+    dir: Dir,
+    dt: f32,
+    dash: Dir,
+    charge: bool,
+}
+
+/// The joining player's side of a network game. Its own paddle moves as
+/// soon as a key is pressed and is corrected when the host disagrees, and
+/// the ball is blended between the host's snapshots rather than jumping.
+pub struct Client {
+    next_seq: u32,
+    pending: VecDeque<PendingInput>,
+    last_tick: u32,
+    last_snapshot_at: Option<Instant>,
+    // Smoothed time between snapshots, in seconds
+    snapshot_interval: f32,
+    rtt: Option<Duration>,
+    prediction_error: i16,
+    rate_window_start: Instant,
+    rate_count: u32,
+    snapshot_rate: u32,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Client {
+            next_seq: 1,
+            pending: VecDeque::new(),
+            last_tick: 0,
+            last_snapshot_at: None,
+            snapshot_interval: 1.0 / 60.0,
+            rtt: None,
+            prediction_error: 0,
+            rate_window_start: Instant::now(),
+            rate_count: 0,
+            snapshot_rate: 0,
+        }
+    }
+
+    /// Send this frame's keys to the host and apply the paddle movement, for
+    /// the `dt` seconds of the frame, right away instead of waiting for the
+    /// host to echo it back. Firing a held ball is left to the host, and
+    /// anything else in `keys` goes its own way.
+    pub fn send_input(
+        &mut self,
+        connection: &mut Connection,
+        game: &mut Game,
+        keys: TickInput,
+        dt: f32,
+    ) {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        let dt = dt.min(MAX_FRAME_DT);
+        let charge = keys.charge;
+        connection.send(&Message::Input {
+            seq,
+            dir: keys.dir,
+            dt,
+            serve: keys.serve,
+            dash: keys.dash,
+            charge,
+        });
+
+        let (dir, dash) = match game.accepts_input() {
+            true => (keys.dir, keys.dash),
+            false => (Dir::Stay, Dir::Stay),
+        };
+        game.set_charging(game.side_of(2), charge);
+        game.move_paddle(game.side_of(2), dir, dt);
+        game.dash_paddle(game.side_of(2), dash);
+
+        if self.pending.len() >= MAX_UNACKED_INPUTS {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(PendingInput {
+            seq,
+            sent_at: Instant::now(),
+            dir,
+            dt,
+            dash,
+            charge,
+        });
+    }
+
+    /// Take in whatever the host has sent since the last frame
+    pub fn receive(&mut self, connection: &mut Connection, game: &mut Game) {
+        while let Some(message) = connection.receive() {
+            if let Message::Snapshot(snapshot) = message {
+                self.apply_snapshot(game, *snapshot);
+            }
+        }
+
+        // Blend balls from the previous snapshot toward the latest one over
+        // the time we expect the next one to take
+        if let Some(at) = self.last_snapshot_at {
+            let alpha = at.elapsed().as_secs_f32() / self.snapshot_interval.max(0.001);
+            game.ball_alpha = Some(alpha.clamp(0.0, 1.0));
+        }
+    }
+
+    fn apply_snapshot(&mut self, game: &mut Game, snapshot: Snapshot) {
+        // Older than what we already have, so it would only step backwards
+        if self.last_snapshot_at.is_some() && snapshot.tick <= self.last_tick {
+            return;
+        }
+        self.last_tick = snapshot.tick;
+
+        let now = Instant::now();
+        if let Some(previous) = self.last_snapshot_at {
+            let gap = now.duration_since(previous).as_secs_f32();
+            self.snapshot_interval = self.snapshot_interval * 0.9 + gap * 0.1;
+        }
+        self.last_snapshot_at = Some(now);
+        self.rate_count += 1;
+        if now.duration_since(self.rate_window_start) >= Duration::from_secs(1) {
+            self.snapshot_rate = self.rate_count;
+            self.rate_count = 0;
+            self.rate_window_start = now;
+        }
+
+        while let Some(input) = self.pending.front() {
+            if input.seq > snapshot.ack {
+                break;
+            }
+            if input.seq == snapshot.ack {
+                let sample = now.duration_since(input.sent_at);
+                self.rtt = Some(match self.rtt {
+                    Some(rtt) => (rtt * 7 + sample) / 8,
+                    None => sample,
+                });
+            }
+            self.pending.pop_front();
+        }
+
+        // The joining player is Player 2, on whichever side that is this set
+        let own_y = |game: &Game| match game.side_of(2) {
+            1 => game.p1_y,
+            _ => game.p2_y,
+        };
+        let predicted_y = own_y(game);
+        let ball_positions: Vec<Vec2> = game.balls.iter().map(|b| b.pos).collect();
+        snapshot.apply(game);
+
+        // The host's paddle position is from before the inputs it hasn't seen
+        // yet, so replay those on top of it
+        for input in &self.pending {
+            game.set_charging(game.side_of(2), input.charge);
+            game.move_paddle(game.side_of(2), input.dir, input.dt);
+            game.dash_paddle(game.side_of(2), input.dash);
+        }
+        self.prediction_error = own_y(game) - predicted_y;
+
+        if ball_positions.len() == game.balls.len() {
+            for (ball, &pos) in game.balls.iter_mut().zip(&ball_positions) {
+                let moved = ball.pos - pos;
+                if moved.x.abs() + moved.y.abs() <= MAX_BLEND_DISTANCE {
+                    ball.prev = pos;
+                }
+            }
+        }
+    }
+
+    /// Connection numbers for the `--net-debug` overlay
+    pub fn debug_line(&self) -> String {
+        let rtt = match self.rtt {
+            Some(rtt) => format!("{} ms", rtt.as_millis()),
+            None => "?".to_string(),
+        };
+        format!(
+            " rtt {}  {} snapshots/s  prediction error {:+} ",
+            rtt, self.snapshot_rate, self.prediction_error
+        )
+    }
+}
+
+/// A game heard on the local network
+pub struct FoundHost {
+    pub address: SocketAddr,
+    pub announcement: Announcement,
+    last_seen: Instant,
+}
+
+impl FoundHost {
+    pub fn compatible(&self) -> bool {
+        self.announcement.version == PROTOCOL_VERSION
+    }
+}
+
+/// Listens for hosts announcing their games
+pub struct Finder {
+    socket: UdpSocket,
+    pub hosts: Vec<FoundHost>,
+}
+
+impl Finder {
+    pub fn new() -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT))?;
+        socket.set_nonblocking(true)?;
+        Ok(Finder {
+            socket,
+            hosts: Vec::new(),
+        })
+    }
+
+    /// Take in any announcements that arrived and forget hosts that went
+    /// quiet
+    pub fn poll(&mut self) {
+        let mut packet = [0u8; 512];
+        while let Ok((len, from)) = self.socket.recv_from(&mut packet) {
+            let Some(announcement) = Announcement::decode(&packet[..len]) else {
+                continue;
+            };
+            let address = SocketAddr::new(from.ip(), announcement.port);
+            match self.hosts.iter_mut().find(|host| host.address == address) {
+                Some(host) => {
+                    host.announcement = announcement;
+                    host.last_seen = Instant::now();
+                }
+                None => self.hosts.push(FoundHost {
+                    address,
+                    announcement,
+                    last_seen: Instant::now(),
+                }),
+            }
+        }
+        self.hosts.retain(|host| host.last_seen.elapsed() < HOST_EXPIRY);
+    }
+}
+
+fn write_blocking(stream: &mut TcpStream, message: &Message) -> io::Result<()> {
+    let mut out = Vec::new();
+    message.encode(&mut out);
+    stream.write_all(&out)
+}
+
+fn read_blocking(stream: &mut TcpStream) -> io::Result<Message> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut frame = vec![0u8; u16::from_le_bytes(len) as usize];
+    stream.read_exact(&mut frame)?;
+    Message::decode(&frame)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "garbled message from peer"))
+}
+
+pub(crate) fn handshake_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// What a waiting host broadcasts so `--find` can list it
+pub struct Announcement {
+    pub game: String,
+    pub player: String,
+    pub port: u16,
+    pub version: u16,
+}
+
+impl Announcement {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = ANNOUNCE_MAGIC.to_vec();
+        put_u16(&mut out, self.version);
+        put_u16(&mut out, self.port);
+        for text in [&self.game, &self.player] {
+            let bytes = &text.as_bytes()[..text.len().min(64)];
+            out.push(bytes.len() as u8);
+            out.extend_from_slice(bytes);
+        }
+        out
+    }
+
+    fn decode(packet: &[u8]) -> Option<Self> {
+        let mut input = Reader {
+            data: packet.strip_prefix(ANNOUNCE_MAGIC)?,
+        };
+        let version = input.u16()?;
+        let port = input.u16()?;
+        let mut text = || {
+            let len = input.u8()? as usize;
+            let bytes = input.data.get(..len)?;
+            input.data = &input.data[len..];
+            Some(String::from_utf8_lossy(bytes).into_owned())
+        };
+        let game = text()?;
+        let player = text()?;
+        Some(Announcement {
+            game,
+            player,
+            port,
+            version,
+        })
+    }
+}
+
+/// The name other players see for this machine's games
+pub fn local_player_name() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "Someone".to_string())
+}
+
+/// Wait for the other player to join on `port`, announcing the game on the
+/// local network until they do. Spectators that show up first are kept
+/// waiting until the match starts. Returns the player's connection, the
+/// playfield size both players agreed on (the smaller of the two), the
+/// name the other player goes by, and the server that keeps taking
+/// spectators during the match. With `rollback`, the joining player is
+/// told to run the match too. `name` is the host's own.
+pub fn host(
+    port: u16,
+    width: u16,
+    height: u16,
+    max_spectators: usize,
+    rollback: bool,
+    name: &str,
+) -> io::Result<(Connection, u16, u16, String, Server)> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    listener.set_nonblocking(true)?;
+    let announcement = Announcement {
+        game: "DOSPong".to_string(),
+        player: match name.is_empty() {
+            true => local_player_name(),
+            false => name.to_string(),
+        },
+        port,
+        version: PROTOCOL_VERSION,
+    }
+    .encode();
+    // Without a network to broadcast on, joining by address still works
+    let broadcast = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| socket.set_broadcast(true).map(|_| socket))
+        .ok();
+
+    let mut early_spectators = Vec::new();
+    let mut last_announce: Option<Instant> = None;
+    let (stream, width, height, their_name) = loop {
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+                stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+                // Someone who can't complete a handshake isn't worth stopping
+                // the wait for the real player over
+                let Ok(Message::Hello {
+                    version,
+                    width: their_width,
+                    height: their_height,
+                    spectator,
+                    name: their_name,
+                }) = read_blocking(&mut stream)
+                else {
+                    continue;
+                };
+                if spectator {
+                    if early_spectators.len() < max_spectators {
+                        early_spectators.push(stream);
+                    } else {
+                        let _ = write_blocking(&mut stream, &Message::Refused(SPECTATORS_FULL.into()));
+                    }
+                    continue;
+                }
+
+                let width = width.min(their_width);
+                let height = height.min(their_height);
+                let names = [name.to_string(), their_name.clone()];
+                welcome_player(&mut stream, version, width, height, rollback, &names)?;
+                log::info!("{:?} joined, playing on {}x{}", their_name, width, height);
+                break (stream, width, height, their_name);
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err),
+        }
+        if last_announce.is_none_or(|at| at.elapsed() >= ANNOUNCE_INTERVAL) {
+            if let Some(socket) = &broadcast {
+                let _ = socket.send_to(&announcement, (Ipv4Addr::BROADCAST, DISCOVERY_PORT));
+            }
+            last_announce = Some(Instant::now());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(None)?;
+
+    let mut server = Server {
+        listener,
+        max_spectators,
+        width,
+        height,
+        names: [name.to_string(), their_name.clone()],
+        joining: Vec::new(),
+        spectators: Vec::new(),
+    };
+    for mut stream in early_spectators {
+        // Spectators are sent snapshots either way
+        let welcome = Message::Welcome {
+            version: PROTOCOL_VERSION,
+            width,
+            height,
+            rollback: false,
+            names: server.names.clone(),
+        };
+        if write_blocking(&mut stream, &welcome).is_ok() {
+            if let Ok(connection) = Connection::new(stream) {
+                server.spectators.push(connection);
+            }
+        }
+    }
+
+    Ok((Connection::new(stream)?, width, height, their_name, server))
+}
+
+/// Host the match in a room on the relay at `relay`, calling `on_code` with
+/// the code the other player joins it with. Spectators can't reach a game
+/// this way. Returns the player's connection, the playfield size and the
+/// other player's name.
+pub fn host_online(
+    relay: &str,
+    width: u16,
+    height: u16,
+    rollback: bool,
+    name: &str,
+    on_code: impl FnOnce(&str),
+) -> io::Result<(Connection, u16, u16, String)> {
+    let mut stream = relay::open_room(relay, on_code)?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let Message::Hello {
+        version,
+        width: their_width,
+        height: their_height,
+        spectator: false,
+        name: their_name,
+    } = read_blocking(&mut stream)?
+    else {
+        return Err(handshake_error("the other player didn't say hello".to_string()));
+    };
+    let width = width.min(their_width);
+    let height = height.min(their_height);
+    let names = [name.to_string(), their_name.clone()];
+    welcome_player(&mut stream, version, width, height, rollback, &names)?;
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(None)?;
+    Ok((Connection::new(stream)?, width, height, their_name))
+}
+
+/// Tell the joining player the field size, how the match is played and
+/// who's who, and turn them away if they speak another protocol version
+fn welcome_player(
+    stream: &mut TcpStream,
+    version: u16,
+    width: u16,
+    height: u16,
+    rollback: bool,
+    names: &[String; 2],
+) -> io::Result<()> {
+    write_blocking(
+        stream,
+        &Message::Welcome {
+            version: PROTOCOL_VERSION,
+            width,
+            height,
+            rollback,
+            names: names.clone(),
+        },
+    )?;
+    if version != PROTOCOL_VERSION {
+        return Err(handshake_error(format!(
+            "the joining player uses protocol version {}, but this game speaks {}",
+            version, PROTOCOL_VERSION
+        )));
+    }
+    Ok(())
+}
+
+const MAX_JOINING: usize = 8;
+const SPECTATORS_FULL: &str = "the game already has as many spectators as it allows";
+
+/// The host's listening socket once the match is running. It keeps letting
+/// spectators in and streams every snapshot to them. Nothing here blocks,
+/// so a slow or stuck spectator can't hold up the game.
+pub struct Server {
+    listener: TcpListener,
+    max_spectators: usize,
+    width: u16,
+    height: u16,
+    // Players 1 and 2, for spectators to know them by
+    names: [String; 2],
+    // Connections that haven't said hello yet
+    joining: Vec<Connection>,
+    spectators: Vec<Connection>,
+}
+
+impl Server {
+    pub fn spectator_count(&self) -> usize {
+        self.spectators.len()
+    }
+
+    /// Let in new spectators. Each one gets the full state of `game` as soon
+    /// as it's welcomed, rather than waiting for the next snapshot.
+    pub fn poll(&mut self, game: &Game, tick: u32, ack: u32) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            // Don't let connections that never say hello pile up
+            if self.joining.len() >= MAX_JOINING {
+                self.joining.remove(0);
+            }
+            if let Ok(connection) = Connection::new(stream) {
+                self.joining.push(connection);
+            }
+        }
+
+        let mut i = 0;
+        while i < self.joining.len() {
+            let connection = &mut self.joining[i];
+            let Some(message) = connection.receive() else {
+                if connection.is_closed() {
+                    self.joining.swap_remove(i);
+                } else {
+                    i += 1;
+                }
+                continue;
+            };
+
+            let mut connection = self.joining.swap_remove(i);
+            match message {
+                Message::Hello {
+                    spectator: true, ..
+                } if self.spectators.len() < self.max_spectators => {
+                    connection.send(&Message::Welcome {
+                        version: PROTOCOL_VERSION,
+                        width: self.width,
+                        height: self.height,
+                        rollback: false,
+                        names: self.names.clone(),
+                    });
+                    connection.send(&Message::Snapshot(Box::new(Snapshot::capture(game, tick, ack))));
+                    self.spectators.push(connection);
+                    log::info!("a spectator joined, {} watching", self.spectators.len());
+                }
+                Message::Hello {
+                    spectator: true, ..
+                } => connection.send(&Message::Refused(SPECTATORS_FULL.into())),
+                _ => connection.send(&Message::Refused(
+                    "this game already has two players, try --spectate".into(),
+                )),
+            }
+        }
+
+        // Spectators have nothing to say, but reading is how a hang-up shows
+        for spectator in &mut self.spectators {
+            while spectator.receive().is_some() {}
+        }
+        self.spectators.retain(|spectator| !spectator.is_closed());
+    }
+
+    /// Pass a snapshot on to every spectator. One that has fallen behind
+    /// misses it instead of making everyone wait.
+    pub fn broadcast(&mut self, message: &Message) {
+        for spectator in &mut self.spectators {
+            spectator.send(message);
+        }
+    }
+}
+
+/// Connect to a host, as the second player under `name` or as a
+/// spectator. Returns the connection, the playfield size the host picked,
+/// whether the match is played with rollback and what Players 1 and 2 are
+/// called.
+pub fn join(
+    address: &str,
+    width: u16,
+    height: u16,
+    spectator: bool,
+    name: &str,
+) -> io::Result<(Connection, u16, u16, bool, [String; 2])> {
+    let address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| handshake_error(format!("couldn't resolve '{}'", address)))?;
+    let stream = TcpStream::connect_timeout(&address, HANDSHAKE_TIMEOUT)?;
+    say_hello(stream, width, height, spectator, name)
+}
+
+/// Join the match in the room `code` on the relay at `relay`
+pub fn join_online(
+    relay: &str,
+    code: &str,
+    width: u16,
+    height: u16,
+    name: &str,
+) -> io::Result<(Connection, u16, u16, bool, [String; 2])> {
+    say_hello(relay::join_room(relay, code)?, width, height, false, name)
+}
+
+/// The joining side of the handshake, over whatever reached the host
+fn say_hello(
+    mut stream: TcpStream,
+    width: u16,
+    height: u16,
+    spectator: bool,
+    name: &str,
+) -> io::Result<(Connection, u16, u16, bool, [String; 2])> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+    write_blocking(
+        &mut stream,
+        &Message::Hello {
+            version: PROTOCOL_VERSION,
+            width,
+            height,
+            spectator,
+            name: name.to_string(),
+        },
+    )?;
+    // A spectator waits here until the host's match has a second player
+    if spectator {
+        stream.set_read_timeout(None)?;
+    }
+    let (version, width, height, rollback, names) = match read_blocking(&mut stream)? {
+        Message::Welcome {
+            version,
+            width,
+            height,
+            rollback,
+            names,
+        } => (version, width, height, rollback, names),
+        Message::Refused(reason) => return Err(handshake_error(reason)),
+        _ => return Err(handshake_error("host didn't welcome us".to_string())),
+    };
+    if version != PROTOCOL_VERSION {
+        return Err(handshake_error(format!(
+            "the host uses protocol version {}, but this game speaks {}",
+            version, PROTOCOL_VERSION
+        )));
+    }
+
+    Ok((Connection::new(stream)?, width, height, rollback, names))
+}
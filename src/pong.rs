@@ -0,0 +1,3471 @@
+//! The game itself: paddles, balls, powerups and the rules that move them.
+
+use crate::collision::{self, Line, Rect, Segment, Vec2};
+use crate::events::{GameEvent, TimedEvent};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+mod effects;
+
+pub use effects::PowerUpEffect;
+
+pub const PADDLE_HEIGHT: u16 = 5;
+const BALL_SPEED: f32 = 0.75;
+const PADDLE_SPEED: i16 = 1;
+/// How far `Tuning` may go from the defaults. The ball speed is a multiple
+/// of the usual one.
+pub const BALL_SPEED_RANGE: std::ops::RangeInclusive<f32> = 0.5..=2.0;
+pub const PADDLE_SPEED_RANGE: std::ops::RangeInclusive<i16> = 1..=3;
+pub const PADDLE_HEIGHT_RANGE: std::ops::RangeInclusive<u16> = 3..=9;
+/// How far a long rally can raise the speed limit, as a multiple of it
+pub const MAX_BALL_SPEED_RANGE: std::ops::RangeInclusive<f32> = 1.0..=2.0;
+/// How much faster or slower a handicap can make one player's paddle
+pub const HANDICAP_SPEED_RANGE: std::ops::RangeInclusive<f32> = 0.5..=2.0;
+/// Width of the top and bottom paddles in four-player mode
+pub const EDGE_PADDLE_LEN: u16 = 9;
+/// Rows covered by a practice target
+pub const PRACTICE_TARGET_HEIGHT: u16 = 3;
+// Fastest a ball normally goes along each axis, per 60th of a second
+const MAX_BALL_VX: f32 = 1.0;
+const MAX_BALL_VY: f32 = 0.8;
+// Slowest a ball goes across a paddle after a serve or a hit, so it can't
+// settle into going straight back and forth
+const MIN_BALL_VY: f32 = 0.15;
+// Within a rally a moving ball speeds up a little every second, and every
+// return raises the speed limit a little, up to the match's max_ball_speed.
+// Both start over with the next serve.
+const RALLY_SPEEDUP: f32 = 0.01;
+const RALLY_LIMIT_PER_HIT: f32 = 0.03;
+// After this long without touching a paddle the balls speed up every second,
+// up to a limit, until someone misses
+const STALL_AFTER: Duration = Duration::from_secs(20);
+const STALL_SPEEDUP: f32 = 0.05;
+const MAX_STALL_SPEED: f32 = 2.0;
+// Spin a moving paddle puts on a ball, and the most it can carry. Each
+// 60th of a second spin is added to the vertical speed, then fades.
+const SPIN_PER_HIT: f32 = 0.012;
+const MAX_SPIN: f32 = 0.02;
+const SPIN_DECAY: f32 = 0.96;
+// How much spin bends a bounce off the top or bottom
+const SPIN_BOUNCE: f32 = 4.0;
+/// Spin below this doesn't show on the ball
+pub const SPIN_VISIBLE: f32 = 0.004;
+pub const SURVIVAL_LIVES: u8 = 3;
+// Each return in survival speeds the ball up by this much on top of the
+// paddle's usual push
+const SURVIVAL_SPEEDUP: f32 = 1.02;
+/// Returns per survival level. Each level adds a ball and raises the speed
+/// limit.
+pub const SURVIVAL_LEVEL_RETURNS: u32 = 10;
+const SURVIVAL_LEVEL_SPEED: f32 = 0.15;
+// Walls an obstacle course puts up for each point, and how tall each is
+const OBSTACLE_COUNT: std::ops::RangeInclusive<usize> = 2..=4;
+const OBSTACLE_HEIGHT: std::ops::RangeInclusive<u16> = 3..=5;
+// Places tried for each obstacle before doing without it
+const OBSTACLE_TRIES: usize = 16;
+pub const DEFAULT_POWERUP_RATE: f32 = 0.12;
+pub const DEFAULT_MAX_POWERUPS: usize = 2;
+// Places tried for a new powerup before giving up on it
+const POWERUP_SPAWN_TRIES: usize = 16;
+// Cells kept clear between a new powerup and any ball
+const POWERUP_BALL_GAP: i32 = 3;
+// Splitting stops adding balls once there are this many
+const MAX_BALLS: usize = 5;
+const POWERUP_LIFETIME: Duration = Duration::from_secs(15);
+/// Powerups blink for this long before they disappear
+pub const POWERUP_BLINK: Duration = Duration::from_secs(3);
+// How long a Sticky paddle holds a caught ball before firing it by itself
+const STICKY_HOLD: Duration = Duration::from_secs(2);
+pub const PORTAL_HEIGHT: u16 = 3;
+// How long a ball that came out of a portal can't go back into one
+const PORTAL_IMMUNITY: Duration = Duration::from_millis(300);
+// Columns in front of a paddle where an invisible ball shows up again
+const INVISIBLE_REVEAL: f32 = 8.0;
+pub const SHIELD_FLASH: Duration = Duration::from_millis(300);
+/// How long a paddle flashes when it's hit, about 3 frames
+pub const HIT_FLASH: Duration = Duration::from_millis(50);
+/// How long a new score is celebrated for
+pub const SCORE_CELEBRATION: Duration = Duration::from_secs(1);
+/// How long something stays in the event feed, and the most it holds
+pub const FEED_LIFETIME: Duration = Duration::from_secs(5);
+pub const FEED_LENGTH: usize = 3;
+/// The longest player name, in characters
+pub const MAX_NAME_LENGTH: usize = 12;
+// A hit that sends the ball off at this much of the speed limit or more
+// shakes the screen, for 2 frames at first and 3 at the limit
+const HARD_HIT: f32 = 0.9;
+const SHAKE_SHORTEST: Duration = Duration::from_millis(33);
+const SHAKE_LONGEST: Duration = Duration::from_millis(50);
+pub const POWERUP_SIZE: u16 = 5;
+const PHYSICS_DT: f32 = 1.0 / 120.0;
+// Paddle speeds are rows a sixtieth of a second, the way ball speeds are
+// cells, from when each frame moved a paddle once at sixty frames a second
+const PADDLE_RATE: f32 = 60.0;
+pub const MAX_FRAME_DT: f32 = 0.5;
+pub const DEFAULT_SCORE_LIMIT: u16 = 11;
+pub const SCORE_LIMIT_RANGE: std::ops::RangeInclusive<u16> = 1..=99;
+/// How long a timed match can be set to last
+pub const TIME_LIMIT_RANGE: std::ops::RangeInclusive<Duration> =
+    Duration::from_secs(10)..=Duration::from_secs(60 * 60);
+/// A single game decides the match unless more sets are asked for
+pub const DEFAULT_BEST_OF: u16 = 1;
+const SERVE_COUNTDOWN: Duration = Duration::from_secs(3);
+// How far off straight at the receiver a serve goes, in degrees
+const SERVE_ANGLE: std::ops::RangeInclusive<f32> = 15.0..=40.0;
+/// In arcade scoring a point is worth one more for every this many hits in
+/// the rally that ended with it, up to `MAX_RALLY_POINTS`
+pub const RALLY_POINT_HITS: u32 = 5;
+pub const MAX_RALLY_POINTS: u16 = 5;
+// Pulls on the balls from gravity and the wind, in cells per 60th of a
+// second gained each 60th. Together they never go past MAX_PULL, so a ball
+// can still get across and back up the field.
+const GRAVITY: f32 = 0.004;
+const MAX_WIND: f32 = 0.003;
+const MAX_PULL: f32 = 0.006;
+// A headwind slows a ball across the field to no less than this
+const MIN_PULLED_VX: f32 = 0.3;
+// How much more upward a serve goes under gravity
+const GRAVITY_SERVE_LIFT: f32 = 0.25;
+// How often the wind turns, give or take, and how long it takes to get
+// round to its new heading
+const WIND_CHANGE: std::ops::Range<f32> = 8.0..12.0;
+const WIND_TURN: f32 = 2.0;
+/// Columns of bricks across the middle in breakout, and the most hits a
+/// brick can take
+pub const BRICK_COLUMNS: u16 = 3;
+pub const MAX_BRICK_HP: u8 = 3;
+// How fast the MovingWall slides, in rows per 60th of a second
+const SLIDING_WALL_SPEED: f32 = 0.1;
+/// How many rows a dash moves a side paddle, and how long it has to wait
+/// before dashing again
+pub const DASH_CELLS: i16 = 4;
+pub const DASH_COOLDOWN: Duration = Duration::from_secs(2);
+/// How long a side paddle has to be held still charging before its next
+/// hit is a smash
+pub const SMASH_CHARGE: Duration = Duration::from_millis(1500);
+// How much faster a smash sends the ball off, past the usual speed limit,
+// and how many bounces off the edges or walls it keeps that up for
+const SMASH_SPEEDUP: f32 = 1.5;
+const SMASH_BOUNCES: u8 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerUpType {
+    DoublePaddle,
+    CenterWall,
+    TwoSmallWalls,
+    BentPaddle,
+    SplitBall,
+    /// Stops the opponent's paddle for a moment
+    Freeze,
+    /// Halves the speed of every ball, whoever collected it
+    SlowBall,
+    /// Makes the collector's paddle taller
+    GrowPaddle,
+    /// Makes the opponent's paddle shorter
+    ShrinkOpponent,
+    /// The collector's paddle catches the ball and fires it on serve
+    Sticky,
+    /// Two linked portals, one on each half of the field
+    Portal,
+    /// Hides balls heading for the opponent once they cross the center line
+    InvisibleBall,
+    /// A wall on the collector's goal line that sends one ball back
+    Shield,
+    /// Swaps up and down for the opponent's paddle for a few seconds
+    ReverseControls,
+    /// Doubles the collector's next point, in arcade scoring
+    ScoreMultiplier,
+    /// A short wall that slides up and down the middle
+    MovingWall,
+    /// One added with `Game::add_custom_powerup`, by its place in the list
+    Custom(u8),
+}
+
+impl PowerUpType {
+    pub const ALL: [PowerUpType; 16] = [
+        PowerUpType::DoublePaddle,
+        PowerUpType::CenterWall,
+        PowerUpType::TwoSmallWalls,
+        PowerUpType::BentPaddle,
+        PowerUpType::SplitBall,
+        PowerUpType::Freeze,
+        PowerUpType::SlowBall,
+        PowerUpType::GrowPaddle,
+        PowerUpType::ShrinkOpponent,
+        PowerUpType::Sticky,
+        PowerUpType::Portal,
+        PowerUpType::InvisibleBall,
+        PowerUpType::Shield,
+        PowerUpType::ReverseControls,
+        PowerUpType::ScoreMultiplier,
+        PowerUpType::MovingWall,
+    ];
+
+    /// The name used for it in the config file and on the command line
+    pub fn name(self) -> &'static str {
+        self.effect().name()
+    }
+
+    /// The name shown to players. For a custom powerup the game knows what
+    /// it's really called, see `Game::powerup_title`.
+    pub fn title(self) -> &'static str {
+        self.effect().title()
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase().replace('-', "_");
+        Self::ALL.into_iter().find(|ptype| ptype.name() == name)
+    }
+
+    /// Whether it works the same for the top and bottom paddles
+    pub fn suits_any_paddle(self) -> bool {
+        self.effect().suits_any_paddle()
+    }
+}
+
+/// Which powerups turn up and how often
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PowerUpSettings {
+    /// The pool new powerups are picked from. Empty turns them off.
+    pub enabled: Vec<PowerUpType>,
+    /// How many appear per second on average, while there's room for one
+    pub spawn_rate: f32,
+    /// The most that can wait on the field at once
+    pub max_on_field: usize,
+}
+
+impl Default for PowerUpSettings {
+    fn default() -> Self {
+        PowerUpSettings {
+            enabled: PowerUpType::ALL.to_vec(),
+            spawn_rate: DEFAULT_POWERUP_RATE,
+            max_on_field: DEFAULT_MAX_POWERUPS,
+        }
+    }
+}
+
+/// How fast the ball and paddles go and how tall the paddles are
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tuning {
+    /// Serves and the speed limits, as a multiple of the usual ones
+    pub ball_speed: f32,
+    /// Rows a paddle moves for every move it makes
+    pub paddle_speed: i16,
+    /// The side paddles, before any powerup changes them
+    pub paddle_height: u16,
+    /// The most a long rally raises the speed limits to, as a multiple of
+    /// the ones it starts with
+    pub max_ball_speed: f32,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            ball_speed: 1.0,
+            paddle_speed: PADDLE_SPEED,
+            paddle_height: PADDLE_HEIGHT,
+            max_ball_speed: 1.5,
+        }
+    }
+}
+
+/// What one player gets to even up a match against a stronger one
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Handicap {
+    /// Their paddle before any powerup changes it, in place of the match's
+    pub paddle_height: Option<u16>,
+    /// A multiple of how fast their paddle moves
+    pub paddle_speed: f32,
+    /// Points they start every game with
+    pub head_start: u16,
+}
+
+impl Default for Handicap {
+    fn default() -> Self {
+        Handicap {
+            paddle_height: None,
+            paddle_speed: 1.0,
+            head_start: 0,
+        }
+    }
+}
+
+impl Handicap {
+    /// Whether it changes anything
+    pub fn is_set(&self) -> bool {
+        *self != Handicap::default()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AiDifficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl AiDifficulty {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "easy" => Some(AiDifficulty::Easy),
+            "normal" => Some(AiDifficulty::Normal),
+            "hard" => Some(AiDifficulty::Hard),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AiDifficulty::Easy => "Easy",
+            AiDifficulty::Normal => "Normal",
+            AiDifficulty::Hard => "Hard",
+        }
+    }
+
+    /// Number of physics steps the AI's view of the ball lags behind
+    /// reality
+    fn reaction_delay(self) -> usize {
+        match self {
+            AiDifficulty::Easy => 24,
+            AiDifficulty::Normal => 12,
+            AiDifficulty::Hard => 4,
+        }
+    }
+
+    /// Largest random offset (in rows) added to the AI's target
+    fn tracking_error(self) -> f32 {
+        match self {
+            AiDifficulty::Easy => 3.0,
+            AiDifficulty::Normal => 1.5,
+            AiDifficulty::Hard => 0.5,
+        }
+    }
+
+    /// Chance per physics step that the AI doesn't move at all
+    fn idle_chance(self) -> f64 {
+        match self {
+            AiDifficulty::Easy => 0.35,
+            AiDifficulty::Normal | AiDifficulty::Hard => 0.0,
+        }
+    }
+
+    fn predicts_bounces(self) -> bool {
+        self == AiDifficulty::Hard
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PowerUp {
+    pub x: u16,
+    pub y: u16,
+    pub ptype: PowerUpType,
+    /// How long it waits to be collected before disappearing
+    pub remaining: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ActivePowerUp {
+    pub ptype: PowerUpType,
+    pub player: u8,
+    pub remaining: Duration,
+}
+
+/// A powerup that isn't built in. The game spawns it, gives it to whoever
+/// collects it and times it like the rest, but what it does is up to
+/// whoever added it, who hears about it through `Game::custom_calls`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomPowerUp {
+    pub title: String,
+    pub symbol: char,
+    /// A color name, for the frontend to make sense of
+    pub color: String,
+    pub duration: Duration,
+}
+
+/// Something that happened to a custom powerup, for whoever added it to act
+/// on. `player` is the side, like everywhere else.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CustomCall {
+    Collected { index: u8, player: u8 },
+    Expired { index: u8, player: u8 },
+}
+
+/// What custom powerups have changed, which stays changed until they change
+/// it back
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CustomEffects {
+    /// The left and right paddles' heights, instead of the usual
+    #[serde(with = "side_heights")]
+    pub paddle_height: [Option<u16>; 2],
+    pub center_wall: bool,
+}
+
+/// Something worth showing off that just happened, for the frontend to
+/// draw. Nothing in the game reads these back.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EffectKind {
+    PaddleHit,
+    /// A hit near the speed limit, which shakes the screen
+    HardHit,
+    Score,
+}
+
+/// Something worth hearing that just happened, for the frontend to play
+/// and take away
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sound {
+    PaddleHit,
+    WallBounce,
+    PowerUp,
+    Score,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Effect {
+    pub kind: EffectKind,
+    /// The side it happened to, like everything else named for a player
+    pub player: u8,
+    pub remaining: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ball {
+    pub pos: Vec2,
+    pub vel: Vec2,
+    // Position before the last physics step, for render interpolation
+    pub prev: Vec2,
+    /// The player whose Sticky paddle caught this ball, and how far below
+    /// the top of their paddle it sits
+    pub stuck_to: Option<(u8, f32)>,
+    /// How long it's been held
+    pub stuck_for: Duration,
+    /// Time left before it can go through a portal again
+    pub portal_immunity: Duration,
+    /// The player whose paddle touched it last, who gets any powerup it
+    /// collects
+    pub last_hit_by: Option<u8>,
+    /// Curve from the paddle moving as it hit, added to `vy` every 60th
+    /// of a second while it fades
+    pub spin: f32,
+    /// Bounces left before a smashed ball has to keep to the usual speed
+    /// limit again
+    pub smash_bounces: u8,
+}
+
+impl Ball {
+    pub fn new(x: f32, y: f32, vx: f32, vy: f32) -> Self {
+        Ball::at(Vec2::new(x, y), Vec2::new(vx, vy))
+    }
+
+    pub fn at(pos: Vec2, vel: Vec2) -> Self {
+        Ball {
+            pos,
+            vel,
+            prev: pos,
+            stuck_to: None,
+            stuck_for: Duration::ZERO,
+            portal_immunity: Duration::ZERO,
+            last_hit_by: None,
+            spin: 0.0,
+            smash_bounces: 0,
+        }
+    }
+
+    /// Its move in the last physics step
+    pub fn path(&self) -> Segment {
+        Segment::new(self.prev, self.pos)
+    }
+}
+
+/// The part of a paddle a ball bounced off. Only a bent paddle has more
+/// than one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PaddlePart {
+    Flat,
+    /// The slanted rows of a bent paddle, which send the ball off steeper
+    Angled,
+    /// The point of a bent paddle, which sends it off fast and nearly flat
+    Tip,
+}
+
+impl PaddlePart {
+    /// How much faster the ball leaves, and how much each row away from the
+    /// paddle's center adds to its vertical speed
+    fn response(self) -> (f32, f32) {
+        match self {
+            PaddlePart::Flat => (1.05, 0.15),
+            PaddlePart::Angled => (1.1, 0.3),
+            PaddlePart::Tip => (1.2, 0.03),
+        }
+    }
+}
+
+/// A portal column, `PORTAL_HEIGHT` rows tall from `y` down
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PortalRect {
+    pub x: u16,
+    pub y: u16,
+}
+
+impl PortalRect {
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x as i32, self.y as i32, 1, PORTAL_HEIGHT as i32)
+    }
+
+    fn contains(&self, point: Vec2) -> bool {
+        collision::point_in_rect(point, &self.rect())
+    }
+}
+
+/// What put a wall up, which decides how it's drawn
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WallKind {
+    /// The CenterWall powerup
+    Center,
+    /// One of the TwoSmallWalls
+    Small,
+    /// Put up by the obstacle course for the current point
+    Obstacle,
+    /// Where the MovingWall is this step
+    Moving,
+}
+
+/// A one-column wall `height` rows tall from `y` down, that balls bounce
+/// off either side of
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WallRect {
+    pub x: u16,
+    pub y: u16,
+    pub height: u16,
+    pub kind: WallKind,
+}
+
+impl WallRect {
+    fn rows(&self) -> std::ops::Range<i32> {
+        self.y as i32..(self.y + self.height) as i32
+    }
+
+    fn rect(&self) -> Rect {
+        Rect::new(self.x as i32, self.y as i32, 1, self.height as i32)
+    }
+}
+
+/// The wall a MovingWall puts up, which slides along the middle column
+/// from border to border. The walls are rebuilt from it every step, at
+/// whatever row it has got to.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SlidingWall {
+    /// Its top, which is between rows while it moves
+    pub y: f32,
+    pub height: u16,
+    /// Rows per 60th of a second, going down when positive
+    pub speed: f32,
+}
+
+impl SlidingWall {
+    /// The row its top is in
+    pub fn row(&self) -> u16 {
+        self.y.floor() as u16
+    }
+}
+
+/// One cell of the band of bricks across the middle in breakout, which
+/// breaks after `hp` more hits
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Brick {
+    pub x: u16,
+    pub y: u16,
+    pub hp: u8,
+}
+
+/// A solo warm-up against a wall on the right, with a target on the wall
+/// to aim for
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Practice {
+    /// Top row of the target, `PRACTICE_TARGET_HEIGHT` rows tall
+    pub target_y: u16,
+    /// Targets hit since the last missed return
+    pub streak: u32,
+    pub best_streak: u32,
+}
+
+/// One player alone against a wall, with the balls getting faster and more
+/// numerous until they run out of lives
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Survival {
+    pub lives: u8,
+    /// Balls sent back so far
+    pub returns: u32,
+}
+
+impl Survival {
+    pub fn level(&self) -> u32 {
+        self.returns / SURVIVAL_LEVEL_RETURNS
+    }
+}
+
+/// A match played against the clock instead of to a score. Whoever is
+/// ahead when it runs out wins, and a tie goes to the next point.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Timed {
+    pub limit: Duration,
+    /// Match time still to play, which only runs while the match does
+    pub left: Duration,
+    /// Set once the clock runs out on a tie, so the next point wins
+    pub overtime: bool,
+}
+
+/// Wind that pushes every ball along, turning to a new heading every 10
+/// seconds or so
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Wind {
+    /// How hard it blows and which way, on the same scale as gravity
+    pub force: Vec2,
+    /// What it's turning toward, and how long until it picks another
+    pub heading: Vec2,
+    pub change_in: Duration,
+}
+
+/// Which way a paddle slides
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Axis {
+    /// Up and down along a side of the field
+    Vertical,
+    /// Left and right along the top or bottom
+    Horizontal,
+}
+
+/// One paddle as a run of `len` cells from `pos`, on the column (vertical)
+/// or row (horizontal) `line`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Paddle {
+    pub axis: Axis,
+    pub line: u16,
+    pub pos: i16,
+    pub len: u16,
+    pub player: u8,
+}
+
+impl Paddle {
+    fn center(&self) -> f32 {
+        self.pos as f32 + self.len as f32 / 2.0
+    }
+}
+
+/// Which way a player is moving their paddle this frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Dir {
+    #[default]
+    Stay,
+    Up,
+    Down,
+}
+
+impl Dir {
+    /// Holding both keys at once cancels out
+    pub fn from_keys(up: bool, down: bool) -> Self {
+        match (up, down) {
+            (true, false) => Dir::Up,
+            (false, true) => Dir::Down,
+            _ => Dir::Stay,
+        }
+    }
+
+    fn delta(self) -> i16 {
+        match self {
+            Dir::Stay => 0,
+            Dir::Up => -1,
+            Dir::Down => 1,
+        }
+    }
+}
+
+/// Numbers about the match so far, for the game over screen
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatchStats {
+    /// Paddle hits since the last point
+    pub rally: u32,
+    pub longest_rally: u32,
+    /// Paddle hits from the left, right, top and bottom paddles. The side
+    /// ones follow the players when they swap sides.
+    pub hits: [u32; 4],
+    /// Powerups collected from each paddle, following the players the same
+    /// way
+    pub powerups: [u32; 4],
+    /// The fastest any ball has gone, in cells per second
+    pub top_speed: f32,
+    /// Time played, not counting pauses
+    pub duration: Duration,
+}
+
+/// Both players' paddle input for one frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Input {
+    pub p1: Dir,
+    pub p2: Dir,
+    /// Whether each player pressed their serve key, firing a held ball
+    pub p1_serve: bool,
+    pub p2_serve: bool,
+    /// The top and bottom paddles in four-player mode, where `Up` is left
+    /// and `Down` is right
+    pub p3: Dir,
+    pub p4: Dir,
+    /// Which way each side paddle dashes, `Stay` for not at all
+    pub p1_dash: Dir,
+    pub p2_dash: Dir,
+    /// Whether each player is holding their charge key, which keeps their
+    /// paddle still
+    pub p1_charge: bool,
+    pub p2_charge: bool,
+}
+
+/// `name` as it's safe to show anywhere: printable ASCII only, with no
+/// spaces at either end and cut to `MAX_NAME_LENGTH`
+pub fn clean_name(name: &str) -> String {
+    let printable: String = name.chars().filter(|c| matches!(c, ' '..='~')).collect();
+    printable.trim().chars().take(MAX_NAME_LENGTH).collect::<String>().trim_end().to_string()
+}
+
+/// A copy of everything visible about a game at one moment, for tests and
+/// bots to look at without reaching into `Game`, or to write out
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameSnapshot {
+    pub width: u16,
+    pub height: u16,
+    pub p1_y: i16,
+    pub p2_y: i16,
+    pub p1_height: u16,
+    pub p2_height: u16,
+    pub p1_second_y: Option<i16>,
+    pub p2_second_y: Option<i16>,
+    pub p1_bent: bool,
+    pub p2_bent: bool,
+    pub dash_cooldown: [Duration; 2],
+    pub charge: [Duration; 2],
+    pub edge_paddles: Vec<Paddle>,
+    pub balls: Vec<Ball>,
+    pub p1_score: u16,
+    pub p2_score: u16,
+    pub p3_score: u16,
+    pub p4_score: u16,
+    pub practice: Option<Practice>,
+    pub survival: Option<Survival>,
+    pub timed: Option<Timed>,
+    pub gravity: bool,
+    pub wind: Option<Wind>,
+    pub in_deuce: bool,
+    pub best_of: u16,
+    pub p1_sets: u16,
+    pub p2_sets: u16,
+    pub set_winner: Option<u8>,
+    pub swapped: bool,
+    pub winner: Option<u8>,
+    pub paused: bool,
+    pub serve_timer: Option<Duration>,
+    pub powerups: Vec<PowerUp>,
+    pub active_powerups: Vec<ActivePowerUp>,
+    pub center_wall: bool,
+    pub two_small_walls: bool,
+    pub walls: Vec<WallRect>,
+    pub bricks: Vec<Brick>,
+    pub portals: Option<(PortalRect, PortalRect)>,
+    pub sliding_wall: Option<SlidingWall>,
+    pub p1_shield: bool,
+    pub p2_shield: bool,
+    pub shield_flash: Option<(u8, Duration)>,
+    pub arcade: bool,
+    pub p1_double: bool,
+    pub p2_double: bool,
+    pub stats: MatchStats,
+    pub effects: Vec<Effect>,
+    pub feed: Vec<(Duration, String)>,
+}
+
+/// The whole simulation. It never touches the terminal or the clock: time
+/// only passes through `step`, and randomness only comes from the seed.
+/// Fields are public so frontends can draw from them. It serializes with
+/// everything needed to carry on exactly where it was, leaving out only
+/// what's just for show.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Game {
+    pub width: u16,
+    pub height: u16,
+    pub p1_y: i16,
+    pub p2_y: i16,
+    pub p1_height: u16,
+    pub p2_height: u16,
+    pub p1_second_y: Option<i16>,
+    pub p2_second_y: Option<i16>,
+    pub p1_bent: bool,
+    pub p2_bent: bool,
+    /// How long until each side paddle can dash again
+    pub dash_cooldown: [Duration; 2],
+    /// How long each side paddle has been charging a smash, up to
+    /// `SMASH_CHARGE`
+    pub charge: [Duration; 2],
+    // Whether each side paddle is being held still to charge
+    charging: [bool; 2],
+    /// The top (Player 3) and bottom (Player 4) paddles in four-player
+    /// mode, and empty otherwise
+    pub edge_paddles: Vec<Paddle>,
+    pub balls: Vec<Ball>,
+    pub p1_score: u16,
+    pub p2_score: u16,
+    pub p3_score: u16,
+    pub p4_score: u16,
+    /// Set in practice mode, where the right paddle is replaced by a wall
+    pub practice: Option<Practice>,
+    /// Set in survival mode, which also plays against a wall
+    pub survival: Option<Survival>,
+    /// Set in a timed match, which isn't played to `score_limit`
+    pub timed: Option<Timed>,
+    /// Whether the balls fall toward the bottom of the field
+    pub gravity: bool,
+    /// Set when the wind blows on the balls
+    pub wind: Option<Wind>,
+    pub score_limit: u16,
+    /// Whether a game that reaches one point short of `score_limit` all
+    /// has to be won by two
+    pub deuce: bool,
+    /// How many sets the match is played over; the first to win most of
+    /// them takes it
+    pub best_of: u16,
+    /// Sets won by whoever plays the left and right paddles
+    pub p1_sets: u16,
+    pub p2_sets: u16,
+    /// The side that just took a set, while waiting for the next one
+    pub set_winner: Option<u8>,
+    /// Whether Player 1 is on the right paddle this set. Everything else
+    /// named for a player (`p1_y`, `p1_score` and so on) goes by side.
+    pub swapped: bool,
+    // What Players 1 and 2 are called, empty for one that goes by number
+    names: [String; 2],
+    // And what each gets to even the match up
+    handicaps: [Handicap; 2],
+    /// The side that won the match
+    pub winner: Option<u8>,
+    pub paused: bool,
+    pub serve_timer: Option<Duration>,
+    serve_toward: Option<u8>,
+    // The side the first serve of a match goes to, which alternates from
+    // one match to the next
+    opening_serve: u8,
+    pub powerups: Vec<PowerUp>,
+    pub active_powerups: Vec<ActivePowerUp>,
+    pub center_wall: bool,
+    pub two_small_walls: bool,
+    /// Every wall on the field, from the wall powerups and the obstacle
+    /// course
+    pub walls: Vec<WallRect>,
+    /// What's left of the band of bricks in breakout
+    pub bricks: Vec<Brick>,
+    /// The left and right portals while a Portal is active
+    pub portals: Option<(PortalRect, PortalRect)>,
+    /// The wall up while a MovingWall is active
+    pub sliding_wall: Option<SlidingWall>,
+    /// Whether each player has a Shield waiting on their goal line
+    pub p1_shield: bool,
+    pub p2_shield: bool,
+    /// Whose shield just broke, and how much longer it flashes for
+    pub shield_flash: Option<(u8, Duration)>,
+    /// Whether points are worth more the longer the rally, rather than one
+    /// each
+    pub arcade: bool,
+    /// Whether each player's next point counts double, from a Score
+    /// Multiplier
+    pub p1_double: bool,
+    pub p2_double: bool,
+    pub stats: MatchStats,
+    /// Hit flashes and score celebrations still showing
+    #[serde(skip)]
+    pub effects: Vec<Effect>,
+    /// Sounds made since the frontend last took them, each at most once
+    #[serde(skip)]
+    pub sounds: Vec<Sound>,
+    /// What just happened in words, oldest first, with how long ago each
+    /// thing did
+    #[serde(skip)]
+    pub feed: Vec<(Duration, String)>,
+    // Off when nobody is watching, so no effects are kept at all
+    #[serde(skip, default = "effects_on_at_first")]
+    effects_on: bool,
+    /// What's happened since the frontend last took them, kept only while
+    /// something is listening
+    #[serde(skip)]
+    pub events: Vec<TimedEvent>,
+    #[serde(skip)]
+    events_on: bool,
+    // Powerups added from outside, by index, with None for one that's been
+    // turned off. They're added again each time the game starts.
+    #[serde(skip)]
+    custom_powerups: Vec<Option<CustomPowerUp>>,
+    /// What's happened to custom powerups since they were last taken
+    #[serde(skip)]
+    pub custom_calls: Vec<CustomCall>,
+    custom_effects: CustomEffects,
+    powerup_settings: PowerUpSettings,
+    tuning: Tuning,
+    obstacle_course: bool,
+    breakout: bool,
+    // How far each side paddle has moved since the last physics step, and
+    // in the step before, which between them decide the spin it puts on a
+    // ball
+    paddle_motion: [i16; 2],
+    last_paddle_motion: [i16; 2],
+    // The part of a row each paddle has moved beyond where it stands, by
+    // player, which the next move makes up
+    pub(crate) paddle_carry: [f32; 4],
+    // How long since a ball last touched a paddle
+    untouched: Duration,
+    // This point's obstacles, which `walls` also has
+    obstacles: Vec<WallRect>,
+    // How fast a ball may go along each axis; survival raises these
+    max_vx: f32,
+    max_vy: f32,
+    accumulator: f32,
+    // Every random decision comes from here, so a seed replays a match.
+    // It's the same generator rand's StdRng is, named so it can be saved.
+    #[serde(with = "rng_state")]
+    rng: ChaCha12Rng,
+    // Set when balls are blended between network snapshots instead of
+    // physics steps
+    #[serde(skip)]
+    pub(crate) ball_alpha: Option<f32>,
+    /// The computer on the right paddle
+    pub ai: Option<AiDifficulty>,
+    /// And on the left one, so it plays itself
+    pub left_ai: Option<AiDifficulty>,
+    // Where each side's computer player is aiming, by side
+    ai_targets: [VecDeque<f32>; 2],
+    ai_error: [f32; 2],
+    ai_tracking: [bool; 2],
+}
+
+fn effects_on_at_first() -> bool {
+    true
+}
+
+/// The random generator as its seed and how far along its stream it is,
+/// which is all it takes to carry on from the same place
+mod rng_state {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(rng: &ChaCha12Rng, to: S) -> Result<S::Ok, S::Error> {
+        // Far more words than will ever be drawn fit in 64 bits
+        (rng.get_seed(), rng.get_word_pos() as u64).serialize(to)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(from: D) -> Result<ChaCha12Rng, D::Error> {
+        let (seed, word_pos): ([u8; 32], u64) = Deserialize::deserialize(from)?;
+        let mut rng = ChaCha12Rng::from_seed(seed);
+        rng.set_word_pos(word_pos as u128);
+        Ok(rng)
+    }
+}
+
+/// A height for each side by name, since TOML can't leave a gap in an
+/// array
+mod side_heights {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Heights {
+        left: Option<u16>,
+        right: Option<u16>,
+    }
+
+    pub fn serialize<S: Serializer>(heights: &[Option<u16>; 2], to: S) -> Result<S::Ok, S::Error> {
+        let [left, right] = *heights;
+        Heights { left, right }.serialize(to)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(from: D) -> Result<[Option<u16>; 2], D::Error> {
+        let Heights { left, right } = Deserialize::deserialize(from)?;
+        Ok([left, right])
+    }
+}
+
+impl Game {
+    pub fn new(width: u16, height: u16, seed: u64) -> Self {
+        let mut game = Game {
+            width,
+            height,
+            p1_y: (height / 2) as i16,
+            p2_y: (height / 2) as i16,
+            p1_height: PADDLE_HEIGHT,
+            p2_height: PADDLE_HEIGHT,
+            p1_second_y: None,
+            p2_second_y: None,
+            p1_bent: false,
+            p2_bent: false,
+            dash_cooldown: [Duration::ZERO; 2],
+            charge: [Duration::ZERO; 2],
+            charging: [false; 2],
+            edge_paddles: Vec::new(),
+            balls: vec![Ball::new(
+                (width / 2) as f32,
+                (height / 2) as f32,
+                BALL_SPEED,
+                BALL_SPEED * 0.5,
+            )],
+            p1_score: 0,
+            p2_score: 0,
+            p3_score: 0,
+            p4_score: 0,
+            practice: None,
+            survival: None,
+            timed: None,
+            gravity: false,
+            wind: None,
+            score_limit: DEFAULT_SCORE_LIMIT,
+            deuce: true,
+            best_of: DEFAULT_BEST_OF,
+            p1_sets: 0,
+            p2_sets: 0,
+            set_winner: None,
+            swapped: false,
+            names: Default::default(),
+            handicaps: [Handicap::default(); 2],
+            winner: None,
+            paused: false,
+            serve_timer: None,
+            serve_toward: None,
+            opening_serve: 1,
+            powerups: Vec::new(),
+            active_powerups: Vec::new(),
+            center_wall: false,
+            two_small_walls: false,
+            walls: Vec::new(),
+            bricks: Vec::new(),
+            portals: None,
+            sliding_wall: None,
+            p1_shield: false,
+            p2_shield: false,
+            shield_flash: None,
+            arcade: false,
+            p1_double: false,
+            p2_double: false,
+            stats: MatchStats::default(),
+            effects: Vec::new(),
+            sounds: Vec::new(),
+            feed: Vec::new(),
+            effects_on: true,
+            events: Vec::new(),
+            events_on: false,
+            custom_powerups: Vec::new(),
+            custom_calls: Vec::new(),
+            custom_effects: CustomEffects::default(),
+            powerup_settings: PowerUpSettings::default(),
+            tuning: Tuning::default(),
+            obstacle_course: false,
+            breakout: false,
+            paddle_motion: [0; 2],
+            last_paddle_motion: [0; 2],
+            paddle_carry: [0.0; 4],
+            untouched: Duration::ZERO,
+            obstacles: Vec::new(),
+            max_vx: MAX_BALL_VX,
+            max_vy: MAX_BALL_VY,
+            accumulator: 0.0,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+            ball_alpha: None,
+            ai: None,
+            left_ai: None,
+            ai_targets: Default::default(),
+            ai_error: [0.0; 2],
+            ai_tracking: [false; 2],
+        };
+        game.opening_serve = game.rng.gen_range(1..=2);
+        game.reset_ball(None);
+        game
+    }
+
+    /// Park a single ball in the center and start the serve countdown. The
+    /// serve goes toward `toward`, the side that conceded the last point,
+    /// or with `None` to whichever side the match opens with.
+    fn reset_ball(&mut self, toward: Option<u8>) {
+        self.balls.clear();
+        self.balls.push(Ball::new(
+            (self.width / 2) as f32,
+            (self.height / 2) as f32,
+            0.0,
+            0.0,
+        ));
+        self.serve_timer = Some(SERVE_COUNTDOWN);
+        self.serve_toward = toward;
+        self.update_speed_limits();
+    }
+
+    /// Send the waiting ball on its way, `SERVE_ANGLE` off straight at
+    /// whoever it's going to and to one side or the other at random
+    fn launch_ball(&mut self) {
+        let toward = self.serve_toward.unwrap_or(self.opening_serve);
+        let speed = self.serve_speed();
+        let slope = self.rng.gen_range(SERVE_ANGLE).to_radians().tan();
+        let across = match self.rng.gen() {
+            true => speed * slope,
+            false => -speed * slope,
+        };
+        let (vx, vy) = match toward {
+            1 | 2 => {
+                let vy = Self::steepen(across, &mut self.rng);
+                // Under gravity a serve goes up a little more, so it doesn't
+                // drop straight to the bottom
+                let lift = if self.gravity { GRAVITY_SERVE_LIFT } else { 0.0 };
+                (if toward == 1 { -speed } else { speed }, vy - lift)
+            }
+            // At the top or bottom paddle
+            _ => {
+                let vx = Self::steepen(across, &mut self.rng);
+                (vx, if toward == 3 { -speed } else { speed })
+            }
+        };
+        for ball in &mut self.balls {
+            ball.vel = Vec2::new(vx, vy);
+        }
+        self.untouched = Duration::ZERO;
+        let toward = self.player_at(toward);
+        self.emit(GameEvent::Serve { toward });
+    }
+
+    /// How fast a ball leaves on a serve
+    fn serve_speed(&self) -> f32 {
+        BALL_SPEED * self.tuning.ball_speed
+    }
+
+    /// Keep a ball's speed across a paddle to at least `MIN_BALL_VY`,
+    /// picking a way at random when it's exactly flat
+    fn steepen(v: f32, rng: &mut ChaCha12Rng) -> f32 {
+        if v == 0.0 {
+            if rng.gen() {
+                MIN_BALL_VY
+            } else {
+                -MIN_BALL_VY
+            }
+        } else if v.abs() < MIN_BALL_VY {
+            MIN_BALL_VY.copysign(v)
+        } else {
+            v
+        }
+    }
+
+    /// Start a new match on the same field, keeping settings like the AI
+    pub fn rematch(&mut self) {
+        if let Some(practice) = &mut self.practice {
+            practice.streak = 0;
+        }
+        if self.survival.is_some() {
+            self.start_survival();
+        }
+        if let Some(limit) = self.timed.as_ref().map(|timed| timed.limit) {
+            self.start_timed(limit);
+        }
+        self.update_speed_limits();
+        self.p1_sets = 0;
+        self.p2_sets = 0;
+        self.set_winner = None;
+        self.swapped = false;
+        self.winner = None;
+        self.stats = MatchStats::default();
+        self.serve_toward = None;
+        self.opening_serve = 3 - self.opening_serve;
+        self.clear_field();
+    }
+
+    /// Start the next set once one has been taken. Two players swap sides
+    /// so neither keeps the better end of the keyboard; against the
+    /// computer the sides stay put.
+    pub fn next_set(&mut self) {
+        let Some(set_winner) = self.set_winner.take() else {
+            return;
+        };
+        if self.ai.is_none() {
+            self.swapped = !self.swapped;
+            std::mem::swap(&mut self.p1_sets, &mut self.p2_sets);
+            self.stats.hits.swap(0, 1);
+            self.stats.powerups.swap(0, 1);
+            self.serve_toward = Some(set_winner);
+        } else {
+            self.serve_toward = Some(3 - set_winner);
+        }
+        self.stats.rally = 0;
+        self.clear_field();
+    }
+
+    /// Sets a player needs to take the match
+    pub fn sets_to_win(&self) -> u16 {
+        self.best_of / 2 + 1
+    }
+
+    /// Which side a player is on this set
+    pub fn side_of(&self, player: u8) -> u8 {
+        if self.swapped {
+            3 - player
+        } else {
+            player
+        }
+    }
+
+    /// Which player is on a side this set
+    pub fn player_at(&self, side: u8) -> u8 {
+        // Swapping is its own inverse
+        self.side_of(side)
+    }
+
+    /// What a player is called: the name they gave, or "P1" and so on
+    pub fn name(&self, player: u8) -> String {
+        match self.names.get(player.wrapping_sub(1) as usize) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => format!("P{}", player),
+        }
+    }
+
+    /// The same, but "Player 1" and so on for a player with no name, for
+    /// where there's room to say it in full
+    pub fn long_name(&self, player: u8) -> String {
+        match self.given_name(player) {
+            "" => format!("Player {}", player),
+            name => name.to_string(),
+        }
+    }
+
+    pub fn handicap(&self, player: u8) -> Handicap {
+        self.handicaps.get(player.wrapping_sub(1) as usize).copied().unwrap_or_default()
+    }
+
+    /// Even the match up with a handicap for each of Players 1 and 2. Meant
+    /// for before it starts, since it gives the head starts straight away.
+    pub fn set_handicaps(&mut self, handicaps: [Handicap; 2]) {
+        self.handicaps = handicaps;
+        self.give_head_starts();
+        self.apply_powerup_effects();
+    }
+
+    /// Start a game's scores from each side's head start, in the modes with
+    /// a score for each side to start from
+    fn give_head_starts(&mut self) {
+        if self.practice.is_some() || self.survival.is_some() {
+            return;
+        }
+        self.p1_score = self.handicap(self.player_at(1)).head_start;
+        self.p2_score = self.handicap(self.player_at(2)).head_start;
+    }
+
+    /// How tall a side paddle is before any powerup changes it
+    pub(crate) fn base_height(&self, side: u8) -> u16 {
+        let handicap = self.handicap(self.player_at(side));
+        handicap.paddle_height.unwrap_or(self.tuning.paddle_height)
+    }
+
+    /// The name Player 1 or 2 gave, or an empty one if they go by number
+    pub fn given_name(&self, player: u8) -> &str {
+        self.names.get(player.wrapping_sub(1) as usize).map_or("", String::as_str)
+    }
+
+    /// Call Player 1 or 2 `name`, once it's been through `clean_name`
+    pub fn set_name(&mut self, player: u8, name: &str) {
+        if let Some(slot) = self.names.get_mut(player.wrapping_sub(1) as usize) {
+            *slot = clean_name(name);
+        }
+    }
+
+    /// Add or take away the top and bottom paddles for Players 3 and 4
+    pub fn set_four_player(&mut self, on: bool) {
+        self.edge_paddles.clear();
+        if on {
+            let pos = (self.width.saturating_sub(EDGE_PADDLE_LEN) / 2) as i16;
+            for (player, line) in [(3, 1), (4, self.height.saturating_sub(2))] {
+                self.edge_paddles.push(Paddle {
+                    axis: Axis::Horizontal,
+                    line,
+                    pos,
+                    len: EDGE_PADDLE_LEN,
+                    player,
+                });
+            }
+        }
+        self.powerups.retain(|p| !on || p.ptype.suits_any_paddle());
+        self.active_powerups.retain(|p| !on || p.ptype.suits_any_paddle());
+        self.apply_powerup_effects();
+    }
+
+    /// Turn the right paddle into a wall to practice against. The ball is
+    /// always served to the left paddle.
+    pub fn start_practice(&mut self) {
+        let target_y = self.practice_target_y(None);
+        self.practice = Some(Practice {
+            target_y,
+            streak: 0,
+            best_streak: 0,
+        });
+        self.serve_toward = Some(1);
+        self.powerups.clear();
+    }
+
+    /// Play alone against a wall until the lives run out
+    pub fn start_survival(&mut self) {
+        self.survival = Some(Survival {
+            lives: SURVIVAL_LIVES,
+            returns: 0,
+        });
+        self.serve_toward = Some(1);
+        self.powerups.clear();
+    }
+
+    /// Play against the clock for `limit` rather than to the score limit
+    pub fn start_timed(&mut self, limit: Duration) {
+        self.timed = Some(Timed {
+            limit,
+            left: limit,
+            overtime: false,
+        });
+    }
+
+    /// Run a timed match's clock on by `dt`. When it runs out, whoever is
+    /// ahead wins there and then, and on a tie the match goes on until the
+    /// next point. Returns whether that decided it.
+    fn run_clock(&mut self, dt: f32) -> bool {
+        let Some(timed) = &mut self.timed else {
+            return false;
+        };
+        if timed.left.is_zero() {
+            return false;
+        }
+        timed.left = timed.left.saturating_sub(Duration::from_secs_f32(dt));
+        if !timed.left.is_zero() {
+            return false;
+        }
+        let winner = match self.p1_score.cmp(&self.p2_score) {
+            std::cmp::Ordering::Greater => 1,
+            std::cmp::Ordering::Less => 2,
+            std::cmp::Ordering::Equal => {
+                timed.overtime = true;
+                self.add_event("Time! Next point wins".to_string());
+                return false;
+            }
+        };
+        self.add_event("Time!".to_string());
+        self.winner = Some(winner);
+        self.emit(GameEvent::GameOver {
+            winner: self.player_at(winner),
+            scores: self.scores_by_player(),
+        });
+        true
+    }
+
+    /// Start the wind blowing, from still
+    pub fn start_wind(&mut self) {
+        self.wind = Some(Wind::default());
+    }
+
+    /// Turn the wind on by `dt`, easing it round toward its heading and
+    /// picking a new one when it's time
+    fn blow_wind(&mut self, dt: f32) {
+        let Some(wind) = &mut self.wind else {
+            return;
+        };
+        let elapsed = Duration::from_secs_f32(dt);
+        if wind.change_in <= elapsed {
+            let angle = self.rng.gen_range(0.0..std::f32::consts::TAU);
+            let strength = self.rng.gen_range(MAX_WIND / 3.0..=MAX_WIND);
+            wind.heading = Vec2::from_angle(angle, strength);
+            wind.change_in = Duration::from_secs_f32(self.rng.gen_range(WIND_CHANGE));
+        } else {
+            wind.change_in -= elapsed;
+        }
+        let turn = (dt / WIND_TURN).min(1.0);
+        wind.force += (wind.heading - wind.force) * turn;
+    }
+
+    /// Everything pulling on the balls besides their own speed, kept to
+    /// `MAX_PULL` however gravity and the wind line up
+    pub fn pull(&self) -> Vec2 {
+        let mut pull = self.wind.as_ref().map_or(Vec2::ZERO, |wind| wind.force);
+        if self.gravity {
+            pull.y += GRAVITY;
+        }
+        match pull.length() > MAX_PULL {
+            true => pull * (MAX_PULL / pull.length()),
+            false => pull,
+        }
+    }
+
+    /// Count returns in survival. Every level reached raises the speed
+    /// limit and sends another ball in from the middle, toward the wall.
+    fn survival_returns(&mut self, count: u32) {
+        let Some(survival) = &mut self.survival else {
+            return;
+        };
+        let old_level = survival.level();
+        survival.returns += count;
+        let level = survival.level();
+        self.update_speed_limits();
+        let speed = self.serve_speed();
+        for _ in old_level..level {
+            if self.balls.len() >= MAX_BALLS {
+                break;
+            }
+            let vy = self.rng.gen_range(-speed..speed);
+            self.balls.push(Ball::new(
+                (self.width / 2) as f32,
+                (self.height / 2) as f32,
+                speed,
+                vy,
+            ));
+        }
+    }
+
+    /// How fast a ball may go, from the tuning and the survival level
+    fn update_speed_limits(&mut self) {
+        let level = self.survival.as_ref().map_or(0, Survival::level);
+        let scale = self.tuning.ball_speed * (1.0 + SURVIVAL_LEVEL_SPEED * level as f32);
+        let rally = (1.0 + RALLY_LIMIT_PER_HIT * self.stats.rally as f32)
+            .min(self.tuning.max_ball_speed);
+        let scale = scale * rally;
+        self.max_vx = MAX_BALL_VX * scale;
+        self.max_vy = MAX_BALL_VY * scale;
+    }
+
+    /// How fast a ball can go across the field right now, per 60th of a
+    /// second
+    pub fn speed_limit(&self) -> f32 {
+        self.max_vx
+    }
+
+    /// How far a ball has come from a serve's speed toward the fastest a
+    /// rally can make it, from 0 to 1
+    pub fn ball_heat(&self, ball: &Ball) -> f32 {
+        let serve = self.serve_speed();
+        let top = MAX_BALL_VX * self.tuning.ball_speed * self.tuning.max_ball_speed;
+        match top > serve {
+            true => ((ball.vel.x.abs() - serve) / (top - serve)).clamp(0.0, 1.0),
+            false => 0.0,
+        }
+    }
+
+    /// Whether the right paddle is replaced by a wall, in practice and
+    /// survival
+    pub fn has_wall(&self) -> bool {
+        self.practice.is_some() || self.survival.is_some()
+    }
+
+    /// Every cell a ball bounces off a paddle in, as the collision checks
+    /// see them
+    pub fn paddle_cells(&self) -> Vec<(i16, i16)> {
+        let mut cells = Vec::new();
+        let mut side = |x: u16, y: i16, height: u16, bent: bool| {
+            for i in 0..height {
+                let offset = if bent { Self::bent_offset(i, height) } else { 0 };
+                cells.push(((x + offset) as i16, y + i as i16));
+            }
+        };
+        side(2, self.p1_y, self.p1_height, self.p1_bent);
+        if let Some(y) = self.p1_second_y {
+            side(2, y, self.p1_height, false);
+        }
+        if !self.has_wall() {
+            let p2_x = self.width.saturating_sub(3);
+            side(p2_x, self.p2_y, self.p2_height, self.p2_bent);
+            if let Some(y) = self.p2_second_y {
+                side(p2_x, y, self.p2_height, false);
+            }
+        }
+        for paddle in &self.edge_paddles {
+            for i in 0..paddle.len as i16 {
+                cells.push(match paddle.axis {
+                    Axis::Horizontal => (paddle.pos + i, paddle.line as i16),
+                    Axis::Vertical => (paddle.line as i16, paddle.pos + i),
+                });
+            }
+        }
+        cells
+    }
+
+    /// The column of the wall, where the right paddle would be
+    pub fn wall_x(&self) -> u16 {
+        self.width.saturating_sub(3)
+    }
+
+    /// A random place for the practice target, somewhere other than `old`
+    fn practice_target_y(&mut self, old: Option<u16>) -> u16 {
+        let rows = 1..self.height.saturating_sub(PRACTICE_TARGET_HEIGHT + 1).max(2);
+        loop {
+            let y = self.rng.gen_range(rows.clone());
+            if old != Some(y) || rows.len() < 2 {
+                return y;
+            }
+        }
+    }
+
+    pub fn four_player(&self) -> bool {
+        !self.edge_paddles.is_empty()
+    }
+
+    /// What a point would be worth to whoever wins the rally going on. In
+    /// arcade scoring that goes up with every `RALLY_POINT_HITS` hits, and
+    /// otherwise it's always one.
+    pub fn rally_points(&self) -> u16 {
+        match self.arcade {
+            true => (1 + self.stats.rally / RALLY_POINT_HITS).min(MAX_RALLY_POINTS as u32) as u16,
+            false => 1,
+        }
+    }
+
+    /// Whether the game has reached deuce: one point short of the score
+    /// limit all, so it takes two clear points to win, with no powerups
+    /// turning up until it's over. Only games played to a score between two
+    /// players have it.
+    pub fn in_deuce(&self) -> bool {
+        let to_score = !self.four_player() && !self.has_wall() && self.timed.is_none();
+        self.deuce
+            && to_score
+            && self.score_limit > 1
+            && self.p1_score.min(self.p2_score) >= self.score_limit - 1
+    }
+
+    /// The side a point away from winning at deuce
+    pub fn advantage(&self) -> Option<u8> {
+        match self.p1_score.cmp(&self.p2_score) {
+            _ if !self.in_deuce() => None,
+            std::cmp::Ordering::Greater => Some(1),
+            std::cmp::Ordering::Less => Some(2),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    /// Every main paddle on the field, sides first
+    pub fn paddles(&self) -> Vec<Paddle> {
+        let side = |player, line, pos, len| Paddle {
+            axis: Axis::Vertical,
+            line,
+            pos,
+            len,
+            player,
+        };
+        let mut paddles = vec![
+            side(1, 2, self.p1_y, self.p1_height),
+            side(2, self.width.saturating_sub(3), self.p2_y, self.p2_height),
+        ];
+        paddles.extend_from_slice(&self.edge_paddles);
+        paddles
+    }
+
+    /// Everyone's points, Player 1 first
+    pub fn scores(&self) -> [u16; 4] {
+        [self.p1_score, self.p2_score, self.p3_score, self.p4_score]
+    }
+
+    /// Zero the score and take everything off the field for a fresh game
+    fn clear_field(&mut self) {
+        self.p1_score = 0;
+        self.p2_score = 0;
+        self.p3_score = 0;
+        self.p4_score = 0;
+        self.powerups.clear();
+        self.active_powerups.clear();
+        self.p1_second_y = None;
+        self.p2_second_y = None;
+        self.p1_bent = false;
+        self.p2_bent = false;
+        self.center_wall = false;
+        self.two_small_walls = false;
+        self.portals = None;
+        self.p1_shield = false;
+        self.p2_shield = false;
+        self.shield_flash = None;
+        self.p1_double = false;
+        self.p2_double = false;
+        self.custom_effects = CustomEffects::default();
+        self.effects.clear();
+        // The serve that was coming next still is
+        self.reset_ball(self.serve_toward);
+        self.place_obstacles();
+        self.place_bricks();
+        self.give_head_starts();
+        // Which paddle is whose changes between sets, and a handicapped
+        // height goes with its player
+        self.apply_powerup_effects();
+    }
+
+    pub fn powerup_settings(&self) -> &PowerUpSettings {
+        &self.powerup_settings
+    }
+
+    /// Change which powerups turn up. Any of a type that's no longer
+    /// allowed disappear at once, from the field and from the players.
+    pub fn set_powerup_settings(&mut self, mut settings: PowerUpSettings) {
+        // Always pick from the pool in the same order, so the same seed and
+        // settings spawn the same powerups however they were listed
+        settings.enabled = PowerUpType::ALL
+            .into_iter()
+            .filter(|ptype| settings.enabled.contains(ptype))
+            .collect();
+        // Custom powerups come and go with the rest
+        let allowed = |ptype: PowerUpType| {
+            settings.enabled.contains(&ptype)
+                || matches!(ptype, PowerUpType::Custom(_)) && !settings.enabled.is_empty()
+        };
+        self.powerups.retain(|p| allowed(p.ptype));
+        self.active_powerups.retain(|p| allowed(p.ptype));
+        if !settings.enabled.contains(&PowerUpType::Shield) {
+            self.p1_shield = false;
+            self.p2_shield = false;
+            self.shield_flash = None;
+        }
+        if !settings.enabled.contains(&PowerUpType::ScoreMultiplier) {
+            self.p1_double = false;
+            self.p2_double = false;
+        }
+        self.powerup_settings = settings;
+        self.apply_powerup_effects();
+    }
+
+    /// Add a powerup to the ones that turn up, returning the type it's
+    /// spawned as
+    pub fn add_custom_powerup(&mut self, powerup: CustomPowerUp) -> PowerUpType {
+        self.custom_powerups.push(Some(powerup));
+        PowerUpType::Custom(self.custom_powerups.len() as u8 - 1)
+    }
+
+    /// A custom powerup, unless it's been turned off
+    pub fn custom_powerup(&self, index: u8) -> Option<&CustomPowerUp> {
+        self.custom_powerups.get(index as usize).and_then(Option::as_ref)
+    }
+
+    /// Stop a custom powerup turning up, and take away any there are
+    pub fn disable_custom_powerup(&mut self, index: u8) {
+        if let Some(powerup) = self.custom_powerups.get_mut(index as usize) {
+            *powerup = None;
+        }
+        let ptype = PowerUpType::Custom(index);
+        self.powerups.retain(|p| p.ptype != ptype);
+        self.active_powerups.retain(|p| p.ptype != ptype);
+        self.apply_powerup_effects();
+    }
+
+    /// What a powerup is called, custom ones included
+    pub fn powerup_title(&self, ptype: PowerUpType) -> String {
+        match ptype {
+            PowerUpType::Custom(index) => self
+                .custom_powerup(index)
+                .map_or("Custom", |powerup| &powerup.title)
+                .to_string(),
+            ptype => ptype.title().to_string(),
+        }
+    }
+
+    /// Put another ball in play, unless there are as many as there can be
+    /// already. False when there wasn't room.
+    pub fn add_ball(&mut self, ball: Ball) -> bool {
+        if self.balls.len() >= MAX_BALLS {
+            return false;
+        }
+        self.balls.push(ball);
+        true
+    }
+
+    pub fn custom_effects(&self) -> CustomEffects {
+        self.custom_effects
+    }
+
+    pub fn set_custom_effects(&mut self, effects: CustomEffects) {
+        self.custom_effects = effects;
+        self.apply_powerup_effects();
+    }
+
+    pub fn tuning(&self) -> Tuning {
+        self.tuning
+    }
+
+    /// Change the speeds and paddle height, in the middle of a point too.
+    /// Balls already moving speed up or slow down to match, and paddles
+    /// that grow past the edge of the field are pushed back onto it.
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        let ratio = tuning.ball_speed / self.tuning.ball_speed;
+        for ball in &mut self.balls {
+            ball.vel = ball.vel * ratio;
+        }
+        self.tuning = tuning;
+        self.update_speed_limits();
+        self.apply_powerup_effects();
+    }
+
+    /// Keep effects for the frontend, or drop them all for a game nobody
+    /// sees
+    pub fn set_effects(&mut self, on: bool) {
+        self.effects_on = on;
+        if !on {
+            self.effects.clear();
+            self.sounds.clear();
+            self.feed.clear();
+        }
+    }
+
+    /// Keep events for something listening to the match, or stop
+    pub fn set_events(&mut self, on: bool) {
+        self.events_on = on;
+        if !on {
+            self.events.clear();
+        }
+    }
+
+    fn emit(&mut self, event: GameEvent) {
+        if self.events_on {
+            let time = self.stats.duration.as_secs_f32();
+            self.events.push(TimedEvent { time, event });
+        }
+    }
+
+    /// Everyone's points by player rather than by side, for as many
+    /// players as there are
+    fn scores_by_player(&self) -> Vec<u16> {
+        let mut scores = self.scores();
+        if self.swapped {
+            scores.swap(0, 1);
+        }
+        let players = if self.four_player() { 4 } else { 2 };
+        scores[..players].to_vec()
+    }
+
+    /// Put `text` in the event feed, pushing out the oldest once it's full
+    pub fn add_event(&mut self, text: String) {
+        if !self.effects_on {
+            return;
+        }
+        if self.feed.len() >= FEED_LENGTH {
+            self.feed.remove(0);
+        }
+        self.feed.push((Duration::ZERO, text));
+    }
+
+    /// Make `sound`, unless it's already waiting to be played
+    pub fn add_sound(&mut self, sound: Sound) {
+        if self.effects_on && !self.sounds.contains(&sound) {
+            self.sounds.push(sound);
+        }
+    }
+
+    /// Show `kind` for `player`, starting it over if it's already showing
+    pub fn add_effect(&mut self, kind: EffectKind, player: u8) {
+        let remaining = match kind {
+            EffectKind::PaddleHit => HIT_FLASH,
+            EffectKind::HardHit => SHAKE_LONGEST,
+            EffectKind::Score => SCORE_CELEBRATION,
+        };
+        self.push_effect(kind, player, remaining);
+    }
+
+    fn push_effect(&mut self, kind: EffectKind, player: u8, remaining: Duration) {
+        if !self.effects_on {
+            return;
+        }
+        self.effects.retain(|e| e.kind != kind || e.player != player);
+        self.effects.push(Effect {
+            kind,
+            player,
+            remaining,
+        });
+    }
+
+    /// Run effects down in real time, so they wear off even while the game
+    /// is held
+    pub fn tick_effects(&mut self, dt: f32) {
+        let elapsed = Duration::from_secs_f32(dt.min(MAX_FRAME_DT));
+        for effect in &mut self.effects {
+            effect.remaining = effect.remaining.saturating_sub(elapsed);
+        }
+        self.effects.retain(|e| !e.remaining.is_zero());
+        for (age, _) in &mut self.feed {
+            *age += elapsed;
+        }
+        self.feed.retain(|(age, _)| *age < FEED_LIFETIME);
+    }
+
+    /// The effect of `kind` showing for `player`, if there is one
+    pub fn effect(&self, kind: EffectKind, player: u8) -> Option<&Effect> {
+        self.effects.iter().find(|e| e.kind == kind && e.player == player)
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Whether paddles should respond to the players right now
+    pub fn accepts_input(&self) -> bool {
+        !self.is_halted()
+    }
+
+    /// Change the playfield size, scaling everything on it proportionally
+    pub fn resize(&mut self, width: u16, height: u16) {
+        if width == self.width && height == self.height {
+            return;
+        }
+
+        // Coming back from no room at all starts from the top left
+        let scale_x = width as f32 / self.width.max(1) as f32;
+        let scale_y = height as f32 / self.height.max(1) as f32;
+        let old_bricks = self.brick_columns();
+        self.width = width;
+        self.height = height;
+
+        // The band stays in the middle, losing any rows that no longer fit
+        let new_bricks = self.brick_columns();
+        for brick in &mut self.bricks {
+            brick.x = brick.x - old_bricks.start + new_bricks.start;
+        }
+        self.bricks.retain(|brick| brick.y < height.saturating_sub(1));
+
+        self.p1_y = (self.p1_y as f32 * scale_y) as i16;
+        self.p2_y = (self.p2_y as f32 * scale_y) as i16;
+        if let Some(practice) = &mut self.practice {
+            let max_y = height.saturating_sub(PRACTICE_TARGET_HEIGHT + 1).max(1);
+            practice.target_y = ((practice.target_y as f32 * scale_y) as u16).clamp(1, max_y);
+        }
+        for paddle in &mut self.edge_paddles {
+            paddle.pos = (paddle.pos as f32 * scale_x) as i16;
+            if paddle.player == 4 {
+                paddle.line = height.saturating_sub(2);
+            }
+        }
+        self.clamp_paddles();
+
+        for ball in &mut self.balls {
+            ball.pos.x = match ball.stuck_to {
+                // Stay in front of the paddle, which moved with the edge
+                Some((1, _)) => 3.0,
+                Some(_) => width.saturating_sub(4) as f32,
+                None => (ball.pos.x * scale_x).clamp(0.0, width.saturating_sub(1) as f32),
+            };
+            ball.pos.y = (ball.pos.y * scale_y).clamp(0.0, height.saturating_sub(1) as f32);
+            ball.prev = ball.pos;
+        }
+        self.carry_stuck_balls();
+
+        let reach = POWERUP_SIZE / 2;
+        self.powerups
+            .retain(|p| p.x + reach < width && p.y + reach < height.saturating_sub(1));
+        if let Some(portals) = &mut self.portals {
+            for portal in [&mut portals.0, &mut portals.1] {
+                portal.x = (portal.x as f32 * scale_x) as u16;
+                portal.y = ((portal.y as f32 * scale_y) as u16)
+                    .clamp(1, height.saturating_sub(PORTAL_HEIGHT + 1).max(1));
+            }
+        }
+
+        for targets in &mut self.ai_targets {
+            targets.clear();
+        }
+        if let Some(wall) = &mut self.sliding_wall {
+            let lowest = height.saturating_sub(wall.height + 1).max(1) as f32;
+            wall.y = (wall.y * scale_y).clamp(1.0, lowest);
+        }
+        // The old layout may not fit, so put up a new one
+        self.place_obstacles();
+        self.apply_powerup_effects();
+    }
+
+    /// Nothing moves once a set or the match is decided, or while paused
+    pub fn is_halted(&self) -> bool {
+        self.winner.is_some() || self.set_winner.is_some() || self.paused
+    }
+
+    /// Run one frame: advance the simulation by `dt` seconds in fixed-size
+    /// physics steps, moving the paddles for `input` on each (the AI moves a
+    /// paddle instead when it's playing that side), so they go as far in a
+    /// second at any frame rate. Dashes and serves happen once, up front.
+    /// Leftover time carries over to the next frame.
+    pub fn step(&mut self, input: Input, dt: f32) {
+        self.tick_effects(dt);
+        if self.is_halted() {
+            self.accumulator = 0.0;
+            return;
+        }
+
+        if self.left_ai.is_none() {
+            self.set_charging(1, input.p1_charge);
+            self.dash_paddle(1, input.p1_dash);
+        }
+        if self.ai.is_none() {
+            self.set_charging(2, input.p2_charge);
+            self.dash_paddle(2, input.p2_dash);
+        }
+        if input.p1_serve {
+            self.release_balls(1);
+        }
+        if input.p2_serve {
+            self.release_balls(2);
+        }
+        self.carry_stuck_balls();
+
+        self.accumulator += dt.min(MAX_FRAME_DT);
+        while self.accumulator >= PHYSICS_DT {
+            // A point can end the set partway through the frame
+            if self.is_halted() {
+                self.accumulator = 0.0;
+                break;
+            }
+            match self.left_ai {
+                Some(_) => self.update_ai(1),
+                None => self.move_paddle(1, input.p1, PHYSICS_DT),
+            }
+            match self.ai {
+                Some(_) => self.update_ai(2),
+                None => self.move_paddle(2, input.p2, PHYSICS_DT),
+            }
+            if self.four_player() {
+                self.move_paddle(3, input.p3, PHYSICS_DT);
+                self.move_paddle(4, input.p4, PHYSICS_DT);
+            }
+            self.carry_stuck_balls();
+            self.physics_step(PHYSICS_DT);
+            self.accumulator -= PHYSICS_DT;
+            self.last_paddle_motion = std::mem::take(&mut self.paddle_motion);
+        }
+    }
+
+    /// How far between the last two physics steps to draw the balls
+    pub fn interpolation(&self) -> f32 {
+        self.ball_alpha
+            .unwrap_or((self.accumulator / PHYSICS_DT).clamp(0.0, 1.0))
+    }
+
+    pub fn snapshot(&self) -> GameSnapshot {
+        GameSnapshot {
+            width: self.width,
+            height: self.height,
+            p1_y: self.p1_y,
+            p2_y: self.p2_y,
+            p1_height: self.p1_height,
+            p2_height: self.p2_height,
+            p1_second_y: self.p1_second_y,
+            p2_second_y: self.p2_second_y,
+            p1_bent: self.p1_bent,
+            p2_bent: self.p2_bent,
+            dash_cooldown: self.dash_cooldown,
+            charge: self.charge,
+            edge_paddles: self.edge_paddles.clone(),
+            balls: self.balls.clone(),
+            p1_score: self.p1_score,
+            p2_score: self.p2_score,
+            p3_score: self.p3_score,
+            p4_score: self.p4_score,
+            practice: self.practice.clone(),
+            survival: self.survival.clone(),
+            timed: self.timed.clone(),
+            gravity: self.gravity,
+            wind: self.wind.clone(),
+            in_deuce: self.in_deuce(),
+            best_of: self.best_of,
+            p1_sets: self.p1_sets,
+            p2_sets: self.p2_sets,
+            set_winner: self.set_winner,
+            swapped: self.swapped,
+            winner: self.winner,
+            paused: self.paused,
+            serve_timer: self.serve_timer,
+            powerups: self.powerups.clone(),
+            active_powerups: self.active_powerups.clone(),
+            center_wall: self.center_wall,
+            two_small_walls: self.two_small_walls,
+            walls: self.walls.clone(),
+            bricks: self.bricks.clone(),
+            portals: self.portals,
+            sliding_wall: self.sliding_wall,
+            p1_shield: self.p1_shield,
+            p2_shield: self.p2_shield,
+            shield_flash: self.shield_flash,
+            arcade: self.arcade,
+            p1_double: self.p1_double,
+            p2_double: self.p2_double,
+            stats: self.stats.clone(),
+            effects: self.effects.clone(),
+            feed: self.feed.clone(),
+        }
+    }
+
+    fn physics_step(&mut self, dt: f32) {
+        if self.is_halted() {
+            return;
+        }
+        // The clock counts steps rather than the time on the wall, so it
+        // stops whenever the match does
+        if self.run_clock(dt) {
+            return;
+        }
+        self.blow_wind(dt);
+        for cooldown in &mut self.dash_cooldown {
+            *cooldown = cooldown.saturating_sub(Duration::from_secs_f32(dt));
+        }
+        for (charge, _) in self.charge.iter_mut().zip(self.charging).filter(|(_, on)| *on) {
+            *charge = (*charge + Duration::from_secs_f32(dt)).min(SMASH_CHARGE);
+        }
+
+        // Hold the ball in the center until the serve countdown runs out
+        if let Some(remaining) = self.serve_timer {
+            let remaining = remaining.saturating_sub(Duration::from_secs_f32(dt));
+            if !remaining.is_zero() {
+                self.serve_timer = Some(remaining);
+                self.apply_powerup_effects();
+                return;
+            }
+            self.serve_timer = None;
+            self.launch_ball();
+        }
+
+        // Spawn powerups, though not while a serve is counting down
+        let settings = &self.powerup_settings;
+        let four_player = self.four_player();
+        let mut pool: Vec<PowerUpType> = settings
+            .enabled
+            .iter()
+            .copied()
+            .filter(|ptype| !four_player || ptype.suits_any_paddle())
+            .filter(|ptype| self.arcade || !ptype.effect().arcade_only())
+            .filter(|ptype| !self.breakout || !ptype.effect().uses_middle())
+            .collect();
+        if !settings.enabled.is_empty() {
+            let custom = self.custom_powerups.iter().enumerate();
+            let custom = custom.filter(|(_, powerup)| powerup.is_some());
+            pool.extend(custom.map(|(i, _)| PowerUpType::Custom(i as u8)));
+        }
+        if self.rng.gen::<f32>() < settings.spawn_rate * dt
+            && self.powerups.len() < settings.max_on_field
+            && !pool.is_empty()
+            && self.serve_timer.is_none()
+            && !self.has_wall()
+            && !self.in_deuce()
+        {
+            if let Some((x, y)) = self.powerup_spot() {
+                let ptype = pool[self.rng.gen_range(0..pool.len())];
+                self.powerups.push(PowerUp {
+                    x,
+                    y,
+                    ptype,
+                    remaining: POWERUP_LIFETIME,
+                });
+                log::debug!("{:?} turned up at ({}, {})", ptype, x, y);
+                self.emit(GameEvent::PowerupSpawn { powerup: ptype, x, y });
+            }
+        }
+
+        // The sliding wall moves first, so balls are checked against it
+        // where it is now
+        if self.slide_wall(dt) {
+            self.rebuild_walls();
+        }
+
+        // Extract data needed for collision checks
+        let width = self.width;
+        let height = self.height;
+        let p2_x = width.saturating_sub(3);
+        let p1_y = self.p1_y;
+        let p2_y = self.p2_y;
+        let p1_height = self.p1_height;
+        let p2_height = self.p2_height;
+        let p1_second_y = self.p1_second_y;
+        let p2_second_y = self.p2_second_y;
+        let p1_bent = self.p1_bent;
+        let p2_bent = self.p2_bent;
+        let walls = self.walls.clone();
+        let speed_scale = self.ball_speed_scale();
+        let speed_of = |ball: &Ball| ball.vel.length() * speed_scale * 60.0;
+        let mut p1_shield = self.p1_shield;
+        let mut p2_shield = self.p2_shield;
+        let edge_paddles = self.edge_paddles.clone();
+        let practice_target = self.practice.as_ref().map(|p| p.target_y);
+        let mut target_hits = 0;
+        let has_wall = self.has_wall();
+        let survival = self.survival.is_some();
+        let (max_vx, max_vy) = (self.max_vx, self.max_vy);
+        // The bottom edge, when the top and bottom bounce rather than being
+        // goals
+        let edge = (!four_player).then_some((height - 1) as f32);
+        let step = speed_scale * dt * 60.0;
+        let [p1_motion, p2_motion] = [0, 1].map(|side| {
+            self.paddle_motion[side].saturating_add(self.last_paddle_motion[side])
+        });
+        let pull = self.pull();
+        // Under gravity a ball always comes off the bottom with enough to
+        // hop, rather than rolling along it
+        let floor_hop = if self.gravity { MIN_BALL_VY } else { 0.0 };
+
+        // A Sticky paddle catches one ball at a time
+        let holding = |player| {
+            self.balls
+                .iter()
+                .any(|b| b.stuck_to.is_some_and(|(p, _)| p == player))
+        };
+        let mut p1_catches = self.has_powerup(PowerUpType::Sticky, 1) && !holding(1);
+        let mut p2_catches = self.has_powerup(PowerUpType::Sticky, 2) && !holding(2);
+        // A full charge smashes the next ball back, just the one
+        let [mut p1_smashes, mut p2_smashes] = self.charge.map(|charge| charge >= SMASH_CHARGE);
+
+        // Update balls
+        // Every ball that gets past a paddle counts, and only those leave
+        let mut scored = Vec::new();
+        let mut hits = [0; 4];
+        // Who each hit was from, and how fast the ball came in
+        let mut hit_speeds = Vec::new();
+        // The hardest hit this step, as a share of the speed limit, and who
+        // made it
+        let mut hardest = (0.0, 0);
+        let mut top_speed = self.stats.top_speed;
+        let mut bounced = false;
+        // Who broke each brick that went this step
+        let mut bricks_broken = Vec::new();
+        let mut smashes = Vec::new();
+
+        for (i, ball) in self.balls.iter_mut().enumerate() {
+            // A caught ball rides with its paddle until it's fired
+            if ball.stuck_to.is_some() {
+                continue;
+            }
+            ball.prev = ball.pos;
+            ball.vel = ball.vel * (1.0 + RALLY_SPEEDUP * dt);
+            ball.pos += ball.vel * step;
+            ball.vel.y += ball.spin * step;
+            ball.spin *= SPIN_DECAY.powf(dt * 60.0);
+            ball.vel += pull * step;
+            if ball.vel.x * pull.x < 0.0 && ball.vel.x.abs() < MIN_PULLED_VX {
+                ball.vel.x = MIN_PULLED_VX.copysign(ball.vel.x);
+            }
+
+            // Everything the ball crosses on the way is checked along its
+            // straight path, folded back into the field where it would have
+            // bounced off the top or bottom first. The bounce itself comes
+            // last.
+
+            // Walls never overlap, so a ball can only bounce off one
+            for wall in &walls {
+                if let Some((face, _)) = Self::sweep_column(ball, wall.rect(), edge) {
+                    Self::reflect_off_face(ball, face);
+                    ball.smash_bounces = ball.smash_bounces.saturating_sub(1);
+                    bounced = true;
+                    break;
+                }
+            }
+
+            // The wall returns everything. In practice it counts the ones
+            // that land on the target.
+            if has_wall {
+                let wall_x = width.saturating_sub(3);
+                let column = Rect::new(wall_x as i32, 0, 1, height as i32);
+                let hit = Self::sweep_column(ball, column, edge);
+                if let Some((face, hit_y)) = hit {
+                    Self::reflect_off_face(ball, face);
+                    ball.smash_bounces = ball.smash_bounces.saturating_sub(1);
+                    bounced = true;
+                    let row = practice_target.map(|y| hit_y.floor() as i32 - y as i32);
+                    if row.is_some_and(|row| (0..PRACTICE_TARGET_HEIGHT as i32).contains(&row)) {
+                        target_hits += 1;
+                    }
+                }
+            }
+
+            // Bricks only stop a ball somebody has hit, so a serve goes
+            // through them
+            let brick = ball.last_hit_by.and_then(|_| Self::brick_hit(ball, &self.bricks, edge));
+            if let Some((index, line)) = brick {
+                collision::reflect(&mut ball.pos, &mut ball.vel, line);
+                bounced = true;
+                let brick = &mut self.bricks[index];
+                brick.hp -= 1;
+                if brick.hp == 0 {
+                    bricks_broken.extend(ball.last_hit_by);
+                }
+            }
+
+            // P1 paddle collision
+            if ball.vel.x < 0.0 {
+                // Which paddle was hit decides the angle it leaves at
+                let hit =
+                    Self::check_paddle_collision_static(ball, 2, p1_y, p1_height, p1_bent, edge)
+                    .map(|hit| (hit, p1_y))
+                    .or_else(|| {
+                        p1_second_y.and_then(|y| {
+                            Self::check_paddle_collision_static(ball, 2, y, p1_height, false, edge)
+                                .map(|hit| (hit, y))
+                        })
+                    });
+                if hit.is_some() {
+                    ball.last_hit_by = Some(1);
+                    hits[0] += 1;
+                    hit_speeds.push((1, speed_of(ball)));
+                }
+                if let Some(((face, hit_y, _), _)) = hit.filter(|_| p1_catches) {
+                    p1_catches = false;
+                    Self::catch_ball(ball, 1, face, hit_y, p1_y);
+                } else if let Some(((face, hit_y, segment), paddle_y)) = hit {
+                    let rest = Self::rest_of_move(ball, face);
+                    Self::reflect_off_face(ball, face);
+                    let (speedup, angle) = segment.response();
+                    ball.vel.x *= speedup;
+                    if survival {
+                        ball.vel.x *= SURVIVAL_SPEEDUP;
+                    }
+                    ball.smash_bounces = 0;
+                    if p1_smashes {
+                        p1_smashes = false;
+                        ball.vel.x *= SMASH_SPEEDUP;
+                        ball.smash_bounces = SMASH_BOUNCES;
+                        smashes.push(1);
+                    }
+                    if ball.vel.x.abs() / max_vx > hardest.0 {
+                        hardest = (ball.vel.x.abs() / max_vx, 1);
+                    }
+                    let paddle_center = paddle_y as f32 + p1_height as f32 / 2.0;
+                    ball.vel.y = Self::steepen((hit_y - paddle_center) * angle, &mut self.rng);
+                    ball.spin = Self::spin_from(p1_motion);
+                    // Carry on from the paddle at the new angle
+                    ball.pos.y = hit_y + ball.vel.y * step * rest;
+                }
+            }
+
+            // P2 paddle collision
+            if ball.vel.x > 0.0 && !has_wall {
+                // Which paddle was hit decides the angle it leaves at
+                let hit =
+                    Self::check_paddle_collision_static(ball, p2_x, p2_y, p2_height, p2_bent, edge)
+                    .map(|hit| (hit, p2_y))
+                    .or_else(|| {
+                        p2_second_y.and_then(|y| {
+                            let hit = Self::check_paddle_collision_static(
+                                ball, p2_x, y, p2_height, false, edge,
+                            );
+                            hit.map(|hit| (hit, y))
+                        })
+                    });
+                if hit.is_some() {
+                    ball.last_hit_by = Some(2);
+                    hits[1] += 1;
+                    hit_speeds.push((2, speed_of(ball)));
+                }
+                if let Some(((face, hit_y, _), _)) = hit.filter(|_| p2_catches) {
+                    p2_catches = false;
+                    Self::catch_ball(ball, 2, face - 1.0, hit_y, p2_y);
+                } else if let Some(((face, hit_y, segment), paddle_y)) = hit {
+                    let rest = Self::rest_of_move(ball, face);
+                    Self::reflect_off_face(ball, face);
+                    let (speedup, angle) = segment.response();
+                    ball.vel.x *= speedup;
+                    ball.smash_bounces = 0;
+                    if p2_smashes {
+                        p2_smashes = false;
+                        ball.vel.x *= SMASH_SPEEDUP;
+                        ball.smash_bounces = SMASH_BOUNCES;
+                        smashes.push(2);
+                    }
+                    if ball.vel.x.abs() / max_vx > hardest.0 {
+                        hardest = (ball.vel.x.abs() / max_vx, 2);
+                    }
+                    let paddle_center = paddle_y as f32 + p2_height as f32 / 2.0;
+                    ball.vel.y = Self::steepen((hit_y - paddle_center) * angle, &mut self.rng);
+                    ball.spin = Self::spin_from(p2_motion);
+                    ball.pos.y = hit_y + ball.vel.y * step * rest;
+                }
+            }
+
+            // Top and bottom paddles in four-player mode
+            for paddle in &edge_paddles {
+                let toward = if paddle.player == 3 { ball.vel.y < 0.0 } else { ball.vel.y > 0.0 };
+                if !toward {
+                    continue;
+                }
+                if let Some((face, hit_x)) = Self::check_edge_paddle_collision(ball, paddle) {
+                    ball.last_hit_by = Some(paddle.player);
+                    hits[paddle.player as usize - 1] += 1;
+                    hit_speeds.push((paddle.player, speed_of(ball)));
+                    Self::reflect_off_row(ball, face);
+                    let (speedup, angle) = PaddlePart::Flat.response();
+                    ball.vel.y *= speedup;
+                    ball.vel.x = Self::steepen((hit_x - paddle.center()) * angle, &mut self.rng);
+                }
+            }
+
+            // Top/bottom bounce, unless those are goals too. The ball ends
+            // up well inside the field so it can't slide along the edge,
+            // and spin bends the bounce a little.
+            if let Some(edge) = edge {
+                let bounce = if ball.pos.y <= 0.0 {
+                    Some(((-ball.pos.y).max(0.5), ball.vel.y.abs()))
+                } else if ball.pos.y >= edge {
+                    let vy = -ball.vel.y.abs().max(floor_hop);
+                    Some(((2.0 * edge - ball.pos.y).min(edge - 0.5), vy))
+                } else {
+                    None
+                };
+                if let Some((y, vy)) = bounce {
+                    ball.pos.y = y.clamp(0.0, edge);
+                    ball.vel.y = vy + ball.spin * SPIN_BOUNCE;
+                    ball.smash_bounces = ball.smash_bounces.saturating_sub(1);
+                    bounced = true;
+                }
+            }
+
+            // Scoring against whoever owns the edge, unless a shield on
+            // that goal line sends the ball back. A shield only stops one
+            // ball, however many arrive at once
+            if ball.pos.x <= 0.0 && p1_shield {
+                p1_shield = false;
+                ball.pos.x = 0.0;
+                ball.vel.x = ball.vel.x.abs();
+                bounced = true;
+            } else if ball.pos.x >= (width - 1) as f32 && p2_shield {
+                p2_shield = false;
+                ball.pos.x = (width - 1) as f32;
+                ball.vel.x = -ball.vel.x.abs();
+                bounced = true;
+            } else if ball.pos.x <= 0.0 {
+                scored.push((i, 1, ball.last_hit_by));
+            } else if ball.pos.x >= (width - 1) as f32 {
+                scored.push((i, 2, ball.last_hit_by));
+            } else if four_player && ball.pos.y <= 0.0 {
+                scored.push((i, 3, ball.last_hit_by));
+            } else if four_player && ball.pos.y >= (height - 1) as f32 {
+                scored.push((i, 4, ball.last_hit_by));
+            }
+            if let Some(&(_, conceded, _)) = scored.last().filter(|&&(index, _, _)| index == i) {
+                log::info!(
+                    "ball {} got past side {} at ({:.2}, {:.2}) going ({:.3}, {:.3})",
+                    i,
+                    conceded,
+                    ball.pos.x,
+                    ball.pos.y,
+                    ball.vel.x,
+                    ball.vel.y
+                );
+            }
+
+            // Clamp ball speed, more loosely for a while after a smash
+            let limit = match ball.smash_bounces {
+                0 => 1.0,
+                _ => SMASH_SPEEDUP,
+            };
+            ball.vel.x = ball.vel.x.clamp(-max_vx * limit, max_vx * limit);
+            ball.vel.y = ball.vel.y.clamp(-max_vy * limit, max_vy * limit);
+            top_speed = top_speed.max(speed_of(ball));
+        }
+
+        for side in smashes {
+            self.charge[side as usize - 1] = Duration::ZERO;
+            let name = self.name(self.player_at(side));
+            self.add_event(format!("{} smashed it", name));
+        }
+
+        // Each brick is a point to whoever broke it, though the match is
+        // only decided once the rally is over
+        self.bricks.retain(|brick| brick.hp > 0);
+        for side in bricks_broken {
+            match side {
+                1 => self.p1_score += 1,
+                _ => self.p2_score += 1,
+            }
+            let (left, right) = (self.p1_score, self.p2_score);
+            let name = self.name(self.player_at(side));
+            self.add_event(format!("{} broke a brick +1 {}-{}", name, left, right));
+        }
+
+        let broke = [self.p1_shield && !p1_shield, self.p2_shield && !p2_shield];
+        for (side, broke) in [1, 2].into_iter().zip(broke) {
+            if broke {
+                self.shield_flash = Some((side, SHIELD_FLASH));
+                self.add_event(format!("{}'s shield broke", self.name(self.player_at(side))));
+            }
+        }
+        self.p1_shield = p1_shield;
+        self.p2_shield = p2_shield;
+
+        let (share, player) = hardest;
+        if share >= HARD_HIT {
+            let share = ((share.min(1.0) - HARD_HIT) / (1.0 - HARD_HIT)).clamp(0.0, 1.0);
+            let longer = (SHAKE_LONGEST - SHAKE_SHORTEST).mul_f32(share);
+            self.push_effect(EffectKind::HardHit, player, SHAKE_SHORTEST + longer);
+        }
+        for (player, count) in hits.into_iter().enumerate() {
+            if count > 0 {
+                self.add_effect(EffectKind::PaddleHit, player as u8 + 1);
+                self.add_sound(Sound::PaddleHit);
+            }
+            self.stats.hits[player] += count;
+            self.stats.rally += count;
+        }
+        self.stats.longest_rally = self.stats.longest_rally.max(self.stats.rally);
+        for (side, speed) in hit_speeds {
+            let player = self.player_at(side);
+            self.emit(GameEvent::PaddleHit { player, speed });
+        }
+        if bounced {
+            self.add_sound(Sound::WallBounce);
+            self.emit(GameEvent::WallBounce);
+        }
+        // What the rally was worth, which the points scored now are
+        let rally_points = self.rally_points();
+        self.update_speed_limits();
+        if hits.iter().any(|&count| count > 0) || !scored.is_empty() {
+            self.untouched = Duration::ZERO;
+        } else {
+            self.untouched += Duration::from_secs_f32(dt);
+        }
+        self.stats.top_speed = top_speed;
+        self.stats.duration += Duration::from_secs_f32(dt);
+
+        let mut index = 0;
+        self.balls.retain(|_| {
+            let keep = !scored.iter().any(|&(i, _, _)| i == index);
+            index += 1;
+            keep
+        });
+
+        self.teleport_balls(dt);
+        // Nothing should ever leave a ball off the field or going nowhere,
+        // but when something does it's worth knowing how
+        let (right, bottom) = ((width - 1) as f32, (height - 1) as f32);
+        for (i, ball) in self.balls.iter().enumerate() {
+            let Vec2 { x, y } = ball.pos;
+            let outside = !(0.0..=right).contains(&x) || !(0.0..=bottom).contains(&y);
+            if outside || !ball.vel.x.is_finite() || !ball.vel.y.is_finite() {
+                log::warn!(
+                    "ball {} is at ({:.2}, {:.2}) going ({:.3}, {:.3}) on a {}x{} field",
+                    i,
+                    x,
+                    y,
+                    ball.vel.x,
+                    ball.vel.y,
+                    width,
+                    height
+                );
+            }
+        }
+
+        // The balls as they are now, since split balls join as they're collected
+        let balls: Vec<Ball> =
+            self.balls.iter().filter(|b| b.stuck_to.is_none()).cloned().collect();
+        let mut collected = Vec::new();
+
+        for ball in balls {
+            let (bx, by) = (ball.pos.x as u16, ball.pos.y as u16);
+            // A ball no paddle has touched yet goes to whoever's half it's on
+            let player = ball.last_hit_by.unwrap_or(if bx < self.width / 2 { 1 } else { 2 });
+
+            let mut hit = Vec::new();
+            self.powerups.retain(|p| {
+                let area = Rect::around(p.x as i32, p.y as i32, (POWERUP_SIZE / 2) as i32);
+                let touching = area.contains_cell(bx as i32, by as i32);
+                if touching {
+                    hit.push(p.ptype);
+                }
+                !touching
+            });
+            for ptype in hit {
+                self.stats.powerups[player as usize - 1] += 1;
+                collected.push((player, ptype));
+                self.collect_powerup(ptype, player, &ball);
+            }
+        }
+
+        if !collected.is_empty() {
+            self.add_sound(Sound::PowerUp);
+        }
+        for (side, ptype) in collected {
+            let player = self.player_at(side);
+            let name = self.name(player);
+            log::debug!("player {} on side {} collected {:?}", player, side, ptype);
+            self.add_event(format!("{} collected {}", name, self.powerup_title(ptype)));
+            self.emit(GameEvent::PowerupCollected {
+                player,
+                powerup: ptype,
+            });
+        }
+
+        // Nobody went for these, so make room for new ones
+        let elapsed = Duration::from_secs_f32(dt);
+        for powerup in &mut self.powerups {
+            powerup.remaining = powerup.remaining.saturating_sub(elapsed);
+            if powerup.remaining.is_zero() {
+                log::debug!("{:?} went uncollected", powerup.ptype);
+            }
+        }
+        self.powerups.retain(|p| !p.remaining.is_zero());
+
+        if survival && hits[0] > 0 {
+            self.survival_returns(hits[0]);
+        }
+
+        if target_hits > 0 {
+            let target_y = self.practice_target_y(practice_target);
+            if let Some(practice) = &mut self.practice {
+                practice.streak += target_hits;
+                practice.best_streak = practice.best_streak.max(practice.streak);
+                practice.target_y = target_y;
+            }
+        }
+
+        for &(_, conceded, last_hit_by) in &scored {
+            // With four players the point goes to whoever hit it last, and
+            // to nobody when that's the player who let it through
+            let scorer = if four_player {
+                last_hit_by.filter(|&player| player != conceded)
+            } else if let Some(practice) = &mut self.practice {
+                // A missed return ends the run
+                practice.streak = 0;
+                None
+            } else if let Some(survival) = &mut self.survival {
+                survival.lives = survival.lives.saturating_sub(1);
+                None
+            } else {
+                Some(3 - conceded)
+            };
+            let mut points = rally_points;
+            let double = match scorer {
+                Some(1) => Some(&mut self.p1_double),
+                Some(2) => Some(&mut self.p2_double),
+                _ => None,
+            };
+            if let Some(double) = double.filter(|double| **double) {
+                *double = false;
+                points *= 2;
+            }
+            match scorer {
+                Some(1) => self.p1_score += points,
+                Some(2) => self.p2_score += points,
+                Some(3) => self.p3_score += points,
+                Some(4) => self.p4_score += points,
+                _ => {}
+            }
+            if let Some(player) = scorer {
+                self.add_effect(EffectKind::Score, player);
+                let event = if four_player {
+                    format!("{} scores!", self.name(player))
+                } else {
+                    let (left, right) = (self.p1_score, self.p2_score);
+                    format!("{} scores! {}-{}", self.name(self.player_at(player)), left, right)
+                };
+                self.add_event(event);
+            }
+            let to = scorer.map_or("nobody".to_string(), |side| format!("side {}", side));
+            let scores = self.scores();
+            log::info!("side {} conceded, {} to {}, scores {:?}", conceded, points, to, scores);
+            self.emit(GameEvent::Score {
+                player: scorer.map(|side| self.player_at(side)),
+                conceded: self.player_at(conceded),
+                scores: self.scores_by_player(),
+            });
+        }
+        if !scored.is_empty() {
+            self.add_sound(Sound::Score);
+            if four_player {
+                // Sets aren't played with four, so the first game decides it
+                let scores = self.scores();
+                self.winner = (1..=4u8).find(|&player| {
+                    let score = scores[player as usize - 1];
+                    score >= self.score_limit
+                        && (1..=4u8).all(|other| other == player || scores[other as usize - 1] < score)
+                });
+            } else if let Some(survival) = &self.survival {
+                // The wall wins in the end
+                if survival.lives == 0 {
+                    self.winner = Some(2);
+                }
+            } else if let Some(timed) = &self.timed {
+                // Nothing is decided by points until the clock runs out
+                if timed.overtime && self.p1_score != self.p2_score {
+                    self.winner = Some(if self.p1_score > self.p2_score { 1 } else { 2 });
+                }
+            } else if self.practice.is_none() {
+                // At deuce only a lead of two takes it
+                let lead = if self.in_deuce() { 2 } else { 1 };
+                let set_winner = if self.p1_score >= self.score_limit
+                    && self.p1_score >= self.p2_score + lead
+                {
+                    self.p1_sets += 1;
+                    Some(1)
+                } else if self.p2_score >= self.score_limit
+                    && self.p2_score >= self.p1_score + lead
+                {
+                    self.p2_sets += 1;
+                    Some(2)
+                } else {
+                    None
+                };
+                if self.p1_sets.max(self.p2_sets) >= self.sets_to_win() {
+                    self.winner = set_winner;
+                } else {
+                    self.set_winner = set_winner;
+                }
+            }
+            if self.winner.is_none() && self.set_winner.is_none() && self.in_deuce() {
+                let event = match self.advantage() {
+                    Some(side) => format!("Advantage {}", self.name(self.player_at(side))),
+                    None => "Deuce!".to_string(),
+                };
+                self.add_event(event);
+            }
+            if let Some(winner) = self.winner {
+                self.emit(GameEvent::GameOver {
+                    winner: self.player_at(winner),
+                    scores: self.scores_by_player(),
+                });
+            }
+        }
+        // The rally goes on while any ball is left in play
+        if !scored.is_empty()
+            && (self.balls.is_empty() || self.winner.is_some() || self.set_winner.is_some())
+        {
+            // The last ball out ended the rally, so the serve goes to
+            // whoever let that one through
+            let conceded = scored.last().map(|&(_, conceded, _)| conceded);
+            self.stats.rally = 0;
+            self.reset_ball(conceded);
+            self.powerups.clear();
+            self.center_wall = false;
+            self.two_small_walls = false;
+            self.portals = None;
+            self.active_powerups.retain(|p| {
+                p.ptype != PowerUpType::Portal && p.ptype != PowerUpType::ReverseControls
+            });
+            self.place_obstacles();
+            self.rebuild_walls();
+            // A cleared band comes back for the next point
+            if self.bricks.is_empty() {
+                self.place_bricks();
+            }
+        }
+
+        // Update active powerups
+        for powerup in &mut self.active_powerups {
+            powerup.remaining = powerup.remaining.saturating_sub(elapsed);
+        }
+        let expired: Vec<(PowerUpType, u8)> = self
+            .active_powerups
+            .iter()
+            .filter(|p| p.remaining.is_zero())
+            .map(|p| (p.ptype, p.player))
+            .collect();
+        for (ptype, player) in expired {
+            log::debug!("side {}'s {:?} expired", player, ptype);
+            self.add_event(format!("{} expired", self.powerup_title(ptype)));
+            if let PowerUpType::Custom(index) = ptype {
+                self.custom_calls.push(CustomCall::Expired { index, player });
+            }
+        }
+        self.active_powerups.retain(|p| !p.remaining.is_zero());
+        self.shield_flash = self
+            .shield_flash
+            .map(|(player, left)| (player, left.saturating_sub(elapsed)))
+            .filter(|(_, left)| !left.is_zero());
+
+        // Held balls go by themselves after a while, or once the Sticky
+        // that caught them wears off
+        for player in 1..=2 {
+            let expired = self.balls.iter().any(|b| {
+                b.stuck_to.is_some_and(|(p, _)| p == player) && b.stuck_for >= STICKY_HOLD
+            });
+            if expired || !self.has_powerup(PowerUpType::Sticky, player) {
+                self.release_balls(player);
+            }
+        }
+        for ball in &mut self.balls {
+            if ball.stuck_to.is_some() {
+                ball.stuck_for += elapsed;
+            }
+        }
+
+        self.apply_powerup_effects();
+        self.carry_stuck_balls();
+    }
+
+    /// Somewhere in the middle half of the field for a new powerup, where
+    /// it doesn't overlap another one, a wall or a portal and isn't right
+    /// next to a ball. After a few tries in a crowded field it gives up.
+    fn powerup_spot(&mut self) -> Option<(u16, u16)> {
+        let spawn_x = self.width / 4..3 * self.width / 4;
+        let spawn_y = 2..self.height.saturating_sub(2);
+        if spawn_x.is_empty() || spawn_y.is_empty() {
+            return None;
+        }
+        let half = (POWERUP_SIZE / 2) as i32;
+        let bricks = self.brick_columns();
+        let bricks = bricks.start as i32..bricks.end as i32;
+
+        for _ in 0..POWERUP_SPAWN_TRIES {
+            let x = self.rng.gen_range(spawn_x.clone());
+            let y = self.rng.gen_range(spawn_y.clone());
+            let (cx, cy) = (x as i32, y as i32);
+            let rows = cy - half..cy + half + 1;
+            let on_powerup = self.powerups.iter().any(|p| {
+                (p.x as i32 - cx).abs() < POWERUP_SIZE as i32
+                    && (p.y as i32 - cy).abs() < POWERUP_SIZE as i32
+            });
+            let on_wall = self.walls.iter().any(|wall| {
+                let wall_rows = wall.rows();
+                (wall.x as i32 - cx).abs() <= half
+                    && wall_rows.start < rows.end
+                    && rows.start < wall_rows.end
+            });
+            let on_portal = self.portals.is_some_and(|(left, right)| {
+                [left, right].iter().any(|portal| {
+                    (portal.x as i32 - cx).abs() <= half
+                        && (portal.y as i32) < rows.end
+                        && rows.start < (portal.y + PORTAL_HEIGHT) as i32
+                })
+            });
+            let on_bricks = self.breakout && cx - half < bricks.end && bricks.start <= cx + half;
+            let clear = Rect::around(cx, cy, half + POWERUP_BALL_GAP);
+            let near_ball = self.balls.iter().any(|b| collision::point_in_rect(b.pos, &clear));
+            if !on_powerup && !on_wall && !on_portal && !on_bricks && !near_ball {
+                return Some((x, y));
+            }
+        }
+        None
+    }
+
+    /// One portal somewhere on each half, clear of the paddles and the
+    /// middle column the walls use
+    fn place_portals(
+        rng: &mut ChaCha12Rng,
+        width: u16,
+        height: u16,
+    ) -> Option<(PortalRect, PortalRect)> {
+        let left = width / 6..(width / 2).saturating_sub(3);
+        let right = width / 2 + 4..width - width / 6;
+        let rows = 1..height.saturating_sub(PORTAL_HEIGHT);
+        if left.is_empty() || right.is_empty() || rows.is_empty() {
+            return None;
+        }
+        let left = PortalRect {
+            x: rng.gen_range(left),
+            y: rng.gen_range(rows.clone()),
+        };
+        let right = PortalRect {
+            x: rng.gen_range(right),
+            y: rng.gen_range(rows),
+        };
+        Some((left, right))
+    }
+
+    /// Send balls that entered a portal out of the other one, at the same
+    /// height within it and keeping their speed and direction
+    fn teleport_balls(&mut self, dt: f32) {
+        let Some((left, right)) = self.portals else {
+            return;
+        };
+        let elapsed = Duration::from_secs_f32(dt);
+        for i in 0..self.balls.len() {
+            let ball = &mut self.balls[i];
+            if ball.stuck_to.is_some() {
+                continue;
+            }
+            if !ball.portal_immunity.is_zero() {
+                ball.portal_immunity = ball.portal_immunity.saturating_sub(elapsed);
+                continue;
+            }
+            let (entry, exit) = if left.contains(ball.pos) {
+                (left, right)
+            } else if right.contains(ball.pos) {
+                (right, left)
+            } else {
+                continue;
+            };
+
+            // Come out on the side the ball is heading, so it leaves the
+            // portal behind, and past anything standing right there
+            let step = if ball.vel.x < 0.0 { -1.0 } else { 1.0 };
+            let y = exit.y as f32 + (ball.pos.y - entry.y as f32);
+            let mut x = exit.x as f32 + 0.5 + step;
+            while self.cell_blocked(x, y) && x > 1.0 && x < (self.width - 2) as f32 {
+                x += step;
+            }
+
+            let ball = &mut self.balls[i];
+            ball.pos = Vec2::new(x, y);
+            ball.prev = ball.pos;
+            ball.portal_immunity = PORTAL_IMMUNITY;
+        }
+    }
+
+    /// Whether a paddle or a wall takes up the cell at `x`, `y`
+    fn cell_blocked(&self, x: f32, y: f32) -> bool {
+        let (cx, row) = (x.floor() as i32, y.floor() as i32);
+        if self
+            .walls
+            .iter()
+            .any(|wall| wall.rect().contains_cell(cx, row))
+        {
+            return true;
+        }
+
+        let p2_x = self.width.saturating_sub(3);
+        let paddles = [
+            (2, Some(self.p1_y), self.p1_second_y, self.p1_height, self.p1_bent),
+            (p2_x, Some(self.p2_y), self.p2_second_y, self.p2_height, self.p2_bent),
+        ];
+        paddles.iter().any(|&(px, main, second, paddle_height, bent)| {
+            // A bent paddle reaches up to two cells right of its column
+            let reach = if bent { 2 } else { 0 };
+            let in_column = |px: u16| cx >= px as i32 && cx <= px as i32 + reach;
+            in_column(px)
+                && [main, second].iter().flatten().any(|&top| {
+                    row >= top as i32 && row < top as i32 + paddle_height as i32
+                })
+        })
+    }
+
+    fn has_powerup(&self, ptype: PowerUpType, player: u8) -> bool {
+        self.active_powerups
+            .iter()
+            .any(|p| p.ptype == ptype && p.player == player)
+    }
+
+    /// Hold a ball against the paddle it just hit, `face_x` being where it
+    /// sits in front of it
+    fn catch_ball(ball: &mut Ball, player: u8, face_x: f32, hit_y: f32, paddle_y: i16) {
+        ball.pos = Vec2::new(face_x, hit_y);
+        ball.prev = ball.pos;
+        ball.vel = Vec2::ZERO;
+        ball.spin = 0.0;
+        ball.stuck_to = Some((player, hit_y - paddle_y as f32));
+        ball.stuck_for = Duration::ZERO;
+    }
+
+    /// The spin a paddle that moved `motion` rows puts on a ball. A still
+    /// paddle takes any spin off.
+    fn spin_from(motion: i16) -> f32 {
+        (motion as f32 * SPIN_PER_HIT).clamp(-MAX_SPIN, MAX_SPIN)
+    }
+
+    /// Fire whatever ball `player` is holding. Like a normal hit the angle
+    /// comes from how far off center it sits, only steeper.
+    pub fn release_balls(&mut self, player: u8) {
+        let (paddle_y, second_y, paddle_height) = if player == 1 {
+            (self.p1_y, self.p1_second_y, self.p1_height)
+        } else {
+            (self.p2_y, self.p2_second_y, self.p2_height)
+        };
+        let center = |y: i16| y as f32 + paddle_height as f32 / 2.0;
+        let speed = self.serve_speed();
+        for ball in &mut self.balls {
+            if ball.stuck_to.is_some_and(|(p, _)| p == player) {
+                // It may be sitting on the second paddle rather than the main one
+                let off = |y: i16| (center(y) - ball.pos.y).abs();
+                let paddle_center = match second_y {
+                    Some(y) if off(y) < off(paddle_y) => center(y),
+                    _ => center(paddle_y),
+                };
+                ball.stuck_to = None;
+                ball.vel.x = if player == 1 { speed } else { -speed };
+                let vy = ((ball.pos.y - paddle_center) * 0.3).clamp(-0.8, 0.8);
+                ball.vel.y = Self::steepen(vy, &mut self.rng);
+            }
+        }
+    }
+
+    /// Keep caught balls on their paddles as they move
+    fn carry_stuck_balls(&mut self) {
+        let max_y = self.height.saturating_sub(1) as f32;
+        for ball in &mut self.balls {
+            if let Some((player, offset)) = ball.stuck_to {
+                let paddle_y = if player == 1 { self.p1_y } else { self.p2_y };
+                ball.pos.y = (paddle_y as f32 + offset).clamp(0.0, max_y);
+                ball.prev = ball.pos;
+            }
+        }
+    }
+
+    fn apply_powerup_effects(&mut self) {
+        // Reset powerup effects
+        self.p1_second_y = None;
+        self.p2_second_y = None;
+        self.p1_bent = false;
+        self.p2_bent = false;
+        self.center_wall = false;
+        self.two_small_walls = false;
+        if !self.active_powerups.iter().any(|p| p.ptype == PowerUpType::Portal) {
+            self.portals = None;
+        }
+        if !self.active_powerups.iter().any(|p| p.ptype == PowerUpType::MovingWall) {
+            self.sliding_wall = None;
+        }
+        self.p1_height = self.base_height(1);
+        self.p2_height = self.base_height(2);
+
+        self.apply_active_effects();
+        self.rebuild_walls();
+    }
+
+    /// Gather the walls the powerups and the obstacle course put up
+    fn rebuild_walls(&mut self) {
+        let (wall_x, height) = (self.width / 2, self.height);
+        self.walls.clear();
+        if self.center_wall {
+            self.walls.push(WallRect {
+                x: wall_x,
+                y: 0,
+                height,
+                kind: WallKind::Center,
+            });
+        }
+        if self.two_small_walls {
+            for y in [height / 4, 3 * height / 4 - height / 6] {
+                self.walls.push(WallRect {
+                    x: wall_x,
+                    y,
+                    height: height / 6,
+                    kind: WallKind::Small,
+                });
+            }
+        }
+        if let Some(wall) = &self.sliding_wall {
+            self.walls.push(WallRect {
+                x: wall_x,
+                y: wall.row(),
+                height: wall.height,
+                kind: WallKind::Moving,
+            });
+        }
+        let obstacles = self.obstacles.iter().filter(|obstacle| {
+            // A center wall already covers that column
+            !(self.center_wall && obstacle.x == wall_x)
+        });
+        self.walls.extend(obstacles.copied().collect::<Vec<_>>());
+    }
+
+    /// Put up a new set of obstacles for the point about to be served,
+    /// somewhere in the middle third, apart from each other and from any
+    /// ball
+    fn place_obstacles(&mut self) {
+        self.obstacles.clear();
+        if !self.obstacle_course {
+            return;
+        }
+        let columns = self.width / 3..2 * self.width / 3;
+        let count = self.rng.gen_range(OBSTACLE_COUNT);
+        for _ in 0..count {
+            for _ in 0..OBSTACLE_TRIES {
+                let height = self.rng.gen_range(OBSTACLE_HEIGHT);
+                // Clear of the borders and the edge paddles alongside them
+                let rows = 2..self.height.saturating_sub(height + 2);
+                if columns.is_empty() || rows.is_empty() {
+                    return;
+                }
+                let wall = WallRect {
+                    x: self.rng.gen_range(columns.clone()),
+                    y: self.rng.gen_range(rows),
+                    height,
+                    kind: WallKind::Obstacle,
+                };
+                let wall_rows = wall.rows();
+                // Keep a free cell all round, so no two join up
+                let crowded = self.obstacles.iter().any(|other| {
+                    let other_rows = other.rows();
+                    (other.x as i32 - wall.x as i32).abs() <= 2
+                        && other_rows.start <= wall_rows.end
+                        && wall_rows.start <= other_rows.end
+                });
+                let on_ball = self.balls.iter().any(|ball| {
+                    (ball.pos.x.floor() as i32 - wall.x as i32).abs() <= 2
+                        && ball.pos.y.floor() as i32 >= wall_rows.start - 2
+                        && (ball.pos.y.floor() as i32) < wall_rows.end + 2
+                });
+                if !crowded && !on_ball {
+                    self.obstacles.push(wall);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Put up fresh walls for every point, or take them away
+    pub fn set_obstacle_course(&mut self, on: bool) {
+        self.obstacle_course = on;
+        self.place_obstacles();
+        self.rebuild_walls();
+    }
+
+    pub fn obstacle_course(&self) -> bool {
+        self.obstacle_course
+    }
+
+    /// Turn breakout on or off, with a fresh band of bricks when it's on
+    pub fn set_breakout(&mut self, on: bool) {
+        self.breakout = on;
+        self.place_bricks();
+    }
+
+    pub fn breakout(&self) -> bool {
+        self.breakout
+    }
+
+    /// A MovingWall's wall, a quarter of the field tall, setting off from
+    /// the middle one way or the other
+    fn new_sliding_wall(&mut self) -> SlidingWall {
+        let height = (self.height / 4).max(2);
+        let speed = match self.rng.gen() {
+            true => SLIDING_WALL_SPEED,
+            false => -SLIDING_WALL_SPEED,
+        };
+        SlidingWall {
+            y: self.height.saturating_sub(height) as f32 / 2.0,
+            height,
+            speed,
+        }
+    }
+
+    /// Slide the MovingWall's wall on by `dt`, turning back at the borders,
+    /// and push out any ball it has come down or up onto so none gets
+    /// caught inside it, even against a border. A ball goes out the side it
+    /// was heading for. Returns whether there's a wall that moved.
+    fn slide_wall(&mut self, dt: f32) -> bool {
+        let Some(wall) = &mut self.sliding_wall else {
+            return false;
+        };
+        let lowest = self.height.saturating_sub(wall.height + 1).max(1) as f32;
+        wall.y += wall.speed * dt * 60.0;
+        if wall.y <= 1.0 {
+            wall.y = 1.0;
+            wall.speed = wall.speed.abs();
+        } else if wall.y >= lowest {
+            wall.y = lowest;
+            wall.speed = -wall.speed.abs();
+        }
+        let x = (self.width / 2) as f32;
+        let rows = wall.row() as f32..(wall.row() + wall.height) as f32;
+        for ball in self.balls.iter_mut().filter(|ball| ball.stuck_to.is_none()) {
+            let inside = ball.pos.x.floor() == x && rows.contains(&ball.pos.y);
+            if inside {
+                ball.pos.x = if ball.vel.x < 0.0 { x - 0.01 } else { x + 1.0 };
+                ball.prev = ball.pos;
+            }
+        }
+        true
+    }
+
+    /// The columns across the middle that the bricks fill in breakout
+    pub fn brick_columns(&self) -> std::ops::Range<u16> {
+        let start = (self.width / 2).saturating_sub(BRICK_COLUMNS / 2);
+        start..start + BRICK_COLUMNS
+    }
+
+    /// Fill the band from top to bottom with bricks good for one to
+    /// `MAX_BRICK_HP` hits, leaving the borders alone
+    fn place_bricks(&mut self) {
+        self.bricks.clear();
+        if !self.breakout {
+            return;
+        }
+        for y in 1..self.height.saturating_sub(1) {
+            for x in self.brick_columns() {
+                let hp = self.rng.gen_range(1..=MAX_BRICK_HP);
+                self.bricks.push(Brick { x, y, hp });
+            }
+        }
+    }
+
+    /// The first brick the ball ran into during its last move, by its index
+    /// and the face it crossed. Bricks already broken this step don't count.
+    fn brick_hit(ball: &Ball, bricks: &[Brick], edge: Option<f32>) -> Option<(usize, Line)> {
+        let path = ball.path();
+        let mut first: Option<(f32, usize, Line)> = None;
+        for (index, brick) in bricks.iter().enumerate().filter(|(_, brick)| brick.hp > 0) {
+            let cell = Rect::new(brick.x as i32, brick.y as i32, 1, 1);
+            let side = Self::sweep_column(ball, cell, edge)
+                .map(|(face, _)| (path.fraction_at_x(face), Line::Vertical(face)));
+            let end = Self::sweep_row(ball, cell)
+                .map(|(face, _)| (path.fraction_at_y(face), Line::Horizontal(face)));
+            for (done, line) in [side, end].into_iter().flatten() {
+                let done = done.unwrap_or(0.0);
+                if first.is_none_or(|(earliest, _, _)| done < earliest) {
+                    first = Some((done, index, line));
+                }
+            }
+        }
+        first.map(|(_, index, line)| (index, line))
+    }
+
+    /// Swept test of the ball's last move against `column`, one cell wide.
+    /// Returns the x of the face it crossed and the y where it crossed it.
+    /// With an `edge`, a move that goes past the top or that bottom edge is
+    /// folded back into the field the way the ball bounces.
+    fn sweep_column(ball: &Ball, column: Rect, edge: Option<f32>) -> Option<(f32, f32)> {
+        let path = ball.path();
+        let face = Self::face_toward(column.x, path.delta().x);
+        let t = collision::segment_vs_vline(&path, face)?;
+        let y = Self::fold_y(path.at(t).y, edge);
+        column.contains_row(y.floor() as i32).then_some((face, y))
+    }
+
+    /// The side of the cell at `cell` that something going at `speed`
+    /// along that axis comes up against
+    fn face_toward(cell: i32, speed: f32) -> f32 {
+        if speed < 0.0 {
+            cell as f32 + 1.0
+        } else {
+            cell as f32
+        }
+    }
+
+    /// Where a ball at `y` on its straight path really is, once it has
+    /// bounced off the top or the bottom `edge`
+    fn fold_y(y: f32, edge: Option<f32>) -> f32 {
+        match edge {
+            Some(_) if y < 0.0 => -y,
+            Some(edge) if y > edge => 2.0 * edge - y,
+            _ => y,
+        }
+    }
+
+    /// How much of its last move the ball still had to go when it crossed
+    /// the column face at `face`
+    fn rest_of_move(ball: &Ball, face: f32) -> f32 {
+        let done = ball.path().fraction_at_x(face);
+        done.map_or(0.0, |done| (1.0 - done).clamp(0.0, 1.0))
+    }
+
+    /// Bounce the ball back from a vertical face it crossed this step,
+    /// mirroring whatever distance it travelled past it
+    fn reflect_off_face(ball: &mut Ball, face: f32) {
+        collision::reflect(&mut ball.pos, &mut ball.vel, Line::Vertical(face));
+    }
+
+    /// Where the ball struck a paddle during its last move, as the face it
+    /// crossed, the y of the impact and the part of the paddle it hit
+    fn check_paddle_collision_static(
+        ball: &Ball,
+        paddle_x: u16,
+        paddle_y: i16,
+        paddle_height: u16,
+        bent: bool,
+        edge: Option<f32>,
+    ) -> Option<(f32, f32, PaddlePart)> {
+        if bent {
+            // Bent paddle shape: <>
+            let tip = (paddle_height.max(1) - 1) / 2;
+            for i in 0..paddle_height {
+                let offset = Self::bent_offset(i, paddle_height);
+                let row = (paddle_y + i as i16) as i32;
+                let cell = Rect::new((paddle_x + offset) as i32, row, 1, 1);
+                let hit = Self::sweep_column(ball, cell, edge)
+                    .or_else(|| Self::resting_in_cell(ball, cell, edge));
+                if let Some((face, hit_y)) = hit {
+                    let part = if offset == tip { PaddlePart::Tip } else { PaddlePart::Angled };
+                    return Some((face, hit_y, part));
+                }
+            }
+            None
+        } else {
+            // Normal paddle
+            let column = Rect::new(paddle_x as i32, paddle_y as i32, 1, paddle_height as i32);
+            Self::sweep_column(ball, column, edge)
+                .or_else(|| Self::resting_in_cell(ball, column, edge))
+                .map(|(face, hit_y)| (face, hit_y, PaddlePart::Flat))
+        }
+    }
+
+    /// How far the `i`th cell down a bent paddle sticks out from its column
+    fn bent_offset(i: u16, height: u16) -> u16 {
+        if i < height / 2 {
+            i
+        } else {
+            height - i - 1
+        }
+    }
+
+    /// The same as `sweep_column`, for `row`, one cell tall. Returns the y
+    /// of the face it crossed and the x where it crossed it.
+    fn sweep_row(ball: &Ball, row: Rect) -> Option<(f32, f32)> {
+        let path = ball.path();
+        let face = Self::face_toward(row.y, path.delta().y);
+        let t = collision::segment_vs_hline(&path, face)?;
+        let x = path.at(t).x;
+        row.contains_column(x.floor() as i32).then_some((face, x))
+    }
+
+    /// Bounce the ball back from a horizontal face it crossed this step
+    fn reflect_off_row(ball: &mut Ball, face: f32) {
+        collision::reflect(&mut ball.pos, &mut ball.vel, Line::Horizontal(face));
+    }
+
+    /// Where the ball struck a top or bottom paddle during its last move,
+    /// as the face it crossed and the x of the impact
+    fn check_edge_paddle_collision(ball: &Ball, paddle: &Paddle) -> Option<(f32, f32)> {
+        let row = Rect::new(paddle.pos as i32, paddle.line as i32, paddle.len as i32, 1);
+        Self::sweep_row(ball, row).or_else(|| {
+            // The paddle may have slid onto a ball already in its row
+            collision::point_in_rect(ball.pos, &row)
+                .then(|| (Self::face_toward(row.y, ball.vel.y), ball.pos.x))
+        })
+    }
+
+    /// A paddle can also move onto a ball that's already inside its column
+    fn resting_in_cell(ball: &Ball, column: Rect, edge: Option<f32>) -> Option<(f32, f32)> {
+        let y = Self::fold_y(ball.pos.y, edge);
+        let at = Vec2::new(ball.pos.x, y);
+        if !collision::point_in_rect(at, &column) {
+            return None;
+        }
+        Some((Self::face_toward(column.x, ball.vel.x), y))
+    }
+
+    /// Move the paddle on `side` the way the computer would
+    fn update_ai(&mut self, side: u8) {
+        let (difficulty, paddle_x, toward) = if side == 1 {
+            (self.left_ai, 2.0, -1.0)
+        } else {
+            (self.ai, self.width.saturating_sub(3) as f32, 1.0)
+        };
+        let Some(difficulty) = difficulty else {
+            return;
+        };
+        let index = side as usize - 1;
+        // Follow the incoming ball closest to the paddle, or drift back to
+        // the middle when every ball is heading away
+        let incoming = self
+            .balls
+            .iter()
+            .filter(|b| b.vel.x * toward > 0.0 && (paddle_x - b.pos.x) * toward >= 0.0)
+            .min_by(|a, b| (paddle_x - a.pos.x).abs().total_cmp(&(paddle_x - b.pos.x).abs()));
+
+        let target_y = match incoming {
+            Some(ball) => {
+                // Pick a fresh aiming error each time a new ball comes in
+                if !self.ai_tracking[index] {
+                    let error = difficulty.tracking_error();
+                    self.ai_error[index] = self.rng.gen_range(-error..=error);
+                    self.ai_tracking[index] = true;
+                }
+                let y = if difficulty.predicts_bounces() {
+                    self.predict_ball_y(ball, paddle_x)
+                } else {
+                    ball.pos.y
+                };
+                y + self.ai_error[index]
+            }
+            None => {
+                self.ai_tracking[index] = false;
+                self.height as f32 / 2.0
+            }
+        };
+
+        // Only react to where the ball was a few frames ago
+        let targets = &mut self.ai_targets[index];
+        targets.push_back(target_y);
+        while targets.len() > difficulty.reaction_delay() + 1 {
+            targets.pop_front();
+        }
+        let target_y = targets[0];
+
+        if self.rng.gen_bool(difficulty.idle_chance()) {
+            return;
+        }
+
+        let (paddle_y, paddle_height) = if side == 1 {
+            (self.p1_y, self.p1_height)
+        } else {
+            (self.p2_y, self.p2_height)
+        };
+        let paddle_center = paddle_y as f32 + paddle_height as f32 / 2.0;
+        let diff = target_y - paddle_center;
+        if diff.abs() >= 1.0 {
+            let dir = if diff > 0.0 { Dir::Down } else { Dir::Up };
+            self.move_paddle(side, dir, PHYSICS_DT);
+        }
+    }
+
+    /// Where `ball` will be vertically when it reaches `target_x`, following
+    /// its reflections off the top and bottom borders
+    fn predict_ball_y(&self, ball: &Ball, target_x: f32) -> f32 {
+        let max_y = (self.height - 1) as f32;
+        if ball.vel.x == 0.0 || max_y <= 0.0 {
+            return ball.pos.y;
+        }
+        let t = (target_x - ball.pos.x) / ball.vel.x;
+        let y = ball.pos.y + ball.vel.y * t;
+
+        // Unfold the bounces: the path repeats every two field heights
+        let period = 2.0 * max_y;
+        let y = y.rem_euclid(period);
+        if y > max_y {
+            period - y
+        } else {
+            y
+        }
+    }
+
+    /// How fast the balls move compared to their velocity. SlowBall only
+    /// scales the movement, so the balls pick up their old speed when it ends.
+    pub fn ball_speed_scale(&self) -> f32 {
+        let slowed = self.active_powerups.iter().any(|p| p.ptype == PowerUpType::SlowBall);
+        let stalled = self.untouched.saturating_sub(STALL_AFTER).as_secs() as f32;
+        let stall = (1.0 + stalled * STALL_SPEEDUP).min(MAX_STALL_SPEED);
+        if slowed {
+            0.5 * stall
+        } else {
+            stall
+        }
+    }
+
+    /// Whether the other player's Freeze is holding this player's paddle
+    pub fn is_frozen(&self, player: u8) -> bool {
+        self.active_powerups
+            .iter()
+            .any(|p| p.ptype == PowerUpType::Freeze && p.player != player)
+    }
+
+    /// Whether an InvisibleBall hides this ball from the player it's heading
+    /// for. It only counts on their half, and never for the last few columns
+    /// so the ball can still be returned.
+    pub fn is_invisible(&self, ball: &Ball) -> bool {
+        let center = self.width as f32 / 2.0;
+        if ball.vel.x > 0.0 && self.has_powerup(PowerUpType::InvisibleBall, 1) {
+            let p2_x = self.width.saturating_sub(3) as f32;
+            ball.pos.x > center && ball.pos.x < p2_x - INVISIBLE_REVEAL
+        } else if ball.vel.x < 0.0 && self.has_powerup(PowerUpType::InvisibleBall, 2) {
+            ball.pos.x < center && ball.pos.x > 3.0 + INVISIBLE_REVEAL
+        } else {
+            false
+        }
+    }
+
+    /// Whether the other player's ReverseControls has swapped this player's
+    /// up and down
+    pub fn controls_reversed(&self, player: u8) -> bool {
+        self.active_powerups
+            .iter()
+            .any(|p| p.ptype == PowerUpType::ReverseControls && p.player != player)
+    }
+
+    /// Move one player's paddle for `dt` seconds of holding `dir`. It moves
+    /// whole rows, keeping what's left over for the next move so it comes
+    /// out right on average, however the time is cut up.
+    pub fn move_paddle(&mut self, player: u8, dir: Dir, dt: f32) {
+        if dir == Dir::Stay || self.is_frozen(player) || self.is_charging(player) {
+            return;
+        }
+        let mut speed = dir.delta() as f32 * self.tuning.paddle_speed as f32;
+        if self.controls_reversed(player) {
+            speed = -speed;
+        }
+        if player <= 2 {
+            speed *= self.handicap(self.player_at(player)).paddle_speed;
+        }
+        let carry = &mut self.paddle_carry[player as usize - 1];
+        let rows = speed * PADDLE_RATE * dt + *carry;
+        let delta = rows.round();
+        *carry = rows - delta;
+        let delta = delta as i16;
+        if let Some(paddle) = self.edge_paddles.iter_mut().find(|p| p.player == player) {
+            paddle.pos += delta;
+            let (min, max) = Self::edge_paddle_range(self.width, paddle.len);
+            paddle.pos = paddle.pos.clamp(min, max);
+            return;
+        }
+        let (paddle_y, paddle_height) = if player == 1 {
+            (&mut self.p1_y, self.p1_height)
+        } else {
+            (&mut self.p2_y, self.p2_height)
+        };
+
+        let before = *paddle_y;
+        *paddle_y += delta;
+        *paddle_y = (*paddle_y).clamp(0, (self.height as i16 - paddle_height as i16).max(0));
+        let motion = &mut self.paddle_motion[player as usize - 1];
+        *motion = motion.saturating_add(*paddle_y - before);
+    }
+
+    /// Hold a side paddle still to charge a smash, or let it go again,
+    /// which loses whatever charge it had built up
+    pub fn set_charging(&mut self, player: u8, on: bool) {
+        let side = player as usize - 1;
+        self.charging[side] = on;
+        if !on {
+            self.charge[side] = Duration::ZERO;
+        }
+    }
+
+    pub fn is_charging(&self, player: u8) -> bool {
+        player <= 2 && self.charging[player as usize - 1]
+    }
+
+    /// Dash a side paddle `DASH_CELLS` rows at once, if it's had time to
+    /// recover from its last dash. It goes a row at a time and stops short
+    /// of a ball in its column, which it then hits as usual, rather than
+    /// jumping over it.
+    pub fn dash_paddle(&mut self, player: u8, dir: Dir) {
+        let cooldown = self.dash_cooldown[player as usize - 1];
+        let no_paddle = player == 2 && self.has_wall();
+        let held = self.is_frozen(player) || self.is_charging(player);
+        if dir == Dir::Stay || !cooldown.is_zero() || no_paddle || held {
+            return;
+        }
+        let mut step = dir.delta();
+        if self.controls_reversed(player) {
+            step = -step;
+        }
+        let (x, y, height) = match player {
+            1 => (2, self.p1_y, self.p1_height),
+            _ => (self.width.saturating_sub(3), self.p2_y, self.p2_height),
+        };
+        let max_y = (self.height as i16 - height as i16).max(0);
+        let mut to = y;
+        for _ in 0..DASH_CELLS {
+            let next = (to + step).clamp(0, max_y);
+            // The row the paddle's leading end moves into
+            let row = if step < 0 { next } else { next + height as i16 - 1 };
+            let cell = Rect::new(x as i32, row as i32, 1, 1);
+            let in_the_way = self
+                .balls
+                .iter()
+                .any(|b| b.stuck_to.is_none() && collision::point_in_rect(b.pos, &cell));
+            if next == to || in_the_way {
+                break;
+            }
+            to = next;
+        }
+        if to == y {
+            return;
+        }
+        match player {
+            1 => self.p1_y = to,
+            _ => self.p2_y = to,
+        }
+        let motion = &mut self.paddle_motion[player as usize - 1];
+        *motion = motion.saturating_add(to - y);
+        self.dash_cooldown[player as usize - 1] = DASH_COOLDOWN;
+    }
+
+    /// Where a second paddle goes: a gap below the main one, or above it
+    /// when that would leave the field
+    fn second_paddle_y(&self, paddle_y: i16, paddle_height: u16) -> i16 {
+        let max_y = (self.height as i16 - paddle_height as i16).max(0);
+        let below = paddle_y + paddle_height as i16 + 2;
+        let above = paddle_y - paddle_height as i16 - 2;
+        if below <= max_y {
+            below
+        } else {
+            above.clamp(0, max_y)
+        }
+    }
+
+    /// Where a top or bottom paddle can go: between the side paddles'
+    /// columns
+    fn edge_paddle_range(width: u16, len: u16) -> (i16, i16) {
+        let max = (width as i16 - 3 - len as i16).max(3);
+        (3, max)
+    }
+
+    /// Keep every paddle inside the field at its current size
+    fn clamp_paddles(&mut self) {
+        let max_y = |paddle_height: u16| (self.height as i16 - paddle_height as i16).max(0);
+        self.p1_y = self.p1_y.clamp(0, max_y(self.p1_height));
+        self.p2_y = self.p2_y.clamp(0, max_y(self.p2_height));
+        for paddle in &mut self.edge_paddles {
+            let (min, max) = Self::edge_paddle_range(self.width, paddle.len);
+            paddle.pos = paddle.pos.clamp(min, max);
+        }
+    }
+}
@@ -0,0 +1,901 @@
+//! What each built-in powerup is and does. Every one is a unit struct with
+//! a `PowerUpEffect` impl, and `PowerUpType::effect` is the one table that
+//! lists them, so spawning, drawing and the effects all read from the same
+//! place and a powerup left out of it doesn't compile.
+
+use super::{ActivePowerUp, Ball, Game, PowerUpType, MAX_BALLS, POWERUP_SIZE};
+use crate::collision::Vec2;
+use rand::Rng;
+use std::time::Duration;
+
+/// Rows Grow Paddle adds and Shrink Opponent takes away
+const GROW_BY: u16 = 3;
+const SHRINK_BY: u16 = 2;
+const POWERUP_DURATION: Duration = Duration::from_secs(10);
+// Split balls leave this many degrees either side of the original
+const SPLIT_SPREAD: std::ops::Range<f32> = 20.0..30.0;
+const FREEZE_DURATION: Duration = Duration::from_secs(3);
+const REVERSE_DURATION: Duration = Duration::from_secs(6);
+
+/// Players here are sides, as the game keeps them
+pub trait PowerUpEffect: Sync {
+    /// How config files and the command line call it
+    fn name(&self) -> &'static str;
+    fn title(&self) -> &'static str;
+    /// A line for the help screen
+    fn description(&self) -> &'static str;
+    /// What it's drawn with on the field and in the list of active effects
+    fn symbol(&self) -> char;
+    /// A color name, for the frontend to make sense of
+    fn color(&self) -> &'static str;
+    /// Its name on the status bar in colorblind mode
+    fn label(&self) -> &'static str;
+    /// The pattern it's drawn in in colorblind mode, one string per row of
+    /// its `POWERUP_SIZE` square, filled wherever it isn't a space
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize];
+
+    /// How long it lasts once collected, or `None` for one that's done
+    /// with as soon as it's collected
+    fn duration(&self) -> Option<Duration> {
+        Some(POWERUP_DURATION)
+    }
+
+    /// Whether another one while it lasts runs alongside it, rather than
+    /// starting the count again
+    fn stacks(&self) -> bool {
+        true
+    }
+
+    /// Whether it works the same for the top and bottom paddles. The rest
+    /// change the shape of a side paddle or guard a side goal, so they
+    /// don't turn up in four-player mode.
+    fn suits_any_paddle(&self) -> bool {
+        false
+    }
+
+    /// Whether it changes the field for both players rather than helping
+    /// or hindering one of them
+    fn affects_field(&self) -> bool {
+        false
+    }
+
+    /// Whether it's about how much points are worth, so only turns up in
+    /// arcade scoring
+    fn arcade_only(&self) -> bool {
+        false
+    }
+
+    /// Whether it puts walls up down the middle, where breakout has its
+    /// bricks, so doesn't turn up there
+    fn uses_middle(&self) -> bool {
+        false
+    }
+
+    /// What happens the moment `player` collects it with `ball`, before it
+    /// joins the active powerups
+    fn on_collect(&self, _game: &mut Game, _player: u8, _ball: &Ball) {}
+
+    /// Change the paddle heights, which happens for every active powerup
+    /// before `apply_while_active`, since a second paddle sits below the
+    /// first
+    fn resize_while_active(&self, _game: &mut Game, _player: u8) {}
+
+    /// Put its effect back on the field, which starts each frame without
+    /// any
+    fn apply_while_active(&self, _game: &mut Game, _player: u8) {}
+}
+
+pub struct DoublePaddle;
+
+impl PowerUpEffect for DoublePaddle {
+    fn name(&self) -> &'static str {
+        "double_paddle"
+    }
+
+    fn title(&self) -> &'static str {
+        "Double Paddle"
+    }
+
+    fn description(&self) -> &'static str {
+        "Double Paddle: a second paddle for a while"
+    }
+
+    fn symbol(&self) -> char {
+        '║'
+    }
+
+    fn color(&self) -> &'static str {
+        "cyan"
+    }
+
+    fn label(&self) -> &'static str {
+        "DBL"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["x   x", "x   x", "x   x", "x   x", "x   x"]
+    }
+
+    fn apply_while_active(&self, game: &mut Game, player: u8) {
+        if player == 1 {
+            game.p1_second_y = Some(game.second_paddle_y(game.p1_y, game.p1_height));
+        } else {
+            game.p2_second_y = Some(game.second_paddle_y(game.p2_y, game.p2_height));
+        }
+    }
+}
+
+pub struct CenterWall;
+
+impl PowerUpEffect for CenterWall {
+    fn name(&self) -> &'static str {
+        "center_wall"
+    }
+
+    fn title(&self) -> &'static str {
+        "Center Wall"
+    }
+
+    fn description(&self) -> &'static str {
+        "Center Wall: a wall down the middle"
+    }
+
+    fn symbol(&self) -> char {
+        '█'
+    }
+
+    fn color(&self) -> &'static str {
+        "yellow"
+    }
+
+    fn label(&self) -> &'static str {
+        "WALL"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["  x  ", "  x  ", "  x  ", "  x  ", "  x  "]
+    }
+
+    fn suits_any_paddle(&self) -> bool {
+        true
+    }
+
+    fn affects_field(&self) -> bool {
+        true
+    }
+
+    fn uses_middle(&self) -> bool {
+        true
+    }
+
+    fn apply_while_active(&self, game: &mut Game, _player: u8) {
+        game.center_wall = true;
+    }
+}
+
+pub struct TwoSmallWalls;
+
+impl PowerUpEffect for TwoSmallWalls {
+    fn name(&self) -> &'static str {
+        "two_small_walls"
+    }
+
+    fn title(&self) -> &'static str {
+        "Two Small Walls"
+    }
+
+    fn description(&self) -> &'static str {
+        "Two Small Walls: two short walls in the middle"
+    }
+
+    fn symbol(&self) -> char {
+        '▓'
+    }
+
+    fn color(&self) -> &'static str {
+        "magenta"
+    }
+
+    fn label(&self) -> &'static str {
+        "WALLS"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["  x  ", "  x  ", "     ", "  x  ", "  x  "]
+    }
+
+    fn suits_any_paddle(&self) -> bool {
+        true
+    }
+
+    fn affects_field(&self) -> bool {
+        true
+    }
+
+    fn uses_middle(&self) -> bool {
+        true
+    }
+
+    fn apply_while_active(&self, game: &mut Game, _player: u8) {
+        game.two_small_walls = true;
+    }
+}
+
+pub struct BentPaddle;
+
+impl PowerUpEffect for BentPaddle {
+    fn name(&self) -> &'static str {
+        "bent_paddle"
+    }
+
+    fn title(&self) -> &'static str {
+        "Bent Paddle"
+    }
+
+    fn description(&self) -> &'static str {
+        "Bent Paddle: steeper returns, a sweet spot tip"
+    }
+
+    fn symbol(&self) -> char {
+        '⟨'
+    }
+
+    fn color(&self) -> &'static str {
+        "green"
+    }
+
+    fn label(&self) -> &'static str {
+        "BENT"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["   x ", "  x  ", " x   ", "  x  ", "   x "]
+    }
+
+    fn apply_while_active(&self, game: &mut Game, player: u8) {
+        if player == 1 {
+            game.p1_bent = true;
+        } else {
+            game.p2_bent = true;
+        }
+    }
+}
+
+pub struct SplitBall;
+
+impl PowerUpEffect for SplitBall {
+    fn name(&self) -> &'static str {
+        "split_ball"
+    }
+
+    fn title(&self) -> &'static str {
+        "Split Ball"
+    }
+
+    fn description(&self) -> &'static str {
+        "Split Ball: the ball splits in three"
+    }
+
+    fn symbol(&self) -> char {
+        '✦'
+    }
+
+    fn color(&self) -> &'static str {
+        "white"
+    }
+
+    fn label(&self) -> &'static str {
+        "SPLIT"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["x x x", " xxx ", "  x  ", "  x  ", "  x  "]
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        None
+    }
+
+    fn suits_any_paddle(&self) -> bool {
+        true
+    }
+
+    fn on_collect(&self, game: &mut Game, _player: u8, ball: &Ball) {
+        // The two new balls carry on at the same speed, fanned out to
+        // either side of the original
+        let speed = ball.vel.length();
+        let heading = ball.vel.angle();
+        for side in [-1.0, 1.0] {
+            if game.balls.len() >= MAX_BALLS {
+                break;
+            }
+            let spread = game.rng.gen_range(SPLIT_SPREAD).to_radians();
+            let vel = Vec2::from_angle(heading + side * spread, speed);
+            game.balls.push(Ball {
+                last_hit_by: ball.last_hit_by,
+                smash_bounces: ball.smash_bounces,
+                ..Ball::at(ball.pos, vel)
+            });
+        }
+    }
+}
+
+pub struct Freeze;
+
+impl PowerUpEffect for Freeze {
+    fn name(&self) -> &'static str {
+        "freeze"
+    }
+
+    fn title(&self) -> &'static str {
+        "Freeze"
+    }
+
+    fn description(&self) -> &'static str {
+        "Freeze: the opponent can't move"
+    }
+
+    fn symbol(&self) -> char {
+        '❄'
+    }
+
+    fn color(&self) -> &'static str {
+        "dark_blue"
+    }
+
+    fn label(&self) -> &'static str {
+        "FRZ"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["x x x", " xxx ", "xxxxx", " xxx ", "x x x"]
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        Some(FREEZE_DURATION)
+    }
+
+    fn stacks(&self) -> bool {
+        false
+    }
+
+    fn suits_any_paddle(&self) -> bool {
+        true
+    }
+}
+
+pub struct SlowBall;
+
+impl PowerUpEffect for SlowBall {
+    fn name(&self) -> &'static str {
+        "slow_ball"
+    }
+
+    fn title(&self) -> &'static str {
+        "Slow Ball"
+    }
+
+    fn description(&self) -> &'static str {
+        "Slow Ball: every ball at half speed"
+    }
+
+    fn symbol(&self) -> char {
+        '◷'
+    }
+
+    fn color(&self) -> &'static str {
+        "dark_cyan"
+    }
+
+    fn label(&self) -> &'static str {
+        "SLOW"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        [" xxx ", "x   x", "x x x", "x   x", " xxx "]
+    }
+
+    fn suits_any_paddle(&self) -> bool {
+        true
+    }
+
+    fn affects_field(&self) -> bool {
+        true
+    }
+}
+
+pub struct GrowPaddle;
+
+impl PowerUpEffect for GrowPaddle {
+    fn name(&self) -> &'static str {
+        "grow_paddle"
+    }
+
+    fn title(&self) -> &'static str {
+        "Grow Paddle"
+    }
+
+    fn description(&self) -> &'static str {
+        "Grow Paddle: a taller paddle"
+    }
+
+    fn symbol(&self) -> char {
+        '▲'
+    }
+
+    fn color(&self) -> &'static str {
+        "dark_green"
+    }
+
+    fn label(&self) -> &'static str {
+        "GROW"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["  x  ", " xxx ", "x x x", "  x  ", "  x  "]
+    }
+
+    fn resize_while_active(&self, game: &mut Game, player: u8) {
+        let height = game.base_height(player) + GROW_BY;
+        if player == 1 {
+            game.p1_height = height;
+        } else {
+            game.p2_height = height;
+        }
+    }
+}
+
+pub struct ShrinkOpponent;
+
+impl PowerUpEffect for ShrinkOpponent {
+    fn name(&self) -> &'static str {
+        "shrink_opponent"
+    }
+
+    fn title(&self) -> &'static str {
+        "Shrink Opponent"
+    }
+
+    fn description(&self) -> &'static str {
+        "Shrink Opponent: a shorter paddle for them"
+    }
+
+    fn symbol(&self) -> char {
+        '▼'
+    }
+
+    fn color(&self) -> &'static str {
+        "dark_magenta"
+    }
+
+    fn label(&self) -> &'static str {
+        "SHRNK"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["  x  ", "  x  ", "x x x", " xxx ", "  x  "]
+    }
+
+    fn resize_while_active(&self, game: &mut Game, player: u8) {
+        let height = game.base_height(3 - player).saturating_sub(SHRINK_BY).max(1);
+        if player == 1 {
+            game.p2_height = height;
+        } else {
+            game.p1_height = height;
+        }
+    }
+}
+
+pub struct Sticky;
+
+impl PowerUpEffect for Sticky {
+    fn name(&self) -> &'static str {
+        "sticky"
+    }
+
+    fn title(&self) -> &'static str {
+        "Sticky"
+    }
+
+    fn description(&self) -> &'static str {
+        "Sticky: catch the ball and fire it on serve"
+    }
+
+    fn symbol(&self) -> char {
+        '◎'
+    }
+
+    fn color(&self) -> &'static str {
+        "dark_yellow"
+    }
+
+    fn label(&self) -> &'static str {
+        "STICK"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["xxxxx", "xxxxx", "xxxxx", "xxxxx", "xxxxx"]
+    }
+}
+
+pub struct Portal;
+
+impl PowerUpEffect for Portal {
+    fn name(&self) -> &'static str {
+        "portal"
+    }
+
+    fn title(&self) -> &'static str {
+        "Portal"
+    }
+
+    fn description(&self) -> &'static str {
+        "Portal: two linked portals, one on each half"
+    }
+
+    fn symbol(&self) -> char {
+        '◌'
+    }
+
+    fn color(&self) -> &'static str {
+        "dark_cyan"
+    }
+
+    fn label(&self) -> &'static str {
+        "PORT"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["xxxxx", "x   x", "x   x", "x   x", "xxxxx"]
+    }
+
+    fn suits_any_paddle(&self) -> bool {
+        true
+    }
+
+    fn affects_field(&self) -> bool {
+        true
+    }
+
+    fn on_collect(&self, game: &mut Game, _player: u8, _ball: &Ball) {
+        let (width, height) = (game.width, game.height);
+        game.portals = Game::place_portals(&mut game.rng, width, height);
+    }
+}
+
+pub struct InvisibleBall;
+
+impl PowerUpEffect for InvisibleBall {
+    fn name(&self) -> &'static str {
+        "invisible_ball"
+    }
+
+    fn title(&self) -> &'static str {
+        "Invisible Ball"
+    }
+
+    fn description(&self) -> &'static str {
+        "Invisible Ball: your shots vanish past the middle"
+    }
+
+    fn symbol(&self) -> char {
+        '◍'
+    }
+
+    fn color(&self) -> &'static str {
+        "dark_grey"
+    }
+
+    fn label(&self) -> &'static str {
+        "INVIS"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["x x x", "     ", "x x x", "     ", "x x x"]
+    }
+}
+
+pub struct Shield;
+
+impl PowerUpEffect for Shield {
+    fn name(&self) -> &'static str {
+        "shield"
+    }
+
+    fn title(&self) -> &'static str {
+        "Shield"
+    }
+
+    fn description(&self) -> &'static str {
+        "Shield: stops one ball on your goal line"
+    }
+
+    fn symbol(&self) -> char {
+        '▯'
+    }
+
+    fn color(&self) -> &'static str {
+        "grey"
+    }
+
+    fn label(&self) -> &'static str {
+        "SHLD"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["xxxxx", "x   x", "x   x", " x x ", "  x  "]
+    }
+
+    /// It lasts until a ball breaks it
+    fn duration(&self) -> Option<Duration> {
+        None
+    }
+
+    fn on_collect(&self, game: &mut Game, player: u8, _ball: &Ball) {
+        if player == 1 {
+            game.p1_shield = true;
+        } else {
+            game.p2_shield = true;
+        }
+    }
+}
+
+pub struct ReverseControls;
+
+impl PowerUpEffect for ReverseControls {
+    fn name(&self) -> &'static str {
+        "reverse_controls"
+    }
+
+    fn title(&self) -> &'static str {
+        "Reverse Controls"
+    }
+
+    fn description(&self) -> &'static str {
+        "Reverse Controls: up and down swap for them"
+    }
+
+    fn symbol(&self) -> char {
+        '⇅'
+    }
+
+    fn color(&self) -> &'static str {
+        "dark_red"
+    }
+
+    fn label(&self) -> &'static str {
+        "REV"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["  x  ", " xxx ", "     ", " xxx ", "  x  "]
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        Some(REVERSE_DURATION)
+    }
+
+    fn stacks(&self) -> bool {
+        false
+    }
+
+    fn suits_any_paddle(&self) -> bool {
+        true
+    }
+}
+
+pub struct ScoreMultiplier;
+
+impl PowerUpEffect for ScoreMultiplier {
+    fn name(&self) -> &'static str {
+        "score_multiplier"
+    }
+
+    fn title(&self) -> &'static str {
+        "Score Multiplier"
+    }
+
+    fn description(&self) -> &'static str {
+        "Score Multiplier: your next point counts double"
+    }
+
+    fn symbol(&self) -> char {
+        '×'
+    }
+
+    fn color(&self) -> &'static str {
+        "red"
+    }
+
+    fn label(&self) -> &'static str {
+        "X2"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["x   x", " x x ", "  x  ", " x x ", "x   x"]
+    }
+
+    /// It lasts until its player scores
+    fn duration(&self) -> Option<Duration> {
+        None
+    }
+
+    fn arcade_only(&self) -> bool {
+        true
+    }
+
+    fn on_collect(&self, game: &mut Game, player: u8, _ball: &Ball) {
+        if player == 1 {
+            game.p1_double = true;
+        } else {
+            game.p2_double = true;
+        }
+    }
+}
+
+pub struct MovingWall;
+
+impl PowerUpEffect for MovingWall {
+    fn name(&self) -> &'static str {
+        "moving_wall"
+    }
+
+    fn title(&self) -> &'static str {
+        "Moving Wall"
+    }
+
+    fn description(&self) -> &'static str {
+        "Moving Wall: a short wall sliding up and down the middle"
+    }
+
+    fn symbol(&self) -> char {
+        '⇕'
+    }
+
+    fn color(&self) -> &'static str {
+        "dark_yellow"
+    }
+
+    fn label(&self) -> &'static str {
+        "SLIDE"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["  x  ", " xxx ", "  x  ", " xxx ", "  x  "]
+    }
+
+    fn suits_any_paddle(&self) -> bool {
+        true
+    }
+
+    fn affects_field(&self) -> bool {
+        true
+    }
+
+    fn uses_middle(&self) -> bool {
+        true
+    }
+
+    /// Another one while it's up keeps the same wall going
+    fn on_collect(&self, game: &mut Game, _player: u8, _ball: &Ball) {
+        if game.sliding_wall.is_none() {
+            game.sliding_wall = Some(game.new_sliding_wall());
+        }
+    }
+}
+
+/// What every custom powerup has in common. The rest, its look and how
+/// long it lasts, comes from the `CustomPowerUp` it was added with, and
+/// what it does is up to whoever added it.
+pub struct Custom;
+
+impl PowerUpEffect for Custom {
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+
+    fn title(&self) -> &'static str {
+        "Custom"
+    }
+
+    fn description(&self) -> &'static str {
+        "Custom: whatever its script says"
+    }
+
+    fn symbol(&self) -> char {
+        '?'
+    }
+
+    fn color(&self) -> &'static str {
+        "white"
+    }
+
+    fn label(&self) -> &'static str {
+        "CUST"
+    }
+
+    fn shape(&self) -> [&'static str; POWERUP_SIZE as usize] {
+        ["x x x", " x x ", "x x x", " x x ", "x x x"]
+    }
+
+    fn suits_any_paddle(&self) -> bool {
+        true
+    }
+}
+
+impl PowerUpType {
+    /// The one table of what each powerup is and does
+    pub fn effect(self) -> &'static dyn PowerUpEffect {
+        match self {
+            PowerUpType::DoublePaddle => &DoublePaddle,
+            PowerUpType::CenterWall => &CenterWall,
+            PowerUpType::TwoSmallWalls => &TwoSmallWalls,
+            PowerUpType::BentPaddle => &BentPaddle,
+            PowerUpType::SplitBall => &SplitBall,
+            PowerUpType::Freeze => &Freeze,
+            PowerUpType::SlowBall => &SlowBall,
+            PowerUpType::GrowPaddle => &GrowPaddle,
+            PowerUpType::ShrinkOpponent => &ShrinkOpponent,
+            PowerUpType::Sticky => &Sticky,
+            PowerUpType::Portal => &Portal,
+            PowerUpType::InvisibleBall => &InvisibleBall,
+            PowerUpType::Shield => &Shield,
+            PowerUpType::ReverseControls => &ReverseControls,
+            PowerUpType::ScoreMultiplier => &ScoreMultiplier,
+            PowerUpType::MovingWall => &MovingWall,
+            PowerUpType::Custom(_) => &Custom,
+        }
+    }
+}
+
+impl Game {
+    /// Give `player` the powerup they just collected with `ball`
+    pub(super) fn collect_powerup(&mut self, ptype: PowerUpType, player: u8, ball: &Ball) {
+        let effect = ptype.effect();
+        let duration = if let PowerUpType::Custom(index) = ptype {
+            let custom = self.custom_powerups.get(index as usize);
+            let duration = custom.and_then(|c| c.as_ref().map(|c| c.duration));
+            self.custom_calls.push(super::CustomCall::Collected { index, player });
+            duration.or(Some(Duration::ZERO))
+        } else {
+            effect.on_collect(self, player, ball);
+            effect.duration()
+        };
+        let Some(duration) = duration else {
+            return;
+        };
+        // One that doesn't stack starts the count again rather than adding
+        // to it
+        let active = self
+            .active_powerups
+            .iter_mut()
+            .find(|a| !effect.stacks() && a.ptype == ptype && a.player == player);
+        match active {
+            Some(active) => active.remaining = duration,
+            None => self.active_powerups.push(ActivePowerUp {
+                ptype,
+                player,
+                remaining: duration,
+            }),
+        }
+    }
+
+    /// Put every active powerup's effect on the field
+    pub(super) fn apply_active_effects(&mut self) {
+        let active: Vec<(PowerUpType, u8)> =
+            self.active_powerups.iter().map(|a| (a.ptype, a.player)).collect();
+        for &(ptype, player) in &active {
+            ptype.effect().resize_while_active(self, player);
+        }
+        let [p1_custom, p2_custom] = self.custom_effects.paddle_height;
+        self.p1_height = p1_custom.unwrap_or(self.p1_height);
+        self.p2_height = p2_custom.unwrap_or(self.p2_height);
+        // A paddle growing back near the bottom would stick out of the field
+        self.clamp_paddles();
+
+        for &(ptype, player) in &active {
+            ptype.effect().apply_while_active(self, player);
+        }
+        self.center_wall |= self.custom_effects.center_wall;
+    }
+}
@@ -0,0 +1,148 @@
+//! Lifetime numbers kept between runs, in a small file in the data
+//! directory.
+
+use dospong::pong::Game;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Records {
+    /// Arcade matches aren't counted here, since their points go further,
+    /// but in `arcade_games` and `arcade_points`
+    pub games_played: u32,
+    pub p1_wins: u32,
+    pub p2_wins: u32,
+    pub total_points: u32,
+    pub arcade_games: u32,
+    pub arcade_points: u32,
+    /// The most points one player has scored in an arcade match
+    pub best_arcade_score: u32,
+    pub longest_rally: u32,
+    /// In cells per second
+    pub top_speed: f32,
+    /// Most returns in one survival run
+    pub best_survival: u32,
+    /// Matches where either player had a handicap, which count towards
+    /// the rest as well
+    pub handicap_games: u32,
+    /// Matches won by each player who gave a name, whichever paddle
+    pub wins_by_name: BTreeMap<String, u32>,
+}
+
+/// Which of a finished match's numbers beat the old records
+#[derive(Clone, Copy, Default)]
+pub struct NewBests {
+    pub rally: bool,
+    pub speed: bool,
+    pub survival: bool,
+}
+
+impl Records {
+    pub fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|data| data.join("dospong").join("stats.toml"))
+    }
+
+    /// Read the records, starting over if there are none yet or the file
+    /// can't be read
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Records::default();
+        };
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Records::default();
+        };
+        toml::from_str(&text).unwrap_or_else(|err| {
+            eprintln!(
+                "DOSPong: starting the stats over, {} is damaged: {}",
+                path.display(),
+                err
+            );
+            Records::default()
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::path().ok_or("no data directory to save the stats in")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        let text = toml::to_string_pretty(self).map_err(|err| err.to_string())?;
+        fs::write(&path, text).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Count a finished match
+    pub fn add_match(&mut self, game: &Game) -> NewBests {
+        // A survival run only has the one number worth keeping
+        if let Some(survival) = &game.survival {
+            let new_best = survival.returns > self.best_survival;
+            self.best_survival = self.best_survival.max(survival.returns);
+            return NewBests {
+                survival: new_best,
+                ..NewBests::default()
+            };
+        }
+        let stats = &game.stats;
+        let new_bests = NewBests {
+            rally: stats.longest_rally > self.longest_rally,
+            speed: stats.top_speed > self.top_speed,
+            survival: false,
+        };
+        let points = game.scores().iter().map(|&points| points as u32).sum::<u32>();
+        match game.arcade {
+            true => {
+                self.arcade_games += 1;
+                self.arcade_points += points;
+                let best = game.p1_score.max(game.p2_score) as u32;
+                self.best_arcade_score = self.best_arcade_score.max(best);
+            }
+            false => {
+                self.games_played += 1;
+                self.total_points += points;
+            }
+        }
+        if [1, 2].iter().any(|&player| game.handicap(player).is_set()) {
+            self.handicap_games += 1;
+        }
+        let winner = game.winner.map(|side| game.player_at(side));
+        match winner {
+            Some(1) => self.p1_wins += 1,
+            Some(2) => self.p2_wins += 1,
+            // Four-player games only count as played
+            _ => {}
+        }
+        if let Some(name) = winner.map(|player| game.given_name(player)) {
+            if !name.is_empty() {
+                *self.wins_by_name.entry(name.to_string()).or_default() += 1;
+            }
+        }
+        self.longest_rally = self.longest_rally.max(stats.longest_rally);
+        self.top_speed = self.top_speed.max(stats.top_speed);
+        new_bests
+    }
+
+    /// The records as a table, for --stats
+    pub fn table(&self) -> String {
+        [
+            format!("{:<16}{:>8}", "Games played", self.games_played),
+            format!("{:<16}{:>8}", "Player 1 wins", self.p1_wins),
+            format!("{:<16}{:>8}", "Player 2 wins", self.p2_wins),
+            format!("{:<16}{:>8}", "Points scored", self.total_points),
+            format!("{:<16}{:>8}", "Arcade games", self.arcade_games),
+            format!("{:<16}{:>8}", "Arcade points", self.arcade_points),
+            format!("{:<16}{:>8}", "Best arcade", self.best_arcade_score),
+            format!("{:<16}{:>8}", "Longest rally", self.longest_rally),
+            format!("{:<16}{:>8.1} cells/s", "Fastest ball", self.top_speed),
+            format!("{:<16}{:>8}", "Best survival", self.best_survival),
+            format!("{:<16}{:>8}", "Handicap games", self.handicap_games),
+        ]
+        .into_iter()
+        .chain(self.wins_by_name.iter().map(|(name, wins)| {
+            format!("{:<16}{:>8}", format!("{} wins", name), wins)
+        }))
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+}
@@ -0,0 +1,221 @@
+//! Matches over the internet, met up through `dospong-server`. Both players
+//! connect out to the relay, which puts them in a room by a short code and
+//! from then on passes every frame from one to the other without looking
+//! inside, so the game itself is played just as over a direct connection.
+//! What's here is shared by the game and the relay, so the two can't come
+//! to disagree about it.
+
+use rand::Rng;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Bumped whenever talking to the relay changes
+pub const RELAY_VERSION: u16 = 1;
+pub const DEFAULT_PORT: u16 = 47_778;
+/// The biggest frame the relay passes on. A whole game sent to start a
+/// rollback match has to fit in one too, which the host checks.
+pub const MAX_PACKET_SIZE: usize = 32 * 1024;
+/// A room nobody joins is closed after this long
+pub const ROOM_WAIT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// And a match that goes this long without a frame either way, since both
+/// sides send one every frame while it's on
+pub const ROOM_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+pub const CODE_LENGTH: usize = 6;
+// No 0, O, 1 or I, which are easily read as each other
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const TAG_CREATE: u8 = 0;
+const TAG_JOIN: u8 = 1;
+const TAG_CREATED: u8 = 2;
+const TAG_PAIRED: u8 = 3;
+const TAG_REFUSED: u8 = 4;
+
+/// What a player asks the relay for, as the first frame they send
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    /// Open a room for someone to join
+    Create { version: u16 },
+    /// Join the room with this code
+    Join { version: u16, code: String },
+}
+
+/// The relay's answers. After `Paired`, everything is the other player's.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reply {
+    Created { code: String },
+    Paired,
+    Refused(String),
+}
+
+impl Request {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Request::Create { version } => {
+                out.push(TAG_CREATE);
+                out.extend_from_slice(&version.to_le_bytes());
+            }
+            Request::Join { version, code } => {
+                out.push(TAG_JOIN);
+                out.extend_from_slice(&version.to_le_bytes());
+                put_text(&mut out, code);
+            }
+        }
+        out
+    }
+
+    pub fn decode(frame: &[u8]) -> Option<Self> {
+        let (&tag, rest) = frame.split_first()?;
+        let version = u16::from_le_bytes(rest.get(..2)?.try_into().ok()?);
+        match tag {
+            TAG_CREATE => Some(Request::Create { version }),
+            TAG_JOIN => Some(Request::Join {
+                version,
+                code: text(&rest[2..])?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Reply {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Reply::Created { code } => {
+                out.push(TAG_CREATED);
+                put_text(&mut out, code);
+            }
+            Reply::Paired => out.push(TAG_PAIRED),
+            Reply::Refused(reason) => {
+                out.push(TAG_REFUSED);
+                put_text(&mut out, reason);
+            }
+        }
+        out
+    }
+
+    pub fn decode(frame: &[u8]) -> Option<Self> {
+        let (&tag, rest) = frame.split_first()?;
+        match tag {
+            TAG_CREATED => Some(Reply::Created { code: text(rest)? }),
+            TAG_PAIRED => Some(Reply::Paired),
+            TAG_REFUSED => Some(Reply::Refused(text(rest)?)),
+            _ => None,
+        }
+    }
+}
+
+fn put_text(out: &mut Vec<u8>, text: &str) {
+    let bytes = &text.as_bytes()[..text.len().min(255)];
+    out.push(bytes.len() as u8);
+    out.extend_from_slice(bytes);
+}
+
+fn text(input: &[u8]) -> Option<String> {
+    let (&len, rest) = input.split_first()?;
+    let bytes = rest.get(..len as usize)?;
+    Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// A fresh room code
+pub fn random_code(rng: &mut impl Rng) -> String {
+    (0..CODE_LENGTH)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// `code` as the relay knows it, whatever case it was typed in, or `None`
+/// if it can't be one
+pub fn normalize_code(code: &str) -> Option<String> {
+    let code = code.trim().to_ascii_uppercase();
+    let valid = code.len() == CODE_LENGTH && code.bytes().all(|b| CODE_ALPHABET.contains(&b));
+    valid.then_some(code)
+}
+
+/// Frames go both ways with their length first, the same as the game's own
+fn write_frame(stream: &mut impl Write, frame: &[u8]) -> io::Result<()> {
+    let mut out = Vec::with_capacity(2 + frame.len());
+    out.extend_from_slice(&(frame.len() as u16).to_le_bytes());
+    out.extend_from_slice(frame);
+    stream.write_all(&out)
+}
+
+fn read_frame(stream: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let len = u16::from_le_bytes(len) as usize;
+    if len > MAX_PACKET_SIZE {
+        return Err(relay_error("frame too big".to_string()));
+    }
+    let mut frame = vec![0u8; len];
+    stream.read_exact(&mut frame)?;
+    Ok(frame)
+}
+
+fn relay_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_reply(stream: &mut TcpStream) -> io::Result<Reply> {
+    match Reply::decode(&read_frame(stream)?) {
+        Some(Reply::Refused(reason)) => Err(relay_error(reason)),
+        Some(reply) => Ok(reply),
+        None => Err(relay_error("garbled reply from the relay".to_string())),
+    }
+}
+
+/// Connect to the relay at `address`, on the default port unless it says
+/// otherwise
+fn connect(address: &str) -> io::Result<TcpStream> {
+    let with_port = match address.contains(':') {
+        true => address.to_string(),
+        false => format!("{}:{}", address, DEFAULT_PORT),
+    };
+    let resolved = with_port
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| relay_error(format!("couldn't resolve '{}'", address)))?;
+    let stream = TcpStream::connect_timeout(&resolved, CONNECT_TIMEOUT)?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// Open a room on the relay, calling `on_code` with its code once there is
+/// one, and wait for someone to join it. Returns the stream to them.
+pub fn open_room(address: &str, on_code: impl FnOnce(&str)) -> io::Result<TcpStream> {
+    let mut stream = connect(address)?;
+    write_frame(&mut stream, &Request::Create { version: RELAY_VERSION }.encode())?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    let Reply::Created { code } = read_reply(&mut stream)? else {
+        return Err(relay_error("the relay didn't open a room".to_string()));
+    };
+    on_code(&code);
+    // However long the other player takes, up to the relay's own limit
+    stream.set_read_timeout(None)?;
+    match read_reply(&mut stream)? {
+        Reply::Paired => Ok(stream),
+        _ => Err(relay_error("the relay didn't pair us up".to_string())),
+    }
+}
+
+/// Join the room `code` on the relay. Returns the stream to whoever
+/// opened it.
+pub fn join_room(address: &str, code: &str) -> io::Result<TcpStream> {
+    let mut stream = connect(address)?;
+    let request = Request::Join {
+        version: RELAY_VERSION,
+        code: code.to_string(),
+    };
+    write_frame(&mut stream, &request.encode())?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    match read_reply(&mut stream)? {
+        Reply::Paired => {
+            stream.set_read_timeout(None)?;
+            Ok(stream)
+        }
+        _ => Err(relay_error("the relay didn't pair us up".to_string())),
+    }
+}
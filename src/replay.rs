@@ -0,0 +1,382 @@
+//! Recording matches to a file and playing them back. Everything random
+//! comes from the seed and physics runs in fixed steps, so the seed plus
+//! every frame's input is enough to play a match out again exactly.
+
+use crate::net::{dir_from_u8, dir_to_u8};
+use crate::pong::{
+    clean_name, AiDifficulty, Handicap, Input, PowerUpSettings, PowerUpType, Tuning,
+};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::time::Duration;
+
+const MAGIC: &[u8; 8] = b"DPREPLAY";
+const FORMAT_VERSION: u16 = 24;
+
+const TAG_FRAME: u8 = 0;
+const TAG_PAUSE: u8 = 1;
+const TAG_HELP: u8 = 2;
+const TAG_REMATCH: u8 = 3;
+const TAG_RESIZE: u8 = 4;
+const TAG_NEXT_SET: u8 = 5;
+const TAG_QUIT_PROMPT: u8 = 6;
+const TAG_SETTINGS: u8 = 7;
+
+/// What a match was started with
+pub struct Header {
+    pub seed: u64,
+    pub width: u16,
+    pub height: u16,
+    pub score_limit: u16,
+    pub deuce: bool,
+    pub best_of: u16,
+    /// How long a timed match lasts
+    pub time_limit: Option<Duration>,
+    /// Points worth more the longer the rally
+    pub arcade: bool,
+    pub gravity: bool,
+    pub wind: bool,
+    pub four_player: bool,
+    pub practice: bool,
+    pub survival: bool,
+    pub obstacles: bool,
+    pub breakout: bool,
+    /// Drawn in half blocks, so the field has twice the terminal's rows
+    pub half_block: bool,
+    pub ai: Option<AiDifficulty>,
+    pub powerups: PowerUpSettings,
+    pub tuning: Tuning,
+    /// What Players 1 and 2 were called, empty for one that went by number
+    pub names: [String; 2],
+    pub handicaps: [Handicap; 2],
+}
+
+pub enum Record {
+    /// One pass of the main loop: the paddle input and the real time that
+    /// passed
+    Frame { input: Input, dt: f32 },
+    Pause,
+    Help,
+    Rematch,
+    NextSet,
+    /// The question whether to quit opening or closing, which holds the
+    /// match while it's up
+    QuitPrompt,
+    Resize { width: u16, height: u16 },
+    /// The settings changed in the middle of the match
+    Settings {
+        tuning: Tuning,
+        score_limit: u16,
+        powerups: Vec<PowerUpType>,
+    },
+}
+
+fn write_tuning(out: &mut impl Write, tuning: &Tuning) -> io::Result<()> {
+    out.write_all(&tuning.ball_speed.to_le_bytes())?;
+    out.write_all(&[tuning.paddle_speed as u8, tuning.paddle_height as u8])?;
+    out.write_all(&tuning.max_ball_speed.to_le_bytes())
+}
+
+fn read_tuning(input: &mut Reader) -> Option<Tuning> {
+    Some(Tuning {
+        ball_speed: input.take().map(f32::from_le_bytes)?,
+        paddle_speed: input.u8()? as i16,
+        paddle_height: input.u8()? as u16,
+        max_ball_speed: input.take().map(f32::from_le_bytes)?,
+    })
+}
+
+fn read_handicap(input: &mut Reader) -> Option<Handicap> {
+    Some(Handicap {
+        // No paddle is 0 rows tall, so 0 is the match's height
+        paddle_height: Some(input.u8()? as u16).filter(|&height| height > 0),
+        paddle_speed: input.take().map(f32::from_le_bytes)?,
+        head_start: input.u16()?,
+    })
+}
+
+fn ai_to_u8(ai: Option<AiDifficulty>) -> u8 {
+    match ai {
+        None => 0,
+        Some(AiDifficulty::Easy) => 1,
+        Some(AiDifficulty::Normal) => 2,
+        Some(AiDifficulty::Hard) => 3,
+    }
+}
+
+/// The enabled powerups as one bit each, in the order of `PowerUpType::ALL`
+fn powerups_to_mask(enabled: &[PowerUpType]) -> u32 {
+    PowerUpType::ALL
+        .iter()
+        .enumerate()
+        .filter(|(_, ptype)| enabled.contains(ptype))
+        .fold(0, |mask, (i, _)| mask | 1 << i)
+}
+
+fn powerups_from_mask(mask: u32) -> Vec<PowerUpType> {
+    PowerUpType::ALL
+        .into_iter()
+        .enumerate()
+        .filter(|&(i, _)| mask & 1 << i != 0)
+        .map(|(_, ptype)| ptype)
+        .collect()
+}
+
+fn ai_from_u8(value: u8) -> Option<Option<AiDifficulty>> {
+    match value {
+        0 => Some(None),
+        1 => Some(Some(AiDifficulty::Easy)),
+        2 => Some(Some(AiDifficulty::Normal)),
+        3 => Some(Some(AiDifficulty::Hard)),
+        _ => None,
+    }
+}
+
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &str, header: &Header) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        let game_version = env!("CARGO_PKG_VERSION").as_bytes();
+        out.write_all(&[game_version.len() as u8])?;
+        out.write_all(game_version)?;
+        out.write_all(&header.seed.to_le_bytes())?;
+        out.write_all(&header.width.to_le_bytes())?;
+        out.write_all(&header.height.to_le_bytes())?;
+        out.write_all(&header.score_limit.to_le_bytes())?;
+        out.write_all(&[header.deuce as u8])?;
+        out.write_all(&header.best_of.to_le_bytes())?;
+        let time_limit = header.time_limit.map_or(0, |limit| limit.as_millis() as u32);
+        out.write_all(&time_limit.to_le_bytes())?;
+        out.write_all(&[header.arcade as u8])?;
+        out.write_all(&[header.gravity as u8, header.wind as u8])?;
+        out.write_all(&[
+            header.four_player as u8,
+            header.practice as u8,
+            header.survival as u8,
+            header.obstacles as u8,
+            header.breakout as u8,
+            header.half_block as u8,
+        ])?;
+        out.write_all(&[ai_to_u8(header.ai)])?;
+        let powerups = &header.powerups;
+        out.write_all(&powerups_to_mask(&powerups.enabled).to_le_bytes())?;
+        out.write_all(&powerups.spawn_rate.to_le_bytes())?;
+        out.write_all(&[powerups.max_on_field.min(u8::MAX as usize) as u8])?;
+        write_tuning(&mut out, &header.tuning)?;
+        for name in &header.names {
+            let name = clean_name(name);
+            out.write_all(&[name.len() as u8])?;
+            out.write_all(name.as_bytes())?;
+        }
+        for handicap in &header.handicaps {
+            out.write_all(&[handicap.paddle_height.unwrap_or(0) as u8])?;
+            out.write_all(&handicap.paddle_speed.to_le_bytes())?;
+            out.write_all(&handicap.head_start.to_le_bytes())?;
+        }
+        Ok(Recorder { out })
+    }
+
+    pub fn record(&mut self, record: &Record) -> io::Result<()> {
+        match *record {
+            Record::Frame { input, dt } => {
+                let keys = dir_to_u8(input.p1) as u16
+                    | (dir_to_u8(input.p2) as u16) << 2
+                    | (input.p1_serve as u16) << 4
+                    | (input.p2_serve as u16) << 5
+                    | (dir_to_u8(input.p3) as u16) << 6
+                    | (dir_to_u8(input.p4) as u16) << 8
+                    | (dir_to_u8(input.p1_dash) as u16) << 10
+                    | (dir_to_u8(input.p2_dash) as u16) << 12
+                    | (input.p1_charge as u16) << 14
+                    | (input.p2_charge as u16) << 15;
+                self.out.write_all(&[TAG_FRAME])?;
+                self.out.write_all(&keys.to_le_bytes())?;
+                self.out.write_all(&dt.to_le_bytes())
+            }
+            Record::Pause => self.out.write_all(&[TAG_PAUSE]),
+            Record::Help => self.out.write_all(&[TAG_HELP]),
+            Record::Rematch => self.out.write_all(&[TAG_REMATCH]),
+            Record::NextSet => self.out.write_all(&[TAG_NEXT_SET]),
+            Record::QuitPrompt => self.out.write_all(&[TAG_QUIT_PROMPT]),
+            Record::Resize { width, height } => {
+                self.out.write_all(&[TAG_RESIZE])?;
+                self.out.write_all(&width.to_le_bytes())?;
+                self.out.write_all(&height.to_le_bytes())
+            }
+            Record::Settings {
+                tuning,
+                score_limit,
+                ref powerups,
+            } => {
+                self.out.write_all(&[TAG_SETTINGS])?;
+                write_tuning(&mut self.out, &tuning)?;
+                self.out.write_all(&score_limit.to_le_bytes())?;
+                self.out.write_all(&powerups_to_mask(powerups).to_le_bytes())
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// A recorded match, read back in full
+pub struct Replay {
+    pub header: Header,
+    pub records: Vec<Record>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, rest) = self.data.split_first_chunk::<N>()?;
+        self.data = rest;
+        Some(*head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take::<1>().map(|[b]| b)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    fn name(&mut self) -> Option<String> {
+        let len = self.u8()? as usize;
+        let bytes = self.data.get(..len)?;
+        self.data = &self.data[len..];
+        Some(clean_name(&String::from_utf8_lossy(bytes)))
+    }
+}
+
+impl Replay {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let mut data = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|err| format!("{}: {}", path, err))?;
+        Self::parse(&data).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    fn parse(data: &[u8]) -> Result<Self, String> {
+        let truncated = || "the replay is cut off".to_string();
+        let mut input = Reader {
+            data: data
+                .strip_prefix(MAGIC)
+                .ok_or("not a DOSPong replay")?,
+        };
+
+        let format = input.u16().ok_or_else(truncated)?;
+        if format != FORMAT_VERSION {
+            return Err(format!(
+                "replay format {} isn't supported (this version reads format {})",
+                format, FORMAT_VERSION
+            ));
+        }
+        // Any change to the physics can make an old replay play out
+        // differently, so only the version that recorded it may play it
+        let len = input.u8().ok_or_else(truncated)? as usize;
+        let recorded_by = input.data.get(..len).ok_or_else(truncated)?;
+        let recorded_by = String::from_utf8_lossy(recorded_by).into_owned();
+        input.data = &input.data[len..];
+        if recorded_by != env!("CARGO_PKG_VERSION") {
+            return Err(format!(
+                "recorded with DOSPong {}, but this is {}, so it would play out differently",
+                recorded_by,
+                env!("CARGO_PKG_VERSION")
+            ));
+        }
+
+        let header = (|| {
+            Some(Header {
+                seed: input.take().map(u64::from_le_bytes)?,
+                width: input.u16()?,
+                height: input.u16()?,
+                score_limit: input.u16()?,
+                deuce: input.u8()? != 0,
+                best_of: input.u16()?,
+                time_limit: match input.take().map(u32::from_le_bytes)? {
+                    0 => None,
+                    limit => Some(Duration::from_millis(limit as u64)),
+                },
+                arcade: input.u8()? != 0,
+                gravity: input.u8()? != 0,
+                wind: input.u8()? != 0,
+                four_player: input.u8()? != 0,
+                practice: input.u8()? != 0,
+                survival: input.u8()? != 0,
+                obstacles: input.u8()? != 0,
+                breakout: input.u8()? != 0,
+                half_block: input.u8()? != 0,
+                ai: ai_from_u8(input.u8()?)?,
+                powerups: PowerUpSettings {
+                    enabled: powerups_from_mask(input.take().map(u32::from_le_bytes)?),
+                    spawn_rate: input.take().map(f32::from_le_bytes)?,
+                    max_on_field: input.u8()? as usize,
+                },
+                tuning: read_tuning(&mut input)?,
+                names: [input.name()?, input.name()?],
+                handicaps: [read_handicap(&mut input)?, read_handicap(&mut input)?],
+            })
+        })()
+        .ok_or("the replay header is damaged")?;
+
+        let mut records = Vec::new();
+        while let Some(tag) = input.u8() {
+            let record = match tag {
+                TAG_FRAME => {
+                    let keys = input.u16().ok_or_else(truncated)?;
+                    let dt = input.take().map(f32::from_le_bytes).ok_or_else(truncated)?;
+                    let damaged = || "the replay has a damaged frame".to_string();
+                    Record::Frame {
+                        input: Input {
+                            p1: dir_from_u8(keys as u8 & 3).ok_or_else(damaged)?,
+                            p2: dir_from_u8((keys >> 2) as u8 & 3).ok_or_else(damaged)?,
+                            p1_serve: keys & 16 != 0,
+                            p2_serve: keys & 32 != 0,
+                            p3: dir_from_u8((keys >> 6) as u8 & 3).ok_or_else(damaged)?,
+                            p4: dir_from_u8((keys >> 8) as u8 & 3).ok_or_else(damaged)?,
+                            p1_dash: dir_from_u8((keys >> 10) as u8 & 3).ok_or_else(damaged)?,
+                            p2_dash: dir_from_u8((keys >> 12) as u8 & 3).ok_or_else(damaged)?,
+                            p1_charge: keys & 1 << 14 != 0,
+                            p2_charge: keys & 1 << 15 != 0,
+                        },
+                        dt,
+                    }
+                }
+                TAG_PAUSE => Record::Pause,
+                TAG_HELP => Record::Help,
+                TAG_REMATCH => Record::Rematch,
+                TAG_NEXT_SET => Record::NextSet,
+                TAG_QUIT_PROMPT => Record::QuitPrompt,
+                TAG_RESIZE => Record::Resize {
+                    width: input.u16().ok_or_else(truncated)?,
+                    height: input.u16().ok_or_else(truncated)?,
+                },
+                TAG_SETTINGS => Record::Settings {
+                    tuning: read_tuning(&mut input).ok_or_else(truncated)?,
+                    score_limit: input.u16().ok_or_else(truncated)?,
+                    powerups: input
+                        .take()
+                        .map(u32::from_le_bytes)
+                        .map(powerups_from_mask)
+                        .ok_or_else(truncated)?,
+                },
+                other => return Err(format!("unknown record type {} in the replay", other)),
+            };
+            records.push(record);
+        }
+
+        Ok(Replay { header, records })
+    }
+}
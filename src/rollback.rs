@@ -0,0 +1,439 @@
+//! Network play where both players run the match. Each side moves its own
+//! paddle straight away and sends its input for every tick to the other,
+//! guessing that the other paddle keeps doing whatever it last did. When the
+//! real input turns out different, the game goes back to the tick it was
+//! guessed for and plays forward again, so nobody waits a round trip to see
+//! their paddle move. Every second both sides send a checksum of the game,
+//! and a match whose two games have drifted apart stops instead of carrying
+//! on as two different ones.
+
+use crate::net::{handshake_error, Connection, Message, HANDSHAKE_TIMEOUT, MAX_START_STATE};
+use crate::pong::{Dir, Game, Input};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// How long a tick is. Both sides play every tick with this same time step,
+/// whatever their frame rates, which is what keeps their games alike.
+pub const TICK_DT: f32 = 1.0 / 60.0;
+// How many ticks a side may play past the last one it has the other's
+// input for before it waits, which is also as far back as it ever goes
+const MAX_ROLLBACK: usize = 120;
+const CHECKSUM_INTERVAL: u32 = 60;
+// A side further ahead of the other than this plays a little slower until
+// they even out, so neither keeps going back further than the lag needs
+const MAX_LEAD: i32 = 1;
+const CATCH_UP_RATE: f32 = 0.9;
+// Time beyond this since the last frame is dropped rather than played in
+// one burst
+const MAX_FRAME_DT: f32 = 0.25;
+
+/// One player's input for one tick
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TickInput {
+    pub dir: Dir,
+    pub serve: bool,
+    pub dash: Dir,
+    /// Held like `dir`, rather than pressed
+    pub charge: bool,
+    pub pause: bool,
+    /// Start the next set, if one just ended
+    pub next_set: bool,
+    /// Play the match again, if it's over
+    pub rematch: bool,
+}
+
+impl TickInput {
+    /// The guess for a tick the other side hasn't been heard from for: the
+    /// paddle keeps moving or charging, but nothing is pressed again
+    fn held(self) -> Self {
+        TickInput {
+            dir: self.dir,
+            charge: self.charge,
+            ..TickInput::default()
+        }
+    }
+}
+
+/// Where the two games stopped matching, with this side's game as of then
+/// so it can be compared with the other side's
+#[derive(Serialize)]
+pub struct Desync {
+    pub tick: u32,
+    /// The player this side played
+    pub player: u8,
+    pub checksum: u64,
+    pub their_checksum: u64,
+    pub game: Game,
+}
+
+struct Frame {
+    // The game just before this tick was played
+    state: Game,
+    // By player
+    inputs: [TickInput; 2],
+    // Whether the other player's input is the one they sent, not a guess
+    confirmed: bool,
+}
+
+pub struct Session {
+    // The player this side plays: 1 hosting and 2 joining
+    player: u8,
+    // The tick `frames` starts at, the first one the other player's input
+    // hasn't come in for
+    first: u32,
+    // Every tick played since then, to go back to
+    frames: VecDeque<Frame>,
+    // The other player's inputs for ticks not played here yet
+    early: VecDeque<(u32, TickInput)>,
+    // The other player's latest input, which the guesses go on
+    last_remote: TickInput,
+    // The latest tick heard from the other player, and how far ahead of
+    // this side they were then
+    remote_tick: Option<u32>,
+    remote_lead: i32,
+    // The earliest tick that was played with a wrong guess
+    wrong_from: Option<u32>,
+    // Presses waiting to go out with the next tick
+    pending: TickInput,
+    accumulator: f32,
+    // This side's checksums still waiting for the other's, each with the
+    // game it was taken of, and the other's still waiting for this side's
+    sums: VecDeque<(u32, u64, Game)>,
+    their_sums: VecDeque<(u32, u64)>,
+    rollbacks: u32,
+    deepest: usize,
+}
+
+impl Session {
+    fn new(player: u8) -> Self {
+        Session {
+            player,
+            first: 0,
+            frames: VecDeque::new(),
+            early: VecDeque::new(),
+            last_remote: TickInput::default(),
+            remote_tick: None,
+            remote_lead: 0,
+            wrong_from: None,
+            pending: TickInput::default(),
+            accumulator: 0.0,
+            sums: VecDeque::new(),
+            their_sums: VecDeque::new(),
+            rollbacks: 0,
+            deepest: 0,
+        }
+    }
+
+    /// The host's side, which plays Player 1
+    pub fn host() -> Self {
+        Session::new(1)
+    }
+
+    /// The joining player's side, which plays Player 2
+    pub fn join() -> Self {
+        Session::new(2)
+    }
+
+    /// Agree on the game the match starts from: the host sends `game` over,
+    /// and the joining player waits for it and plays that one instead
+    pub fn start(&self, conn: &mut Connection, game: &mut Game) -> io::Result<()> {
+        if self.player == 1 {
+            let state =
+                serde_json::to_string(game).map_err(|err| handshake_error(err.to_string()))?;
+            // A tall field full of bricks can come to more than one frame
+            // holds, and cut short it would only be read wrong
+            if state.len() > MAX_START_STATE {
+                return Err(handshake_error(format!(
+                    "the game comes to {} KiB, more than the {} KiB a match can start from; \
+                     try a smaller terminal or a match without breakout",
+                    state.len().div_ceil(1024),
+                    MAX_START_STATE / 1024
+                )));
+            }
+            conn.send(&Message::Start(state));
+            return Ok(());
+        }
+        let started = Instant::now();
+        loop {
+            match conn.receive() {
+                Some(Message::Start(state)) => {
+                    *game = serde_json::from_str(&state).map_err(|err| {
+                        handshake_error(format!("couldn't read the host's game: {}", err))
+                    })?;
+                    return Ok(());
+                }
+                Some(_) => {}
+                None if conn.is_closed() => {
+                    return Err(handshake_error("the host left before the match started".into()))
+                }
+                None if started.elapsed() >= HANDSHAKE_TIMEOUT => {
+                    return Err(handshake_error("the host never started the match".into()))
+                }
+                None => std::thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    }
+
+    /// Which player this side plays
+    pub fn player(&self) -> u8 {
+        self.player
+    }
+
+    /// The next tick to be played
+    pub fn tick(&self) -> u32 {
+        self.first + self.frames.len() as u32
+    }
+
+    /// Pause or unpause for both players on the next tick
+    pub fn pause(&mut self) {
+        self.pending.pause = true;
+    }
+
+    pub fn next_set(&mut self) {
+        self.pending.next_set = true;
+    }
+
+    pub fn rematch(&mut self) {
+        self.pending.rematch = true;
+    }
+
+    /// Take in what the other player has sent, go back over any ticks that
+    /// were played with a wrong guess, then play the ticks `dt` seconds
+    /// come to with this side's paddle keys as in `keys`. Only its paddle
+    /// keys count; pausing and the rest go through their own calls. Fails
+    /// once the two games are found to have drifted apart.
+    pub fn update(
+        &mut self,
+        conn: &mut Connection,
+        game: &mut Game,
+        keys: TickInput,
+        dt: f32,
+    ) -> Result<(), Box<Desync>> {
+        self.pending.serve |= keys.serve;
+        if keys.dash != Dir::Stay {
+            self.pending.dash = keys.dash;
+        }
+        while let Some(message) = conn.receive() {
+            match message {
+                Message::Tick { tick, input, lead } => self.hear(tick, input, lead),
+                Message::Checksum { tick, sum } => self.their_sums.push_back((tick, sum)),
+                _ => {}
+            }
+        }
+        self.roll_back(game);
+
+        let rate = match (self.lead() - self.remote_lead) / 2 > MAX_LEAD {
+            true => CATCH_UP_RATE,
+            false => 1.0,
+        };
+        self.accumulator += dt.min(MAX_FRAME_DT) * rate;
+        while self.accumulator >= TICK_DT {
+            if self.frames.len() >= MAX_ROLLBACK {
+                // Waiting on the other player, who may not be there at all
+                self.accumulator = TICK_DT;
+                break;
+            }
+            self.accumulator -= TICK_DT;
+            let input = TickInput {
+                dir: keys.dir,
+                charge: keys.charge,
+                ..std::mem::take(&mut self.pending)
+            };
+            self.play(conn, game, input);
+        }
+
+        self.confirm(conn, game);
+        self.compare()
+    }
+
+    /// How many ticks ahead of the other player's last input this side is
+    fn lead(&self) -> i32 {
+        self.remote_tick
+            .map_or(0, |tick| (self.tick() as i64 - tick as i64).clamp(-128, 127) as i32)
+    }
+
+    fn remote(&self) -> usize {
+        // Player 1's input goes first
+        if self.player == 1 {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn hear(&mut self, tick: u32, input: TickInput, lead: i8) {
+        self.last_remote = input;
+        self.remote_tick = Some(tick);
+        self.remote_lead = lead as i32;
+        if tick < self.first {
+            return;
+        }
+        let remote = self.remote();
+        let Some(frame) = self.frames.get_mut((tick - self.first) as usize) else {
+            self.early.push_back((tick, input));
+            return;
+        };
+        frame.confirmed = true;
+        if frame.inputs[remote] != input {
+            frame.inputs[remote] = input;
+            self.wrong_from = Some(self.wrong_from.map_or(tick, |from| from.min(tick)));
+        }
+    }
+
+    /// Put the game back as it was before the first wrongly guessed tick
+    /// and play every tick since again
+    fn roll_back(&mut self, game: &mut Game) {
+        let Some(from) = self.wrong_from.take() else {
+            return;
+        };
+        let start = (from - self.first) as usize;
+        // The ticks played over were heard the first time round
+        let sounds = std::mem::take(&mut game.sounds);
+        *game = self.frames[start].state.clone();
+        for i in start..self.frames.len() {
+            if i > start {
+                self.frames[i].state = snapshot(game);
+            }
+            play_tick(game, self.frames[i].inputs);
+        }
+        game.sounds = sounds;
+        // Whatever those ticks logged the first time round is logged again
+        log::debug!("rolled back {} ticks to tick {}", self.frames.len() - start, from);
+        self.rollbacks += 1;
+        self.deepest = self.deepest.max(self.frames.len() - start);
+    }
+
+    fn play(&mut self, conn: &mut Connection, game: &mut Game, local: TickInput) {
+        let tick = self.tick();
+        let (remote, confirmed) = match self.early.front() {
+            Some(&(early, input)) if early == tick => {
+                self.early.pop_front();
+                (input, true)
+            }
+            _ => (self.last_remote.held(), false),
+        };
+        let lead = self.lead() as i8;
+        conn.send(&Message::Tick {
+            tick,
+            input: local,
+            lead,
+        });
+        let mut inputs = [local; 2];
+        inputs[self.remote()] = remote;
+        self.frames.push_back(Frame {
+            state: snapshot(game),
+            inputs,
+            confirmed,
+        });
+        play_tick(game, inputs);
+    }
+
+    /// Let go of the ticks both inputs are known for, taking a checksum
+    /// every `CHECKSUM_INTERVAL` of them
+    fn confirm(&mut self, conn: &mut Connection, game: &Game) {
+        while self.frames.front().is_some_and(|frame| frame.confirmed) {
+            self.frames.pop_front();
+            self.first += 1;
+            if self.first.is_multiple_of(CHECKSUM_INTERVAL) {
+                let state = self.frames.front().map_or(game, |frame| &frame.state);
+                let sum = checksum(state);
+                conn.send(&Message::Checksum {
+                    tick: self.first,
+                    sum,
+                });
+                self.sums.push_back((self.first, sum, state.clone()));
+            }
+        }
+    }
+
+    fn compare(&mut self) -> Result<(), Box<Desync>> {
+        while let Some(&(tick, theirs)) = self.their_sums.front() {
+            // Checksums only go one way until the other side catches up
+            while self.sums.front().is_some_and(|&(ours, ..)| ours < tick) {
+                self.sums.pop_front();
+            }
+            match self.sums.front() {
+                Some(&(ours, _, _)) if ours > tick => {
+                    self.their_sums.pop_front();
+                }
+                Some(&(_, sum, _)) if sum != theirs => {
+                    let (_, checksum, game) = self.sums.pop_front().expect("just looked");
+                    return Err(Box::new(Desync {
+                        tick,
+                        player: self.player,
+                        checksum,
+                        their_checksum: theirs,
+                        game,
+                    }));
+                }
+                Some(_) => {
+                    self.sums.pop_front();
+                    self.their_sums.pop_front();
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// How the match is keeping up, for the `--net-debug` overlay
+    pub fn debug_line(&self) -> String {
+        format!(
+            " tick {}  {} ahead  {} unconfirmed  {} rollbacks, deepest {} ",
+            self.tick(),
+            self.lead(),
+            self.frames.len(),
+            self.rollbacks,
+            self.deepest
+        )
+    }
+}
+
+fn snapshot(game: &Game) -> Game {
+    let mut state = game.clone();
+    state.sounds.clear();
+    state
+}
+
+/// Play one tick with both players' inputs, the same way on both sides
+fn play_tick(game: &mut Game, inputs: [TickInput; 2]) {
+    for input in inputs {
+        if input.pause {
+            game.toggle_pause();
+        }
+        if input.next_set {
+            game.next_set();
+        }
+        if input.rematch && game.winner.is_some() {
+            game.rematch();
+        }
+    }
+    // Input goes by side, and the players swap sides between sets
+    let [left, right] = match game.swapped {
+        true => [inputs[1], inputs[0]],
+        false => inputs,
+    };
+    let input = Input {
+        p1: left.dir,
+        p1_serve: left.serve,
+        p1_dash: left.dash,
+        p1_charge: left.charge,
+        p2: right.dir,
+        p2_serve: right.serve,
+        p2_dash: right.dash,
+        p2_charge: right.charge,
+        ..Input::default()
+    };
+    game.step(input, TICK_DT);
+}
+
+/// FNV-1a over the game as it serializes, which leaves out everything
+/// that's only for show
+fn checksum(game: &Game) -> u64 {
+    let bytes = serde_json::to_vec(game).unwrap_or_default();
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
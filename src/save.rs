@@ -0,0 +1,70 @@
+//! A match put aside to finish later, kept in the data directory next to
+//! the stats. The whole game goes in, random generator and all, so it
+//! carries on exactly where it stopped.
+
+use dospong::pong::Game;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Bumped whenever the game's state changes shape, so an old save is
+/// turned down instead of read wrong
+const FORMAT_VERSION: u32 = 16;
+
+#[derive(Serialize)]
+struct SavedMatch<'a> {
+    version: u32,
+    game: &'a Game,
+}
+
+#[derive(Deserialize)]
+struct LoadedMatch {
+    game: Game,
+}
+
+pub fn path() -> Option<PathBuf> {
+    dirs::data_dir().map(|data| data.join("dospong").join("saved_match.toml"))
+}
+
+pub fn exists() -> bool {
+    path().is_some_and(|path| path.exists())
+}
+
+pub fn save(game: &Game) -> Result<PathBuf, String> {
+    let path = path().ok_or("no data directory to save the match in")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    }
+    let saved = SavedMatch {
+        version: FORMAT_VERSION,
+        game,
+    };
+    let text = toml::to_string_pretty(&saved).map_err(|err| err.to_string())?;
+    fs::write(&path, text).map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(path)
+}
+
+/// Read the saved match back, and take it off disk so it's only resumed
+/// once
+pub fn resume() -> Result<Game, String> {
+    let path = path().ok_or("no data directory to find a saved match in")?;
+    let text = fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    // The version is read on its own first, since a save in another format
+    // may not get any further
+    let table: toml::Table =
+        toml::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let version = table.get("version").and_then(|version| version.as_integer());
+    if version != Some(FORMAT_VERSION as i64) {
+        let found = version.map_or("an unknown format".to_string(), |v| format!("format {}", v));
+        return Err(format!(
+            "{} was saved in {}, but this version of DOSPong reads format {}",
+            path.display(),
+            found,
+            FORMAT_VERSION
+        ));
+    }
+    let loaded: LoadedMatch =
+        toml::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+    fs::remove_file(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(loaded.game)
+}
@@ -0,0 +1,321 @@
+//! Powerups of your own, written in rhai. Every `.rhai` file in the
+//! `powerups` directory next to the config file is one: it ends with a map
+//! saying what the powerup is called and how it looks, and defines any of
+//! `on_collect(game, player)`, `on_tick(game, player, dt)` and
+//! `on_expire(game, player)`. `game` only offers what's safe to change:
+//! ball speeds, paddle heights, extra balls and the center wall. A script
+//! that goes wrong loses its powerup, and never the game.
+
+use crossterm::style::Color;
+use dospong::pong::{Ball, CustomCall, CustomPowerUp, Game, PowerUpType};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, FLOAT, INT};
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+// Enough for any reasonable callback, and few enough that one stuck in a
+// loop gives up within a frame
+const MAX_OPERATIONS: u64 = 100_000;
+
+struct Script {
+    /// The file it came from, to say which one went wrong
+    file: String,
+    powerup: CustomPowerUp,
+    ast: AST,
+    // Where the game put it once registered
+    index: Option<u8>,
+}
+
+pub struct Scripts {
+    engine: Engine,
+    scripts: Vec<Script>,
+    /// What went wrong with any of them, to mention once the game is over
+    pub errors: Vec<String>,
+}
+
+/// What a callback sees of the game and everything it asks to change, which
+/// is only made once it has finished without an error
+#[derive(Default)]
+struct FieldState {
+    width: u16,
+    height: u16,
+    swapped: bool,
+    // Every ball's place and speed in cells per second, changes included
+    balls: Vec<[FLOAT; 4]>,
+    changed_balls: Vec<usize>,
+    new_balls: Vec<[FLOAT; 4]>,
+    // By side, as the game keeps them
+    paddle_heights: [u16; 2],
+    custom_heights: [Option<u16>; 2],
+    center_wall: bool,
+}
+
+impl FieldState {
+    fn side(&self, player: INT) -> Result<usize, Box<EvalAltResult>> {
+        match player {
+            1 | 2 if self.swapped => Ok(2 - player as usize),
+            1 | 2 => Ok(player as usize - 1),
+            _ => Err(format!("there's no paddle for player {}", player).into()),
+        }
+    }
+
+    fn ball(&self, index: INT) -> Result<[FLOAT; 4], Box<EvalAltResult>> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.balls.get(index).copied())
+            .ok_or_else(|| format!("there's no ball {}", index).into())
+    }
+}
+
+/// The game as scripts get it
+#[derive(Clone)]
+struct Field(Rc<RefCell<FieldState>>);
+
+impl Field {
+    fn of(game: &Game) -> Self {
+        let scale = (game.ball_speed_scale() * 60.0) as FLOAT;
+        let balls = game
+            .balls
+            .iter()
+            .map(|b| {
+                let (pos, vel) = (b.pos, b.vel);
+                [pos.x as FLOAT, pos.y as FLOAT, vel.x as FLOAT * scale, vel.y as FLOAT * scale]
+            })
+            .collect();
+        let effects = game.custom_effects();
+        Field(Rc::new(RefCell::new(FieldState {
+            width: game.width,
+            height: game.height,
+            swapped: game.swapped,
+            balls,
+            paddle_heights: [game.p1_height, game.p2_height],
+            custom_heights: effects.paddle_height,
+            center_wall: effects.center_wall,
+            ..FieldState::default()
+        })))
+    }
+
+    /// Make the changes asked for
+    fn apply(&self, game: &mut Game) {
+        let state = self.0.borrow();
+        let scale = game.ball_speed_scale() * 60.0;
+        for &i in &state.changed_balls {
+            if let Some(ball) = game.balls.get_mut(i) {
+                let [_, _, vx, vy] = state.balls[i];
+                ball.vel.x = vx as f32 / scale;
+                ball.vel.y = vy as f32 / scale;
+            }
+        }
+        for &[x, y, vx, vy] in &state.new_balls {
+            let ball = Ball::new(x as f32, y as f32, vx as f32 / scale, vy as f32 / scale);
+            if !game.add_ball(ball) {
+                break;
+            }
+        }
+        let mut effects = game.custom_effects();
+        effects.paddle_height = state.custom_heights;
+        effects.center_wall = state.center_wall;
+        if effects != game.custom_effects() {
+            game.set_custom_effects(effects);
+        }
+    }
+}
+
+/// The functions scripts can call on `game`
+fn register_api(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<Field>("Game")
+        .register_get("width", |f: &mut Field| f.0.borrow().width as INT)
+        .register_get("height", |f: &mut Field| f.0.borrow().height as INT)
+        .register_fn("ball_count", |f: &mut Field| f.0.borrow().balls.len() as INT)
+        .register_fn("ball_x", |f: &mut Field, i: INT| f.0.borrow().ball(i).map(|b| b[0]))
+        .register_fn("ball_y", |f: &mut Field, i: INT| f.0.borrow().ball(i).map(|b| b[1]))
+        .register_fn("ball_vx", |f: &mut Field, i: INT| f.0.borrow().ball(i).map(|b| b[2]))
+        .register_fn("ball_vy", |f: &mut Field, i: INT| f.0.borrow().ball(i).map(|b| b[3]))
+        .register_fn(
+            "set_ball_velocity",
+            |f: &mut Field, i: INT, vx: FLOAT, vy: FLOAT| -> Result<(), Box<EvalAltResult>> {
+                let mut state = f.0.borrow_mut();
+                state.ball(i)?;
+                let i = i as usize;
+                state.balls[i][2] = vx;
+                state.balls[i][3] = vy;
+                state.changed_balls.push(i);
+                Ok(())
+            },
+        )
+        .register_fn("spawn_ball", |f: &mut Field, x: FLOAT, y: FLOAT, vx: FLOAT, vy: FLOAT| {
+            f.0.borrow_mut().new_balls.push([x, y, vx, vy]);
+        })
+        .register_fn("paddle_height", |f: &mut Field, player: INT| {
+            let state = f.0.borrow();
+            let side = state.side(player)?;
+            let height = state.custom_heights[side].unwrap_or(state.paddle_heights[side]);
+            Ok::<_, Box<EvalAltResult>>(height as INT)
+        })
+        .register_fn("set_paddle_height", |f: &mut Field, player: INT, rows: INT| {
+            let mut state = f.0.borrow_mut();
+            let side = state.side(player)?;
+            // Always something to hit, and never taller than the field
+            let tallest = state.height.saturating_sub(2).max(1) as INT;
+            state.custom_heights[side] = Some(rows.clamp(1, tallest) as u16);
+            Ok::<_, Box<EvalAltResult>>(())
+        })
+        .register_fn("reset_paddle_height", |f: &mut Field, player: INT| {
+            let mut state = f.0.borrow_mut();
+            let side = state.side(player)?;
+            state.custom_heights[side] = None;
+            Ok::<_, Box<EvalAltResult>>(())
+        })
+        .register_fn("set_center_wall", |f: &mut Field, on: bool| {
+            f.0.borrow_mut().center_wall = on;
+        });
+}
+
+pub fn dir() -> Option<PathBuf> {
+    crate::config::Config::path().and_then(|path| Some(path.parent()?.join("powerups")))
+}
+
+impl Scripts {
+    /// Read every script in `dir()`, in name order so each keeps its place
+    /// between runs. Ones that can't be used are left out, with a note in
+    /// `errors`.
+    pub fn load() -> Self {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        register_api(&mut engine);
+        let mut scripts = Scripts {
+            engine,
+            scripts: Vec::new(),
+            errors: Vec::new(),
+        };
+        let Some(dir) = dir() else {
+            return scripts;
+        };
+        let mut paths: Vec<PathBuf> = match fs::read_dir(&dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+                .collect(),
+            Err(_) => return scripts,
+        };
+        paths.sort();
+        for path in paths {
+            match scripts.load_script(&path) {
+                Ok(script) => scripts.scripts.push(script),
+                Err(message) => scripts.errors.push(format!("{}: {}", path.display(), message)),
+            }
+        }
+        scripts
+    }
+
+    fn load_script(&self, path: &Path) -> Result<Script, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let ast = self.engine.compile(&text).map_err(|err| err.to_string())?;
+        let info: Map = self.engine.eval_ast(&ast).map_err(|err| {
+            format!("{} (it should end with a map like #{{ name: \"Boost\", ... }})", err)
+        })?;
+        let field = |key: &str| info.get(key).cloned().ok_or(format!("no {} given", key));
+        let title = field("name")?.into_string().map_err(|_| "name isn't a string")?;
+        let symbol = field("symbol")?.into_string().map_err(|_| "symbol isn't a string")?;
+        let mut chars = symbol.chars();
+        let (Some(symbol), None) = (chars.next(), chars.next()) else {
+            return Err("symbol should be one character".to_string());
+        };
+        let color_name = field("color")?.into_string().map_err(|_| "color isn't a string")?;
+        Color::try_from(color_name.as_str())
+            .map_err(|_| format!("{} isn't a color, try red or dark_cyan", color_name))?;
+        let duration = field("duration")?;
+        let seconds = duration
+            .as_float()
+            .or_else(|_| duration.as_int().map(|seconds| seconds as FLOAT))
+            .ok()
+            .filter(|seconds| (0.0..=600.0).contains(seconds))
+            .ok_or("duration should be a number of seconds, up to 600")?;
+        Ok(Script {
+            file: path.display().to_string(),
+            powerup: CustomPowerUp {
+                title,
+                symbol,
+                color: color_name,
+                duration: Duration::from_secs_f64(seconds),
+            },
+            ast,
+            index: None,
+        })
+    }
+
+    /// Add every powerup to `game`, returning how each one looks for the
+    /// frontend
+    pub fn register(&mut self, game: &mut Game) -> Vec<(char, Color)> {
+        let mut looks = Vec::new();
+        for script in &mut self.scripts {
+            if let PowerUpType::Custom(index) = game.add_custom_powerup(script.powerup.clone()) {
+                script.index = Some(index);
+            }
+            let color = Color::try_from(script.powerup.color.as_str()).unwrap_or(Color::White);
+            looks.push((script.powerup.symbol, color));
+        }
+        looks
+    }
+
+    /// Run the callbacks for whatever happened to the powerups in the last
+    /// frame, then `on_tick` for each one still active
+    pub fn run(&mut self, game: &mut Game, dt: f32) {
+        for call in std::mem::take(&mut game.custom_calls) {
+            let (index, side, callback) = match call {
+                CustomCall::Collected { index, player } => (index, player, "on_collect"),
+                CustomCall::Expired { index, player } => (index, player, "on_expire"),
+            };
+            let player = game.player_at(side) as INT;
+            self.call(game, index, callback, vec![player.into()]);
+        }
+        let active: Vec<(u8, u8)> = game
+            .active_powerups
+            .iter()
+            .filter_map(|active| match active.ptype {
+                PowerUpType::Custom(index) => Some((index, active.player)),
+                _ => None,
+            })
+            .collect();
+        for (index, side) in active {
+            let player = game.player_at(side) as INT;
+            let args = vec![player.into(), (dt as FLOAT).into()];
+            self.call(game, index, "on_tick", args);
+        }
+    }
+
+    /// Call `callback` in the script for the custom powerup `index`, if it
+    /// has one. If it fails, the powerup is taken out of the game.
+    fn call(&mut self, game: &mut Game, index: u8, callback: &str, mut args: Vec<Dynamic>) {
+        let Some(script) = self.scripts.iter().find(|s| s.index == Some(index)) else {
+            return;
+        };
+        if game.custom_powerup(index).is_none()
+            || !script.ast.iter_functions().any(|f| f.name == callback)
+        {
+            return;
+        }
+        let field = Field::of(game);
+        args.insert(0, Dynamic::from(field.clone()));
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut Scope::new(),
+            &script.ast,
+            callback,
+            args,
+        );
+        match result {
+            Ok(_) => field.apply(game),
+            Err(err) => {
+                let title = script.powerup.title.clone();
+                self.errors.push(format!("{}: {} failed: {}", script.file, callback, err));
+                game.disable_custom_powerup(index);
+                game.add_event(format!("{} broke and is off", title));
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! The settings screen, shown from the start menu and over a paused match.
+//! Every change is made to a copy of the config straight away, so whatever
+//! shows the screen can apply it at once, and saving writes that copy out.
+
+use crate::config::{Action, Config};
+use crate::theme::Theme;
+use crossterm::event::KeyCode;
+use dospong::pong::{
+    Tuning, BALL_SPEED_RANGE, PADDLE_HEIGHT_RANGE, PADDLE_SPEED_RANGE, SCORE_LIMIT_RANGE,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Setting {
+    BallSpeed,
+    PaddleSpeed,
+    PaddleHeight,
+    Powerups,
+    ScoreLimit,
+    Theme,
+    Sound,
+}
+
+impl Setting {
+    const ALL: [Setting; 7] = [
+        Setting::BallSpeed,
+        Setting::PaddleSpeed,
+        Setting::PaddleHeight,
+        Setting::Powerups,
+        Setting::ScoreLimit,
+        Setting::Theme,
+        Setting::Sound,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Setting::BallSpeed => "Ball speed",
+            Setting::PaddleSpeed => "Paddle speed",
+            Setting::PaddleHeight => "Paddle height",
+            Setting::Powerups => "Powerups",
+            Setting::ScoreLimit => "Score limit",
+            Setting::Theme => "Theme",
+            Setting::Sound => "Sound",
+        }
+    }
+}
+
+/// What a key did on the settings screen
+pub enum Outcome {
+    Nothing,
+    Changed(Setting),
+    Closed,
+}
+
+pub struct SettingsMenu {
+    pub config: Config,
+    pub selected: usize,
+    /// How the last save went
+    pub message: String,
+}
+
+impl SettingsMenu {
+    pub fn new(config: Config) -> Self {
+        SettingsMenu {
+            config,
+            selected: 0,
+            message: String::new(),
+        }
+    }
+
+    /// One line per setting, then the Save and Back entries
+    pub fn lines(&self) -> Vec<String> {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        let game = &self.config.game;
+        let mut lines: Vec<String> = Setting::ALL
+            .iter()
+            .map(|&setting| {
+                let value = match setting {
+                    Setting::BallSpeed => format!("{:.0}%", game.ball_speed * 100.0),
+                    Setting::PaddleSpeed => game.paddle_speed.to_string(),
+                    Setting::PaddleHeight => game.paddle_height.to_string(),
+                    Setting::Powerups => on_off(self.config.powerups.enabled).to_string(),
+                    Setting::ScoreLimit => game.score_limit.to_string(),
+                    Setting::Theme => self.config.display.theme.clone(),
+                    Setting::Sound => on_off(self.config.display.bell).to_string(),
+                };
+                format!("{:<14} < {} >", setting.label(), value)
+            })
+            .collect();
+        lines.push("Save".to_string());
+        lines.push("Back".to_string());
+        lines
+    }
+
+    pub fn tuning(&self) -> Tuning {
+        // Only ever set within range, here or when the config was loaded
+        self.config.game.tuning().unwrap_or_default()
+    }
+
+    pub fn theme(&self) -> Theme {
+        self.config.display.theme().unwrap_or(Theme::DEFAULT)
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode, action: Option<Action>) -> Outcome {
+        let entries = Setting::ALL.len() + 2;
+        match (code, action) {
+            (KeyCode::Up, _) | (_, Some(Action::P1Up | Action::P2Up)) => {
+                self.selected = self.selected.saturating_sub(1);
+            }
+            (KeyCode::Down, _) | (_, Some(Action::P1Down | Action::P2Down)) => {
+                self.selected = (self.selected + 1).min(entries - 1);
+            }
+            (KeyCode::Left | KeyCode::Right, _) => {
+                let step = if code == KeyCode::Left { -1 } else { 1 };
+                if let Some(&setting) = Setting::ALL.get(self.selected) {
+                    self.adjust(setting, step);
+                    self.message.clear();
+                    return Outcome::Changed(setting);
+                }
+            }
+            (KeyCode::Enter, _) if self.selected == Setting::ALL.len() => {
+                self.message = match self.config.save() {
+                    Ok(()) => "Saved".to_string(),
+                    Err(err) => format!("Couldn't save: {}", err),
+                };
+            }
+            (KeyCode::Enter, _) if self.selected > Setting::ALL.len() => return Outcome::Closed,
+            (KeyCode::Esc, _) | (_, Some(Action::Quit | Action::Settings)) => {
+                return Outcome::Closed;
+            }
+            _ => {}
+        }
+        Outcome::Nothing
+    }
+
+    /// Move a setting one step, never past the ends of its range
+    fn adjust(&mut self, setting: Setting, step: i16) {
+        let game = &mut self.config.game;
+        match setting {
+            Setting::BallSpeed => {
+                // Whole tenths, so stepping back and forth lands on the same
+                // numbers
+                let tenths = (game.ball_speed * 10.0).round() + step as f32;
+                game.ball_speed = (tenths / 10.0)
+                    .clamp(*BALL_SPEED_RANGE.start(), *BALL_SPEED_RANGE.end());
+            }
+            Setting::PaddleSpeed => {
+                game.paddle_speed = (game.paddle_speed + step)
+                    .clamp(*PADDLE_SPEED_RANGE.start(), *PADDLE_SPEED_RANGE.end());
+            }
+            Setting::PaddleHeight => {
+                game.paddle_height = game
+                    .paddle_height
+                    .saturating_add_signed(step)
+                    .clamp(*PADDLE_HEIGHT_RANGE.start(), *PADDLE_HEIGHT_RANGE.end());
+            }
+            Setting::ScoreLimit => {
+                game.score_limit = game
+                    .score_limit
+                    .saturating_add_signed(step)
+                    .clamp(*SCORE_LIMIT_RANGE.start(), *SCORE_LIMIT_RANGE.end());
+            }
+            Setting::Powerups => self.config.powerups.enabled = !self.config.powerups.enabled,
+            Setting::Theme => {
+                let themes = Theme::ALL;
+                let current = themes
+                    .iter()
+                    .position(|theme| theme.name == self.config.display.theme)
+                    .unwrap_or(0);
+                let next = (current as i16 + step).rem_euclid(themes.len() as i16) as usize;
+                self.config.display.theme = themes[next].name.to_string();
+            }
+            Setting::Sound => self.config.display.bell = !self.config.display.bell,
+        }
+    }
+}
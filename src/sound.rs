@@ -0,0 +1,217 @@
+//! Making the game's sounds heard. Without the `audio` feature, or when no
+//! audio device opens, that's the terminal bell on hits and points; with it
+//! every sound is a short square wave blip, played on a thread of its own
+//! so the game never waits on the device.
+
+use dospong::pong::Sound;
+use std::time::{Duration, Instant};
+
+// The closest two bells ring, so a dozen balls hitting at once is one bell
+const BELL_GAP: Duration = Duration::from_millis(100);
+// How long after the first bell of a point the second one rings
+const DOUBLE_BELL_DELAY: Duration = Duration::from_millis(150);
+
+pub struct SoundPlayer {
+    // Whether a bell was asked for since the last frame
+    bell_wanted: bool,
+    last_bell: Option<Instant>,
+    // When the second bell for a point is due
+    second_bell: Option<Instant>,
+    #[cfg(feature = "audio")]
+    audio: Option<audio::AudioThread>,
+    // Set once the audio device has failed to open, so it isn't tried again
+    #[cfg(feature = "audio")]
+    audio_failed: bool,
+}
+
+impl SoundPlayer {
+    pub fn new() -> Self {
+        SoundPlayer {
+            bell_wanted: false,
+            last_bell: None,
+            second_bell: None,
+            #[cfg(feature = "audio")]
+            audio: None,
+            #[cfg(feature = "audio")]
+            audio_failed: false,
+        }
+    }
+
+    pub fn play(&mut self, sound: Sound, now: Instant) {
+        #[cfg(feature = "audio")]
+        if self.blip(sound) {
+            return;
+        }
+        match sound {
+            Sound::PaddleHit => self.bell_wanted = true,
+            Sound::Score => {
+                self.bell_wanted = true;
+                self.second_bell = Some(now + DOUBLE_BELL_DELAY);
+            }
+            Sound::WallBounce | Sound::PowerUp => {}
+        }
+    }
+
+    /// Whether a bell should ring this frame. Any asked for too soon after
+    /// the last one are dropped, except the second bell of a point, which
+    /// waits its turn.
+    pub fn bell_due(&mut self, now: Instant) -> bool {
+        let second = self.second_bell.is_some_and(|at| at <= now);
+        let wanted = std::mem::take(&mut self.bell_wanted) || second;
+        let quiet = self
+            .last_bell
+            .is_none_or(|last| now.duration_since(last) >= BELL_GAP);
+        if !wanted || !quiet {
+            return false;
+        }
+        if second {
+            self.second_bell = None;
+        }
+        self.last_bell = Some(now);
+        true
+    }
+
+    /// Hand `sound` to the audio thread, starting it the first time. False
+    /// when there's no audio device to play it on.
+    #[cfg(feature = "audio")]
+    fn blip(&mut self, sound: Sound) -> bool {
+        if self.audio_failed {
+            return false;
+        }
+        let audio = self.audio.get_or_insert_with(audio::AudioThread::start);
+        if audio.play(sound) {
+            return true;
+        }
+        // The thread only stops early when the device wouldn't open
+        self.audio_failed = true;
+        self.audio = None;
+        false
+    }
+}
+
+#[cfg(feature = "audio")]
+mod audio {
+    use dospong::pong::Sound;
+    use rodio::{OutputStream, Source};
+    use std::sync::mpsc::{self, Sender};
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    const SAMPLE_RATE: u32 = 44_100;
+    // Square waves are loud, so they're kept well down
+    const VOLUME: f32 = 0.15;
+
+    /// The pitch and length of each blip a sound is made of, and how long
+    /// after the sound starts each one does
+    fn blips(sound: Sound) -> &'static [(f32, u64, u64)] {
+        match sound {
+            Sound::PaddleHit => &[(440.0, 40, 0)],
+            Sound::WallBounce => &[(220.0, 30, 0)],
+            Sound::PowerUp => &[(660.0, 40, 0), (880.0, 60, 40)],
+            Sound::Score => &[(523.0, 90, 0), (784.0, 140, 120)],
+        }
+    }
+
+    /// A square wave that fades out over its length, so it ends without a
+    /// click
+    struct Blip {
+        pitch: f32,
+        length: usize,
+        sample: usize,
+    }
+
+    impl Blip {
+        fn new(pitch: f32, millis: u64) -> Self {
+            Blip {
+                pitch,
+                length: (SAMPLE_RATE as u64 * millis / 1000) as usize,
+                sample: 0,
+            }
+        }
+    }
+
+    impl Iterator for Blip {
+        type Item = f32;
+
+        fn next(&mut self) -> Option<f32> {
+            if self.sample >= self.length {
+                return None;
+            }
+            let time = self.sample as f32 / SAMPLE_RATE as f32;
+            let fade = 1.0 - self.sample as f32 / self.length as f32;
+            self.sample += 1;
+            let level = if (time * self.pitch).fract() < 0.5 { VOLUME } else { -VOLUME };
+            Some(level * fade)
+        }
+    }
+
+    impl Source for Blip {
+        fn current_frame_len(&self) -> Option<usize> {
+            Some(self.length - self.sample)
+        }
+
+        fn channels(&self) -> u16 {
+            1
+        }
+
+        fn sample_rate(&self) -> u32 {
+            SAMPLE_RATE
+        }
+
+        fn total_duration(&self) -> Option<Duration> {
+            Some(Duration::from_secs_f32(self.length as f32 / SAMPLE_RATE as f32))
+        }
+    }
+
+    /// The thread holding the audio device. It stops when the device won't
+    /// open, and otherwise once it's dropped.
+    pub struct AudioThread {
+        sounds: Option<Sender<Sound>>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl AudioThread {
+        /// Start the thread without waiting for the device to open. Sounds
+        /// sent before it does are played once it has.
+        pub fn start() -> Self {
+            let (sender, sounds) = mpsc::channel();
+            let thread = thread::spawn(move || {
+                // The stream has to stay on the thread that opened it, and
+                // plays for as long as it's kept
+                let Ok((_stream, handle)) = OutputStream::try_default() else {
+                    return;
+                };
+                for sound in sounds {
+                    for &(pitch, millis, after) in blips(sound) {
+                        let blip = Blip::new(pitch, millis).delay(Duration::from_millis(after));
+                        if handle.play_raw(blip).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+            AudioThread {
+                sounds: Some(sender),
+                thread: Some(thread),
+            }
+        }
+
+        /// False once the thread has stopped, and nothing will be heard
+        pub fn play(&self, sound: Sound) -> bool {
+            self.sounds
+                .as_ref()
+                .is_some_and(|sounds| sounds.send(sound).is_ok())
+        }
+    }
+
+    impl Drop for AudioThread {
+        fn drop(&mut self) {
+            // With nothing left to send, the thread's loop ends and it lets
+            // the device go
+            self.sounds = None;
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! The colors, and a few of the characters, the game is drawn with. Every
+//! theme only uses the 16 basic terminal colors, so they all work anywhere
+//! the default one does.
+
+use crossterm::style::Color;
+
+#[derive(Clone, Copy)]
+pub struct Theme {
+    pub name: &'static str,
+    /// The main and second paddle colors for players 1 to 4
+    pub players: [(Color, Color); 4],
+    pub ball: Color,
+    /// The ball once a rally has sped it up, then once it's close to the
+    /// fastest it can go
+    pub fast_ball: [Color; 2],
+    pub center_wall: Color,
+    pub small_walls: Color,
+    pub moving_wall: Color,
+    pub obstacles: Color,
+    /// Bricks with one, two and three hits left
+    pub bricks: [Color; 3],
+    pub practice_wall: Color,
+    pub practice_target: Color,
+    /// One color for every powerup, or None to give each its own
+    pub powerups: Option<Color>,
+    /// A paddle that was just hit, a shield breaking and the sweet spot of
+    /// a bent paddle
+    pub flash: Color,
+    pub border: Color,
+    pub center_line: Color,
+    pub text: Color,
+    /// Hints and numbers that matter less than the text around them
+    pub dim_text: Color,
+    pub faint_text: Color,
+    /// The serve countdown, new records and anything selected
+    pub highlight: Color,
+    /// The connection lost banner, and what it's drawn across
+    pub alert: Color,
+    pub alert_banner: Color,
+    pub ball_glyph: char,
+    /// The ball while it has visible spin on it
+    pub spin_glyph: char,
+    pub paddle_glyph: char,
+    /// Whether balls may be drawn in braille dots when that's turned on
+    pub braille: bool,
+}
+
+impl Theme {
+    pub const DEFAULT: Theme = Theme {
+        name: "default",
+        players: [
+            (Color::Blue, Color::Cyan),
+            (Color::Red, Color::Magenta),
+            (Color::Green, Color::DarkGreen),
+            (Color::Yellow, Color::DarkYellow),
+        ],
+        ball: Color::White,
+        fast_ball: [Color::Yellow, Color::Red],
+        center_wall: Color::Yellow,
+        small_walls: Color::Cyan,
+        moving_wall: Color::DarkYellow,
+        obstacles: Color::Grey,
+        bricks: [Color::Green, Color::Yellow, Color::Red],
+        practice_wall: Color::DarkGrey,
+        practice_target: Color::Green,
+        powerups: None,
+        flash: Color::White,
+        border: Color::White,
+        center_line: Color::DarkGrey,
+        text: Color::White,
+        dim_text: Color::Grey,
+        faint_text: Color::DarkGrey,
+        highlight: Color::Yellow,
+        alert: Color::Red,
+        alert_banner: Color::DarkRed,
+        ball_glyph: '●',
+        spin_glyph: '◉',
+        paddle_glyph: '█',
+        braille: true,
+    };
+
+    /// CGA's second palette at high intensity: cyan, magenta and white
+    pub const CGA: Theme = Theme {
+        name: "cga",
+        players: [
+            (Color::DarkCyan, Color::Cyan),
+            (Color::DarkMagenta, Color::Magenta),
+            (Color::Grey, Color::White),
+            (Color::Grey, Color::White),
+        ],
+        ball: Color::Grey,
+        fast_ball: [Color::White, Color::Magenta],
+        center_wall: Color::DarkMagenta,
+        small_walls: Color::DarkCyan,
+        moving_wall: Color::Magenta,
+        obstacles: Color::White,
+        bricks: [Color::Cyan, Color::Magenta, Color::White],
+        practice_wall: Color::Magenta,
+        practice_target: Color::DarkCyan,
+        powerups: Some(Color::Grey),
+        flash: Color::Grey,
+        border: Color::DarkCyan,
+        center_line: Color::Magenta,
+        text: Color::Grey,
+        dim_text: Color::DarkCyan,
+        faint_text: Color::Cyan,
+        highlight: Color::DarkMagenta,
+        alert: Color::DarkMagenta,
+        alert_banner: Color::Magenta,
+        ball_glyph: '●',
+        spin_glyph: '◉',
+        paddle_glyph: '█',
+        braille: true,
+    };
+
+    pub const AMBER: Theme = Self::monochrome("amber", Color::DarkYellow, Color::Yellow);
+
+    pub const GREEN: Theme = Self::monochrome("green", Color::DarkGreen, Color::Green);
+
+    pub const ALL: [Theme; 4] = [Self::DEFAULT, Self::CGA, Self::AMBER, Self::GREEN];
+
+    /// A single phosphor monitor, in a bright and a dim shade of one color.
+    /// With no colors to tell things apart, the ball and paddles are drawn
+    /// as plain characters the way old text mode games drew them, and never
+    /// in braille.
+    const fn monochrome(name: &'static str, bright: Color, dim: Color) -> Theme {
+        Theme {
+            name,
+            players: [(bright, dim); 4],
+            ball: bright,
+            fast_ball: [bright, bright],
+            center_wall: bright,
+            small_walls: bright,
+            moving_wall: bright,
+            obstacles: dim,
+            bricks: [dim, bright, bright],
+            practice_wall: dim,
+            practice_target: bright,
+            powerups: Some(bright),
+            flash: bright,
+            border: dim,
+            center_line: dim,
+            text: bright,
+            dim_text: dim,
+            faint_text: dim,
+            highlight: bright,
+            alert: bright,
+            alert_banner: dim,
+            ball_glyph: 'O',
+            spin_glyph: '@',
+            paddle_glyph: '#',
+            braille: false,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Theme> {
+        Self::ALL.into_iter().find(|theme| theme.name == name)
+    }
+
+    /// The built in themes' names, for error messages
+    pub fn names() -> String {
+        Self::ALL.map(|theme| theme.name).join(", ")
+    }
+}
@@ -0,0 +1,45 @@
+//! Balls are swept along their whole move, so however fast one goes it
+//! can't jump over something a cell wide
+
+use dospong::collision::{self, Segment, Vec2};
+use dospong::pong::{Ball, Input};
+
+mod common;
+
+/// One physics step
+const STEP: f32 = 1.0 / 120.0;
+
+#[test]
+fn a_move_crosses_a_line_between_its_ends() {
+    let path = Segment::new(Vec2::new(4.5, 10.0), Vec2::new(1.5, 11.5));
+    assert_eq!(collision::segment_vs_vline(&path, 3.0), Some(0.5));
+    assert_eq!(path.at(0.5), Vec2::new(3.0, 10.75));
+    assert_eq!(collision::segment_vs_vline(&path, 5.0), None);
+    assert_eq!(collision::segment_vs_vline(&path, 1.0), None);
+}
+
+#[test]
+fn a_fast_ball_bounces_off_the_left_paddle_rather_than_through_it() {
+    // Three cells in the step, from in front of the paddle's column at 2
+    // to behind it
+    let mut game = common::game_with(5, Ball::new(4.5, 0.0, -6.0, 0.0));
+    game.balls[0].pos.y = game.p1_y as f32 + 2.5;
+    game.step(Input::default(), STEP);
+    let ball = &game.balls[0];
+    assert!(ball.vel.x > 0.0, "went through to {:?}", ball.pos);
+    assert!(ball.pos.x >= 3.0, "ended up at {:?}", ball.pos);
+    assert_eq!(ball.last_hit_by, Some(1));
+}
+
+#[test]
+fn a_fast_ball_bounces_off_the_right_paddle_rather_than_through_it() {
+    // The right paddle's column is 77
+    let mut game = common::game_with(5, Ball::new(75.5, 0.0, 6.0, 0.0));
+    game.balls[0].pos.y = game.p2_y as f32 + 2.5;
+    game.step(Input::default(), STEP);
+    let ball = &game.balls[0];
+    assert!(ball.vel.x < 0.0, "went through to {:?}", ball.pos);
+    assert!(ball.pos.x < 77.0, "ended up at {:?}", ball.pos);
+    assert_eq!(ball.last_hit_by, Some(2));
+    assert_eq!(game.p1_score, 0);
+}
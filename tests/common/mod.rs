@@ -0,0 +1,17 @@
+//! What the tests share
+
+use dospong::pong::{Ball, Game, PowerUpSettings};
+
+/// An 80x24 game from `seed` already in play with one ball, `ball`, and no
+/// powerups spawning to get in its way. A ball's velocity is in cells per
+/// 60th of a second, so it covers half of it in a physics step.
+pub fn game_with(seed: u64, ball: Ball) -> Game {
+    let mut game = Game::new(80, 24, seed);
+    game.set_powerup_settings(PowerUpSettings {
+        enabled: Vec::new(),
+        ..PowerUpSettings::default()
+    });
+    game.serve_timer = None;
+    game.balls = vec![ball];
+    game
+}
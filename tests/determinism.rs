@@ -0,0 +1,49 @@
+//! A seed and a script of inputs decide a whole match, down to the last
+//! ball and powerup
+
+use dospong::pong::{AiDifficulty, Dir, Game, Input};
+
+const TICKS: u32 = 10_000;
+
+/// What the left player does on `tick`: sweeping up and down, now and
+/// then serving or dashing
+fn scripted(tick: u32) -> Input {
+    let p1 = match tick / 45 % 3 {
+        0 => Dir::Up,
+        1 => Dir::Stay,
+        _ => Dir::Down,
+    };
+    Input {
+        p1,
+        p1_serve: tick.is_multiple_of(97),
+        p1_dash: if tick.is_multiple_of(331) { p1 } else { Dir::Stay },
+        ..Input::default()
+    }
+}
+
+fn played(seed: u64) -> Game {
+    let mut game = Game::new(80, 24, seed);
+    game.ai = Some(AiDifficulty::Normal);
+    // Long enough that the match is still going at the end
+    game.score_limit = 99;
+    for tick in 0..TICKS {
+        game.step(scripted(tick), 1.0 / 60.0);
+    }
+    game
+}
+
+#[test]
+fn the_same_seed_and_inputs_play_out_the_same() {
+    let (first, second) = (played(42), played(42));
+    let state = |game: &Game| serde_json::to_string(game).unwrap();
+    assert_eq!(state(&first), state(&second));
+    // Enough happened for that to mean something
+    assert!(first.p1_score + first.p2_score > 0);
+    assert!(first.stats.powerups.iter().sum::<u32>() > 0);
+}
+
+#[test]
+fn another_seed_plays_out_differently() {
+    let state = |game: Game| serde_json::to_string(&game).unwrap();
+    assert_ne!(state(played(42)), state(played(43)));
+}
@@ -0,0 +1,31 @@
+//! Paddle movement, which has to come out the same whatever rate the
+//! frames come at
+
+use dospong::pong::{AiDifficulty, Dir, Game, Input};
+
+/// How far the left paddle gets for a second of holding down, and how far
+/// the computer moves the right one in the same time
+fn moved_in_a_second(frame_rate: u32) -> (i16, i16) {
+    let mut game = Game::new(80, 200, 7);
+    game.ai = Some(AiDifficulty::Hard);
+    // The computer heads back to the middle from the top, further than it
+    // gets in a second
+    game.p2_y = 0;
+    let p1_start = game.p1_y;
+    let input = Input {
+        p1: Dir::Down,
+        ..Input::default()
+    };
+    for _ in 0..frame_rate {
+        game.step(input, 1.0 / frame_rate as f32);
+    }
+    (game.p1_y - p1_start, game.p2_y)
+}
+
+#[test]
+fn a_held_key_moves_as_far_at_any_frame_rate() {
+    let at_60 = moved_in_a_second(60);
+    assert_eq!(at_60, (60, 60));
+    assert_eq!(moved_in_a_second(30), at_60);
+    assert_eq!(moved_in_a_second(120), at_60);
+}
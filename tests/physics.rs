@@ -0,0 +1,187 @@
+//! The ball against the edges, the paddles and the goals, with nothing but
+//! a `Game` and its inputs
+
+use dospong::collision::Vec2;
+use dospong::pong::{ActivePowerUp, Ball, Game, Input, PortalRect, PowerUpType};
+use std::time::Duration;
+
+mod common;
+
+const FRAME: f32 = 1.0 / 60.0;
+
+#[test]
+fn the_ball_bounces_off_the_top() {
+    let mut game = common::game_with(3, Ball::new(40.0, 2.0, 0.5, -1.0));
+    for _ in 0..10 {
+        game.step(Input::default(), FRAME);
+        assert!(game.balls[0].pos.y >= 0.0);
+        if game.balls[0].vel.y > 0.0 {
+            break;
+        }
+    }
+    let ball = &game.balls[0];
+    assert!(ball.vel.y > 0.0, "still heading up at {:?}", ball.pos);
+    assert!(ball.vel.x > 0.0);
+    assert_eq!((game.p1_score, game.p2_score), (0, 0));
+}
+
+#[test]
+fn a_paddle_hit_sends_the_ball_back_faster() {
+    let mut game = common::game_with(3, Ball::new(8.0, 0.0, -0.5, 0.0));
+    // Straight at the middle of the left paddle
+    let center = game.p1_y as f32 + game.p1_height as f32 / 2.0;
+    game.balls[0].pos.y = center;
+    let mut speed = 0.0;
+    for _ in 0..20 {
+        speed = game.balls[0].vel.x.abs();
+        game.step(Input::default(), FRAME);
+        if game.balls[0].vel.x > 0.0 {
+            break;
+        }
+    }
+    let ball = &game.balls[0];
+    assert!(ball.vel.x > 0.0, "not sent back from {:?}", ball.pos);
+    assert!(ball.vel.x > speed, "came in at {} and left at {}", speed, ball.vel.x);
+    assert_eq!(ball.last_hit_by, Some(1));
+    assert_eq!((game.p1_score, game.p2_score), (0, 0));
+}
+
+#[test]
+fn a_goal_scores_for_the_other_side() {
+    // Along the top, well clear of the left paddle
+    let mut game = common::game_with(3, Ball::new(6.0, 1.0, -1.0, 0.0));
+    game.p1_y = game.height as i16 - game.p1_height as i16;
+    for _ in 0..20 {
+        game.step(Input::default(), FRAME);
+        if game.p2_score > 0 {
+            break;
+        }
+    }
+    assert_eq!((game.p1_score, game.p2_score), (0, 1));
+}
+
+#[test]
+fn two_balls_out_at_once_both_count_and_the_rest_play_on() {
+    // One slipping past each paddle along the top, and one in the middle
+    let out_left = Ball::new(0.5, 2.0, -1.0, 0.0);
+    let out_right = Ball::new(78.6, 2.0, 1.0, 0.0);
+    let survivor = Ball::new(40.0, 12.0, 0.5, 0.3);
+    let mut game = common::game_with(3, out_left);
+    game.balls.extend([survivor.clone(), out_right]);
+    game.stats.rally = 3;
+    // Portals and reversed controls last as long as the rally, well clear
+    // of where the ball that stays goes
+    for ptype in [PowerUpType::Portal, PowerUpType::ReverseControls] {
+        game.active_powerups.push(ActivePowerUp {
+            ptype,
+            player: 1,
+            remaining: Duration::from_secs(5),
+        });
+    }
+    let portals = (PortalRect { x: 20, y: 18 }, PortalRect { x: 60, y: 18 });
+    game.portals = Some(portals);
+    // The same ball on its own, to say where it should end up
+    let mut alone = common::game_with(3, survivor);
+
+    game.step(Input::default(), 1.0 / 120.0);
+    alone.step(Input::default(), 1.0 / 120.0);
+
+    assert_eq!((game.p1_score, game.p2_score), (1, 1));
+    assert_eq!(game.balls.len(), 1);
+    let (ball, expected) = (&game.balls[0], &alone.balls[0]);
+    assert_eq!((ball.pos, ball.vel), (expected.pos, expected.vel));
+    // Still in play, not waiting on a serve
+    assert_eq!(game.serve_timer, None);
+    assert_eq!(game.stats.rally, 3);
+    assert_eq!(game.portals, Some(portals));
+    assert_eq!(game.active_powerups.len(), 2);
+}
+
+#[test]
+fn a_dead_center_hit_never_sends_the_ball_back_flat() {
+    let mut game = common::game_with(3, Ball::new(40.0, 0.0, -0.75, 0.0));
+    let mut ups = 0;
+    for bounce in 0..100 {
+        // Flat at the middle of whichever paddle it's heading for
+        let (paddle_y, height) = match game.balls[0].vel.x < 0.0 {
+            true => (game.p1_y, game.p1_height),
+            false => (game.p2_y, game.p2_height),
+        };
+        let ball = &mut game.balls[0];
+        ball.pos = Vec2::new(40.0, paddle_y as f32 + height as f32 / 2.0);
+        ball.vel.y = 0.0;
+        let heading = ball.vel.x.signum();
+        for _ in 0..200 {
+            game.step(Input::default(), 1.0 / 120.0);
+            if game.balls[0].vel.x.signum() != heading {
+                break;
+            }
+        }
+        let ball = &game.balls[0];
+        assert_ne!(ball.vel.x.signum(), heading, "bounce {} never came back", bounce);
+        // The least a paddle sends it off at, `MIN_BALL_VY`
+        assert!(ball.vel.y.abs() >= 0.15, "bounce {} came off at {:?}", bounce, ball.vel);
+        ups += (ball.vel.y < 0.0) as u32;
+    }
+    // Which way is left to chance
+    assert!((20..=80).contains(&ups), "{} of 100 went up", ups);
+    assert_eq!((game.p1_score, game.p2_score), (0, 0));
+}
+
+/// Send a ball at `corner` from four cells away diagonally, which it
+/// reaches in 10 steps, and follow it for `steps` steps. Every point it
+/// passes through has to be on the field.
+fn into_corner(game: &mut Game, corner: Vec2, vel: Vec2, steps: u32) {
+    let start = corner - vel * 5.0;
+    game.balls = vec![Ball::new(start.x, start.y, vel.x, vel.y)];
+    let (right, bottom) = ((game.width - 1) as f32, (game.height - 1) as f32);
+    for _ in 0..steps {
+        game.step(Input::default(), 1.0 / 120.0);
+        for ball in &game.balls {
+            let Vec2 { x, y } = ball.pos;
+            assert!((0.0..=right).contains(&x) && (0.0..=bottom).contains(&y), "at {:?}", ball);
+        }
+    }
+}
+
+#[test]
+fn a_ball_into_the_top_of_the_left_paddle_comes_back_off_the_edge() {
+    let mut game = common::game_with(3, Ball::new(40.0, 12.0, 0.0, 0.0));
+    game.p1_y = 0;
+    into_corner(&mut game, Vec2::new(2.0, 0.0), Vec2::new(-0.8, -0.8), 40);
+    let ball = &game.balls[0];
+    assert_eq!((game.p1_score, game.p2_score), (0, 0));
+    assert_eq!(ball.last_hit_by, Some(1));
+    assert!(ball.vel.x > 0.0 && ball.vel.y > 0.0, "going {:?}", ball.vel);
+    assert!(ball.pos.y >= 0.5, "hugging the top at {:?}", ball.pos);
+}
+
+#[test]
+fn a_ball_past_the_left_paddle_off_the_top_is_one_point() {
+    let mut game = common::game_with(3, Ball::new(40.0, 12.0, 0.0, 0.0));
+    game.p1_y = game.height as i16 - game.p1_height as i16;
+    into_corner(&mut game, Vec2::new(2.0, 0.0), Vec2::new(-0.8, -0.8), 20);
+    assert_eq!((game.p1_score, game.p2_score), (0, 1));
+}
+
+#[test]
+fn a_ball_into_the_bottom_right_corner_is_one_point() {
+    let mut game = common::game_with(3, Ball::new(40.0, 12.0, 0.0, 0.0));
+    game.p2_y = 0;
+    let corner = Vec2::new((game.width - 1) as f32, (game.height - 1) as f32);
+    into_corner(&mut game, corner, Vec2::new(0.8, 0.8), 20);
+    assert_eq!((game.p1_score, game.p2_score), (1, 0));
+}
+
+#[test]
+fn a_ball_for_the_bottom_right_corner_is_saved_by_a_paddle_there() {
+    let mut game = common::game_with(3, Ball::new(40.0, 12.0, 0.0, 0.0));
+    game.p2_y = game.height as i16 - game.p2_height as i16;
+    let corner = Vec2::new((game.width - 1) as f32, (game.height - 1) as f32);
+    into_corner(&mut game, corner, Vec2::new(0.8, 0.8), 40);
+    let ball = &game.balls[0];
+    assert_eq!((game.p1_score, game.p2_score), (0, 0));
+    assert_eq!(ball.last_hit_by, Some(2));
+    assert!(ball.vel.x < 0.0 && ball.vel.y < 0.0, "going {:?}", ball.vel);
+    assert!(ball.pos.y <= (game.height - 1) as f32 - 0.5, "hugging the bottom at {:?}", ball.pos);
+}
@@ -0,0 +1,200 @@
+//! The powerup table, which everything that spawns, draws or runs a powerup
+//! reads from
+
+use dospong::events::GameEvent;
+use dospong::pong::{
+    ActivePowerUp, AiDifficulty, Ball, Game, Input, PowerUp, PowerUpSettings, PowerUpType,
+    POWERUP_SIZE,
+};
+use std::collections::HashMap;
+use std::time::Duration;
+
+mod common;
+
+/// Panics naming the first two powerups that share a `look`
+fn assert_all_differ<T: std::hash::Hash + Eq + std::fmt::Debug>(
+    what: &str,
+    look: impl Fn(PowerUpType) -> T,
+) {
+    let mut seen = HashMap::new();
+    for ptype in PowerUpType::ALL {
+        if let Some(other) = seen.insert(look(ptype), ptype) {
+            panic!("{:?} and {:?} have the same {}: {:?}", other, ptype, what, look(ptype));
+        }
+    }
+}
+
+#[test]
+fn every_powerup_can_be_told_apart() {
+    assert_all_differ("name", |ptype| ptype.effect().name());
+    assert_all_differ("title", |ptype| ptype.effect().title());
+    assert_all_differ("symbol", |ptype| ptype.effect().symbol());
+    // Colorblind mode goes by these instead of the color
+    assert_all_differ("label", |ptype| ptype.effect().label());
+    assert_all_differ("shape", |ptype| ptype.effect().shape());
+}
+
+#[test]
+fn every_shape_fills_its_square() {
+    for ptype in PowerUpType::ALL {
+        let shape = ptype.effect().shape();
+        for row in shape {
+            assert_eq!(row.chars().count(), POWERUP_SIZE as usize, "{:?}'s shape", ptype);
+        }
+        assert!(shape.iter().any(|row| row.trim() != ""), "{:?} draws nothing", ptype);
+    }
+}
+
+#[test]
+fn every_powerup_goes_by_its_name() {
+    for ptype in PowerUpType::ALL {
+        assert_eq!(PowerUpType::from_name(ptype.name()), Some(ptype));
+    }
+}
+
+#[test]
+fn powerups_turn_up_clear_of_everything() {
+    let mut game = Game::new(80, 24, 9);
+    game.ai = Some(AiDifficulty::Hard);
+    game.left_ai = Some(AiDifficulty::Hard);
+    game.score_limit = 99;
+    game.set_obstacle_course(true);
+    game.set_events(true);
+    // One every step there's room for
+    game.set_powerup_settings(PowerUpSettings {
+        spawn_rate: 1e6,
+        max_on_field: 3,
+        ..PowerUpSettings::default()
+    });
+    let half = (POWERUP_SIZE / 2) as i32;
+    let (mut spawns, mut with_walls) = (0, 0);
+    for _ in 0..200_000 {
+        if spawns == 10_000 {
+            break;
+        }
+        // Make room for the next one, leaving the rest to be kept clear of
+        if game.powerups.len() == 3 {
+            game.powerups.remove(0);
+        }
+        // The field as the new one found it
+        let (walls, balls) = (game.walls.clone(), game.balls.clone());
+        game.step(Input::default(), 1.0 / 120.0);
+        for event in std::mem::take(&mut game.events) {
+            let GameEvent::PowerupSpawn { x, y, .. } = event.event else {
+                continue;
+            };
+            spawns += 1;
+            with_walls += !walls.is_empty() as u32;
+            let (x, y) = (x as i32, y as i32);
+            let covers = |cx: i32, cy: i32| (cx - x).abs() <= half && (cy - y).abs() <= half;
+            for (cx, cy) in game.paddle_cells() {
+                assert!(!covers(cx as i32, cy as i32), "({}, {}) is on a paddle", x, y);
+            }
+            for wall in &walls {
+                let rows = wall.y as i32..(wall.y + wall.height) as i32;
+                assert!(!rows.into_iter().any(|row| covers(wall.x as i32, row)), "on a wall");
+            }
+            for ball in &balls {
+                let (bx, by) = ball.pos.cell();
+                assert!(!covers(bx, by), "({}, {}) is on a ball at ({}, {})", x, y, bx, by);
+            }
+            let others = game.powerups.iter().filter(|p| (p.x as i32, p.y as i32) != (x, y));
+            for other in others {
+                let apart = (other.x as i32 - x).abs().max((other.y as i32 - y).abs());
+                assert!(apart >= POWERUP_SIZE as i32, "({}, {}) is on another", x, y);
+            }
+        }
+    }
+    assert_eq!(spawns, 10_000);
+    assert!(with_walls > 5_000, "only {} had walls to keep clear of", with_walls);
+}
+
+#[test]
+fn a_powerup_goes_to_whoever_hit_the_ball_even_over_the_line() {
+    // The left player's return, about to cross into the right player's half
+    let mut ball = Ball::new(36.0, 6.0, 0.75, 0.0);
+    ball.last_hit_by = Some(1);
+    let mut game = common::game_with(2, ball);
+    game.powerups.push(PowerUp {
+        x: 50,
+        y: 6,
+        ptype: PowerUpType::GrowPaddle,
+        remaining: Duration::from_secs(10),
+    });
+    let height = game.p1_height;
+    for _ in 0..60 {
+        game.step(Input::default(), 1.0 / 60.0);
+        if game.powerups.is_empty() {
+            break;
+        }
+    }
+    assert!(game.powerups.is_empty(), "never collected");
+    assert!(game.balls[0].pos.x > 40.0);
+    assert_eq!(game.stats.powerups[..2], [1, 0]);
+    assert_eq!(game.active_powerups[0].player, 1);
+    assert!(game.p1_height > height);
+    assert_eq!(game.p2_height, height);
+}
+
+#[test]
+fn split_balls_fan_out_at_the_same_speed() {
+    let mut ball = Ball::new(44.0, 8.0, 0.7, 0.2);
+    ball.last_hit_by = Some(1);
+    let mut game = common::game_with(2, ball);
+    game.powerups.push(PowerUp {
+        x: 50,
+        y: 10,
+        ptype: PowerUpType::SplitBall,
+        remaining: Duration::from_secs(10),
+    });
+    // A step at a time, to see the new balls before they've moved
+    for _ in 0..60 {
+        game.step(Input::default(), 1.0 / 120.0);
+        if game.powerups.is_empty() {
+            break;
+        }
+    }
+    assert_eq!(game.balls.len(), 3, "never split");
+    let original = game.balls[0].vel;
+    let mut sides = Vec::new();
+    for split in &game.balls[1..] {
+        assert!((split.vel.length() - original.length()).abs() < 1e-5);
+        assert_eq!(split.pos, game.balls[0].pos);
+        assert_eq!(split.last_hit_by, Some(1));
+        let spread = (split.vel.angle() - original.angle()).to_degrees();
+        assert!((20.0..=30.0).contains(&spread.abs()), "fanned out {}°", spread);
+        sides.push(spread.signum());
+    }
+    // One each side
+    assert_eq!(sides.iter().sum::<f32>(), 0.0);
+}
+
+#[test]
+fn a_second_paddle_fits_above_one_at_the_bottom() {
+    let mut game = common::game_with(2, Ball::new(40.0, 12.0, 0.0, 0.0));
+    game.p1_y = game.height as i16 - game.p1_height as i16;
+    game.active_powerups.push(ActivePowerUp {
+        ptype: PowerUpType::DoublePaddle,
+        player: 1,
+        remaining: Duration::from_secs(10),
+    });
+    game.step(Input::default(), 1.0 / 120.0);
+    let second = game.p1_second_y.expect("no second paddle");
+    let height = game.p1_height as i16;
+    assert!(second >= 0 && second + height <= game.height as i16, "at {}", second);
+    assert!(second + height <= game.p1_y, "{} runs into the paddle at {}", second, game.p1_y);
+
+    // Straight at the middle of the second paddle, which should send it
+    // back level rather than at the angle the first one's edge would
+    game.balls = vec![Ball::new(8.0, second as f32 + height as f32 / 2.0, -0.5, 0.0)];
+    for _ in 0..40 {
+        game.step(Input::default(), 1.0 / 120.0);
+        if game.balls[0].vel.x > 0.0 {
+            break;
+        }
+    }
+    let ball = &game.balls[0];
+    assert!(ball.vel.x > 0.0, "missed it, at {:?}", ball.pos);
+    assert!(ball.vel.y.abs() <= 0.2, "came off at {:?}", ball.vel);
+    assert_eq!(game.p1_second_y, Some(second));
+}
@@ -0,0 +1,21 @@
+//! Resizing takes any size, however little room that leaves, and the game
+//! carries on once it's given room again
+
+use dospong::pong::{Game, Input};
+
+#[test]
+fn a_game_resizes_to_nothing_and_back() {
+    for (width, height) in [(80, 0), (0, 24), (0, 0), (1, 1), (3, 2), (10, 5)] {
+        let mut game = Game::new(80, 24, 1);
+        game.set_breakout(true);
+        game.set_obstacle_course(true);
+        game.resize(width, height);
+        assert_eq!((game.width, game.height), (width, height));
+        game.resize(80, 24);
+        for _ in 0..300 {
+            game.step(Input::default(), 1.0 / 60.0);
+        }
+        let ball = &game.balls[0];
+        assert!((0.0..=79.0).contains(&ball.pos.x) && (0.0..=23.0).contains(&ball.pos.y));
+    }
+}
@@ -0,0 +1,82 @@
+//! A game written out as JSON comes back as the same game, now and after
+//! the format has moved on
+
+use dospong::pong::{AiDifficulty, Dir, Game, Input};
+use serde_json::Value;
+
+/// A game partway through a match, with powerups waiting on the field,
+/// written by an earlier version. `BLESS=1 cargo test --test serde_state`
+/// writes it afresh, which only a change that breaks old games should need.
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/game.json");
+
+/// What the left player does on `tick` against the computer
+fn input(tick: u32) -> Input {
+    let p1 = match tick / 45 % 3 {
+        0 => Dir::Up,
+        1 => Dir::Stay,
+        _ => Dir::Down,
+    };
+    Input {
+        p1,
+        ..Input::default()
+    }
+}
+
+/// A seeded match against the computer, `ticks` in
+fn mid_match(ticks: u32) -> Game {
+    let mut game = Game::new(80, 24, 42);
+    game.ai = Some(AiDifficulty::Normal);
+    for tick in 0..ticks {
+        game.step(input(tick), 1.0 / 60.0);
+    }
+    game
+}
+
+/// Panics where `got` differs from `want`, leaving out fields `want`
+/// doesn't have, which a newer version is free to add
+fn assert_keeps(want: &Value, got: &Value, path: &str) {
+    match (want, got) {
+        (Value::Object(want), Value::Object(got)) => {
+            for (key, want) in want {
+                let path = format!("{}.{}", path, key);
+                let got = got.get(key).unwrap_or_else(|| panic!("{} went missing", path));
+                assert_keeps(want, got, &path);
+            }
+        }
+        (Value::Array(want), Value::Array(got)) => {
+            assert_eq!(want.len(), got.len(), "{} changed length", path);
+            for (i, (want, got)) in want.iter().zip(got).enumerate() {
+                assert_keeps(want, got, &format!("{}[{}]", path, i));
+            }
+        }
+        _ => assert_eq!(want, got, "{} changed", path),
+    }
+}
+
+#[test]
+fn a_saved_game_loads_as_it_was() {
+    if std::env::var_os("BLESS").is_some() {
+        let game = mid_match(640);
+        std::fs::write(FIXTURE, serde_json::to_string_pretty(&game).unwrap()).unwrap();
+    }
+    let text = std::fs::read_to_string(FIXTURE).unwrap();
+    let game: Game = serde_json::from_str(&text).unwrap();
+    assert_eq!((game.p1_score, game.p2_score), (0, 1));
+    assert_eq!(game.powerups.len(), 2);
+    // Both read back from text, so the numbers are rounded the same way
+    let want: Value = serde_json::from_str(&text).unwrap();
+    let got: Value = serde_json::from_str(&serde_json::to_string(&game).unwrap()).unwrap();
+    assert_keeps(&want, &got, "game");
+}
+
+#[test]
+fn a_game_carries_on_the_same_after_a_round_trip() {
+    let mut game = mid_match(640);
+    let mut copy: Game = serde_json::from_str(&serde_json::to_string(&game).unwrap()).unwrap();
+    for tick in 640..1640 {
+        game.step(input(tick), 1.0 / 60.0);
+        copy.step(input(tick), 1.0 / 60.0);
+        let state = |game: &Game| serde_json::to_value(game).unwrap();
+        assert_eq!(state(&game), state(&copy), "apart by tick {}", tick);
+    }
+}
@@ -0,0 +1,65 @@
+//! Serves go to whoever gave away the last point, at an angle that's
+//! neither flat nor steep
+
+use dospong::collision::Vec2;
+use dospong::pong::{Game, Input, PowerUpSettings};
+
+/// Step until the ball is on its way, and say how it left
+fn next_serve(game: &mut Game) -> Vec2 {
+    for _ in 0..600 {
+        game.step(Input::default(), 1.0 / 120.0);
+        let vel = game.balls[0].vel;
+        if vel != Vec2::new(0.0, 0.0) {
+            return vel;
+        }
+    }
+    panic!("no serve");
+}
+
+/// Slip the ball past the paddle on `side`, so the other side scores
+fn concede(game: &mut Game, side: u8) {
+    let ball = &mut game.balls[0];
+    ball.pos = Vec2::new(if side == 1 { 0.5 } else { (game.width - 1) as f32 - 0.5 }, 1.0);
+    ball.vel = Vec2::new(if side == 1 { -1.0 } else { 1.0 }, 0.0);
+    let score = game.p1_score + game.p2_score;
+    game.step(Input::default(), 1.0 / 120.0);
+    assert_eq!(game.p1_score + game.p2_score, score + 1);
+}
+
+/// Degrees off straight across
+fn angle(vel: Vec2) -> f32 {
+    (vel.y.abs() / vel.x.abs()).atan().to_degrees()
+}
+
+#[test]
+fn serves_go_to_whoever_conceded_at_a_bounded_angle() {
+    let (mut openers, mut ups) = ([0; 2], 0);
+    let mut serves = 0;
+    for seed in 0..200 {
+        let mut game = Game::new(80, 24, seed);
+        game.set_powerup_settings(PowerUpSettings {
+            enabled: Vec::new(),
+            ..PowerUpSettings::default()
+        });
+        // Well away from where the ball goes out
+        (game.p1_y, game.p2_y) = (15, 15);
+        let opening = next_serve(&mut game);
+        openers[(opening.x > 0.0) as usize] += 1;
+        let mut check = |vel: Vec2| {
+            let degrees = angle(vel);
+            assert!((14.99..=40.01).contains(&degrees), "seed {} served at {}°", seed, degrees);
+            ups += (vel.y < 0.0) as u32;
+            serves += 1;
+        };
+        check(opening);
+        for side in [1, 2, 2, 1] {
+            concede(&mut game, side);
+            let vel = next_serve(&mut game);
+            assert_eq!(vel.x < 0.0, side == 1, "seed {} didn't serve to {}", seed, side);
+            check(vel);
+        }
+    }
+    // Either way to open, and up or down, are up to chance
+    assert!(openers.iter().all(|&n| n > 60), "opened {:?}", openers);
+    assert!((serves * 2 / 5..=serves * 3 / 5).contains(&ups), "{} of {} went up", ups, serves);
+}
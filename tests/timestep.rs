@@ -0,0 +1,30 @@
+//! The simulation moves in fixed steps, so how the time is handed to it
+//! makes no difference
+
+use dospong::pong::{AiDifficulty, Ball, Game, Input, MAX_FRAME_DT};
+
+/// A game against the computer with the ball already on its way, far
+/// enough to bounce off the bottom in half a second
+fn in_play() -> Game {
+    let mut game = Game::new(80, 24, 11);
+    game.ai = Some(AiDifficulty::Hard);
+    game.serve_timer = None;
+    game.balls = vec![Ball::new(40.0, 12.0, 0.7, 0.4)];
+    game
+}
+
+#[test]
+fn one_long_frame_goes_the_same_as_many_short_ones() {
+    let (mut long, mut short) = (in_play(), in_play());
+    long.step(Input::default(), MAX_FRAME_DT);
+    for _ in 0..60 {
+        short.step(Input::default(), 1.0 / 120.0);
+    }
+    let path = |game: &Game| {
+        let balls = game.balls.iter().map(|ball| (ball.pos, ball.vel, ball.prev));
+        (balls.collect::<Vec<_>>(), game.p1_y, game.p2_y)
+    };
+    assert_eq!(path(&long), path(&short));
+    // It did get somewhere
+    assert_ne!(long.balls[0].pos, in_play().balls[0].pos);
+}
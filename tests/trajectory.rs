@@ -0,0 +1,55 @@
+//! Where the balls go, kept to the bit. Anything that only reorganizes the
+//! geometry has to leave every position and velocity here exactly as it
+//! was; a change that means to alter the physics records it afresh with
+//! `BLESS=1 cargo test --test trajectory`.
+
+use dospong::pong::{AiDifficulty, Dir, Game, Input};
+use std::fmt::Write;
+
+const GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/trajectory.txt");
+const TICKS: u32 = 3000;
+/// Every this many ticks goes in the record, enough to catch a drift long
+/// before it shows
+const EVERY: u32 = 10;
+
+/// Every ball's position and velocity through a seeded match against the
+/// computer, one line per ball, with the score at the end
+fn trajectory() -> String {
+    let mut game = Game::new(80, 24, 42);
+    game.ai = Some(AiDifficulty::Normal);
+    let mut record = String::new();
+    for tick in 0..TICKS {
+        let p1 = match tick / 45 % 3 {
+            0 => Dir::Up,
+            1 => Dir::Stay,
+            _ => Dir::Down,
+        };
+        let input = Input {
+            p1,
+            ..Input::default()
+        };
+        game.step(input, 1.0 / 60.0);
+        if !tick.is_multiple_of(EVERY) {
+            continue;
+        }
+        for ball in &game.balls {
+            let (pos, vel) = (ball.pos, ball.vel);
+            let _ = writeln!(record, "{} {:?} {:?} {:?} {:?}", tick, pos.x, pos.y, vel.x, vel.y);
+        }
+    }
+    let _ = writeln!(record, "score {} {}", game.p1_score, game.p2_score);
+    record
+}
+
+#[test]
+fn the_balls_follow_the_recorded_trajectory() {
+    let record = trajectory();
+    if std::env::var_os("BLESS").is_some() {
+        std::fs::write(GOLDEN, &record).unwrap();
+    }
+    let golden = std::fs::read_to_string(GOLDEN).unwrap();
+    for (line, (got, want)) in record.lines().zip(golden.lines()).enumerate() {
+        assert_eq!(got, want, "line {} of {}", line + 1, GOLDEN);
+    }
+    assert_eq!(record.lines().count(), golden.lines().count());
+}